@@ -0,0 +1,225 @@
+use crate::runs;
+use crate::state::{JobState, RunState};
+use clap::{Parser, ValueHint};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "batchelor archive",
+    about = "Bundle a previous run's state, scripts, manifests and markers into a .tar.gz"
+)]
+pub struct ArchiveCli {
+    /// Output directory the run was submitted from.
+    #[arg(long, default_value = ".batchelor", value_hint = ValueHint::DirPath)]
+    out_dir: PathBuf,
+
+    /// Run ID to archive (default: the most recent run).
+    #[arg(long)]
+    run: Option<String>,
+
+    /// Archive to write, e.g. run42.tar.gz.
+    #[arg(long)]
+    out: PathBuf,
+
+    /// Also include the jobs' scheduler logs.
+    #[arg(long)]
+    logs: bool,
+}
+
+/// A file to archive: where it is and its name inside the archive, below
+/// the top-level directory.
+struct Entry {
+    source: PathBuf,
+    name: String,
+}
+
+pub fn archive(cli: ArchiveCli) -> Result<(), Box<dyn std::error::Error>> {
+    let state = RunState::load_run(&cli.out_dir, cli.run.as_deref())?;
+    let run_dir = runs::run_dir(&cli.out_dir, &state.run_id);
+    let canonical_run_dir = fs::canonicalize(&run_dir)
+        .map_err(|e| format!("could not read {}: {}", run_dir.display(), e))?;
+
+    // Everything in the run's directory: state, manifests, markers, the
+    // rerun script and, since run directories, the scripts.
+    let mut entries = Vec::new();
+    let mut names = fs::read_dir(&run_dir)?
+        .map(|entry| entry.map(|e| e.file_name()))
+        .collect::<Result<Vec<_>, _>>()?;
+    names.sort();
+    for name in names {
+        let source = run_dir.join(&name);
+        if source.is_file() {
+            entries.push(Entry {
+                name: format!("run/{}", name.to_string_lossy()),
+                source,
+            });
+        }
+    }
+
+    // Files the run recorded elsewhere: scripts of --flat-out-dir runs,
+    // markers of runs from before per-run markers, and with --logs the logs.
+    let mut missing = Vec::new();
+    let mut seen = HashSet::new();
+    let mut outside = |kind: &str, path: &Path, missing: &mut Vec<String>| {
+        if path.starts_with(&canonical_run_dir) || !seen.insert(path.to_path_buf()) {
+            return;
+        }
+        if !path.is_file() {
+            missing.push(format!("{} {}", kind, path.display()));
+            return;
+        }
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        entries.push(Entry {
+            source: path.to_path_buf(),
+            name: format!("{}/{}", kind, file_name),
+        });
+    };
+    for job in &state.jobs {
+        if let Some(script) = &job.script {
+            outside("scripts", script, &mut missing);
+        }
+        for marker in job.done_file.iter().chain(&job.failed_file) {
+            outside("markers", marker, &mut missing);
+        }
+        if cli.logs {
+            for log in log_paths(job) {
+                outside("logs", &log, &mut missing);
+            }
+        }
+    }
+    // Scripts removed after submission are gone; the state records what
+    // each batch ran.
+    for job in &state.jobs {
+        if let Some(script) = job
+            .script
+            .as_ref()
+            .filter(|s| s.starts_with(&canonical_run_dir))
+        {
+            if !script.is_file() {
+                missing.push(format!("scripts {}", script.display()));
+            }
+        }
+    }
+
+    let top = format!("batchelor-{}", state.run_id);
+    let readme = readme(&state, &entries, &missing, cli.logs);
+    let write = || -> std::io::Result<()> {
+        let file = File::create(&cli.out)?;
+        let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(readme.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        );
+        header.set_cksum();
+        tar.append_data(
+            &mut header,
+            format!("{}/README.txt", top),
+            readme.as_bytes(),
+        )?;
+        for entry in &entries {
+            tar.append_path_with_name(&entry.source, format!("{}/{}", top, entry.name))?;
+        }
+        tar.into_inner()?.finish()?;
+        Ok(())
+    };
+    write().map_err(|e| format!("could not write {}: {}", cli.out.display(), e))?;
+
+    println!(
+        "Archived run {} ({} file(s)) to {}",
+        state.run_id,
+        entries.len() + 1,
+        cli.out.display()
+    );
+    if !missing.is_empty() {
+        println!("{} file(s) no longer exist; see README.txt", missing.len());
+    }
+    Ok(())
+}
+
+/// The job's log for every submission: one per job ID when the log name
+/// contains it.
+fn log_paths(job: &JobState) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    paths.extend(job.log_path());
+    for id in job.previous_job_ids.iter().chain(&job.job_id) {
+        let submission = JobState {
+            job_id: Some(id.clone()),
+            ..job.clone()
+        };
+        paths.extend(submission.log_path());
+    }
+    paths.dedup();
+    paths
+}
+
+fn readme(state: &RunState, entries: &[Entry], missing: &[String], logs: bool) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "batchelor run {}", state.run_id);
+    let _ = writeln!(out, "{}", "=".repeat(14 + state.run_id.len()));
+    let _ = writeln!(out);
+    if let Some(timestamp) = &state.timestamp {
+        let _ = writeln!(out, "Submitted: {}", timestamp);
+    }
+    let _ = writeln!(out, "Scheduler: {:?}", state.scheduler);
+    let _ = writeln!(out, "Submit:    {}", state.submit);
+    if !state.args.is_empty() {
+        let _ = writeln!(out, "Command:   {}", state.args.join(" "));
+    }
+    let _ = writeln!(
+        out,
+        "Batches:   {} covering {} input(s)",
+        state.jobs.len(),
+        state.jobs.iter().map(|j| j.inputs.len()).sum::<usize>()
+    );
+    let _ = writeln!(out);
+    let _ = writeln!(out, "Contents");
+    let _ = writeln!(out, "--------");
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "run/        the run's directory: state.json (every batch with its inputs,\n            \
+         job IDs and states), manifests, *.done/*.failed markers listing\n            \
+         the inputs each batch finished or failed, and the batch scripts"
+    );
+    if entries.iter().any(|e| e.name.starts_with("scripts/")) {
+        let _ = writeln!(
+            out,
+            "scripts/    batch scripts written outside the run directory"
+        );
+    }
+    if entries.iter().any(|e| e.name.starts_with("markers/")) {
+        let _ = writeln!(out, "markers/    markers written outside the run directory");
+    }
+    if logs {
+        let _ = writeln!(out, "logs/       the jobs' scheduler logs");
+    }
+    let _ = writeln!(out);
+    for entry in entries {
+        let _ = writeln!(out, "  {}", entry.name);
+    }
+    if !missing.is_empty() {
+        let _ = writeln!(out);
+        let _ = writeln!(out, "Missing");
+        let _ = writeln!(out, "-------");
+        let _ = writeln!(out);
+        let _ = writeln!(
+            out,
+            "Recorded by the run but no longer on disk (scripts are removed after\n\
+             submission unless --keep is given):"
+        );
+        let _ = writeln!(out);
+        for line in missing {
+            let _ = writeln!(out, "  {}", line);
+        }
+    }
+    out
+}
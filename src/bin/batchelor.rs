@@ -1,7 +1,129 @@
-use batchelor::{run, Cli};
+//! Exit codes of a run (subcommands exit 1 on any error):
+//!
+//! - 0: success
+//! - 1: any other error
+//! - 2: invalid options, such as `--batch 0`, a missing or empty script, a
+//!   bad `--glob` pattern, or options the inputs or `--submit` command
+//!   cannot satisfy (`--on-clamp error`, `--only-batch` past the last batch,
+//!   `--hold` without a scheduler, ...)
+//! - 3: no inputs matched
+//! - 4: `--out-dir` is not writable or a batch script could not be written
+//! - 5: a submission failed
+//! - 6: with `--keep-going`, some submissions failed
+//! - 130: interrupted with Ctrl-C
+//!
+//! With `--wait`, a run that submitted every batch exits with how its jobs
+//! came out instead:
+//!
+//! - 0: every input succeeded
+//! - 1: some inputs failed, as the jobs' `.failed` markers record
+//! - 2: some job failed as a whole: it timed out, ran out of memory, was
+//!   cancelled or lost, or failed without a marker saying which input
+
+use batchelor::reporter::ColorChoice;
+use batchelor::{
+    archive, cancel, clean, completions, config, failures, history, logs, man, plan_and_print,
+    release, resubmit, run_and_print, stats, status, watch, ArchiveCli, BatchelorError, CancelCli,
+    CleanCli, Cli, CompletionsCli, ConfigCli, FailuresCli, HistoryCli, LogsCli, ManCli, ReleaseCli,
+    ResubmitCli, StatsCli, StatusCli, WatchCli,
+};
 use clap::Parser;
+use std::ffi::{OsStr, OsString};
+use std::io::IsTerminal;
+
+/// Writes log events to stderr, for -v/-vv.
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!(
+                "[{}] {}",
+                record.level().as_str().to_lowercase(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
-    run(cli)
+    let subcommand_args = || std::env::args_os().skip(1);
+    match std::env::args_os()
+        .nth(1)
+        .as_deref()
+        .and_then(OsStr::to_str)
+    {
+        Some("archive") => archive(ArchiveCli::parse_from(subcommand_args())),
+        Some("cancel") => cancel(CancelCli::parse_from(subcommand_args())),
+        Some("clean") => clean(CleanCli::parse_from(subcommand_args())),
+        Some("completions") => completions(CompletionsCli::parse_from(subcommand_args())),
+        Some("config") => config(ConfigCli::parse_from(subcommand_args())),
+        Some("failures") => failures(FailuresCli::parse_from(subcommand_args())),
+        Some("history") => history(HistoryCli::parse_from(subcommand_args())),
+        Some("logs") => logs(LogsCli::parse_from(subcommand_args())),
+        Some("man") => man(ManCli::parse_from(subcommand_args())),
+        Some("release") => release(ReleaseCli::parse_from(subcommand_args())),
+        Some("resubmit") => resubmit(ResubmitCli::parse_from(subcommand_args())),
+        Some("stats") => stats(StatsCli::parse_from(subcommand_args())),
+        Some("status") => status(StatusCli::parse_from(subcommand_args())),
+        Some("watch") => match watch(WatchCli::parse_from(subcommand_args()))? {
+            0 => Ok(()),
+            code => std::process::exit(code),
+        },
+        Some("plan") => {
+            let cli = parse_run(std::iter::once("batchelor plan".into()).chain(run_args(2)));
+            let color = cli.color();
+            match plan_and_print(cli) {
+                Ok(_) => Ok(()),
+                Err(e) => fail(e, color),
+            }
+        }
+        Some("submit") => submit(parse_run(
+            std::iter::once("batchelor submit".into()).chain(run_args(2)),
+        )),
+        // Without a subcommand, like `batchelor submit`.
+        _ => submit(parse_run(run_args(0))),
+    }
+}
+
+fn run_args(skip: usize) -> impl Iterator<Item = OsString> {
+    std::env::args_os().skip(skip)
+}
+
+/// The options of a run, with logging set up as they say.
+fn parse_run(args: impl Iterator<Item = OsString>) -> Cli {
+    let cli = config::parse_from(args);
+    if log::set_logger(&StderrLogger).is_ok() {
+        log::set_max_level(cli.log_level());
+    }
+    cli
+}
+
+fn submit(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(clean_cli) = CleanCli::clean_only(&cli) {
+        return clean(clean_cli);
+    }
+    let color = cli.color();
+    match run_and_print(cli) {
+        Ok(report) => match report.exit_code() {
+            0 => Ok(()),
+            code => std::process::exit(code),
+        },
+        Err(e) => fail(e, color),
+    }
+}
+
+fn fail(e: BatchelorError, color: ColorChoice) -> ! {
+    if color.enabled(std::io::stderr().is_terminal()) {
+        eprintln!("\x1b[31mError:\x1b[0m {}", e);
+    } else {
+        eprintln!("Error: {}", e);
+    }
+    std::process::exit(e.exit_code())
 }
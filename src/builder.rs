@@ -0,0 +1,341 @@
+//! Building a [`Cli`] in code instead of parsing a command line.
+
+use crate::manifest::ManifestFormat;
+use crate::naming::{self, JobNameFormat};
+use crate::report::ReportFormat;
+use crate::scheduler::NotifyEvent;
+use crate::scheduler::Scheduler;
+use crate::{check_cli, runs, BatchelorError, Cli, Interrupt, OnClamp, OutputFormat};
+use std::path::PathBuf;
+
+/// Builds a [`Cli`] with the defaults of the command line. Options not
+/// set here keep those defaults.
+#[derive(Clone, Debug)]
+pub struct CliBuilder {
+    cli: Cli,
+    check_script: bool,
+}
+
+impl Cli {
+    pub fn builder() -> CliBuilder {
+        CliBuilder::new()
+    }
+}
+
+/// The defaults of the command line. `script` is empty and there are no
+/// inputs; a run needs both.
+impl Default for Cli {
+    fn default() -> Cli {
+        Cli {
+            script: Default::default(),
+            glob: Default::default(),
+            input_list: Default::default(),
+            glob_errors: Default::default(),
+            on_missing_input: Default::default(),
+            expand_threads: Default::default(),
+            io_threads: Default::default(),
+            meta_cache: Default::default(),
+            incremental: Default::default(),
+            incremental_key: Default::default(),
+            input_flag: "--input".to_string(),
+            input_list_flag: Default::default(),
+            raw_template: Default::default(),
+            pad_positional: Default::default(),
+            clamp_positional: Default::default(),
+            batch: 1,
+            on_clamp: Default::default(),
+            out_dir: PathBuf::from(".batchelor"),
+            run_id: Default::default(),
+            out_dir_timestamp: Default::default(),
+            flat_out_dir: Default::default(),
+            wait_for_lock: Default::default(),
+            no_lock: Default::default(),
+            script_mode: Default::default(),
+            artifact_mode: Default::default(),
+            group: Default::default(),
+            submit: "sbatch".to_string(),
+            job_name_prefix: "batch".to_string(),
+            job_name_format: naming::DEFAULT_JOB_NAME_FORMAT
+                .parse()
+                .expect("the default job name format parses"),
+            strict_names: Default::default(),
+            check_script: Default::default(),
+            script_args: Default::default(),
+            trailing_args: Default::default(),
+            dry_run: Default::default(),
+            no_write: Default::default(),
+            keep: Default::default(),
+            clean_only: Default::default(),
+            force_rewrite: Default::default(),
+            clean_strict: Default::default(),
+            force_clean: Default::default(),
+            multi_input: Default::default(),
+            max_command_bytes: 128 * 1024,
+            wrap: Default::default(),
+            no_auto_job_name: Default::default(),
+            singleton: Default::default(),
+            job_log_dir: Default::default(),
+            job_log_template: "{job_name}.%j.out".to_string(),
+            split_stderr: Default::default(),
+            cancel_on_failure: Default::default(),
+            keep_going: Default::default(),
+            max_submit_failures: Default::default(),
+            submit_parallel: 1,
+            yes: Default::default(),
+            confirm: Default::default(),
+            preflight: Default::default(),
+            no_preflight: Default::default(),
+            confirm_above: 50,
+            mem_per_byte: Default::default(),
+            mem_base: Default::default(),
+            mem_cap: Default::default(),
+            time_per_byte: Default::default(),
+            time_base: Default::default(),
+            time_cap: Default::default(),
+            hold: Default::default(),
+            scheduler: Default::default(),
+            notify: Default::default(),
+            notify_on: vec![NotifyEvent::End, NotifyEvent::Fail],
+            notify_once: Default::default(),
+            submit_stdin: Default::default(),
+            submit_timeout: Default::default(),
+            submit_record: Default::default(),
+            skip_submit_check: Default::default(),
+            only_batch: Default::default(),
+            submit_overrides: Default::default(),
+            resource_rules: Default::default(),
+            report: Default::default(),
+            progress: Default::default(),
+            no_progress: Default::default(),
+            report_format: ReportFormat::Tsv,
+            manifest: Default::default(),
+            manifest_format: ManifestFormat::Tsv,
+            plan_json: Default::default(),
+            execute: Default::default(),
+            output_format: OutputFormat::Human,
+            emit: Default::default(),
+            per_input: Default::default(),
+            wait: Default::default(),
+            wait_interval: 30,
+            status_command: Default::default(),
+            pending_warn: 30 * 60,
+            pending_fail: Default::default(),
+            metrics_out: Default::default(),
+            on_complete: Default::default(),
+            on_failure: Default::default(),
+            webhook_url: Default::default(),
+            profile: Default::default(),
+            verbose: Default::default(),
+            quiet: Default::default(),
+            color: Default::default(),
+            interrupt: Default::default(),
+        }
+    }
+}
+
+impl Default for CliBuilder {
+    fn default() -> CliBuilder {
+        CliBuilder::new()
+    }
+}
+
+impl CliBuilder {
+    pub fn new() -> CliBuilder {
+        CliBuilder {
+            cli: Cli::default(),
+            check_script: true,
+        }
+    }
+
+    /// `--script`.
+    pub fn script(mut self, path: impl Into<PathBuf>) -> CliBuilder {
+        self.cli.script = path.into();
+        self
+    }
+
+    /// Adds a `--glob` pattern or literal input.
+    pub fn glob(mut self, pattern: impl Into<String>) -> CliBuilder {
+        self.cli.glob.push(pattern.into());
+        self
+    }
+
+    /// Adds `--glob` patterns or literal inputs.
+    pub fn globs<I, S>(mut self, patterns: I) -> CliBuilder
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.cli.glob.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// `--input-list`.
+    pub fn input_list(mut self, path: impl Into<PathBuf>) -> CliBuilder {
+        self.cli.input_list = Some(path.into());
+        self
+    }
+
+    /// `--input-flag`.
+    pub fn input_flag(mut self, flag: impl Into<String>) -> CliBuilder {
+        self.cli.input_flag = flag.into();
+        self
+    }
+
+    /// `--batch`.
+    pub fn batch(mut self, n: usize) -> CliBuilder {
+        self.cli.batch = n;
+        self
+    }
+
+    /// `--on-clamp`.
+    pub fn on_clamp(mut self, on_clamp: OnClamp) -> CliBuilder {
+        self.cli.on_clamp = on_clamp;
+        self
+    }
+
+    /// `--out-dir`.
+    pub fn out_dir(mut self, dir: impl Into<PathBuf>) -> CliBuilder {
+        self.cli.out_dir = dir.into();
+        self
+    }
+
+    /// `--flat-out-dir`.
+    pub fn flat_out_dir(mut self, flat: bool) -> CliBuilder {
+        self.cli.flat_out_dir = flat;
+        self
+    }
+
+    /// `--run-id`.
+    pub fn run_id(mut self, id: impl Into<String>) -> CliBuilder {
+        self.cli.run_id = Some(id.into());
+        self
+    }
+
+    /// `--submit`.
+    pub fn submit(mut self, command: impl Into<String>) -> CliBuilder {
+        self.cli.submit = command.into();
+        self
+    }
+
+    /// `--scheduler`.
+    pub fn scheduler(mut self, scheduler: Scheduler) -> CliBuilder {
+        self.cli.scheduler = Some(scheduler);
+        self
+    }
+
+    /// `--job-name-prefix`.
+    pub fn job_name_prefix(mut self, prefix: impl Into<String>) -> CliBuilder {
+        self.cli.job_name_prefix = prefix.into();
+        self
+    }
+
+    /// `--job-name-format`.
+    pub fn job_name_format(mut self, format: JobNameFormat) -> CliBuilder {
+        self.cli.job_name_format = format;
+        self
+    }
+
+    /// `--script-args`.
+    pub fn script_args<I, S>(mut self, args: I) -> CliBuilder
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.cli.script_args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// `--dry-run`.
+    pub fn dry_run(mut self, dry_run: bool) -> CliBuilder {
+        self.cli.dry_run = dry_run;
+        self
+    }
+
+    /// `--keep`.
+    pub fn keep(mut self, keep: bool) -> CliBuilder {
+        self.cli.keep = keep;
+        self
+    }
+
+    /// `--multi-input`.
+    pub fn multi_input(mut self, multi_input: bool) -> CliBuilder {
+        self.cli.multi_input = multi_input;
+        self
+    }
+
+    /// `--wrap`.
+    pub fn wrap(mut self, wrap: bool) -> CliBuilder {
+        self.cli.wrap = wrap;
+        self
+    }
+
+    /// `--yes`: submit without asking, as library callers usually want.
+    pub fn yes(mut self, yes: bool) -> CliBuilder {
+        self.cli.yes = yes;
+        self
+    }
+
+    /// `--keep-going`.
+    pub fn keep_going(mut self, keep_going: bool) -> CliBuilder {
+        self.cli.keep_going = keep_going;
+        self
+    }
+
+    /// `--submit-parallel`; 0 counts as 1.
+    pub fn submit_parallel(mut self, n: u16) -> CliBuilder {
+        self.cli.submit_parallel = n.max(1);
+        self
+    }
+
+    /// `--wait`.
+    pub fn wait(mut self, wait: bool) -> CliBuilder {
+        self.cli.wait = wait;
+        self
+    }
+
+    /// `--preflight` with `true`, `--no-preflight` with `false`. By default
+    /// the first batch is checked where the scheduler has a test-only
+    /// submit mode; see [`crate::submitter::Submitter::preflight`].
+    pub fn preflight(mut self, preflight: bool) -> CliBuilder {
+        self.cli.preflight = preflight;
+        self.cli.no_preflight = !preflight;
+        self
+    }
+
+    /// `--skip-submit-check`.
+    pub fn skip_submit_check(mut self, skip: bool) -> CliBuilder {
+        self.cli.skip_submit_check = skip;
+        self
+    }
+
+    /// Stops the run when `interrupt` is interrupted, e.g. from another
+    /// thread or a signal handler (see [`Interrupt::install_handler`]).
+    pub fn interrupt(mut self, interrupt: Interrupt) -> CliBuilder {
+        self.cli.interrupt = interrupt;
+        self
+    }
+
+    /// Whether [`build`](CliBuilder::build) checks that the script exists
+    /// (default: yes). [`crate::run`] checks it regardless, so a script
+    /// written later can be named up front.
+    pub fn check_script(mut self, check: bool) -> CliBuilder {
+        self.check_script = check;
+        self
+    }
+
+    /// The [`Cli`], checked as `run` checks it before looking at inputs.
+    pub fn build(self) -> Result<Cli, BatchelorError> {
+        let cli = self.cli;
+        if cli.script.as_os_str().is_empty() {
+            return Err("a script is required".into());
+        }
+        if cli.glob.is_empty() && cli.input_list.is_none() {
+            return Err("a --glob pattern or an --input-list is required".into());
+        }
+        if let Some(id) = &cli.run_id {
+            runs::parse_run_id(id).map_err(|e| format!("--run-id: {}", e))?;
+        }
+        check_cli(&cli, self.check_script)?;
+        Ok(cli)
+    }
+}
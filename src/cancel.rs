@@ -0,0 +1,253 @@
+#[cfg(feature = "cli")]
+use crate::reporter::{ColorChoice, Reporter, StreamReporter, Verbosity};
+#[cfg(feature = "cli")]
+use crate::runs::resolve_jobs;
+#[cfg(feature = "cli")]
+use crate::scheduler::Scheduler;
+use crate::submitter::Submitter;
+#[cfg(feature = "cli")]
+use clap::{Parser, ValueHint};
+#[cfg(feature = "cli")]
+use std::path::PathBuf;
+#[cfg(feature = "cli")]
+use std::process::Command;
+
+/// A job that was handed to the scheduler during this run.
+#[derive(Clone, Debug)]
+pub struct SubmittedJob {
+    pub job_name: String,
+    /// `None` when the submit command's output did not contain a job ID.
+    pub job_id: Option<String>,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Parser, Debug)]
+#[command(
+    name = "batchelor cancel",
+    about = "Cancel the still-queued or running jobs of a previous run"
+)]
+pub struct CancelCli {
+    /// Output directory the run was submitted from.
+    #[arg(long, default_value = ".batchelor", value_hint = ValueHint::DirPath)]
+    out_dir: PathBuf,
+
+    /// Run ID to cancel (default: the most recent run).
+    #[arg(long, conflicts_with = "job_ids")]
+    run: Option<String>,
+
+    /// File with one job ID per line, instead of a recorded run.
+    #[arg(long)]
+    job_ids: Option<PathBuf>,
+
+    /// Scheduler the IDs in --job-ids belong to.
+    #[arg(long, value_enum, default_value = "slurm", requires = "job_ids")]
+    scheduler: Scheduler,
+
+    /// Only cancel jobs matching a filter, e.g. state=PENDING or
+    /// state=PENDING,SUSPENDED.
+    #[arg(long)]
+    filter: Vec<String>,
+
+    /// Print only what happened to each job and errors: not the run,
+    /// jobs without an ID or warnings.
+    #[arg(long)]
+    quiet: bool,
+
+    /// Color warnings and errors: on a terminal unless NO_COLOR is set
+    /// (auto), always or never.
+    #[arg(long, value_enum, value_name = "WHEN", default_value = "auto")]
+    color: ColorChoice,
+}
+
+#[cfg(feature = "cli")]
+pub fn cancel(cli: CancelCli) -> Result<(), Box<dyn std::error::Error>> {
+    let reporter = StreamReporter::stdio()
+        .with_verbosity(Verbosity::from_quiet(cli.quiet))
+        .with_color(cli.color);
+    cancel_jobs(cli, &reporter)
+}
+
+/// What happened to each job is data; the run and the jobs that could not
+/// be looked at are status lines.
+#[cfg(feature = "cli")]
+fn cancel_jobs(cli: CancelCli, reporter: &dyn Reporter) -> Result<(), Box<dyn std::error::Error>> {
+    let state_filter = parse_state_filter(&cli.filter)?;
+
+    let (scheduler, jobs) = resolve_jobs(
+        &cli.out_dir,
+        cli.run.as_deref(),
+        cli.job_ids.as_deref(),
+        cli.scheduler,
+        reporter,
+    )?;
+
+    let program = scheduler
+        .cancel_program()
+        .ok_or("no cancel command known for this run's submitter")?;
+
+    for job in jobs.iter().filter(|j| j.job_id.is_none()) {
+        reporter.diagnostic(&format!("untracked: {} (no job ID recorded)", job.job_name));
+    }
+    let ids = jobs
+        .iter()
+        .filter_map(|j| j.job_id.as_deref())
+        .collect::<Vec<_>>();
+
+    let states = scheduler.queue_states(&ids);
+    if states.is_none() {
+        reporter.diagnostic("warning: could not query the queue; attempting to cancel every job");
+    }
+
+    let mut to_cancel = Vec::new();
+    let mut finished = Vec::new();
+    let mut skipped = Vec::new();
+    for id in ids {
+        let state = states.as_ref().map(|s| s.get(id));
+        match state {
+            Some(None) => finished.push(id),
+            Some(Some(state)) => {
+                if state_filter
+                    .as_ref()
+                    .is_none_or(|wanted| wanted.contains(&state.to_ascii_uppercase()))
+                {
+                    to_cancel.push(id);
+                } else {
+                    skipped.push(format!("{} ({})", id, state));
+                }
+            }
+            // Without queue information the filter cannot be honoured.
+            None if state_filter.is_some() => skipped.push(format!("{} (state unknown)", id)),
+            None => to_cancel.push(id),
+        }
+    }
+
+    let mut failed = Vec::new();
+    let mut cancelled = Vec::new();
+    for (id, result) in per_job_outcomes(&to_cancel, usize::MAX, |chunk| {
+        let mut command = Command::new(program);
+        command.args(chunk);
+        command
+    }) {
+        match result {
+            Ok(()) => cancelled.push(id),
+            Err(e) => failed.push(format!("{} ({})", id, e)),
+        }
+    }
+
+    print_group(reporter, "cancelled", &cancelled);
+    print_group(reporter, "already finished", &finished);
+    print_group(reporter, "skipped by filter", &skipped);
+    print_group(reporter, "failed to cancel", &failed);
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} job(s) could not be cancelled", failed.len()).into())
+    }
+}
+
+#[cfg(feature = "cli")]
+pub(crate) fn print_group<T: std::fmt::Display>(reporter: &dyn Reporter, label: &str, items: &[T]) {
+    if items.is_empty() {
+        return;
+    }
+    let items = items.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+    reporter.data(&format!(
+        "{} ({}): {}\n",
+        label,
+        items.len(),
+        items.join(" ")
+    ));
+}
+
+/// Parses `--filter state=A,B` into the set of wanted (upper-case) states.
+#[cfg(feature = "cli")]
+fn parse_state_filter(filters: &[String]) -> Result<Option<Vec<String>>, String> {
+    let mut states: Option<Vec<String>> = None;
+    for filter in filters {
+        let (key, value) = filter
+            .split_once('=')
+            .ok_or_else(|| format!("--filter must look like key=value, got {:?}", filter))?;
+        if key != "state" {
+            return Err(format!("unknown --filter key {:?} (supported: state)", key));
+        }
+        states.get_or_insert_with(Vec::new).extend(
+            value
+                .split(',')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_ascii_uppercase()),
+        );
+    }
+    Ok(states)
+}
+
+/// Runs the command built by `command_for` over `ids` in chunks of
+/// `chunk_size`. A failed chunk is retried one job at a time so every job
+/// gets its own outcome (the command's stderr on failure).
+#[cfg(feature = "cli")]
+pub(crate) fn per_job_outcomes<'a>(
+    ids: &[&'a str],
+    chunk_size: usize,
+    command_for: impl Fn(&[&str]) -> Command,
+) -> Vec<(&'a str, Result<(), String>)> {
+    let mut outcomes = Vec::new();
+    for chunk in ids.chunks(chunk_size.max(1)) {
+        if run_job_command(command_for(chunk)).is_ok() {
+            outcomes.extend(chunk.iter().map(|id| (*id, Ok(()))));
+            continue;
+        }
+        if chunk.len() == 1 {
+            outcomes.push((chunk[0], run_job_command(command_for(chunk))));
+            continue;
+        }
+        for id in chunk {
+            outcomes.push((*id, run_job_command(command_for(&[id]))));
+        }
+    }
+    outcomes
+}
+
+#[cfg(feature = "cli")]
+fn run_job_command(mut command: Command) -> Result<(), String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .output()
+        .map_err(|e| format!("could not run {}: {}", program, e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+/// Cancels `jobs` through `submitter` and prints what happened. Errors
+/// are reported, never returned: this runs while another error is already
+/// being propagated.
+pub fn cancel_submitted(submitter: &dyn Submitter, jobs: &[SubmittedJob]) {
+    if jobs.is_empty() {
+        return;
+    }
+
+    for job in jobs.iter().filter(|j| j.job_id.is_none()) {
+        eprintln!(
+            "cannot cancel {}: no job ID was captured from the submit output",
+            job.job_name
+        );
+    }
+
+    let ids = jobs
+        .iter()
+        .filter_map(|j| j.job_id.as_ref())
+        .collect::<Vec<_>>();
+    if ids.is_empty() {
+        return;
+    }
+
+    eprintln!("Cancelling {} submitted job(s)...", ids.len());
+    for id in ids {
+        match submitter.cancel(id) {
+            Ok(()) => eprintln!("Cancelled job {}", id),
+            Err(e) => eprintln!("could not cancel job {}: {}", id, e),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use crate::is_generated_script;
+use crate::runs;
+use crate::safety;
+use crate::scheduler::is_final_state;
+use crate::script_format;
+use crate::state::{JobState, RunState};
+use crate::units;
+use crate::Cli;
+use clap::{Parser, ValueHint};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "batchelor clean",
+    about = "Remove the scripts, logs, markers and state of old runs"
+)]
+pub struct CleanCli {
+    /// Output directory the runs were submitted from.
+    #[arg(long, default_value = ".batchelor", value_hint = ValueHint::DirPath)]
+    out_dir: PathBuf,
+
+    /// Clean runs submitted at least this long ago, e.g. 7d or 12h.
+    #[arg(long, value_parser = units::parse_duration, required_unless_present = "run")]
+    older_than: Option<u64>,
+
+    /// Clean these runs (repeatable).
+    #[arg(long, conflicts_with = "older_than")]
+    run: Vec<String>,
+
+    /// Also clean runs whose jobs are still queued or running, or whose
+    /// jobs' state cannot be checked.
+    #[arg(long)]
+    force: bool,
+
+    /// Also remove files in the current directory, a repository root or the
+    /// home directory, which are otherwise kept.
+    #[arg(long)]
+    force_clean: bool,
+
+    /// List what would be removed without removing anything.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl CleanCli {
+    /// What a run with `--clean-only` cleans; `None` without it.
+    pub fn clean_only(run: &Cli) -> Option<CleanCli> {
+        run.clean_only.then(|| CleanCli {
+            out_dir: run.out_dir.clone(),
+            older_than: Some(0),
+            run: Vec::new(),
+            force: false,
+            force_clean: run.force_clean,
+            dry_run: run.dry_run,
+        })
+    }
+}
+
+pub fn clean(cli: CleanCli) -> Result<(), Box<dyn std::error::Error>> {
+    let all = runs::run_ids(&cli.out_dir)?;
+    for id in &cli.run {
+        if !all.contains(id) {
+            return Err(format!(
+                "no run {} under {}",
+                id,
+                cli.out_dir.join(runs::RUNS_DIR).display()
+            )
+            .into());
+        }
+    }
+
+    let now = SystemTime::now();
+    let mut selected = Vec::new();
+    let mut kept = Vec::new();
+    let mut refused = 0;
+    for id in all {
+        // Unreadable states are cleaned by age of their directory.
+        let state = RunState::load(&cli.out_dir, &id).ok();
+        let wanted = match cli.older_than {
+            Some(secs) => run_age(&cli.out_dir, &id, state.as_ref(), now) >= secs,
+            None => cli.run.contains(&id),
+        };
+        if !wanted {
+            kept.push(state);
+            continue;
+        }
+        // A newer batchelor may mark its scripts differently; even --force
+        // does not guess which files are its own.
+        if let Some(Err(reason)) = state
+            .as_ref()
+            .map(|s| script_format::check(s.script_format))
+        {
+            eprintln!("skipping run {}: {}", id, reason);
+            refused += 1;
+            kept.push(state);
+            continue;
+        }
+        if let Some(Err(reason)) = state.as_ref().map(check_finished) {
+            if !cli.force {
+                eprintln!("skipping run {}: {} (--force cleans it anyway)", id, reason);
+                refused += 1;
+                kept.push(state);
+                continue;
+            }
+        }
+        selected.push((id, state));
+    }
+
+    // Job names (and so script and log paths) are reused across runs; keep
+    // whatever a remaining run still refers to.
+    let in_use = kept
+        .iter()
+        .flatten()
+        .flat_map(|state| state.jobs.iter().flat_map(job_files))
+        .collect::<HashSet<_>>();
+
+    let verb = if cli.dry_run {
+        "would remove"
+    } else {
+        "removed"
+    };
+    for (id, state) in &selected {
+        let scripts = state
+            .iter()
+            .flat_map(|state| state.jobs.iter().filter_map(|job| job.script.as_ref()))
+            .collect::<HashSet<_>>();
+        let mut files = state
+            .iter()
+            .flat_map(|state| state.jobs.iter().flat_map(job_files))
+            .filter(|path| !in_use.contains(path) && path.is_file())
+            .collect::<Vec<_>>();
+        files.sort();
+        files.dedup();
+        let mut foreign = Vec::new();
+        let mut protected = HashMap::new();
+        for path in &files {
+            let dir = path.parent().unwrap_or(Path::new("."));
+            let refused = protected
+                .entry(dir)
+                .or_insert_with(|| safety::check_cleanup(dir, cli.force_clean).err());
+            if let Some(reason) = refused {
+                eprintln!("warning: keeping {}: {}", path.display(), reason);
+                foreign.push(path);
+                continue;
+            }
+            if scripts.contains(path) && !is_generated_script(path) {
+                eprintln!(
+                    "warning: keeping {}: it was not generated by batchelor",
+                    path.display()
+                );
+                foreign.push(path);
+                continue;
+            }
+            if !cli.dry_run {
+                fs::remove_file(path)
+                    .map_err(|e| format!("could not remove {}: {}", path.display(), e))?;
+            }
+            println!("{} {}", verb, path.display());
+        }
+        let dir = runs::run_dir(&cli.out_dir, id);
+        let canonical = fs::canonicalize(&dir).unwrap_or_else(|_| dir.clone());
+        if foreign.iter().any(|path| path.starts_with(&canonical)) {
+            // The directory stays with the files that are kept.
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                let name = canonical.join(path.file_name().unwrap_or_default());
+                // Kept files stay; the run's own files are handled above.
+                if files.contains(&name) {
+                    continue;
+                }
+                if !cli.dry_run {
+                    let removed = if path.is_dir() {
+                        fs::remove_dir_all(&path)
+                    } else {
+                        fs::remove_file(&path)
+                    };
+                    removed.map_err(|e| format!("could not remove {}: {}", path.display(), e))?;
+                }
+                println!("{} {}", verb, path.display());
+            }
+            continue;
+        }
+        if !cli.dry_run {
+            fs::remove_dir_all(&dir)
+                .map_err(|e| format!("could not remove {}: {}", dir.display(), e))?;
+        }
+        println!("{} {}", verb, dir.display());
+    }
+
+    println!(
+        "{} {} run(s){}",
+        if cli.dry_run {
+            "Would clean"
+        } else {
+            "Cleaned"
+        },
+        selected.len(),
+        if refused > 0 {
+            format!("; skipped {} (see above)", refused)
+        } else {
+            String::new()
+        }
+    );
+    Ok(())
+}
+
+/// Seconds since the run was submitted, or since its directory was last
+/// written for runs that do not record when.
+fn run_age(out_dir: &Path, run_id: &str, state: Option<&RunState>, now: SystemTime) -> u64 {
+    let submitted = state
+        .and_then(|s| s.timestamp.as_deref())
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .map(SystemTime::from)
+        .or_else(|| {
+            fs::metadata(runs::run_dir(out_dir, run_id))
+                .and_then(|m| m.modified())
+                .ok()
+        });
+    submitted
+        .and_then(|t| now.duration_since(t).ok())
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Asks the scheduler whether any of the run's jobs is still queued or
+/// running.
+fn check_finished(state: &RunState) -> Result<(), String> {
+    let ids = state.job_ids();
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let statuses = state
+        .scheduler
+        .job_statuses(&ids)
+        .ok_or("cannot check its jobs with this scheduler")?;
+    let active = statuses
+        .values()
+        .filter(|s| !is_final_state(&s.state))
+        .count();
+    if active > 0 {
+        return Err(format!("{} job(s) still queued or running", active));
+    }
+    Ok(())
+}
+
+/// Files a job left outside its run directory: script, markers of runs
+/// from before per-run markers, and its logs (one per submission when the
+/// log name contains the job ID).
+fn job_files(job: &JobState) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    files.extend(job.script.clone());
+    files.extend(job.done_file.clone());
+    files.extend(job.failed_file.clone());
+    files.extend(job.log_path());
+    for id in job.previous_job_ids.iter().chain(&job.job_id) {
+        let submission = JobState {
+            job_id: Some(id.clone()),
+            ..job.clone()
+        };
+        files.extend(submission.log_path());
+    }
+    files
+}
@@ -0,0 +1,48 @@
+//! `batchelor completions`: shell completion for the options of a run and
+//! of every subcommand.
+
+use clap::Parser;
+use clap_complete::Shell;
+use std::io;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "batchelor completions",
+    about = "Print the shell completion script for batchelor",
+    after_help = "\
+Install it where your shell looks for completions, e.g.:
+  batchelor completions bash > ~/.local/share/bash-completion/completions/batchelor
+  batchelor completions zsh > ~/.zfunc/_batchelor
+  batchelor completions fish > ~/.config/fish/completions/batchelor.fish"
+)]
+pub struct CompletionsCli {
+    /// Shell to print the completion script for.
+    #[arg(value_enum)]
+    shell: Shell,
+}
+
+pub fn completions(cli: CompletionsCli) -> Result<(), Box<dyn std::error::Error>> {
+    let mut command = crate::command_tree();
+    clap_complete::generate(cli.shell, &mut command, "batchelor", &mut io::stdout());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ValueEnum;
+
+    #[test]
+    fn every_shell_gets_a_script() {
+        for shell in Shell::value_variants() {
+            let mut command = crate::command_tree();
+            let mut script = Vec::new();
+            clap_complete::generate(*shell, &mut command, "batchelor", &mut script);
+            let script = String::from_utf8(script).unwrap();
+            assert!(!script.is_empty(), "{}", shell);
+            for word in ["batchelor", "script", "out-dir", "status", "clean"] {
+                assert!(script.contains(word), "{}: no {}", shell, word);
+            }
+        }
+    }
+}
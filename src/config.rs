@@ -0,0 +1,709 @@
+//! Config files: `batchelor.toml` in the working directory or the nearest
+//! parent with one, and `~/.config/batchelor/config.toml`. Their keys are
+//! the long options of a run, with `_` for `-`:
+//!
+//! ```toml
+//! submit = "sbatch --partition=short --account=lab"
+//! out_dir = "/scratch/me/batchelor"
+//! batch = 20
+//! keep_going = true
+//! resource_rule = ["size>10G => --mem=64G"]
+//! ```
+//!
+//! Options on the command line win over environment variables, which win
+//! over the project file, which wins over the user file. `--config <FILE>`
+//! reads that file instead of both.
+//!
+//! `--profile NAME` (or `BATCHELOR_PROFILE`) also applies the
+//! `[profile.NAME]` tables of the files, over the rest of the files:
+//!
+//! ```toml
+//! [profile.hawk]
+//! submit = "sbatch --partition=hawk"
+//! resource_rule = ["size>1G => --mem=8G"]  # instead of the rules above
+//! "+glob" = ["/shared/hawk/*.fq"]         # in addition to the globs above
+//! ```
+
+use crate::Cli;
+use clap::builder::Resettable;
+use clap::parser::ValueSource;
+use clap::{
+    value_parser, Arg, ArgAction, ArgMatches, Command, CommandFactory, FromArgMatches, Parser,
+    ValueHint,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const PROJECT_FILE: &str = "batchelor.toml";
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "batchelor config",
+    about = "Show the config files of a run and the options they set"
+)]
+pub struct ConfigCli {
+    /// Read this file instead of batchelor.toml and the user config.
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    config: Option<PathBuf>,
+
+    /// Apply the options of this profile.
+    #[arg(long, value_name = "NAME", env = "BATCHELOR_PROFILE")]
+    profile: Option<String>,
+
+    /// Print every option with its value and where the value comes from.
+    #[arg(long)]
+    show: bool,
+}
+
+pub fn config(cli: ConfigCli) -> Result<(), Box<dyn std::error::Error>> {
+    let mut command = command();
+    let config = Config::load(&command, cli.config.as_deref(), cli.profile.as_deref())?;
+    if !cli.show {
+        if config.files.is_empty() {
+            println!(
+                "No config files (looked for {} here and in parent directories, and {})",
+                PROJECT_FILE,
+                user_file()
+                    .map_or_else(|| "a user config".to_string(), |p| p.display().to_string())
+            );
+        }
+        for path in &config.files {
+            println!("{}", path.display());
+        }
+        if !config.profiles.is_empty() {
+            let profiles = config.profiles.iter().cloned().collect::<Vec<_>>();
+            println!("Profiles: {}", profiles.join(", "));
+        }
+        return Ok(());
+    }
+
+    command.build();
+    if let Some(profile) = &cli.profile {
+        println!("# profile {}", profile);
+    }
+    for arg in command.get_arguments() {
+        let Some(key) = key(arg) else {
+            continue;
+        };
+        let env = arg
+            .get_env()
+            .and_then(|name| Some((name, env::var_os(name)?)));
+        let (value, source) = if let Some((name, value)) = env {
+            (
+                toml::Value::String(value.to_string_lossy().into_owned()),
+                format!("env {}", name.to_string_lossy()),
+            )
+        } else if let Some((value, path)) = config.values.get(arg.get_id().as_str()) {
+            (value.clone(), path.display().to_string())
+        } else if let Some(value) = default(arg) {
+            (value, "default".to_string())
+        } else {
+            continue;
+        };
+        println!("{} = {}  # {}", key, value, source);
+    }
+    Ok(())
+}
+
+/// The options the config files set, by argument ID, with the file each
+/// came from.
+struct Config {
+    files: Vec<PathBuf>,
+    /// The profiles any of the files define.
+    profiles: BTreeSet<String>,
+    values: HashMap<String, (toml::Value, PathBuf)>,
+}
+
+impl Config {
+    /// Reads `explicit`, or the user and project files that exist, and
+    /// then the tables of `profile` in them.
+    fn load(
+        command: &Command,
+        explicit: Option<&Path>,
+        profile: Option<&str>,
+    ) -> Result<Config, String> {
+        let files = match explicit {
+            Some(path) => vec![path.to_path_buf()],
+            None => user_file()
+                .filter(|path| path.is_file())
+                .into_iter()
+                .chain(project_file())
+                .collect(),
+        };
+        let mut tables = Vec::new();
+        let mut profiles = BTreeMap::new();
+        for path in &files {
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("could not read config {}: {}", path.display(), e))?;
+            let mut table: toml::Table =
+                toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+            match table.remove("profile") {
+                Some(toml::Value::Table(defined)) => {
+                    for (name, value) in defined {
+                        let toml::Value::Table(value) = value else {
+                            return Err(format!(
+                                "{}: profile.{} must be a table",
+                                path.display(),
+                                name
+                            ));
+                        };
+                        profiles
+                            .entry(name)
+                            .or_insert_with(Vec::new)
+                            .push((path.clone(), value));
+                    }
+                }
+                Some(_) => {
+                    return Err(format!(
+                        "{}: profile must be a table of [profile.NAME] tables",
+                        path.display()
+                    ))
+                }
+                None => {}
+            }
+            tables.push((path.clone(), table));
+        }
+        if let Some(name) = profile {
+            let tables_of_profile = profiles.get(name).ok_or_else(|| {
+                if profiles.is_empty() {
+                    format!("unknown profile {:?}: the config files define none", name)
+                } else {
+                    format!(
+                        "unknown profile {:?} (available: {})",
+                        name,
+                        profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+                    )
+                }
+            })?;
+            tables.extend(tables_of_profile.iter().cloned());
+        }
+
+        let args = command
+            .get_arguments()
+            .filter_map(|arg| Some((key(arg)?, arg)))
+            .collect::<HashMap<_, _>>();
+        let mut values = HashMap::new();
+        // Lowest precedence first, so later tables overwrite.
+        for (path, table) in tables {
+            for (name, value) in table {
+                let (append, key) = match name.strip_prefix('+') {
+                    Some(key) => (true, key.replace('-', "_")),
+                    None => (false, name.replace('-', "_")),
+                };
+                let Some(arg) = args.get(&key) else {
+                    eprintln!(
+                        "warning: {}: unknown key {:?}{}",
+                        path.display(),
+                        name,
+                        nearest_key(&key, args.keys())
+                            .map(|k| format!(" (did you mean {:?}?)", k))
+                            .unwrap_or_default()
+                    );
+                    continue;
+                };
+                if append && !matches!(arg.get_action(), ArgAction::Append) {
+                    return Err(format!(
+                        "{}: {}: only options that can be repeated can be added to",
+                        path.display(),
+                        name
+                    ));
+                }
+                let id = arg.get_id().to_string();
+                let value = match values.remove(&id) {
+                    Some((before, _)) if append => {
+                        toml::Value::Array(items(before).chain(items(value)).collect())
+                    }
+                    _ => value,
+                };
+                values.insert(id, (value, path.clone()));
+            }
+        }
+        Ok(Config {
+            files,
+            profiles: profiles.into_keys().collect(),
+            values,
+        })
+    }
+}
+
+/// The items of an array value; other values are one item.
+fn items(value: toml::Value) -> impl Iterator<Item = toml::Value> {
+    match value {
+        toml::Value::Array(items) => items.into_iter(),
+        value => vec![value].into_iter(),
+    }
+}
+
+/// Parses the command line of a run like `Cli::parse_from`, taking the
+/// options it does not give from the environment and the config files.
+/// Exits with a usage error on invalid options, like clap does.
+pub fn parse_from<I, T>(args: I) -> Cli
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString>,
+{
+    let args = args.into_iter().map(Into::into).collect::<Vec<_>>();
+    let (command, args) = with_config(command(), &args).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(2);
+    });
+    for warning in script_args_warnings(&command, &args) {
+        eprintln!("{}", warning);
+    }
+    let matches = command.get_matches_from(args);
+    from_matches(&matches).unwrap_or_else(|e| e.exit())
+}
+
+/// The run `matches` describe, with the args after `--` appended to
+/// `--script-args`.
+fn from_matches(matches: &ArgMatches) -> Result<Cli, clap::Error> {
+    let mut cli = Cli::from_arg_matches(matches)?;
+    let trailing = std::mem::take(&mut cli.trailing_args);
+    cli.script_args.extend(trailing);
+    Ok(cli)
+}
+
+/// Warnings about `--script-args` values that are batchelor options too:
+/// they go to the script, which is what older batchelors did with every
+/// argument after `--script-args`.
+fn script_args_warnings(command: &Command, args: &[OsString]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let longs = command
+        .get_arguments()
+        .filter_map(Arg::get_long)
+        .collect::<Vec<_>>();
+    let mut iter = args.iter().map(|arg| arg.to_string_lossy());
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg != "--script-args" {
+            continue;
+        }
+        let Some(value) = iter.next() else {
+            break;
+        };
+        let name = value
+            .strip_prefix("--")
+            .map(|v| v.split('=').next().unwrap_or(v));
+        if name.is_some_and(|name| longs.contains(&name)) {
+            warnings.push(format!(
+                "warning: {} after --script-args is passed to the script, not read as batchelor's option; each --script-args takes one value (write --script-args={} to silence this)",
+                value, value
+            ));
+        }
+    }
+    warnings
+}
+
+/// The command line of a run, with `--config`.
+pub(crate) fn command() -> Command {
+    Cli::command().arg(
+        Arg::new("config")
+            .long("config")
+            .value_name("FILE")
+            .value_parser(value_parser!(PathBuf))
+            .value_hint(ValueHint::FilePath)
+            .help(
+                "Read options from this TOML file instead of batchelor.toml (in the working \
+                 directory or a parent) and ~/.config/batchelor/config.toml",
+            ),
+    )
+}
+
+/// `args` with the options of the config files added, for each option the
+/// command line and the environment leave at its default. Options set this
+/// way do not need the options they require on the command line (e.g.
+/// `wait_interval` without `--wait`), so `command` is returned without
+/// those requirements.
+fn with_config(
+    mut command: Command,
+    args: &[OsString],
+) -> Result<(Command, Vec<OsString>), String> {
+    // Lenient, as --script may only be given in a config file; parsing
+    // again afterwards reports what is wrong.
+    let Ok(matches) = command
+        .clone()
+        .ignore_errors(true)
+        .try_get_matches_from(args)
+    else {
+        return Ok((command, args.to_vec()));
+    };
+    let config = Config::load(
+        &command,
+        matches.get_one::<PathBuf>("config").map(PathBuf::as_path),
+        matches.get_one::<String>("profile").map(String::as_str),
+    )?;
+    let mut built = command.clone();
+    built.build();
+
+    let mut front = Vec::new();
+    let mut configured = Vec::new();
+    for arg in built.get_arguments() {
+        let Some((value, path)) = config.values.get(arg.get_id().as_str()) else {
+            continue;
+        };
+        // A config value never overrides, or conflicts with, an option
+        // given explicitly.
+        if given(&matches, arg)
+            || built
+                .get_arg_conflicts_with(arg)
+                .into_iter()
+                .any(|other| given(&matches, other))
+        {
+            continue;
+        }
+        let flags = flags(arg, value).map_err(|e| {
+            format!(
+                "{}: {}: {}",
+                path.display(),
+                key(arg).unwrap_or_default(),
+                e
+            )
+        })?;
+        front.extend(flags);
+        configured.push(arg.get_id().clone());
+    }
+    for id in configured {
+        command = command.mut_arg(id, |arg| arg.requires(Resettable::Reset));
+    }
+    let mut args = args.to_vec();
+    let rest = args.split_off(args.len().min(1));
+    let args = args.into_iter().chain(front).chain(rest);
+    Ok((command, args.collect()))
+}
+
+fn given(matches: &ArgMatches, arg: &Arg) -> bool {
+    matches!(
+        matches.value_source(arg.get_id().as_str()),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    )
+}
+
+/// The config key of an option: its long name with `_` for `-`.
+fn key(arg: &Arg) -> Option<String> {
+    arg.get_long()
+        .filter(|long| !matches!(*long, "help" | "version" | "config" | "profile"))
+        .map(|long| long.replace('-', "_"))
+}
+
+fn nearest_key<'a>(name: &str, keys: impl Iterator<Item = &'a String>) -> Option<&'a str> {
+    let max_distance = (name.len() / 3).max(2);
+    keys.map(|k| (strsim::damerau_levenshtein(name, k), k.as_str()))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, k)| k)
+}
+
+/// The command line options that give `arg` the config value `value`. An
+/// array is one option per item, or one option with all of them for an
+/// option that takes several values at a time.
+fn flags(arg: &Arg, value: &toml::Value) -> Result<Vec<OsString>, String> {
+    let long = format!("--{}", arg.get_long().unwrap_or_default());
+    let flags = match (arg.get_action(), value) {
+        (ArgAction::SetTrue, toml::Value::Boolean(set)) => {
+            if *set {
+                vec![long]
+            } else {
+                Vec::new()
+            }
+        }
+        (ArgAction::SetTrue, _) => return Err("expected true or false".to_string()),
+        (ArgAction::Count, toml::Value::Integer(count)) => {
+            let count = usize::try_from(*count).map_err(|_| "expected a count")?;
+            vec![long; count]
+        }
+        (ArgAction::Count, _) => return Err("expected a count".to_string()),
+        // `--emit make Makefile`: the values of one occurrence.
+        (_, toml::Value::Array(items))
+            if arg.get_num_args().is_some_and(|n| n.min_values() > 1) =>
+        {
+            std::iter::once(Ok(long))
+                .chain(items.iter().map(scalar))
+                .collect::<Result<_, String>>()?
+        }
+        (ArgAction::Append, toml::Value::Array(items)) => items
+            .iter()
+            .map(|item| Ok(format!("{}={}", long, scalar(item)?)))
+            .collect::<Result<_, String>>()?,
+        (_, value) => vec![format!("{}={}", long, scalar(value)?)],
+    };
+    Ok(flags.into_iter().map(OsString::from).collect())
+}
+
+fn scalar(value: &toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(n) => Ok(n.to_string()),
+        toml::Value::Float(n) => Ok(n.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        toml::Value::Datetime(d) => Ok(d.to_string()),
+        toml::Value::Array(_) => Err("expected a single value, not an array".to_string()),
+        toml::Value::Table(_) => Err("expected a value, not a table".to_string()),
+    }
+}
+
+/// `~/.config/batchelor/config.toml`, or under `$XDG_CONFIG_HOME` when set.
+fn user_file() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(dir.join("batchelor").join("config.toml"))
+}
+
+/// The `batchelor.toml` in the working directory or its nearest parent.
+fn project_file() -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    cwd.ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
+
+/// The default of `arg` as a config value.
+fn default(arg: &Arg) -> Option<toml::Value> {
+    let values = arg
+        .get_default_values()
+        .iter()
+        .map(|v| v.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    let [value] = &values[..] else {
+        return None;
+    };
+    Some(match arg.get_action() {
+        ArgAction::SetTrue => toml::Value::Boolean(value == "true"),
+        ArgAction::Append => toml::Value::Array(
+            value
+                .split(arg.get_value_delimiter().unwrap_or(','))
+                .map(|v| toml::Value::String(v.to_string()))
+                .collect(),
+        ),
+        _ => match value.parse::<i64>() {
+            Ok(n) => toml::Value::Integer(n),
+            Err(_) => toml::Value::String(value.clone()),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The run `args` (after the program name) describe with the config
+    /// file `toml`.
+    fn parse(toml: &str, args: &[&str]) -> Result<Cli, String> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("batchelor.toml");
+        fs::write(&path, toml).unwrap();
+        let args = ["batchelor", "--config", path.to_str().unwrap()]
+            .iter()
+            .chain(args)
+            .map(OsString::from)
+            .collect::<Vec<_>>();
+        let (command, args) = with_config(command(), &args)?;
+        let matches = command
+            .try_get_matches_from(args)
+            .map_err(|e| e.to_string())?;
+        from_matches(&matches).map_err(|e| e.to_string())
+    }
+
+    /// The script args of a run of `run.sh` over `*.fq` with `args`.
+    fn script_args(args: &[&str]) -> Result<Vec<String>, String> {
+        let mut all = vec!["--script", "run.sh", "--glob", "*.fq"];
+        all.extend(args);
+        parse("", &all).map(|cli| cli.script_args)
+    }
+
+    /// The warnings `args` get about `--script-args`.
+    fn warnings(args: &[&str]) -> Vec<String> {
+        let args = std::iter::once("batchelor")
+            .chain(args.iter().copied())
+            .map(OsString::from)
+            .collect::<Vec<_>>();
+        script_args_warnings(&command(), &args)
+    }
+
+    #[test]
+    fn config_values_reach_the_run() {
+        let cli = parse(
+            r#"
+            script = "run.sh"
+            glob = ["a/*.fq", "b/*.fq"]
+            batch = 20
+            keep_going = true
+            emit = ["make", "Makefile"]
+            "#,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(cli.script, Path::new("run.sh"));
+        assert_eq!(cli.glob, ["a/*.fq", "b/*.fq"]);
+        assert_eq!(cli.batch, 20);
+        assert!(cli.keep_going);
+        assert_eq!(cli.emit, ["make", "Makefile"]);
+    }
+
+    #[test]
+    fn the_command_line_wins() {
+        let cli = parse(
+            "script = \"run.sh\"\nglob = [\"*.fq\"]\nbatch = 20\nemit = [\"make\", \"Makefile\"]\n",
+            &["--batch", "3", "--emit", "snakemake", "Snakefile"],
+        )
+        .unwrap();
+        assert_eq!(cli.batch, 3);
+        assert_eq!(cli.emit, ["snakemake", "Snakefile"]);
+    }
+
+    #[test]
+    fn options_taking_several_values_are_given_once() {
+        let command = {
+            let mut command = command();
+            command.build();
+            command
+        };
+        let arg = |id: &str| {
+            command
+                .get_arguments()
+                .find(|arg| arg.get_id() == id)
+                .unwrap()
+                .clone()
+        };
+        let array = |items: &[&str]| {
+            toml::Value::Array(items.iter().map(|s| toml::Value::from(*s)).collect())
+        };
+        assert_eq!(
+            flags(&arg("emit"), &array(&["make", "Makefile"])).unwrap(),
+            ["--emit", "make", "Makefile"]
+        );
+        assert_eq!(
+            flags(&arg("glob"), &array(&["a", "b"])).unwrap(),
+            ["--glob=a", "--glob=b"]
+        );
+        assert_eq!(
+            flags(&arg("keep"), &toml::Value::Boolean(true)).unwrap(),
+            ["--keep"]
+        );
+        assert!(flags(&arg("keep"), &toml::Value::Boolean(false))
+            .unwrap()
+            .is_empty());
+        assert!(flags(&arg("batch"), &array(&["1"])).is_err());
+    }
+
+    #[test]
+    fn wrong_counts_are_refused() {
+        let e = parse(
+            "script = \"run.sh\"\nglob = [\"*.fq\"]\nemit = [\"make\"]\n",
+            &[],
+        )
+        .unwrap_err();
+        assert!(e.contains("--emit"), "{}", e);
+    }
+
+    #[test]
+    fn each_script_args_takes_one_value() {
+        let cli = parse(
+            "",
+            &[
+                "--script",
+                "run.sh",
+                "--script-args",
+                "--threads",
+                "--script-args",
+                "4",
+                "--glob",
+                "data/*",
+            ],
+        )
+        .unwrap();
+        assert_eq!(cli.script_args, ["--threads", "4"]);
+        assert_eq!(cli.glob, ["data/*"]);
+        assert!(parse(
+            "",
+            &["--script", "run.sh", "--glob", "*.fq", "--script-args"]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn args_after_the_separator_go_to_the_script_as_they_are() {
+        assert_eq!(
+            script_args(&["--", "--threads", "4", "--glob", "x", "--"]).unwrap(),
+            ["--threads", "4", "--glob", "x", "--"]
+        );
+        // After the --script-args values, wherever those are given.
+        let cli = parse(
+            "",
+            &[
+                "--script-args",
+                "a",
+                "--script",
+                "run.sh",
+                "--glob",
+                "*.fq",
+                "--",
+                "b",
+            ],
+        )
+        .unwrap();
+        assert_eq!(cli.script_args, ["a", "b"]);
+        assert_eq!(script_args(&["--"]).unwrap(), Vec::<String>::new());
+        assert_eq!(script_args(&[]).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn script_args_are_not_split_or_unquoted() {
+        assert_eq!(
+            script_args(&[
+                "--script-args",
+                "a b",
+                "--script-args",
+                "'q' \"r\"",
+                "--",
+                "$x y"
+            ])
+            .unwrap(),
+            ["a b", "'q' \"r\"", "$x y"]
+        );
+        assert_eq!(
+            script_args(&["--script-args", "", "--", ""]).unwrap(),
+            ["", ""]
+        );
+        assert_eq!(
+            script_args(&["--script-args=--glob=x", "--script-args=-v"]).unwrap(),
+            ["--glob=x", "-v"]
+        );
+    }
+
+    #[test]
+    fn config_script_args_come_before_the_separated_ones() {
+        let cli = parse(
+            "script = \"run.sh\"\nglob = [\"*.fq\"]\nscript_args = [\"-x\", \"1\"]\n",
+            &["--", "y"],
+        )
+        .unwrap();
+        assert_eq!(cli.script_args, ["-x", "1", "y"]);
+    }
+
+    #[test]
+    fn batchelor_options_after_script_args_are_warned_about() {
+        let warned = warnings(&["--script-args", "--glob", "x", "--script-args", "--keep=1"]);
+        assert_eq!(
+            warned,
+            [
+                "warning: --glob after --script-args is passed to the script, not read as batchelor's option; each --script-args takes one value (write --script-args=--glob to silence this)",
+                "warning: --keep=1 after --script-args is passed to the script, not read as batchelor's option; each --script-args takes one value (write --script-args=--keep=1 to silence this)",
+            ]
+        );
+        for quiet in [
+            &["--script-args", "--threads"][..],
+            &["--script-args=--glob"],
+            &["--", "--script-args", "--glob"],
+            &["--script-args", "-v"],
+        ] {
+            assert!(warnings(quiet).is_empty(), "{:?}", quiet);
+        }
+    }
+}
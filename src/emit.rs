@@ -0,0 +1,319 @@
+//! `--emit`: write the plan for another workflow tool instead of
+//! submitting it.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum EmitFormat {
+    /// A Makefile with one target per input.
+    Make,
+    /// A Snakefile with one rule per batch (per input with --per-input).
+    Snakemake,
+    /// A directory with `samplesheet.csv` and a `main.nf` stub running the
+    /// script per sample.
+    Nextflow,
+}
+
+impl EmitFormat {
+    const ALL: [EmitFormat; 3] = [
+        EmitFormat::Make,
+        EmitFormat::Snakemake,
+        EmitFormat::Nextflow,
+    ];
+
+    /// The name `--emit` takes.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            EmitFormat::Make => "make",
+            EmitFormat::Snakemake => "snakemake",
+            EmitFormat::Nextflow => "nextflow",
+        }
+    }
+}
+
+/// Stands in for the input when rendering the command for `main.nf`; only
+/// safe shell characters, so it is never quoted.
+pub(crate) const INPUT_PLACEHOLDER: &str = "__BATCHELOR_INPUT__";
+
+/// A unit of work: its inputs, the command line(s) that process them and
+/// the resources the batch would have requested.
+pub(crate) struct EmitJob {
+    pub(crate) name: String,
+    pub(crate) inputs: Vec<String>,
+    pub(crate) commands: Vec<String>,
+    /// Bytes.
+    pub(crate) mem: Option<u64>,
+    /// Seconds.
+    pub(crate) time: Option<u64>,
+}
+
+/// Parses the `FORMAT PATH` pairs of repeated `--emit` options.
+pub(crate) fn parse_emit(values: &[String]) -> Result<Vec<(EmitFormat, PathBuf)>, String> {
+    values
+        .chunks(2)
+        .map(|pair| match pair {
+            [format, path] => EmitFormat::ALL
+                .into_iter()
+                .find(|f| f.name().eq_ignore_ascii_case(format))
+                .map(|format| (format, PathBuf::from(path)))
+                .ok_or_else(|| {
+                    format!(
+                        "--emit: unknown format {:?} (expected {})",
+                        format,
+                        EmitFormat::ALL.map(EmitFormat::name).join(", ")
+                    )
+                }),
+            _ => Err("--emit takes a format and a path".to_string()),
+        })
+        .collect()
+}
+
+/// A Makefile with a target per job (one input each): a stamp file in
+/// `stamp_dir`, touched once the commands succeeded, so `make -j` reruns
+/// only what has not finished. Inputs that are files are the stamps'
+/// prerequisites. Returns the Makefile and warnings about inputs make
+/// cannot name.
+pub(crate) fn makefile(jobs: &[EmitJob], stamp_dir: &Path) -> (String, Vec<String>) {
+    let mut warnings = Vec::new();
+    let stamps = jobs
+        .iter()
+        .map(|job| {
+            let input = job.inputs.first().map(String::as_str).unwrap_or_default();
+            let name = Path::new(input)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let name = name
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || "._-".contains(c) {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect::<String>();
+            stamp_dir.join(format!("{}-{}.done", job.name, name))
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = String::from("# Generated by batchelor --emit make.\n");
+    out.push_str("SHELL := /bin/bash\n.SHELLFLAGS := -euo pipefail -c\n\n");
+    out.push_str(".PHONY: all\nall:");
+    for stamp in &stamps {
+        out.push_str(" \\\n  ");
+        out.push_str(&make_path(stamp));
+    }
+    out.push('\n');
+    for (job, stamp) in jobs.iter().zip(&stamps) {
+        out.push('\n');
+        out.push_str(&make_path(stamp));
+        out.push(':');
+        for input in job.inputs.iter().filter(|i| Path::new(i).is_file()) {
+            if input.contains([':', '\n', '%', '*', '?', '[']) {
+                warnings.push(format!(
+                    "--emit make: {} is not a prerequisite of its target; make cannot name it",
+                    input
+                ));
+            } else {
+                out.push(' ');
+                out.push_str(&make_path(Path::new(input)));
+            }
+        }
+        out.push('\n');
+        for command in &job.commands {
+            for line in command.lines() {
+                out.push('\t');
+                out.push_str(&line.replace('$', "$$"));
+                out.push('\n');
+            }
+        }
+        out.push_str("\t@mkdir -p $(@D) && touch $@\n");
+    }
+    (out, warnings)
+}
+
+/// A Snakefile with a rule per job. Each rule's output is a stamp file in
+/// `stamp_dir`, touched once its commands succeeded; inputs that are files
+/// are its `input:`. Memory and time become `resources` (`mem_mb`,
+/// `runtime` in minutes).
+pub(crate) fn snakefile(jobs: &[EmitJob], stamp_dir: &Path) -> String {
+    let stamp = |job: &EmitJob| stamp_dir.join(format!("{}.done", job.name));
+    let mut out = String::from("# Generated by batchelor --emit snakemake.\n\n");
+    out.push_str("rule all:\n    input:\n");
+    for job in jobs {
+        out.push_str(&format!(
+            "        {},\n",
+            py_str(&stamp(job).to_string_lossy())
+        ));
+    }
+    for job in jobs {
+        out.push_str(&format!("\n\nrule {}:\n", job.name));
+        let files = job
+            .inputs
+            .iter()
+            .filter(|i| Path::new(i).is_file())
+            .collect::<Vec<_>>();
+        if !files.is_empty() {
+            out.push_str("    input:\n");
+            for input in files {
+                out.push_str(&format!("        {},\n", py_str(input)));
+            }
+        }
+        out.push_str(&format!(
+            "    output:\n        touch({}),\n",
+            py_str(&stamp(job).to_string_lossy())
+        ));
+        if job.mem.is_some() || job.time.is_some() {
+            out.push_str("    resources:\n");
+            if let Some(mem) = job.mem {
+                out.push_str(&format!("        mem_mb={},\n", mem.div_ceil(1024 * 1024)));
+            }
+            if let Some(secs) = job.time {
+                out.push_str(&format!("        runtime={},\n", secs.div_ceil(60)));
+            }
+        }
+        // shell: strings go through str.format, so braces are doubled.
+        let shell = job
+            .commands
+            .join("\n")
+            .replace('{', "{{")
+            .replace('}', "}}");
+        out.push_str(&format!("    shell:\n        {}\n", py_str(&shell)));
+    }
+    out
+}
+
+/// `samplesheet.csv` with a `sample,file` row per input. Samples are
+/// named after the file without its extensions, with whitespace replaced
+/// and made unique.
+pub(crate) fn samplesheet(inputs: &[String]) -> String {
+    let mut out = String::from("sample,file\n");
+    let mut seen = HashMap::new();
+    for input in inputs {
+        let name = Path::new(input)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| input.clone());
+        let stem = name
+            .split('.')
+            .next()
+            .filter(|s| !s.is_empty())
+            .unwrap_or(&name)
+            .replace(char::is_whitespace, "_");
+        let count = seen.entry(stem.clone()).or_insert(0usize);
+        *count += 1;
+        let sample = match *count {
+            1 => stem,
+            n => format!("{}_{}", stem, n),
+        };
+        out.push_str(&format!("{},{}\n", csv_field(&sample), csv_field(input)));
+    }
+    out
+}
+
+/// A `main.nf` whose process runs `command` (rendered with
+/// [`INPUT_PLACEHOLDER`] as the input) once per samplesheet row.
+pub(crate) fn main_nf(command: &str) -> String {
+    // The staged file goes through a shell variable, set with its quotes
+    // escaped and quoted where it is used; inside a single-quoted word the
+    // quotes are closed around it.
+    let mut script = String::new();
+    let (mut rest, mut in_single) = (command, false);
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix(INPUT_PLACEHOLDER) {
+            script.push_str(if in_single {
+                r#"'"$input"'"#
+            } else {
+                r#""$input""#
+            });
+            rest = after;
+            continue;
+        }
+        let mut len = c.len_utf8();
+        match c {
+            '\'' => in_single = !in_single,
+            // An escaped character is copied as is.
+            '\\' if !in_single => len += rest[1..].chars().next().map_or(0, char::len_utf8),
+            _ => {}
+        }
+        script.push_str(&rest[..len]);
+        rest = &rest[len..];
+    }
+    // Groovy interpolates `$` and unescapes `\` in the script block.
+    let script = script.replace('\\', "\\\\").replace('$', "\\$");
+    format!(
+        r#"#!/usr/bin/env nextflow
+// Generated by batchelor --emit nextflow: a starting point, not a
+// finished pipeline. Add outputs, resources and publishDir as needed.
+
+nextflow.enable.dsl = 2
+
+params.samplesheet = "${{projectDir}}/samplesheet.csv"
+
+process BATCHELOR {{
+    tag "${{sample}}"
+
+    input:
+    tuple val(sample), path(file)
+
+    script:
+    """
+    input='${{file.toString().replace("'", "'\\''")}}'
+    {}
+    """
+}}
+
+workflow {{
+    Channel.fromPath(params.samplesheet)
+        | splitCsv(header: true)
+        | map {{ row -> tuple(row.sample, file(row.file)) }}
+        | BATCHELOR
+}}
+"#,
+        script
+    )
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// A Python string literal for `s`.
+fn py_str(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Escapes a path for a target or prerequisite list.
+fn make_path(path: &Path) -> String {
+    let mut out = String::new();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            ' ' | '#' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '$' => out.push_str("$$"),
+            c => out.push(c),
+        }
+    }
+    out
+}
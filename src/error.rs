@@ -0,0 +1,264 @@
+//! The errors of a run that callers may want to tell apart. Everything
+//! else is [`BatchelorError::Other`], with the message as before.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, thiserror::Error)]
+pub enum BatchelorError {
+    #[error("--batch must be >= 1")]
+    InvalidBatchCount,
+
+    #[error("script does not exist: {}", .0.display())]
+    ScriptMissing(PathBuf),
+
+    /// The script exists but cannot run, e.g. it is a directory or empty.
+    #[error("--script {}: {reason}", .path.display())]
+    ScriptInvalid { path: PathBuf, reason: String },
+
+    #[error("{}", no_inputs_message(.patterns, .input_list.as_deref(), .removed_by.as_deref()))]
+    NoInputs {
+        patterns: Vec<String>,
+        input_list: Option<PathBuf>,
+        /// The filter that dropped the last of the matched inputs, when
+        /// something matched.
+        removed_by: Option<String>,
+    },
+
+    #[error("--glob {pattern}: {source}")]
+    GlobError {
+        pattern: String,
+        source: glob::PatternError,
+    },
+
+    /// Options that contradict each other or the inputs, e.g. `--only-batch`
+    /// past the last batch or `--hold` with a plain `--submit` command.
+    #[error("{0}")]
+    InvalidOptions(String),
+
+    #[error("cannot write {}: {source}", .path.display())]
+    ScriptWriteError { path: PathBuf, source: io::Error },
+
+    /// `--out-dir` cannot be created or written to, found before anything
+    /// is generated.
+    #[error(
+        "--out-dir {} is not writable: {source} (pick another directory with --out-dir)",
+        .dir.display()
+    )]
+    OutDirNotWritable { dir: PathBuf, source: io::Error },
+
+    /// The file system or quota filled up while scripts were written;
+    /// `written` of `total` were complete.
+    #[error(
+        "out of space writing {}: {source} ({written} of {total} script(s) written; free some space or pick another --out-dir)",
+        .path.display()
+    )]
+    OutOfSpace {
+        path: PathBuf,
+        written: usize,
+        total: usize,
+        source: io::Error,
+    },
+
+    /// The submit command ran and exited unsuccessfully. `status` is its
+    /// exit code, `None` when it was killed by a signal.
+    #[error("{}", submit_failed_message(.program, .script.as_deref(), .job_name, .stderr))]
+    SubmitFailed {
+        program: String,
+        /// `None` with `--wrap`.
+        script: Option<PathBuf>,
+        job_name: String,
+        stderr: String,
+        status: Option<i32>,
+    },
+
+    /// With `--keep-going`: some batches were submitted, `failed` of the
+    /// `attempted` ones were not.
+    #[error("{failed} of {attempted} submission(s) failed")]
+    PartialFailure { failed: usize, attempted: usize },
+
+    /// Ctrl-C while submitting.
+    #[error("interrupted")]
+    Interrupted,
+
+    #[error(transparent)]
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl BatchelorError {
+    /// Exit code of the binary for this error; see the binary's
+    /// documentation for the full list.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            BatchelorError::InvalidBatchCount
+            | BatchelorError::ScriptMissing(_)
+            | BatchelorError::ScriptInvalid { .. }
+            | BatchelorError::GlobError { .. }
+            | BatchelorError::InvalidOptions(_) => 2,
+            BatchelorError::NoInputs { .. } => 3,
+            BatchelorError::ScriptWriteError { .. }
+            | BatchelorError::OutDirNotWritable { .. }
+            | BatchelorError::OutOfSpace { .. } => 4,
+            BatchelorError::SubmitFailed { .. } => 5,
+            BatchelorError::PartialFailure { .. } => 6,
+            BatchelorError::Interrupted => 130,
+            BatchelorError::Other(_) => 1,
+        }
+    }
+}
+
+/// Errors are passed around as `Box<dyn Error>` inside the crate; typed
+/// ones are recovered at the API boundary. Others keep their message, so
+/// the error can cross threads.
+impl From<Box<dyn std::error::Error>> for BatchelorError {
+    fn from(e: Box<dyn std::error::Error>) -> BatchelorError {
+        match e.downcast::<BatchelorError>() {
+            Ok(e) => *e,
+            Err(e) => BatchelorError::Other(e.to_string().into()),
+        }
+    }
+}
+
+impl From<String> for BatchelorError {
+    fn from(message: String) -> BatchelorError {
+        BatchelorError::Other(message.into())
+    }
+}
+
+impl From<&str> for BatchelorError {
+    fn from(message: &str) -> BatchelorError {
+        BatchelorError::Other(message.into())
+    }
+}
+
+fn no_inputs_message(
+    patterns: &[String],
+    input_list: Option<&Path>,
+    removed_by: Option<&str>,
+) -> String {
+    let message = match input_list {
+        Some(path) => format!(
+            "no inputs matched from --glob {:?} or --input-list {}",
+            patterns,
+            path.display()
+        ),
+        None => format!("no inputs matched from --glob {:?}", patterns),
+    };
+    match removed_by {
+        Some(filter) => format!("{} ({})", message, filter),
+        None => message,
+    }
+}
+
+fn submit_failed_message(
+    program: &str,
+    script: Option<&Path>,
+    job_name: &str,
+    stderr: &str,
+) -> String {
+    match script {
+        Some(path) => format!(
+            "{} failed for {}: {} (script kept: {})",
+            program,
+            path.display(),
+            stderr.trim(),
+            path.display()
+        ),
+        None => format!("{} failed for {}: {}", program, job_name, stderr.trim()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let io = || io::Error::other("failed");
+        let cases = [
+            (BatchelorError::InvalidBatchCount, 2),
+            (BatchelorError::ScriptMissing("s.sh".into()), 2),
+            (
+                BatchelorError::ScriptInvalid {
+                    path: "s.sh".into(),
+                    reason: "empty".to_string(),
+                },
+                2,
+            ),
+            (
+                BatchelorError::GlobError {
+                    pattern: "[".to_string(),
+                    source: glob::Pattern::new("[").unwrap_err(),
+                },
+                2,
+            ),
+            (BatchelorError::InvalidOptions("--hold".to_string()), 2),
+            (
+                BatchelorError::NoInputs {
+                    patterns: vec![],
+                    input_list: None,
+                    removed_by: None,
+                },
+                3,
+            ),
+            (
+                BatchelorError::ScriptWriteError {
+                    path: "s.sh".into(),
+                    source: io(),
+                },
+                4,
+            ),
+            (
+                BatchelorError::OutDirNotWritable {
+                    dir: "out".into(),
+                    source: io(),
+                },
+                4,
+            ),
+            (
+                BatchelorError::OutOfSpace {
+                    path: "s.sh".into(),
+                    written: 1,
+                    total: 2,
+                    source: io(),
+                },
+                4,
+            ),
+            (
+                BatchelorError::SubmitFailed {
+                    program: "sbatch".to_string(),
+                    script: None,
+                    job_name: "batch-0001".to_string(),
+                    stderr: String::new(),
+                    status: Some(1),
+                },
+                5,
+            ),
+            (
+                BatchelorError::PartialFailure {
+                    failed: 1,
+                    attempted: 2,
+                },
+                6,
+            ),
+            (BatchelorError::Interrupted, 130),
+            (BatchelorError::from("other"), 1),
+        ];
+        for (e, code) in cases {
+            assert_eq!(e.exit_code(), code, "{:?}", e);
+        }
+    }
+
+    #[test]
+    fn typed_errors_survive_boxing() {
+        let boxed: Box<dyn std::error::Error> = BatchelorError::Interrupted.into();
+        assert!(matches!(
+            BatchelorError::from(boxed),
+            BatchelorError::Interrupted
+        ));
+        let boxed: Box<dyn std::error::Error> = "failed".into();
+        let e = BatchelorError::from(boxed);
+        assert!(matches!(e, BatchelorError::Other(_)));
+        assert_eq!(e.to_string(), "failed");
+    }
+}
@@ -0,0 +1,165 @@
+//! How inputs are written to the line-oriented files batchelor writes and
+//! reads back: the `.done`/`.failed` markers, `failed_inputs.txt` (and
+//! any `--input-list`), and the TSV `--manifest`. A file name may contain
+//! a newline, which would split it over two lines, so `%`, newline,
+//! carriage return and tab are written as `%25`, `%0A`, `%0D` and `%09`.
+//! Any other `%` sequence is read as it is, so lists written by hand or by
+//! older batchelors keep working unless they contain one of these four.
+
+use std::borrow::Cow;
+
+/// The characters that are encoded, with their encodings.
+const ENCODED: [(char, &str); 4] = [('%', "%25"), ('\n', "%0A"), ('\r', "%0D"), ('\t', "%09")];
+
+/// `input` as one line of a line-oriented file.
+pub fn encode(input: &str) -> Cow<'_, str> {
+    if !input.contains(['%', '\n', '\r', '\t']) {
+        return Cow::Borrowed(input);
+    }
+    let mut line = String::with_capacity(input.len() + 8);
+    for c in input.chars() {
+        match ENCODED.iter().find(|(from, _)| *from == c) {
+            Some((_, to)) => line.push_str(to),
+            None => line.push(c),
+        }
+    }
+    Cow::Owned(line)
+}
+
+/// The input [`encode`] wrote as `line`.
+pub fn decode(line: &str) -> Cow<'_, str> {
+    if !line.contains('%') {
+        return Cow::Borrowed(line);
+    }
+    let mut input = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(at) = rest.find('%') {
+        input.push_str(&rest[..at]);
+        let escape = rest.get(at..at + 3);
+        match ENCODED
+            .iter()
+            .find(|(_, to)| escape.is_some_and(|e| e.eq_ignore_ascii_case(to)))
+        {
+            Some((from, _)) => {
+                input.push(*from);
+                rest = &rest[at + 3..];
+            }
+            None => {
+                input.push('%');
+                rest = &rest[at + 1..];
+            }
+        }
+    }
+    input.push_str(rest);
+    Cow::Owned(input)
+}
+
+/// Name of the shell function in [`SHELL_ENCODE`].
+pub(crate) const SHELL_ENCODE_FN: &str = "batchelor_encode";
+
+/// Defines a bash function encoding the lines of its stdin like
+/// [`encode`], for scripts appending inputs they read from a file (which
+/// cannot contain newlines) to a marker.
+pub(crate) const SHELL_ENCODE: &str = r#"batchelor_encode() { local line; while IFS= read -r line || [[ -n $line ]]; do line=${line//"%"/%25}; line=${line//$'\t'/%09}; line=${line//$'\r'/%0D}; printf '%s\n' "$line"; done; }"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    /// File names with each character that needs care, and mixes of them.
+    /// `\u{FFFD}` stands where a non-UTF-8 name had an invalid byte, as
+    /// inputs are read.
+    const HOSTILE: &[&str] = &[
+        "plain.fq",
+        "with space.fq",
+        "  leading and trailing  ",
+        "100%.fq",
+        "%",
+        "%25.fq",
+        "%0A%0d%09",
+        "%zz%2",
+        "line\nbreak.fq",
+        "\n",
+        "carriage\rreturn.fq",
+        "crlf\r\n.fq",
+        "tab\there.fq",
+        "all %\n\r\t of them",
+        "\u{FFFD}lossy\u{FFFD}.fq",
+        "naïve 名前.fq",
+        "$(false) `false` 'q' \"d\" \\ ;|&.fq",
+    ];
+
+    #[test]
+    fn hostile_names_are_single_lines_that_decode_back() {
+        for name in HOSTILE {
+            let line = encode(name);
+            assert!(!line.contains(['\n', '\r', '\t']), "{:?}", line);
+            assert_eq!(decode(&line), *name, "{:?}", line);
+        }
+        assert!(matches!(encode("with space.fq"), Cow::Borrowed(_)));
+        assert_eq!(encode("all %\n\r\t"), "all %25%0A%0D%09");
+    }
+
+    #[test]
+    fn other_percent_sequences_are_read_as_they_are() {
+        for (line, input) in [
+            ("50%", "50%"),
+            ("%41%2", "%41%2"),
+            ("%0a%0D", "\n\r"),
+            ("%%25", "%%"),
+            ("%2525", "%25"),
+        ] {
+            assert_eq!(decode(line), input, "{:?}", line);
+        }
+    }
+
+    /// What `bash` prints running [`SHELL_ENCODE`] on `lines` in `locale`.
+    fn shell_encode(lines: &[u8], locale: &str) -> Vec<u8> {
+        let mut bash = Command::new("bash")
+            .env("LC_ALL", locale)
+            .arg("-c")
+            .arg(format!("{}\n{}", SHELL_ENCODE, SHELL_ENCODE_FN))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        bash.stdin.take().unwrap().write_all(lines).unwrap();
+        let output = bash.wait_with_output().unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        output.stdout
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_encode_encodes_like_encode() {
+        // Lines of a list, which cannot hold newlines; the last one
+        // unterminated.
+        let names = HOSTILE
+            .iter()
+            .filter(|name| !name.contains('\n'))
+            .collect::<Vec<_>>();
+        let list = names
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let encoded = String::from_utf8(shell_encode(list.as_bytes(), "C.UTF-8")).unwrap();
+        let lines = encoded.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), names.len());
+        for (line, name) in lines.iter().zip(&names) {
+            assert_eq!(*line, encode(name), "{:?}", name);
+            assert_eq!(decode(line), **name);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_encode_passes_non_utf8_bytes_through() {
+        for locale in ["C", "C.UTF-8"] {
+            let encoded = shell_encode(b"caf\xe9 50%\t.fq\n\xff\xfe\r\n", locale);
+            assert_eq!(encoded, b"caf\xe9 50%25%09.fq\n\xff\xfe%0D\n", "{}", locale);
+        }
+    }
+}
@@ -0,0 +1,370 @@
+#[cfg(feature = "cli")]
+use crate::perms::{self, Permissions};
+#[cfg(feature = "cli")]
+use crate::reporter::{ColorChoice, Reporter, StreamReporter, Verbosity};
+#[cfg(feature = "cli")]
+use crate::runs::{self, FAILED_INPUTS_FILE};
+use crate::state::JobState;
+#[cfg(feature = "cli")]
+use crate::state::RunState;
+#[cfg(feature = "cli")]
+use crate::write_file_atomic;
+use crate::{escape, shellgen};
+#[cfg(feature = "cli")]
+use clap::{Parser, ValueHint};
+#[cfg(feature = "cli")]
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Per-job input markers the generated scripts append to, in the run's
+/// directory.
+const FAILED_SUFFIX: &str = "failed";
+const DONE_SUFFIX: &str = "done";
+
+/// Category for inputs whose command exited non-zero.
+#[cfg(feature = "cli")]
+const INPUT_FAILED: &str = "input failed";
+/// Category for batches that never reached the scheduler.
+#[cfg(feature = "cli")]
+const SUBMIT_FAILED: &str = "SUBMIT_FAILED";
+
+#[cfg(feature = "cli")]
+#[derive(Parser, Debug)]
+#[command(
+    name = "batchelor failures",
+    about = "Collect the failed inputs of a previous run into one input list"
+)]
+pub struct FailuresCli {
+    /// Output directory the run was submitted from.
+    #[arg(long, default_value = ".batchelor", value_hint = ValueHint::DirPath)]
+    out_dir: PathBuf,
+
+    /// Run ID to collect from (default: the most recent run).
+    #[arg(long)]
+    run: Option<String>,
+
+    /// Octal mode of the failed input list, e.g. 640 [default: that of the
+    /// run state]. Ignored on Windows.
+    #[arg(long, value_name = "OCTAL", value_parser = perms::parse_mode)]
+    artifact_mode: Option<u32>,
+
+    /// Group (name or ID) to give the failed input list. Ignored on
+    /// Windows.
+    #[arg(long, value_name = "NAME")]
+    group: Option<String>,
+
+    /// Print only the summary and errors: not where the list was
+    /// written, nor warnings.
+    #[arg(long)]
+    quiet: bool,
+
+    /// Color warnings and errors: on a terminal unless NO_COLOR is set
+    /// (auto), always or never.
+    #[arg(long, value_enum, value_name = "WHEN", default_value = "auto")]
+    color: ColorChoice,
+}
+
+#[cfg(feature = "cli")]
+pub fn failures(cli: FailuresCli) -> Result<(), Box<dyn std::error::Error>> {
+    let reporter = StreamReporter::stdio()
+        .with_verbosity(Verbosity::from_quiet(cli.quiet))
+        .with_color(cli.color);
+    collect_failures(cli, &reporter)
+}
+
+/// The summary of the list is data; where it went and the warnings are
+/// status lines.
+#[cfg(feature = "cli")]
+fn collect_failures(
+    cli: FailuresCli,
+    reporter: &dyn Reporter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let state = RunState::load_run(&cli.out_dir, cli.run.as_deref())?;
+
+    // Input -> failure category, in the order inputs were first seen.
+    let mut failed: Vec<(String, String)> = Vec::new();
+    let mut done = HashSet::new();
+    for job in &state.jobs {
+        let (done_file, failed_file) = marker_files(&cli.out_dir, job);
+        for input in read_markers(&failed_file)? {
+            failed.push((input, INPUT_FAILED.to_string()));
+        }
+        done.extend(read_markers(&done_file)?);
+    }
+    for job in &state.jobs {
+        let category = match (&job.submit_error, &job.state) {
+            (Some(_), _) => SUBMIT_FAILED,
+            (None, Some(s)) if job.failed() => s.as_str(),
+            _ => continue,
+        };
+        // Inputs without a marker: the job died (OOM, timeout, node
+        // failure, ...) before getting to them.
+        for input in &job.inputs {
+            failed.push((input.path.clone(), category.to_string()));
+        }
+    }
+    if state.jobs.iter().all(|j| j.inputs.is_empty()) {
+        reporter.diagnostic(&format!(
+            "warning: run {} does not record its inputs; only inputs with failure markers are listed",
+            state.run_id
+        ));
+    }
+
+    // A requeued or resubmitted job may fail an input and later finish it.
+    // Finished inputs of a job that failed as a whole never failed.
+    let mut seen = HashSet::new();
+    let mut recovered = HashSet::new();
+    let mut counts = BTreeMap::new();
+    let mut inputs = Vec::new();
+    for (input, category) in failed {
+        if done.contains(&input) {
+            if category == INPUT_FAILED {
+                recovered.insert(input);
+            }
+            continue;
+        }
+        if seen.insert(input.clone()) {
+            *counts.entry(category).or_insert(0usize) += 1;
+            inputs.push(input);
+        }
+    }
+
+    let path = runs::run_dir(&cli.out_dir, &state.run_id).join(FAILED_INPUTS_FILE);
+    let mut text = inputs
+        .iter()
+        .map(|input| escape::encode(input))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    // Like the list the run writes: the run's artifact mode unless asked
+    // otherwise.
+    let perms = Permissions::new(None, cli.artifact_mode, cli.group.as_deref())?;
+    let perms = if perms.is_set() {
+        perms
+    } else {
+        Permissions::like(&RunState::path(&cli.out_dir, &state.run_id))
+    };
+    write_file_atomic(&path, text.as_bytes(), perms::Kind::Artifact, &perms)
+        .map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+
+    let mut summary = format!("Run {}: {} failed input(s)\n", state.run_id, inputs.len());
+    for (category, count) in &counts {
+        summary += &format!("  {:>6}  {}\n", count, category);
+    }
+    if !recovered.is_empty() {
+        summary += &format!(
+            "  ({} input(s) failed but later succeeded and are not listed)\n",
+            recovered.len()
+        );
+    }
+    reporter.data(&summary);
+    reporter.message(&format!("Written to {}", path.display()));
+    if !inputs.is_empty() {
+        reporter.message(&format!("Rerun them with --input-list {}", path.display()));
+    }
+    Ok(())
+}
+
+/// Number of distinct inputs a job has finished and failed so far, from
+/// its markers. Inputs that failed and were later finished
+/// count as finished.
+pub(crate) fn marker_counts(
+    out_dir: &Path,
+    job: &JobState,
+) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+    let (done, failed) = marker_inputs(out_dir, job)?;
+    Ok((done.len(), failed.len()))
+}
+
+/// The inputs counted by [`marker_counts`]: those a job has finished, and
+/// those it failed and did not finish later.
+pub(crate) fn marker_inputs(
+    out_dir: &Path,
+    job: &JobState,
+) -> Result<(HashSet<String>, HashSet<String>), Box<dyn std::error::Error>> {
+    let (done_file, failed_file) = marker_files(out_dir, job);
+    let done = read_markers(&done_file)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let failed = read_markers(&failed_file)?
+        .into_iter()
+        .filter(|input| !done.contains(input))
+        .collect::<HashSet<_>>();
+    Ok((done, failed))
+}
+
+fn marker_path(dir: &Path, job_name: &str, suffix: &str) -> PathBuf {
+    dir.join(format!("{}.{}", job_name, suffix))
+}
+
+/// Where a batch's scripts record finished and failed inputs, given the
+/// absolute run directory.
+pub(crate) fn marker_paths(dir: &Path, job_name: &str) -> (PathBuf, PathBuf) {
+    (
+        marker_path(dir, job_name, DONE_SUFFIX),
+        marker_path(dir, job_name, FAILED_SUFFIX),
+    )
+}
+
+/// The job's `.done` and `.failed` markers as recorded in the run state,
+/// or where runs from before that put them (`--out-dir`).
+fn marker_files(out_dir: &Path, job: &JobState) -> (PathBuf, PathBuf) {
+    let (done, failed) = marker_paths(out_dir, &job.job_name);
+    (
+        job.done_file.clone().unwrap_or(done),
+        job.failed_file.clone().unwrap_or(failed),
+    )
+}
+
+fn read_markers(path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(|l| escape::decode(l).into_owned())
+            .collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("could not read {}: {}", path.display(), e).into()),
+    }
+}
+
+/// Makes each command append its inputs, encoded as `escape` says, to the
+/// job's `.done` or `.failed` marker in `marker_dir`. A failing command
+/// still ends the job with its exit status.
+pub(crate) fn track_inputs(
+    commands: Vec<String>,
+    inputs: &[String],
+    multi_input: bool,
+    marker_dir: &Path,
+    job_name: &str,
+) -> Vec<String> {
+    let (done, failed) = marker_paths(marker_dir, job_name);
+    let (done, failed) = (shellgen::quote_path(&done), shellgen::quote_path(&failed));
+    let track = |command: &str, inputs: &[String]| {
+        let quoted = inputs
+            .iter()
+            .map(|i| shellgen::quote(&escape::encode(i)).into_owned())
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!(
+            "{} || {{ rc=$?; printf '%s\\n' {} >> {}; exit $rc; }}\nprintf '%s\\n' {} >> {}",
+            command, quoted, failed, quoted, done
+        )
+    };
+    if multi_input {
+        commands.iter().map(|c| track(c, inputs)).collect()
+    } else {
+        commands
+            .iter()
+            .zip(inputs)
+            .map(|(c, input)| track(c, std::slice::from_ref(input)))
+            .collect()
+    }
+}
+
+/// Like [`track_inputs`] for a command reading its inputs from `list`: all
+/// of them are recorded as failed when it fails, since it cannot tell
+/// which.
+pub(crate) fn track_input_list(
+    command: &str,
+    list: &Path,
+    marker_dir: &Path,
+    job_name: &str,
+) -> String {
+    let (done, failed) = marker_paths(marker_dir, job_name);
+    let list = shellgen::quote_path(list);
+    format!(
+        "{}\n{} || {{ rc=$?; {} < {} >> {}; exit $rc; }}\n{} < {} >> {}",
+        escape::SHELL_ENCODE,
+        command,
+        escape::SHELL_ENCODE_FN,
+        list,
+        shellgen::quote_path(&failed),
+        escape::SHELL_ENCODE_FN,
+        list,
+        shellgen::quote_path(&done)
+    )
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    /// Names with `%`, newline, carriage return, tab and spaces; `\u{FFFD}`
+    /// stands where a non-UTF-8 name had an invalid byte, as inputs are
+    /// read.
+    const HOSTILE: &[&str] = &[
+        "with space.fq",
+        "100%25%.fq",
+        "line\nbreak.fq",
+        "carriage\rreturn.fq",
+        "tab\there.fq",
+        " %\n\r\t ",
+        "\u{FFFD}lossy.fq",
+        "$(false) `false` 'q'.fq",
+    ];
+
+    fn strings(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    /// Runs `lines` as a bash script, returning whether it succeeded.
+    fn bash(lines: &[String]) -> bool {
+        std::process::Command::new("bash")
+            .arg("-c")
+            .arg(lines.join("\n"))
+            .status()
+            .unwrap()
+            .success()
+    }
+
+    #[test]
+    fn tracked_inputs_read_back_from_the_markers() {
+        let dir = tempfile::tempdir().unwrap();
+        let (done, failed) = marker_paths(dir.path(), "job");
+        let inputs = strings(HOSTILE);
+        // The last command fails and ends the job.
+        let mut commands = vec!["true".to_string(); inputs.len() - 1];
+        commands.push("false".to_string());
+        let tracked = track_inputs(commands, &inputs, false, dir.path(), "job");
+        assert!(!bash(&tracked));
+        assert_eq!(read_markers(&done).unwrap(), inputs[..inputs.len() - 1]);
+        assert_eq!(read_markers(&failed).unwrap(), inputs[inputs.len() - 1..]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let (done, failed) = marker_paths(dir.path(), "job");
+        let tracked = track_inputs(vec!["true".to_string()], &inputs, true, dir.path(), "job");
+        assert!(bash(&tracked));
+        assert_eq!(read_markers(&done).unwrap(), inputs);
+        assert!(read_markers(&failed).unwrap().is_empty());
+    }
+
+    #[test]
+    fn listed_inputs_read_back_from_the_markers() {
+        // An input list holds no newlines.
+        let inputs = strings(HOSTILE)
+            .into_iter()
+            .filter(|input| !input.contains('\n'))
+            .collect::<Vec<_>>();
+        for (command, succeeds) in [("true", true), ("false", false)] {
+            let dir = tempfile::tempdir().unwrap();
+            let list = dir.path().join("job.inputs");
+            fs::write(&list, inputs.join("\n") + "\n").unwrap();
+            let (done, failed) = marker_paths(dir.path(), "job");
+            let tracked = track_input_list(command, &list, dir.path(), "job");
+            assert_eq!(bash(&[tracked]), succeeds);
+            let (recorded, empty) = if succeeds {
+                (done, failed)
+            } else {
+                (failed, done)
+            };
+            assert_eq!(read_markers(&recorded).unwrap(), inputs, "{}", command);
+            assert!(read_markers(&empty).unwrap().is_empty());
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use crate::reporter::{ColorChoice, Reporter, StreamReporter, Verbosity};
+use crate::runs;
+use crate::scheduler::is_final_state;
+use crate::state::{JobState, RunState};
+use crate::units;
+use clap::{Parser, ValueHint};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "batchelor history",
+    about = "List the runs recorded in an output directory, newest first"
+)]
+pub struct HistoryCli {
+    /// Output directory the runs were submitted from.
+    #[arg(long, default_value = ".batchelor", value_hint = ValueHint::DirPath)]
+    out_dir: PathBuf,
+
+    /// Also list each run's batches.
+    #[arg(long, short, conflicts_with = "json")]
+    verbose: bool,
+
+    /// Print the runs' states as a JSON array instead.
+    #[arg(long)]
+    json: bool,
+
+    /// Print only the runs and errors, not a note when there are none.
+    #[arg(long)]
+    quiet: bool,
+
+    /// Color warnings and errors: on a terminal unless NO_COLOR is set
+    /// (auto), always or never.
+    #[arg(long, value_enum, value_name = "WHEN", default_value = "auto")]
+    color: ColorChoice,
+}
+
+pub fn history(cli: HistoryCli) -> Result<(), Box<dyn std::error::Error>> {
+    let reporter = StreamReporter::stdio()
+        .with_verbosity(Verbosity::from_quiet(cli.quiet))
+        .with_color(cli.color);
+    list_runs(cli, &reporter)
+}
+
+/// The table or JSON is data; an empty output directory is a status line.
+fn list_runs(cli: HistoryCli, reporter: &dyn Reporter) -> Result<(), Box<dyn std::error::Error>> {
+    let mut run_ids = runs::run_ids(&cli.out_dir)?;
+    run_ids.reverse();
+    // A state file being rewritten or damaged must not hide the other runs.
+    let runs = run_ids
+        .into_iter()
+        .map(|id| {
+            let state = RunState::load(&cli.out_dir, &id).map_err(|e| e.to_string());
+            (id, state)
+        })
+        .collect::<Vec<_>>();
+
+    if cli.json {
+        let states = runs
+            .iter()
+            .map(|(id, state)| match state {
+                Ok(state) => serde_json::to_value(state),
+                Err(e) => Ok(serde_json::json!({ "run_id": id, "error": e })),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        reporter.data(&format!("{}\n", serde_json::to_string_pretty(&states)?));
+        return Ok(());
+    }
+
+    if runs.is_empty() {
+        reporter.message(&format!(
+            "No runs recorded under {}",
+            cli.out_dir.join(runs::RUNS_DIR).display()
+        ));
+        return Ok(());
+    }
+    let mut table = "run_id\ttimestamp\tjobs\tinputs\tsubmit\tstatus\n".to_string();
+    for (id, state) in &runs {
+        let state = match state {
+            Ok(state) => state,
+            Err(e) => {
+                table += &format!("{}\t-\t-\t-\t-\t(unreadable)\n", id);
+                if cli.verbose {
+                    table += &format!("  {}\n", e);
+                }
+                continue;
+            }
+        };
+        // Runs from before state files do not record their inputs.
+        let inputs = match state.jobs.iter().map(|j| j.inputs.len()).sum::<usize>() {
+            0 => "-".to_string(),
+            n => n.to_string(),
+        };
+        table += &format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\n",
+            state.run_id,
+            state.timestamp.as_deref().unwrap_or("-"),
+            state.jobs.len(),
+            inputs,
+            state.submit,
+            run_status(&state.jobs)
+        );
+        if cli.verbose {
+            for job in &state.jobs {
+                table += &format!(
+                    "  {}\t{}\t{}\t{}\t{} input(s)\n",
+                    job.job_name,
+                    job.job_id.as_deref().unwrap_or("-"),
+                    job_status(job),
+                    job.elapsed_secs
+                        .map_or("-".to_string(), units::format_clock),
+                    job.inputs.len()
+                );
+            }
+        }
+    }
+    reporter.data(&table);
+    Ok(())
+}
+
+/// How the run ended as far as its state file knows: outcomes are only
+/// recorded by `--wait` and `stats --update`, so most runs read
+/// "submitted".
+fn run_status(jobs: &[JobState]) -> String {
+    let submit_failed = jobs.iter().filter(|j| j.submit_error.is_some()).count();
+    let failed = jobs.iter().filter(|j| j.failed()).count();
+    let unknown = jobs
+        .iter()
+        .filter(|j| j.submit_error.is_none() && !j.state.as_deref().is_some_and(is_final_state))
+        .count();
+    let mut parts = Vec::new();
+    if submit_failed > 0 {
+        parts.push(format!("{} submit failed", submit_failed));
+    }
+    if failed > 0 {
+        parts.push(format!("{} failed", failed));
+    }
+    if parts.is_empty() {
+        parts.push(
+            if unknown > 0 {
+                "submitted"
+            } else {
+                "completed"
+            }
+            .to_string(),
+        );
+    }
+    parts.join(", ")
+}
+
+fn job_status(job: &JobState) -> &str {
+    if job.submit_error.is_some() {
+        "SUBMIT_FAILED"
+    } else {
+        job.state.as_deref().unwrap_or("-")
+    }
+}
@@ -0,0 +1,100 @@
+//! `--on-complete`, `--on-failure` and `--webhook-url`: tell something
+//! outside batchelor that a run has finished.
+
+use crate::output::Output;
+use crate::report::{ReportFormat, RunReport};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs the hooks that apply to `report`. Hook failures are reported as
+/// warnings and never fail the run.
+pub(crate) fn run_hooks(
+    on_complete: Option<&str>,
+    on_failure: Option<&str>,
+    webhook_url: Option<&str>,
+    report: &RunReport,
+    output: &Output,
+) {
+    let summary = report.render(ReportFormat::Json);
+    let failed = report.failed() > 0 || report.exit_code() != 0;
+    let env = hook_env(report, failed);
+
+    let commands = [("--on-complete", on_complete), ("--on-failure", on_failure)];
+    for (flag, command) in commands {
+        let Some(command) = command else {
+            continue;
+        };
+        if flag == "--on-failure" && !failed {
+            continue;
+        }
+        if let Err(e) = run_command(command, &summary, &env) {
+            output.eprintln(format!("warning: {} hook failed: {}", flag, e));
+        }
+    }
+    if let Some(url) = webhook_url {
+        if let Err(e) = post_webhook(url, &summary) {
+            output.eprintln(format!("warning: --webhook-url failed: {}", e));
+        }
+    }
+}
+
+/// `BATCHELOR_*` variables describing the run, for hooks that do not want
+/// to parse the JSON on stdin.
+fn hook_env(report: &RunReport, failed: bool) -> Vec<(&'static str, String)> {
+    let jobs = &report.state.jobs;
+    let submitted = jobs.iter().filter(|j| j.submit_error.is_none()).count();
+    let jobs_failed = jobs.iter().filter(|j| j.failed()).count();
+    let inputs = jobs.iter().map(|j| j.inputs.len()).sum::<usize>();
+    let inputs_failed = report.wait.as_ref().map_or(0, |w| w.failed_inputs.len());
+    vec![
+        ("BATCHELOR_RUN_ID", report.state.run_id.clone()),
+        (
+            "BATCHELOR_STATUS",
+            if failed { "failed" } else { "ok" }.to_string(),
+        ),
+        ("BATCHELOR_EXIT_CODE", report.exit_code().to_string()),
+        ("BATCHELOR_JOBS", jobs.len().to_string()),
+        ("BATCHELOR_JOBS_SUBMITTED", submitted.to_string()),
+        ("BATCHELOR_SUBMIT_FAILURES", report.failed().to_string()),
+        ("BATCHELOR_JOBS_FAILED", jobs_failed.to_string()),
+        ("BATCHELOR_INPUTS", inputs.to_string()),
+        ("BATCHELOR_INPUTS_FAILED", inputs_failed.to_string()),
+    ]
+}
+
+fn run_command(
+    command: &str,
+    summary: &str,
+    env: &[(&str, String)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let parts = shlex::split(command).ok_or("could not parse the command")?;
+    let (program, args) = parts.split_first().ok_or("empty command")?;
+    let mut child = Command::new(program)
+        .args(args)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run {}: {}", program, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that ignores its stdin may exit before reading it.
+        let _ = stdin.write_all(summary.as_bytes());
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(format!("{} exited with {}", program, status).into());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "webhook")]
+fn post_webhook(url: &str, summary: &str) -> Result<(), Box<dyn std::error::Error>> {
+    ureq::post(url)
+        .header("Content-Type", "application/json")
+        .send(summary)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "webhook"))]
+fn post_webhook(_url: &str, _summary: &str) -> Result<(), Box<dyn std::error::Error>> {
+    Err("batchelor was built without the `webhook` feature".into())
+}
@@ -0,0 +1,238 @@
+//! `--incremental`: schedule only the inputs that are new since the latest
+//! run under `--out-dir`, or that changed since. What a run handled is in
+//! its state (see [`crate::state`]): the inputs of its submitted jobs,
+//! less those recorded as failed, and the inputs of earlier runs it left
+//! out, so the latest state always holds everything handled so far.
+//!
+//! `--incremental-key` says what makes an input unchanged: the same path;
+//! the same path, size and modification time (the default); or the same
+//! path, size and CRC-32 of the contents, which reads every input. Inputs
+//! that are not files are compared by path. Runs without `--incremental`
+//! record no sizes or times, so after one every file counts as changed
+//! unless the key is the path.
+
+use crate::inputs::{self, Input};
+use crate::plan::Plan;
+use crate::runs;
+use crate::state::{Fingerprint, InputState, InputStatus, RunState};
+use crate::{BatchelorError, Interrupt};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Inputs a hashing thread takes at a time.
+const HASH_CHUNK: usize = 4;
+
+/// What `--incremental` compares to tell an input has not changed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum IncrementalKey {
+    /// The path alone: an input handled before is never scheduled again.
+    Path,
+    /// The path, size and modification time.
+    #[default]
+    #[cfg_attr(feature = "cli", value(name = "path+mtime"))]
+    PathMtime,
+    /// The path, size and a CRC-32 of the contents.
+    #[cfg_attr(feature = "cli", value(name = "path+hash"))]
+    PathHash,
+}
+
+impl IncrementalKey {
+    /// Whether an input with fingerprint `now` is the one recorded as
+    /// `then`.
+    fn unchanged(self, then: Option<&Fingerprint>, now: Option<&Fingerprint>) -> bool {
+        match (self, then, now) {
+            (IncrementalKey::Path, _, _) => true,
+            (_, None, None) => true,
+            (IncrementalKey::PathMtime, Some(then), Some(now)) => {
+                then.size == now.size
+                    && then.modified_ns.is_some()
+                    && then.modified_ns == now.modified_ns
+            }
+            (IncrementalKey::PathHash, Some(then), Some(now)) => {
+                then.size == now.size && then.crc32.is_some() && then.crc32 == now.crc32
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The inputs the latest run under an `--out-dir` handled.
+#[derive(Debug)]
+pub(crate) struct Previous {
+    pub(crate) run_id: String,
+    handled: HashMap<String, InputState>,
+}
+
+impl Previous {
+    /// What the latest run under `out_dir` handled; `None` when no run was
+    /// recorded there.
+    pub(crate) fn load(out_dir: &Path) -> Result<Option<Previous>, Box<dyn std::error::Error>> {
+        let Some(run_id) = runs::latest_run_id(out_dir)? else {
+            return Ok(None);
+        };
+        let state = RunState::load(out_dir, &run_id)?;
+        let mut handled = HashMap::new();
+        for job in state.jobs.into_iter().filter(|job| job.submitted()) {
+            for input in job.inputs {
+                if input.status != Some(InputStatus::Failed) {
+                    handled.insert(input.path.clone(), input);
+                }
+            }
+        }
+        for input in state.unchanged {
+            handled.entry(input.path.clone()).or_insert(input);
+        }
+        Ok(Some(Previous { run_id, handled }))
+    }
+}
+
+/// The inputs of a run split by [`select`].
+#[derive(Debug, Default)]
+pub(crate) struct Selection {
+    /// New or changed, with their fingerprints, in the order found.
+    pub(crate) scheduled: Vec<(Input, Option<Fingerprint>)>,
+    /// How many of the inputs found were left out as unchanged.
+    pub(crate) skipped: usize,
+    /// Everything handled before that is not scheduled again, sorted by
+    /// path: the unchanged inputs and those not found this time.
+    pub(crate) unchanged: Vec<InputState>,
+}
+
+/// Splits `found` into the inputs to schedule and those `previous` handled
+/// and `key` finds unchanged. With `path+hash` the inputs are read on up
+/// to `threads` threads.
+pub(crate) fn select(
+    found: Vec<Input>,
+    previous: Option<&Previous>,
+    key: IncrementalKey,
+    threads: usize,
+    interrupt: &Interrupt,
+) -> Result<Selection, Box<dyn std::error::Error>> {
+    let threads = match threads {
+        0 => inputs::DEFAULT_IO_THREADS,
+        n => n,
+    };
+    let fingerprints = inputs::map_ordered(&found, threads, HASH_CHUNK, |input| {
+        interrupt.check()?;
+        fingerprint(input, key).map_err(|e| {
+            BatchelorError::Other(
+                format!(
+                    "--incremental-key path+hash: could not read {}: {}",
+                    input.path, e
+                )
+                .into(),
+            )
+        })
+    });
+    let mut handled = previous.map(|p| p.handled.clone()).unwrap_or_default();
+    let mut selection = Selection::default();
+    for (input, fingerprint) in found.into_iter().zip(fingerprints) {
+        // Every failure but the first comes back `None`.
+        let fingerprint = fingerprint.ok_or("fingerprinting stopped")??;
+        match handled.get(&input.path) {
+            Some(then) if key.unchanged(then.fingerprint.as_ref(), fingerprint.as_ref()) => {
+                selection.skipped += 1;
+            }
+            _ => {
+                handled.remove(&input.path);
+                selection.scheduled.push((input, fingerprint));
+            }
+        }
+    }
+    selection.unchanged = handled.into_values().collect();
+    selection.unchanged.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(selection)
+}
+
+/// What `input` is now, as far as `key` needs; `None` for inputs that are
+/// not files.
+fn fingerprint(input: &Input, key: IncrementalKey) -> io::Result<Option<Fingerprint>> {
+    let Some(metadata) = input.metadata.as_ref().filter(|m| m.is_file()) else {
+        return Ok(None);
+    };
+    let modified_ns = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .and_then(|since| since.as_nanos().try_into().ok());
+    let crc32 = match key {
+        IncrementalKey::PathHash => Some(format!("{:08x}", crc32(Path::new(&input.path))?)),
+        _ => None,
+    };
+    Ok(Some(Fingerprint {
+        size: metadata.len(),
+        modified_ns,
+        crc32,
+    }))
+}
+
+fn crc32(path: &Path) -> io::Result<u32> {
+    let mut file = fs::File::open(path)?;
+    let mut crc = flate2::Crc::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        match file.read(&mut buf)? {
+            0 => return Ok(crc.sum()),
+            n => crc.update(&buf[..n]),
+        }
+    }
+}
+
+/// Records in `state`, the state of a run of `plan`, what `--incremental`
+/// found: the fingerprints of its inputs and the inputs left out.
+pub(crate) fn record(state: &mut RunState, plan: &Plan) {
+    let fingerprints = plan
+        .inputs
+        .iter()
+        .filter_map(|input| Some((input.path.as_str(), input.fingerprint.as_ref()?)))
+        .collect::<HashMap<_, _>>();
+    if !fingerprints.is_empty() {
+        for input in state.jobs.iter_mut().flat_map(|job| &mut job.inputs) {
+            input.fingerprint = fingerprints.get(input.path.as_str()).map(|f| (*f).clone());
+        }
+    }
+    state.unchanged = plan.unchanged.clone();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn print(size: u64, modified_ns: Option<u64>, crc32: Option<&str>) -> Fingerprint {
+        Fingerprint {
+            size,
+            modified_ns,
+            crc32: crc32.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn keys_compare_what_they_say() {
+        let then = print(4, Some(10), Some("0000abcd"));
+        let touched = print(4, Some(20), Some("0000abcd"));
+        let rewritten = print(4, Some(10), Some("ffff0000"));
+        let grown = print(5, Some(10), Some("0000abcd"));
+        let cases = [
+            (IncrementalKey::Path, [true, true, true]),
+            (IncrementalKey::PathMtime, [false, true, false]),
+            (IncrementalKey::PathHash, [true, false, false]),
+        ];
+        for (key, expected) in cases {
+            let found =
+                [&touched, &rewritten, &grown].map(|now| key.unchanged(Some(&then), Some(now)));
+            assert_eq!(found, expected, "{:?}", key);
+            assert!(key.unchanged(Some(&then), Some(&then)), "{:?}", key);
+        }
+        // Tokens that are not files are compared by path.
+        assert!(IncrementalKey::PathMtime.unchanged(None, None));
+        // A run without --incremental recorded nothing to compare.
+        assert!(!IncrementalKey::PathMtime.unchanged(None, Some(&then)));
+        assert!(!IncrementalKey::PathHash.unchanged(Some(&print(4, None, None)), Some(&then)));
+        assert!(!IncrementalKey::PathMtime
+            .unchanged(Some(&print(4, None, None)), Some(&print(4, None, None))));
+    }
+}
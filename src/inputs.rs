@@ -0,0 +1,1070 @@
+//! Turning `--glob` patterns and `--input-list` files into the inputs of a
+//! run. [`crate::run`] uses the options of its command line; library
+//! callers can expand inputs the same way with their own [`InputOptions`].
+
+use crate::meta_cache::MetaCache;
+use crate::{escape, shellgen, BatchelorError, Interrupt};
+use glob::glob;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+/// How inputs are expanded, filtered and ordered. The default is what the
+/// command line does.
+#[derive(Clone, Debug, Default)]
+pub struct InputOptions {
+    pub canonicalize: Canonicalize,
+    /// Refuse literal inputs (not glob patterns, and lines of an input list)
+    /// that do not exist, instead of passing them on as given.
+    pub strict: bool,
+    /// Drop inputs whose path matches any of these patterns.
+    pub exclude: Vec<glob::Pattern>,
+    pub order: InputOrder,
+    /// Keep only the first of inputs with the same path.
+    pub dedup: bool,
+    /// Read each input's metadata into [`Input::metadata`].
+    pub metadata: bool,
+    pub glob_errors: GlobErrors,
+    pub on_missing: OnMissingInput,
+    /// Checked between inputs; expanding fails with
+    /// [`BatchelorError::Interrupted`] once it is interrupted.
+    pub interrupt: Interrupt,
+    /// How many glob patterns are expanded at a time; 0 is one per
+    /// pattern, up to [`DEFAULT_EXPAND_THREADS`]. The inputs, and which
+    /// error is reported, do not depend on it.
+    pub threads: usize,
+    /// How many threads resolve and stat the matched and listed inputs; 0
+    /// is [`DEFAULT_IO_THREADS`]. The inputs, and which error is reported,
+    /// do not depend on it.
+    pub io_threads: usize,
+    /// Where symlinks resolved before are looked up, and resolved ones
+    /// recorded, with [`Canonicalize::Existing`].
+    pub meta_cache: Option<Arc<MetaCache>>,
+}
+
+/// Most patterns expanded at a time without [`InputOptions::threads`].
+pub const DEFAULT_EXPAND_THREADS: usize = 8;
+
+/// Threads resolving inputs without [`InputOptions::io_threads`].
+pub const DEFAULT_IO_THREADS: usize = 8;
+
+/// Inputs a resolving thread takes at a time.
+const RESOLVE_CHUNK: usize = 64;
+
+/// What happens to entries a glob pattern cannot read, such as a directory
+/// without read permission.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum GlobErrors {
+    /// Skip them quietly; [`Expansion::skipped`] still lists them.
+    Skip,
+    /// Skip them; [`crate::run`] warns about each.
+    #[default]
+    Warn,
+    /// Fail the expansion.
+    Fail,
+}
+
+/// What happens to an input that is there when matched or listed but
+/// cannot be resolved or stat'ed: a dangling symlink, or a file removed in
+/// the meantime.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum OnMissingInput {
+    /// Drop it; [`Expansion::missing`] lists it.
+    Skip,
+    /// Keep it as matched or listed, without metadata; [`Expansion::missing`]
+    /// lists it.
+    KeepRaw,
+    /// Fail the expansion.
+    #[default]
+    Fail,
+}
+
+/// Which inputs are replaced by their canonical, absolute path.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Canonicalize {
+    /// Inputs that exist; other tokens are kept as given.
+    #[default]
+    Existing,
+    /// None: paths stay as matched or listed.
+    Never,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputOrder {
+    /// Sorted by path.
+    #[default]
+    Path,
+    /// In the order of the patterns and lines, each pattern's matches in
+    /// the order glob returns them.
+    Given,
+}
+
+/// One input of a run.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Input {
+    /// The pattern or input list line it came from.
+    pub token: String,
+    /// What the scripts get: the resolved path, or the token for inputs
+    /// that are not files. Non-UTF-8 paths are converted lossily.
+    pub path: String,
+    /// Where it came from: `--glob <pattern>` or `--input-list <file>`.
+    pub source: String,
+    /// With [`InputOptions::metadata`], for inputs that exist. Not
+    /// serialized; read back as `None`.
+    #[serde(skip)]
+    pub metadata: Option<fs::Metadata>,
+}
+
+/// The inputs of a run, with the entries that could not be read.
+#[derive(Clone, Debug, Default)]
+pub struct Expansion {
+    pub inputs: Vec<Input>,
+    /// Why each skipped entry was skipped, naming the pattern and the
+    /// entry (see [`GlobErrors`]).
+    pub skipped: Vec<String>,
+    /// Why each input that could not be resolved was skipped or kept as
+    /// given (see [`OnMissingInput`]).
+    pub missing: Vec<String>,
+}
+
+/// The inputs matched by `patterns`, each a glob pattern or a literal
+/// input.
+pub fn expand(patterns: &[String], options: &InputOptions) -> Result<Vec<Input>, BatchelorError> {
+    let mut listing = Listing::default();
+    expand_patterns(patterns, options, &mut listing)?;
+    Ok(finish(resolve_all(listing, options)?.inputs, options))
+}
+
+/// The inputs listed in `path`: one per line, blank lines and `#` comments
+/// skipped.
+pub fn read_list(path: &Path, options: &InputOptions) -> Result<Vec<Input>, BatchelorError> {
+    let mut listing = Listing::default();
+    read_input_list(path, options, &mut listing)?;
+    Ok(finish(resolve_all(listing, options)?.inputs, options))
+}
+
+/// The inputs of `patterns` and `input_list` together, as the command
+/// line collects them. Finding none is an error.
+pub fn collect(
+    patterns: &[String],
+    input_list: Option<&Path>,
+    options: &InputOptions,
+) -> Result<Vec<Input>, BatchelorError> {
+    Ok(collect_expansion(patterns, input_list, options)?.inputs)
+}
+
+/// Like [`collect`], also listing the entries skipped as
+/// [`InputOptions::glob_errors`] and [`InputOptions::on_missing`] say.
+pub fn collect_expansion(
+    patterns: &[String],
+    input_list: Option<&Path>,
+    options: &InputOptions,
+) -> Result<Expansion, BatchelorError> {
+    let mut listing = Listing::default();
+    expand_patterns(patterns, options, &mut listing)?;
+    if let Some(path) = input_list {
+        read_input_list(path, options, &mut listing)?;
+    }
+    let mut expansion = resolve_all(listing, options)?;
+    let (inputs, removed_by) = filter(std::mem::take(&mut expansion.inputs), options);
+    if inputs.is_empty() {
+        return Err(BatchelorError::NoInputs {
+            patterns: patterns.to_vec(),
+            input_list: input_list.map(Path::to_path_buf),
+            removed_by,
+        });
+    }
+    Ok(Expansion {
+        inputs,
+        ..expansion
+    })
+}
+
+/// Inputs as matched or listed, before they are resolved.
+#[derive(Debug, Default)]
+struct Listing {
+    pending: Vec<Pending>,
+    /// As [`Expansion::skipped`].
+    skipped: Vec<String>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    token: String,
+    path: PathBuf,
+    source: String,
+    /// Matched by a glob pattern, so it was there.
+    matched: bool,
+}
+
+/// Adds the inputs of `patterns` to `listing`, in pattern order. Up to
+/// [`InputOptions::threads`] patterns are expanded at a time, each into
+/// its own [`Listing`]; these are joined in pattern order, and the error
+/// reported is that of the first pattern that failed.
+fn expand_patterns(
+    patterns: &[String],
+    options: &InputOptions,
+    listing: &mut Listing,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let threads = match options.threads {
+        0 => DEFAULT_EXPAND_THREADS,
+        n => n,
+    };
+    let results = map_ordered(patterns, threads, 1, |pattern| {
+        let mut own = Listing::default();
+        expand_pattern(pattern, options, &mut own)
+            .map(|()| own)
+            // Typed errors survive the conversion; see `BatchelorError::from`.
+            .map_err(BatchelorError::from)
+    });
+    for own in results.into_iter().flatten() {
+        let own = own?;
+        listing.pending.extend(own.pending);
+        listing.skipped.extend(own.skipped);
+    }
+    Ok(())
+}
+
+/// Adds the inputs of one pattern to `listing`; entries that cannot be
+/// read are added to its `skipped` or fail it, as
+/// [`InputOptions::glob_errors`] says.
+fn expand_pattern(
+    pattern: &str,
+    options: &InputOptions,
+    listing: &mut Listing,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let source = format!("--glob {}", pattern);
+    if has_glob_meta(pattern) {
+        let entries = glob(pattern).map_err(|source| BatchelorError::GlobError {
+            pattern: pattern.to_string(),
+            source,
+        })?;
+        for entry in entries {
+            options.interrupt.check()?;
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    let message = format!(
+                        "{}: could not read {}: {}",
+                        source,
+                        e.path().display(),
+                        e.error()
+                    );
+                    if options.glob_errors == GlobErrors::Fail {
+                        return Err(format!("{} (--glob-errors warn skips it)", message).into());
+                    }
+                    log::debug!("skipping: {}", message);
+                    listing.skipped.push(message);
+                    continue;
+                }
+            };
+            listing.pending.push(Pending {
+                token: pattern.to_string(),
+                path: entry,
+                source: source.clone(),
+                matched: true,
+            });
+        }
+    } else {
+        let path = Path::new(pattern);
+        if options.strict && !path.exists() {
+            return Err(format!("{}: no such file", source).into());
+        }
+        listing.pending.push(Pending {
+            token: pattern.to_string(),
+            path: path.to_path_buf(),
+            source,
+            matched: false,
+        });
+    }
+    Ok(())
+}
+
+fn read_input_list(
+    path: &Path,
+    options: &InputOptions,
+    listing: &mut Listing,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("could not read --input-list {}: {}", path.display(), e))?;
+    let source = format!("--input-list {}", path.display());
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        options.interrupt.check()?;
+        // Lists written by batchelor encode newlines (see `escape`).
+        let line = escape::decode(line);
+        let input = Path::new(line.as_ref());
+        if options.strict && !input.exists() {
+            return Err(format!("{}: {}: no such file", source, line).into());
+        }
+        listing.pending.push(Pending {
+            token: line.to_string(),
+            path: input.to_path_buf(),
+            source: source.clone(),
+            matched: false,
+        });
+    }
+    Ok(())
+}
+
+/// Resolves the inputs of `listing` on up to [`InputOptions::io_threads`]
+/// threads, keeping their order; the error reported is that of the first
+/// input that failed.
+fn resolve_all(
+    listing: Listing,
+    options: &InputOptions,
+) -> Result<Expansion, Box<dyn std::error::Error>> {
+    let threads = match options.io_threads {
+        0 => DEFAULT_IO_THREADS,
+        n => n,
+    };
+    let resolver = Resolver::default();
+    let results = map_ordered(&listing.pending, threads, RESOLVE_CHUNK, |pending| {
+        options.interrupt.check()?;
+        resolve(pending, options, &resolver)
+    });
+    let mut expansion = Expansion {
+        skipped: listing.skipped,
+        ..Expansion::default()
+    };
+    for result in results.into_iter().flatten() {
+        let (input, missing) = result?;
+        expansion.inputs.extend(input);
+        expansion.missing.extend(missing);
+    }
+    Ok(expansion)
+}
+
+/// The input of `pending`, if any, and why it could not be resolved, if
+/// so. An input that is there (any glob match, which may since have
+/// vanished, or a listed path with a directory entry) but cannot be
+/// resolved or stat'ed is handled as [`InputOptions::on_missing`] says;
+/// other tokens are kept as given.
+///
+/// Each input is `lstat`ed once; that tells whether it is there and, for
+/// anything but a symlink, is its metadata. Only symlinks are resolved and
+/// stat'ed in full; other inputs are resolved through their directory
+/// (see [`Resolver`]). Symlinks [`InputOptions::meta_cache`] has are not
+/// resolved again.
+fn resolve(
+    pending: &Pending,
+    options: &InputOptions,
+    resolver: &Resolver,
+) -> Result<(Option<Input>, Option<String>), BatchelorError> {
+    let path = &pending.path;
+    let existing = options.canonicalize == Canonicalize::Existing;
+    let resolved = match fs_calls::symlink_metadata(path) {
+        Err(e) if pending.matched && (existing || options.metadata) => {
+            Err((if existing { "resolve" } else { "stat" }, e))
+        }
+        Err(_) => Ok((path.clone(), None, false)),
+        Ok(lstat) => {
+            let link = lstat.file_type().is_symlink();
+            let cache = options.meta_cache.as_deref().filter(|_| existing && link);
+            match cache.and_then(|cache| cache.get(path, &lstat)) {
+                Some((resolved, target)) => {
+                    Ok((resolved, options.metadata.then_some(target), true))
+                }
+                None => {
+                    let resolved = match (existing, link) {
+                        (true, true) => fs_calls::canonicalize(path),
+                        (true, false) => resolver.canonicalize(path),
+                        (false, _) => Ok(path.clone()),
+                    };
+                    resolved.map_err(|e| ("resolve", e)).and_then(|resolved| {
+                        // A symlink's target is stat'ed for the cache too.
+                        let target = match (link, options.metadata) {
+                            (true, true) => {
+                                Some(fs_calls::metadata(&resolved).map_err(|e| ("stat", e))?)
+                            }
+                            (true, false) if cache.is_some() => fs_calls::metadata(&resolved).ok(),
+                            _ => None,
+                        };
+                        if let (Some(cache), Some(target)) = (cache, &target) {
+                            cache.insert(path, &lstat, &resolved, target);
+                        }
+                        Ok((
+                            resolved,
+                            options.metadata.then(|| target.unwrap_or(lstat)),
+                            existing,
+                        ))
+                    })
+                }
+            }
+        }
+    };
+    let input = |resolved: &Path, metadata| Input {
+        token: pending.token.clone(),
+        path: resolved.to_string_lossy().into_owned(),
+        source: pending.source.clone(),
+        metadata,
+    };
+    match resolved {
+        Ok((resolved, metadata, canonical)) => {
+            let mut input = input(&resolved, metadata);
+            // Canonical paths on Windows are verbatim (`\\?\C:\...`).
+            if canonical && cfg!(windows) {
+                input.path = shellgen::script_path(&input.path).into_owned();
+            }
+            Ok((Some(input), None))
+        }
+        Err((what, e)) => {
+            let message = format!(
+                "{}: could not {} {}: {}",
+                pending.source,
+                what,
+                path.display(),
+                e
+            );
+            match options.on_missing {
+                OnMissingInput::Fail => Err(format!(
+                    "{} (--on-missing-input skip drops it, keep-raw passes it on as is)",
+                    message
+                )
+                .into()),
+                OnMissingInput::Skip => {
+                    log::debug!("skipping: {}", message);
+                    Ok((None, Some(message)))
+                }
+                OnMissingInput::KeepRaw => {
+                    log::debug!("keeping as is: {}", message);
+                    Ok((Some(input(path, None)), Some(message)))
+                }
+            }
+        }
+    }
+}
+
+/// Resolves paths that are not symlinks themselves through the canonical
+/// path of their directory, which it looks up once per directory: the
+/// inputs of a directory of thousands cost one `realpath` between them,
+/// however many threads resolve them.
+#[derive(Debug, Default)]
+struct Resolver {
+    /// `None` for directories that could not be resolved.
+    dirs: Mutex<HashMap<PathBuf, Arc<OnceLock<Option<PathBuf>>>>>,
+}
+
+impl Resolver {
+    /// The canonical path of `path`, which must not be a symlink.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let (Some(dir), Some(Component::Normal(name))) =
+            (path.parent(), path.components().next_back())
+        else {
+            return fs_calls::canonicalize(path);
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let once = self
+            .dirs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(dir.to_path_buf())
+            .or_default()
+            .clone();
+        // Other threads wanting the same directory wait for this lookup.
+        match once.get_or_init(|| fs_calls::canonicalize(dir).ok()) {
+            Some(canonical) => Ok(canonical.join(name)),
+            // The error is the one of the path itself.
+            None => fs_calls::canonicalize(path),
+        }
+    }
+}
+
+/// The file system calls that resolve inputs. Tests record them, to check
+/// what each input costs.
+pub(crate) mod fs_calls {
+    use std::fs;
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    pub(crate) fn symlink_metadata(path: &Path) -> io::Result<fs::Metadata> {
+        record("lstat", path);
+        fs::symlink_metadata(path)
+    }
+
+    pub(crate) fn metadata(path: &Path) -> io::Result<fs::Metadata> {
+        record("stat", path);
+        fs::metadata(path)
+    }
+
+    pub(crate) fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+        record("realpath", path);
+        fs::canonicalize(path)
+    }
+
+    #[cfg(test)]
+    static CALLS: std::sync::Mutex<Vec<(&str, PathBuf)>> = std::sync::Mutex::new(Vec::new());
+
+    #[cfg(test)]
+    fn record(call: &'static str, path: &Path) {
+        CALLS.lock().unwrap().push((call, path.to_path_buf()));
+    }
+
+    #[cfg(not(test))]
+    fn record(_: &'static str, _: &Path) {}
+
+    /// The calls made so far on paths under `root`, which tests running
+    /// at the same time do not share, e.g. `("lstat", "<root>/a.fq")`.
+    #[cfg(test)]
+    pub(crate) fn under(root: &Path) -> Vec<(&'static str, PathBuf)> {
+        CALLS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, path)| path.starts_with(root))
+            .cloned()
+            .collect()
+    }
+}
+
+/// `f` of each of `items`, in order, computed on up to `threads` threads
+/// taking `chunk` items at a time. Once `f` fails for an item, later ones
+/// are no longer started and come back `None`; the earlier ones all
+/// complete, so the first failure is the same however many threads ran.
+pub(crate) fn map_ordered<T, R, E>(
+    items: &[T],
+    threads: usize,
+    chunk: usize,
+    f: impl Fn(&T) -> Result<R, E> + Sync,
+) -> Vec<Option<Result<R, E>>>
+where
+    T: Sync,
+    R: Send,
+    E: Send,
+{
+    let threads = threads.min(items.len().div_ceil(chunk));
+    let mut results: Vec<Option<Result<R, E>>> = Vec::with_capacity(items.len());
+    if threads <= 1 {
+        for item in items {
+            let result = f(item);
+            let failed = result.is_err();
+            results.push(Some(result));
+            if failed {
+                break;
+            }
+        }
+        results.resize_with(items.len(), || None);
+        return results;
+    }
+
+    let next = AtomicUsize::new(0);
+    let first_failed = AtomicUsize::new(usize::MAX);
+    let work = || {
+        let mut done = Vec::new();
+        loop {
+            let start = next.fetch_add(chunk, Ordering::Relaxed);
+            if start >= items.len() || start > first_failed.load(Ordering::Relaxed) {
+                return done;
+            }
+            let mut part = Vec::with_capacity(chunk);
+            for (index, item) in items.iter().enumerate().skip(start).take(chunk) {
+                let result = f(item);
+                let failed = result.is_err();
+                part.push(result);
+                if failed {
+                    first_failed.fetch_min(index, Ordering::Relaxed);
+                    break;
+                }
+            }
+            done.push((start, part));
+        }
+    };
+    let done = thread::scope(|scope| {
+        let workers = (0..threads).map(|_| scope.spawn(work)).collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e))
+            })
+            .collect::<Vec<_>>()
+    });
+    results.resize_with(items.len(), || None);
+    for (start, part) in done {
+        for (offset, result) in part.into_iter().enumerate() {
+            results[start + offset] = Some(result);
+        }
+    }
+    results
+}
+
+/// Applies the filters, order and deduplication of `options`.
+fn finish(inputs: Vec<Input>, options: &InputOptions) -> Vec<Input> {
+    filter(inputs, options).0
+}
+
+/// Filters and orders the matched inputs. When a filter drops the last of
+/// them, the second value says which one did.
+fn filter(mut inputs: Vec<Input>, options: &InputOptions) -> (Vec<Input>, Option<String>) {
+    let mut removed_by = None;
+    for pattern in &options.exclude {
+        let before = inputs.len();
+        inputs.retain(|input| !pattern.matches(&input.path));
+        log::debug!(
+            "exclude {} dropped {} input(s)",
+            pattern,
+            before - inputs.len()
+        );
+        if before > 0 && inputs.is_empty() {
+            removed_by = Some(format!(
+                "exclude pattern {} removed the last {}",
+                pattern, before
+            ));
+        }
+    }
+    if options.order == InputOrder::Path {
+        // Stable, so duplicates keep the source they were found by first.
+        inputs.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+    if options.dedup {
+        let before = inputs.len();
+        let mut seen = HashSet::new();
+        inputs.retain(|input| seen.insert(input.path.clone()));
+        log::debug!("dedup dropped {} input(s)", before - inputs.len());
+    }
+    (inputs, removed_by)
+}
+
+fn has_glob_meta(s: &str) -> bool {
+    s.contains('*') || s.contains('?') || s.contains('[')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory holding `b.fq`, `a.fq`, `c.txt` and `sub/d.fq`, by its
+    /// canonical path.
+    fn tree() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        for name in ["b.fq", "a.fq", "c.txt", "sub/d.fq"] {
+            let path = root.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, name).unwrap();
+        }
+        (dir, root)
+    }
+
+    fn pattern(root: &Path, pattern: &str) -> String {
+        root.join(pattern).to_string_lossy().into_owned()
+    }
+
+    /// The paths of `inputs` relative to `root`.
+    fn names(root: &Path, inputs: &[Input]) -> Vec<String> {
+        inputs
+            .iter()
+            .map(|input| {
+                Path::new(&input.path)
+                    .strip_prefix(root)
+                    .map_or(input.path.clone(), |p| p.to_string_lossy().into_owned())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn inputs_are_sorted_by_path_by_default() {
+        let (_dir, root) = tree();
+        let patterns = [pattern(&root, "sub/*.fq"), pattern(&root, "*.fq")];
+        let inputs = expand(&patterns, &InputOptions::default()).unwrap();
+        assert_eq!(names(&root, &inputs), ["a.fq", "b.fq", "sub/d.fq"]);
+        assert_eq!(inputs[2].token, patterns[0]);
+        assert_eq!(inputs[2].source, format!("--glob {}", patterns[0]));
+    }
+
+    #[test]
+    fn given_order_keeps_the_pattern_order() {
+        let (_dir, root) = tree();
+        let patterns = [
+            pattern(&root, "sub/*.fq"),
+            pattern(&root, "c.txt"),
+            pattern(&root, "*.fq"),
+        ];
+        let options = InputOptions {
+            order: InputOrder::Given,
+            ..InputOptions::default()
+        };
+        let inputs = expand(&patterns, &options).unwrap();
+        assert_eq!(names(&root, &inputs), ["sub/d.fq", "c.txt", "a.fq", "b.fq"]);
+    }
+
+    #[test]
+    fn dedup_keeps_the_first_of_each_path() {
+        let (_dir, root) = tree();
+        let patterns = [
+            pattern(&root, "a.fq"),
+            pattern(&root, "*.fq"),
+            pattern(&root, "./a.fq"),
+        ];
+        let inputs = expand(&patterns, &InputOptions::default()).unwrap();
+        assert_eq!(names(&root, &inputs), ["a.fq", "a.fq", "a.fq", "b.fq"]);
+        for order in [InputOrder::Path, InputOrder::Given] {
+            let options = InputOptions {
+                dedup: true,
+                order,
+                ..InputOptions::default()
+            };
+            let inputs = expand(&patterns, &options).unwrap();
+            assert_eq!(names(&root, &inputs), ["a.fq", "b.fq"], "{:?}", order);
+            assert_eq!(inputs[0].token, patterns[0]);
+        }
+    }
+
+    #[test]
+    fn exclude_drops_matching_paths() {
+        let (_dir, root) = tree();
+        let options = InputOptions {
+            exclude: vec![glob::Pattern::new("*/sub/*").unwrap()],
+            ..InputOptions::default()
+        };
+        let inputs = expand(&[pattern(&root, "**/*.fq")], &options).unwrap();
+        assert_eq!(names(&root, &inputs), ["a.fq", "b.fq"]);
+    }
+
+    #[test]
+    fn excluding_every_input_names_the_pattern() {
+        let (_dir, root) = tree();
+        let options = InputOptions {
+            exclude: vec![glob::Pattern::new("*.fq").unwrap()],
+            ..InputOptions::default()
+        };
+        let patterns = [pattern(&root, "*.fq")];
+        match collect(&patterns, None, &options).unwrap_err() {
+            BatchelorError::NoInputs {
+                patterns: reported,
+                removed_by,
+                ..
+            } => {
+                assert_eq!(reported, patterns);
+                assert_eq!(
+                    removed_by.as_deref(),
+                    Some("exclude pattern *.fq removed the last 2")
+                );
+            }
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn nothing_matched_is_no_inputs() {
+        let (_dir, root) = tree();
+        let e = collect(&[pattern(&root, "*.bam")], None, &InputOptions::default()).unwrap_err();
+        assert!(
+            matches!(
+                e,
+                BatchelorError::NoInputs {
+                    removed_by: None,
+                    ..
+                }
+            ),
+            "{:?}",
+            e
+        );
+        assert_eq!(e.exit_code(), 3);
+    }
+
+    #[test]
+    fn missing_literals_pass_through_unless_strict() {
+        let (_dir, root) = tree();
+        let missing = pattern(&root, "missing.fq");
+        let inputs = expand(std::slice::from_ref(&missing), &InputOptions::default()).unwrap();
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].path, missing);
+        assert!(inputs[0].metadata.is_none());
+
+        let strict = InputOptions {
+            strict: true,
+            ..InputOptions::default()
+        };
+        let e = expand(std::slice::from_ref(&missing), &strict).unwrap_err();
+        assert_eq!(e.to_string(), format!("--glob {}: no such file", missing));
+        // Patterns matching nothing are not literals.
+        assert!(expand(&[pattern(&root, "*.bam")], &strict)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn input_lists_skip_blanks_and_comments() {
+        let (_dir, root) = tree();
+        let list = root.join("inputs.txt");
+        fs::write(
+            &list,
+            format!(
+                "# inputs\n\n  {}  \n{}\nmissing.fq\n",
+                pattern(&root, "b.fq"),
+                pattern(&root, "a.fq")
+            ),
+        )
+        .unwrap();
+        let options = InputOptions {
+            order: InputOrder::Given,
+            ..InputOptions::default()
+        };
+        let inputs = read_list(&list, &options).unwrap();
+        assert_eq!(names(&root, &inputs), ["b.fq", "a.fq", "missing.fq"]);
+        assert_eq!(inputs[0].source, format!("--input-list {}", list.display()));
+
+        let strict = InputOptions {
+            strict: true,
+            ..options
+        };
+        let e = read_list(&list, &strict).unwrap_err();
+        assert!(e.to_string().ends_with("missing.fq: no such file"), "{}", e);
+    }
+
+    #[test]
+    fn collect_joins_patterns_and_the_list() {
+        let (_dir, root) = tree();
+        let list = root.join("inputs.txt");
+        fs::write(&list, pattern(&root, "c.txt")).unwrap();
+        let inputs = collect(
+            &[pattern(&root, "*.fq")],
+            Some(&list),
+            &InputOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(names(&root, &inputs), ["a.fq", "b.fq", "c.txt"]);
+    }
+
+    #[test]
+    fn canonicalize_and_metadata() {
+        let (_dir, root) = tree();
+        let relative = root.join("sub/../a.fq").to_string_lossy().into_owned();
+        let inputs = expand(std::slice::from_ref(&relative), &InputOptions::default()).unwrap();
+        assert_eq!(inputs[0].path, pattern(&root, "a.fq"));
+        assert!(inputs[0].metadata.is_none());
+
+        let options = InputOptions {
+            canonicalize: Canonicalize::Never,
+            metadata: true,
+            ..InputOptions::default()
+        };
+        let inputs = expand(std::slice::from_ref(&relative), &options).unwrap();
+        assert_eq!(inputs[0].path, relative);
+        assert_eq!(inputs[0].metadata.as_ref().unwrap().len(), 4);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dangling_symlinks_follow_on_missing_input() {
+        let (_dir, root) = tree();
+        let gone = root.join("gone.fq");
+        std::os::unix::fs::symlink(root.join("nowhere.fq"), &gone).unwrap();
+        let list = root.join("inputs.txt");
+        fs::write(&list, format!("{}\n", gone.display())).unwrap();
+        let patterns = [pattern(&root, "*.fq")];
+        let options = |on_missing| InputOptions {
+            on_missing,
+            ..InputOptions::default()
+        };
+
+        let e = collect(&patterns, None, &options(OnMissingInput::Fail)).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "--glob {}: could not resolve {}: No such file or directory (os error 2) \
+                 (--on-missing-input skip drops it, keep-raw passes it on as is)",
+                patterns[0],
+                gone.display()
+            )
+        );
+        let e = collect(&[], Some(&list), &options(OnMissingInput::Fail)).unwrap_err();
+        assert!(e.to_string().starts_with(&format!(
+            "--input-list {}: could not resolve {}: ",
+            list.display(),
+            gone.display()
+        )));
+
+        let skip = collect_expansion(&patterns, None, &options(OnMissingInput::Skip)).unwrap();
+        assert_eq!(names(&root, &skip.inputs), ["a.fq", "b.fq"]);
+        assert_eq!(skip.missing.len(), 1);
+        assert!(skip.missing[0].contains(&format!("could not resolve {}", gone.display())));
+
+        let keep = collect_expansion(&patterns, None, &options(OnMissingInput::KeepRaw)).unwrap();
+        assert_eq!(names(&root, &keep.inputs), ["a.fq", "b.fq", "gone.fq"]);
+        assert_eq!(keep.inputs[2].path, gone.to_string_lossy());
+        assert!(keep.inputs[2].metadata.is_none());
+        assert_eq!(keep.missing, skip.missing);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dangling_symlinks_that_cannot_be_stated_follow_on_missing_input() {
+        let (_dir, root) = tree();
+        let gone = root.join("gone.fq");
+        std::os::unix::fs::symlink(root.join("nowhere.fq"), &gone).unwrap();
+        let patterns = [pattern(&root, "*.fq")];
+        // Not resolved, but stat'ed for the size and mtime filters.
+        let options = |on_missing| InputOptions {
+            on_missing,
+            canonicalize: Canonicalize::Never,
+            metadata: true,
+            ..InputOptions::default()
+        };
+
+        let e = collect(&patterns, None, &options(OnMissingInput::Fail)).unwrap_err();
+        assert!(
+            e.to_string()
+                .contains(&format!("could not stat {}", gone.display())),
+            "{}",
+            e
+        );
+        let skip = collect_expansion(&patterns, None, &options(OnMissingInput::Skip)).unwrap();
+        assert_eq!(names(&root, &skip.inputs), ["a.fq", "b.fq"]);
+        assert_eq!(skip.missing.len(), 1);
+        let keep = collect_expansion(&patterns, None, &options(OnMissingInput::KeepRaw)).unwrap();
+        assert_eq!(names(&root, &keep.inputs), ["a.fq", "b.fq", "gone.fq"]);
+        assert!(keep.inputs[2].metadata.is_none());
+        assert!(keep
+            .inputs
+            .iter()
+            .take(2)
+            .all(|input| input.metadata.is_some()));
+    }
+
+    #[test]
+    fn patterns_expand_the_same_on_any_number_of_threads() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let mut patterns = Vec::new();
+        for d in 0..12 {
+            for f in 0..5 {
+                let path = root.join(format!("d{:02}/{}.fq", d, f));
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, "x").unwrap();
+            }
+            patterns.push(pattern(&root, &format!("d{:02}/*.fq", d)));
+        }
+        // Later patterns before earlier ones, so only the join keeps them
+        // in pattern order.
+        patterns.reverse();
+        let expand_on = |patterns: &[String], threads| {
+            let options = InputOptions {
+                order: InputOrder::Given,
+                threads,
+                ..InputOptions::default()
+            };
+            expand(patterns, &options).map(|inputs| {
+                inputs
+                    .into_iter()
+                    .map(|input| (input.token, input.path, input.source))
+                    .collect::<Vec<_>>()
+            })
+        };
+        let serial = expand_on(&patterns, 1).unwrap();
+        assert_eq!(serial.len(), 60);
+        assert!(serial[0].1.ends_with("d11/0.fq"), "{:?}", serial[0]);
+        for threads in [2, 8, 0] {
+            assert_eq!(
+                expand_on(&patterns, threads).unwrap(),
+                serial,
+                "{}",
+                threads
+            );
+        }
+
+        // Two bad patterns: the first in pattern order is reported.
+        patterns.insert(3, pattern(&root, "d03/[.fq"));
+        patterns.insert(9, pattern(&root, "d09/[.fq"));
+        for threads in [1, 8] {
+            let e = expand_on(&patterns, threads).unwrap_err();
+            assert!(
+                e.to_string()
+                    .starts_with(&format!("--glob {}: ", patterns[3])),
+                "{}",
+                e
+            );
+            assert_eq!(e.exit_code(), 2);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn each_input_is_stated_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let mut files = Vec::new();
+        for d in 0..3 {
+            fs::create_dir(root.join(format!("d{}", d))).unwrap();
+            for f in 0..40 {
+                let path = root.join(format!("d{}/{}.fq", d, f));
+                fs::write(&path, "x").unwrap();
+                files.push(path);
+            }
+        }
+        fs::create_dir(root.join("raw")).unwrap();
+        fs::write(root.join("raw/x.fq"), "xyz").unwrap();
+        let link = root.join("link.fq");
+        std::os::unix::fs::symlink(root.join("raw/x.fq"), &link).unwrap();
+
+        let options = InputOptions {
+            metadata: true,
+            io_threads: 4,
+            ..InputOptions::default()
+        };
+        let patterns = [pattern(&root, "d*/*.fq"), pattern(&root, "link.fq")];
+        let inputs = expand(&patterns, &options).unwrap();
+        assert_eq!(inputs.len(), 121);
+        assert!(inputs.iter().all(|input| input.metadata.is_some()));
+        let linked = inputs.iter().find(|i| i.token == patterns[1]).unwrap();
+        assert_eq!(linked.path, pattern(&root, "raw/x.fq"));
+        assert_eq!(linked.metadata.as_ref().unwrap().len(), 3);
+
+        let calls = fs_calls::under(&root);
+        let calls_on = |path: &Path| {
+            calls
+                .iter()
+                .filter(|(_, p)| p == path)
+                .map(|(call, _)| *call)
+                .collect::<Vec<_>>()
+        };
+        // The lstat that found each file is its metadata too.
+        for file in &files {
+            assert_eq!(calls_on(file), ["lstat"], "{}", file.display());
+        }
+        // One realpath per directory, shared by its forty inputs.
+        for d in 0..3 {
+            assert_eq!(calls_on(&root.join(format!("d{}", d))), ["realpath"]);
+        }
+        // Only the link is followed, and its target stat'ed.
+        assert_eq!(calls_on(&link), ["lstat", "realpath"]);
+        assert_eq!(calls_on(&root.join("raw/x.fq")), ["stat"]);
+        assert_eq!(calls.len(), 120 + 3 + 3);
+    }
+
+    #[test]
+    fn inputs_round_trip_without_metadata() {
+        let (_dir, root) = tree();
+        let options = InputOptions {
+            metadata: true,
+            ..InputOptions::default()
+        };
+        let inputs = expand(&[pattern(&root, "*.fq")], &options).unwrap();
+        assert!(inputs[0].metadata.is_some());
+        let json = serde_json::to_string(&inputs).unwrap();
+        let read: Vec<Input> = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.len(), 2);
+        for (read, input) in read.iter().zip(&inputs) {
+            assert_eq!(
+                (&read.token, &read.path, &read.source),
+                (&input.token, &input.path, &input.source)
+            );
+            assert!(read.metadata.is_none());
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! Stopping a run early, e.g. on Ctrl-C. A run checks its [`Interrupt`]
+//! between inputs, between batches and before each submission; it then
+//! records what it had done and ends with [`BatchelorError::Interrupted`].
+
+use crate::BatchelorError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Asks a run to stop. Clones share the flag, so a caller can keep one
+/// and hand the other to the run.
+#[derive(Clone, Debug, Default)]
+pub struct Interrupt(Arc<AtomicBool>);
+
+/// The interrupt signals are delivered to. Signal handlers are per
+/// process, so it is set once and later runs swap in their own interrupt.
+static SIGNAL_TARGET: Mutex<Option<Interrupt>> = Mutex::new(None);
+
+impl Interrupt {
+    pub fn new() -> Interrupt {
+        Interrupt::default()
+    }
+
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn check(&self) -> Result<(), BatchelorError> {
+        if self.is_interrupted() {
+            return Err(BatchelorError::Interrupted);
+        }
+        Ok(())
+    }
+
+    /// Interrupts on SIGINT (Ctrl-C) and SIGTERM from now on. A second
+    /// signal, for a run that does not stop in time, exits right away
+    /// with 130.
+    pub fn install_handler(&self) -> Result<(), BatchelorError> {
+        let mut target = SIGNAL_TARGET.lock().unwrap_or_else(|e| e.into_inner());
+        if target.replace(self.clone()).is_some() {
+            return Ok(());
+        }
+        ctrlc::set_handler(|| {
+            let target = SIGNAL_TARGET.lock().unwrap_or_else(|e| e.into_inner());
+            let Some(interrupt) = target.as_ref() else {
+                return;
+            };
+            if interrupt.is_interrupted() {
+                std::process::exit(130);
+            }
+            interrupt.interrupt();
+        })
+        .map_err(|e| BatchelorError::Other(Box::new(e)))
+    }
+}
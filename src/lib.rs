@@ -1,120 +1,3065 @@
-use clap::Parser;
-use glob::glob;
-use std::ffi::OsStr;
+#[cfg(feature = "cli")]
+use clap::{Parser, ValueEnum, ValueHint};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
 use std::fs;
-use std::io;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
 
-#[derive(Parser, Debug)]
-#[command(author, version, about = "Batch globbed inputs into submit jobs")]
+#[cfg(feature = "cli")]
+pub mod archive;
+pub mod builder;
+pub mod cancel;
+#[cfg(feature = "cli")]
+pub mod clean;
+#[cfg(feature = "cli")]
+pub mod completions;
+#[cfg(feature = "cli")]
+pub mod config;
+mod emit;
+pub mod error;
+pub mod escape;
+pub mod failures;
+#[cfg(feature = "cli")]
+pub mod history;
+mod hooks;
+pub mod incremental;
+pub mod inputs;
+pub mod interrupt;
+mod lock;
+#[cfg(feature = "cli")]
+pub mod logs;
+#[cfg(feature = "cli")]
+pub mod man;
+mod manifest;
+pub mod meta_cache;
+mod metrics;
+pub mod naming;
+mod output;
+pub mod overrides;
+pub mod perms;
+mod placeholder;
+pub mod plan;
+mod record;
+#[cfg(feature = "cli")]
+pub mod release;
+pub mod report;
+pub mod reporter;
+mod rerun;
+#[cfg(feature = "cli")]
+pub mod resubmit;
+pub mod rules;
+pub mod runs;
+mod safety;
+pub mod scheduler;
+pub mod script_format;
+pub mod selection;
+mod serde_path;
+pub mod shellgen;
+pub mod state;
+#[cfg(feature = "cli")]
+pub mod stats;
+#[cfg(feature = "cli")]
+pub mod status;
+mod submissions;
+pub mod submitter;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod units;
+pub mod wait;
+#[cfg(feature = "cli")]
+pub mod watch;
+pub mod which;
+
+#[cfg(feature = "cli")]
+pub use archive::{archive, ArchiveCli};
+pub use builder::CliBuilder;
+#[cfg(feature = "cli")]
+pub use cancel::{cancel, CancelCli};
+#[cfg(feature = "cli")]
+pub use clean::{clean, CleanCli};
+#[cfg(feature = "cli")]
+pub use completions::{completions, CompletionsCli};
+#[cfg(feature = "cli")]
+pub use config::{config, ConfigCli};
+pub use error::BatchelorError;
+#[cfg(feature = "cli")]
+pub use failures::{failures, FailuresCli};
+#[cfg(feature = "cli")]
+pub use history::{history, HistoryCli};
+pub use interrupt::Interrupt;
+#[cfg(feature = "cli")]
+pub use logs::{logs, LogsCli};
+#[cfg(feature = "cli")]
+pub use man::{man, ManCli};
+#[cfg(feature = "cli")]
+pub use release::{release, ReleaseCli};
+#[cfg(feature = "cli")]
+pub use resubmit::{resubmit, ResubmitCli};
+#[cfg(feature = "cli")]
+pub use stats::{stats, StatsCli};
+#[cfg(feature = "cli")]
+pub use status::{status, StatusCli};
+#[cfg(feature = "cli")]
+pub use watch::{watch, WatchCli};
+
+use cancel::{cancel_submitted, SubmittedJob};
+use emit::{EmitFormat, EmitJob};
+use incremental::IncrementalKey;
+use inputs::{Expansion, GlobErrors, Input, InputOptions, OnMissingInput};
+use manifest::{ManifestBatch, ManifestFormat};
+use meta_cache::MetaCache;
+use naming::JobNameFormat;
+use output::Output;
+use overrides::SubmitOverrides;
+use perms::Permissions;
+use plan::{JobSpec, Plan, PlanInput};
+use report::{ReportFormat, RunReport};
+use reporter::{ColorChoice, Quiet, Reporter, StreamReporter, Verbosity};
+use rules::{BatchStats, ResourceRule};
+use runs::{SubmitFailure, FAILED_INPUTS_FILE};
+use scheduler::{NotifyEvent, Scheduler};
+use selection::BatchSet;
+use state::{InputState, JobState, RunState};
+use submissions::Submissions;
+use submitter::{CommandSubmitter, Preflight, Submitted, Submitter};
+use wait::{WaitSummary, WaitedJob};
+
+#[cfg(feature = "cli")]
+const SUBCOMMAND_HELP: &str = "\
+Subcommands:
+  batchelor archive      Bundle a previous run's state, scripts and markers into a .tar.gz
+  batchelor cancel       Cancel the jobs of a previous run
+  batchelor clean        Remove the scripts, logs and state of old runs
+  batchelor completions  Print the shell completion script for batchelor
+  batchelor config       Show the config files of a run and the options they set
+  batchelor failures     Collect the failed inputs of a previous run into an input list
+  batchelor history      List the runs recorded in an output directory
+  batchelor logs         Print, follow or search the logs of a previous run
+  batchelor plan         Print the plan of a run as JSON without writing or submitting anything
+  batchelor release      Release the held jobs of a previous run
+  batchelor resubmit     Resubmit the failed batches of a previous run
+  batchelor stats        Summarize the resource usage of a previous run
+  batchelor status       Show the scheduler state of the jobs of a previous run
+  batchelor submit       Generate and submit the batches; the default without a subcommand
+  batchelor watch        Live view of a previous run's jobs, progress and logs";
+
+/// The whole command line: the options of a run and every subcommand.
+#[cfg(feature = "cli")]
+fn command_tree() -> clap::Command {
+    use clap::CommandFactory;
+    let subcommands = [
+        ("archive", ArchiveCli::command()),
+        ("cancel", CancelCli::command()),
+        ("clean", CleanCli::command()),
+        ("completions", CompletionsCli::command()),
+        ("config", ConfigCli::command()),
+        ("failures", FailuresCli::command()),
+        ("history", HistoryCli::command()),
+        ("logs", LogsCli::command()),
+        ("man", ManCli::command().hide(true)),
+        (
+            "plan",
+            config::command().about("Print the plan of a run as JSON without submitting"),
+        ),
+        ("release", ReleaseCli::command()),
+        ("resubmit", ResubmitCli::command()),
+        ("stats", StatsCli::command()),
+        ("status", StatusCli::command()),
+        (
+            "submit",
+            config::command().about("Generate and submit the batches (the default)"),
+        ),
+        ("watch", WatchCli::command()),
+    ];
+    config::command()
+        .subcommands(
+            subcommands
+                .into_iter()
+                .map(|(name, command)| command.name(name)),
+        )
+        .disable_help_subcommand(true)
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "cli", derive(Parser))]
+#[cfg_attr(feature = "cli", command(
+    author,
+    version,
+    about = "Batch globbed inputs into submit jobs",
+    after_help = SUBCOMMAND_HELP
+))]
 pub struct Cli {
     /// Path to the shell script to execute for each input file.
-    #[arg(long)]
+    #[cfg_attr(feature = "cli", arg(long, value_hint = ValueHint::FilePath))]
     script: PathBuf,
 
     /// One or more glob patterns or literal input tokens.
-    #[arg(long, num_args = 1..)]
+    #[cfg_attr(feature = "cli", arg(long, num_args = 1.., required_unless_present = "input_list"))]
     glob: Vec<String>,
 
+    /// File with one input per line (blank lines and `#` comments are
+    /// skipped), e.g. the failed_inputs.txt written by --wait or
+    /// `batchelor failures`. Combines
+    /// with --glob. %25, %0A, %0D and %09 stand for %, newline, carriage
+    /// return and tab, as batchelor writes them.
+    #[cfg_attr(feature = "cli", arg(long))]
+    input_list: Option<PathBuf>,
+
+    /// What to do with entries a --glob pattern cannot read, such as a
+    /// directory without permission: skip them, skip them with a warning
+    /// each, or fail the run.
+    #[cfg_attr(feature = "cli", arg(long, value_enum, default_value = "warn"))]
+    glob_errors: GlobErrors,
+
+    /// What to do with an input that was matched or listed but cannot be
+    /// resolved or stat'ed, such as a dangling symlink or a file removed
+    /// meanwhile: skip it, keep it as given (both with a warning), or fail
+    /// the run.
+    #[cfg_attr(feature = "cli", arg(long, value_enum, default_value = "fail"))]
+    on_missing_input: OnMissingInput,
+
+    /// Expand up to N --glob patterns at a time, e.g. over slow network
+    /// file systems [default: one per pattern, up to 8]. The inputs and
+    /// their order are the same either way.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..)))]
+    expand_threads: Option<u16>,
+
+    /// Resolve and stat inputs on N threads, e.g. many inputs on a slow
+    /// network file system [default: 8]. The inputs and their order are
+    /// the same either way.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..)))]
+    io_threads: Option<u16>,
+
+    /// Reuse where the symlinks among the inputs led in earlier runs, kept
+    /// in <out-dir>/meta-cache.json, while the links and their targets are
+    /// unchanged (size, modification time, inode). Saves following links
+    /// on slow file systems; every input is still checked.
+    #[cfg_attr(feature = "cli", arg(long))]
+    meta_cache: bool,
+
+    /// Schedule only the inputs that are new since the latest run under
+    /// --out-dir, or changed since (see --incremental-key); the others are
+    /// counted and left out. The run's state records every input handled
+    /// so far, for the next --incremental run.
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with = "out_dir_timestamp"))]
+    incremental: bool,
+
+    /// What tells --incremental an input has not changed: its path; its
+    /// path, size and modification time; or its path, size and a CRC-32 of
+    /// its contents, which reads every input (on --io-threads threads).
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_enum,
+            default_value = "path+mtime",
+            requires = "incremental"
+        )
+    )]
+    incremental_key: IncrementalKey,
+
     /// Either a named flag (e.g. --input), a positional marker like $2,
-    /// or a template that contains $1 placeholders.
-    #[arg(long, default_value = "--input")]
+    /// or a template that contains $1 placeholders (${1} when followed by
+    /// a digit; $$ is a literal $). A template is split
+    /// into words like a shell would, and each word stays one literal
+    /// argument: redirections and pipes belong in the script.
+    #[cfg_attr(feature = "cli", arg(long, default_value = "--input"))]
     input_flag: String,
 
-    /// Number of output batch scripts/jobs to create.
-    #[arg(long, default_value_t = 1)]
-    batch: usize,
+    /// Flag your script takes a file listing inputs with, one per line,
+    /// e.g. --input-list. A batch whose command line would exceed
+    /// --max-command-bytes then calls the script once with that file;
+    /// without it, such a batch passes the inputs through xargs.
+    #[cfg_attr(feature = "cli", arg(long, allow_hyphen_values = true))]
+    input_list_flag: Option<String>,
+
+    /// Write the --input-flag template into the command lines as is, with
+    /// only the input substituted for $1 quoted, so it may hold pipes,
+    /// redirections or variables. Anything in the template runs as shell
+    /// code: never build it from untrusted text.
+    #[cfg_attr(feature = "cli", arg(long))]
+    raw_template: bool,
+
+    /// With a positional --input-flag past the end of the script args, such
+    /// as $5 with two, fill the missing slots with empty arguments.
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with = "clamp_positional"))]
+    pad_positional: bool,
+
+    /// With a positional --input-flag past the end of the script args, put
+    /// the inputs right after the last one.
+    #[cfg_attr(feature = "cli", arg(long))]
+    clamp_positional: bool,
+
+    /// Number of output batch scripts/jobs to create.
+    #[cfg_attr(feature = "cli", arg(long, default_value_t = 1))]
+    batch: usize,
+
+    /// What to do when --batch is more than the number of inputs: warn and
+    /// create one batch per input, fail, or pad with batches that have no
+    /// inputs and do nothing (for a fixed number of job slots).
+    #[cfg_attr(feature = "cli", arg(long, value_enum, default_value = "warn"))]
+    on_clamp: OnClamp,
+
+    /// Directory where generated batch scripts are stored, in a
+    /// subdirectory `runs/<run-id>` per run.
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, default_value = ".batchelor", value_hint = ValueHint::DirPath)
+    )]
+    out_dir: PathBuf,
+
+    /// ID of this run, naming its directory under <out-dir>/runs (default:
+    /// a timestamp plus a random suffix).
+    #[cfg_attr(feature = "cli", arg(long, value_parser = runs::parse_run_id))]
+    run_id: Option<String>,
+
+    /// Use a new directory per invocation: --out-dir with
+    /// -YYYYMMDD-HHMMSS appended. <out-dir>-latest links to the newest.
+    #[cfg_attr(feature = "cli", arg(long))]
+    out_dir_timestamp: bool,
+
+    /// Write scripts and markers directly into --out-dir, as before runs
+    /// had their own directories. The run state is still recorded under
+    /// <out-dir>/runs.
+    #[cfg_attr(feature = "cli", arg(long))]
+    flat_out_dir: bool,
+
+    /// When another run holds the lock on --out-dir, wait this long for it,
+    /// e.g. 30s or 10m, instead of failing right away.
+    #[cfg_attr(feature = "cli", arg(long, value_parser = units::parse_duration))]
+    wait_for_lock: Option<u64>,
+
+    /// Do not lock --out-dir while writing and submitting scripts, e.g.
+    /// when every run has its own directory anyway.
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with = "wait_for_lock"))]
+    no_lock: bool,
+
+    /// Octal mode of the generated scripts, e.g. 750 [default: 755 less
+    /// the umask]. Ignored on Windows.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "OCTAL", value_parser = perms::parse_mode))]
+    script_mode: Option<u32>,
+
+    /// Octal mode of the run state, manifest, report, input lists and
+    /// failed input list, e.g. 640; directories the run creates get it plus
+    /// search permission [default: 644 less the umask]. Ignored on Windows.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "OCTAL", value_parser = perms::parse_mode))]
+    artifact_mode: Option<u32>,
+
+    /// Group (name or ID) to give the scripts, files and directories the
+    /// run writes. Ignored on Windows.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "NAME"))]
+    group: Option<String>,
+
+    /// Submission command, e.g. "sbatch --mem=50G --mincpus 1" or "bash".
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, default_value = "sbatch", value_hint = ValueHint::CommandString)
+    )]
+    submit: String,
+
+    /// Prefix for generated job names: letters, digits, '.', '_' and '-'.
+    /// Other characters are replaced with _, or refused with
+    /// --strict-names.
+    #[cfg_attr(feature = "cli", arg(long, default_value = "batch"))]
+    job_name_prefix: String,
+
+    /// Template for job names, which also name the scripts: {prefix},
+    /// {index} (zero-padded with e.g. {index:05}), {total}, {date}
+    /// (YYYYMMDD), {time} (HHMMSS) and, with one input per batch, {stem};
+    /// {{ and }} are literal braces. Characters other than letters, digits,
+    /// '.', '_' and '-' are replaced with _.
+    #[cfg_attr(feature = "cli", arg(long, default_value = naming::DEFAULT_JOB_NAME_FORMAT))]
+    job_name_format: JobNameFormat,
+
+    /// Refuse job names that would have to be sanitized or truncated to
+    /// suit the scheduler, instead of warning.
+    #[cfg_attr(feature = "cli", arg(long))]
+    strict_names: bool,
+
+    /// Check the script with `bash -n` before anything is generated.
+    /// Scripts whose shebang names another interpreter are not checked.
+    #[cfg_attr(feature = "cli", arg(long))]
+    check_script: bool,
+
+    /// An additional arg passed to your script for each invocation
+    /// (repeatable, one value each, e.g. --script-args --threads
+    /// --script-args 4). Args after `--` are passed too, after these.
+    #[cfg_attr(feature = "cli", arg(long, allow_hyphen_values = true))]
+    script_args: Vec<String>,
+
+    /// Args after `--`, passed to your script as they are; parsing appends
+    /// them to `script_args`.
+    #[cfg_attr(feature = "cli", arg(last = true, value_name = "SCRIPT_ARGS"))]
+    #[cfg_attr(not(feature = "cli"), allow(dead_code))]
+    trailing_args: Vec<String>,
+
+    /// Print what would be submitted without running the submit command.
+    /// Preview scripts go to <out-dir>/dry-run/<run-id>; nothing a real
+    /// run wrote is changed.
+    #[cfg_attr(feature = "cli", arg(long))]
+    dry_run: bool,
+
+    /// With --dry-run, write no preview scripts (or anything else under
+    /// --out-dir).
+    #[cfg_attr(feature = "cli", arg(long, requires = "dry_run"))]
+    no_write: bool,
+
+    /// Keep generated intermediate batch scripts after successful submission.
+    /// --no-clean is the same: a run removes nothing else.
+    #[cfg_attr(feature = "cli", arg(long, visible_alias = "no-clean"))]
+    keep: bool,
+
+    /// Only clean up: remove the scripts, logs and state of the earlier
+    /// finished runs under --out-dir, like `batchelor clean --older-than 0`
+    /// (--dry-run lists them, --force-clean applies), and exit without
+    /// expanding inputs or submitting.
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with_all = ["keep", "plan_json", "emit"]))]
+    clean_only: bool,
+
+    /// Rewrite batch scripts and input lists that already hold exactly
+    /// what would be written. Without it they are left as they are (mtime
+    /// included), and cleanup does not remove them.
+    #[cfg_attr(feature = "cli", arg(long))]
+    force_rewrite: bool,
+
+    /// Fail the run when a submitted batch's script cannot be removed,
+    /// instead of warning and carrying on.
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with = "keep"))]
+    clean_strict: bool,
+
+    /// Remove submitted scripts even when they are in the current
+    /// directory, a repository root or the home directory, where cleanup is
+    /// otherwise skipped.
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with = "keep"))]
+    force_clean: bool,
+
+    /// Call script once per batch with all inputs instead of once per input.
+    #[cfg_attr(feature = "cli", arg(long))]
+    multi_input: bool,
+
+    /// Longest command line (or --wrap command) to write, e.g. 64K. A
+    /// batch that would need a longer one writes its inputs to a file and
+    /// reads them from there (see --input-list-flag); with --wrap, which
+    /// writes no files, it is an error.
+    #[cfg_attr(feature = "cli", arg(long, default_value = "128K", value_parser = units::parse_size))]
+    max_command_bytes: u64,
+
+    /// Submit each batch with `sbatch --wrap` instead of writing a batch script.
+    #[cfg_attr(feature = "cli", arg(long))]
+    wrap: bool,
+
+    /// Do not pass the generated job name to the scheduler.
+    #[cfg_attr(feature = "cli", arg(long))]
+    no_auto_job_name: bool,
+
+    /// Submit every batch under the same job name (--job-name-prefix) with
+    /// --dependency=singleton, so only one of them runs at a time and a
+    /// failed batch does not block the rest. squeue shows the same name for
+    /// all of them; script names and recorded job IDs stay per batch.
+    /// SLURM only.
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with = "no_auto_job_name"))]
+    singleton: bool,
+
+    /// Directory for scheduler stdout/stderr logs (created if missing).
+    #[cfg_attr(feature = "cli", arg(long))]
+    job_log_dir: Option<PathBuf>,
+
+    /// Log file name inside --job-log-dir. {job_name} and {batch_index} are
+    /// expanded by batchelor ({{ and }} are literal braces, other
+    /// placeholders an error), scheduler patterns like %j are left as-is.
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, default_value = "{job_name}.%j.out", requires = "job_log_dir")
+    )]
+    job_log_template: String,
+
+    /// Write stderr to a separate .err file next to the stdout log.
+    #[cfg_attr(feature = "cli", arg(long, requires = "job_log_dir"))]
+    split_stderr: bool,
+
+    /// If a submission fails or Ctrl-C is pressed, cancel the jobs already
+    /// submitted by this run.
+    #[cfg_attr(feature = "cli", arg(long))]
+    cancel_on_failure: bool,
+
+    /// Keep submitting the remaining batches when a submission fails. Failed
+    /// batches keep their scripts and are listed in the run's failures.tsv
+    /// for `batchelor resubmit`; the run still exits nonzero.
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with = "cancel_on_failure"))]
+    keep_going: bool,
+
+    /// With --keep-going, stop submitting after more than N failures.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "N", requires = "keep_going"))]
+    max_submit_failures: Option<usize>,
+
+    /// Submit up to N batches at a time. Results are still reported and
+    /// recorded in batch order.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..)))]
+    submit_parallel: u16,
+
+    /// Answer yes to confirmation prompts.
+    #[cfg_attr(feature = "cli", arg(long))]
+    yes: bool,
+
+    /// Show a summary of the run and ask before submitting anything.
+    #[cfg_attr(feature = "cli", arg(long))]
+    confirm: bool,
+
+    /// Before the first real submission, check that the scheduler accepts
+    /// the first job (on by default where the scheduler has a test-only
+    /// submit mode, e.g. sbatch --test-only; otherwise a held probe job is
+    /// submitted and cancelled).
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with = "no_preflight"))]
+    preflight: bool,
+
+    /// Skip the preflight check.
+    #[cfg_attr(feature = "cli", arg(long))]
+    no_preflight: bool,
+
+    /// Ask before submitting more than this many jobs when stdout is a
+    /// terminal, as if --confirm was given.
+    #[cfg_attr(feature = "cli", arg(long, default_value_t = 50))]
+    confirm_above: usize,
+
+    /// Memory to request per input byte of a batch, added to --mem-base
+    /// (e.g. 2.5 requests 2.5x the batch's total input size).
+    #[cfg_attr(feature = "cli", arg(long))]
+    mem_per_byte: Option<f64>,
+
+    /// Fixed memory requested for every batch, e.g. 4G.
+    #[cfg_attr(feature = "cli", arg(long, value_parser = units::parse_size))]
+    mem_base: Option<u64>,
+
+    /// Upper limit for the computed memory request, e.g. 500G.
+    #[cfg_attr(feature = "cli", arg(long, value_parser = units::parse_size))]
+    mem_cap: Option<u64>,
+
+    /// Wall-clock seconds to request per input byte of a batch, added to
+    /// --time-base (e.g. 1e-8 is 10s per GB).
+    #[cfg_attr(feature = "cli", arg(long))]
+    time_per_byte: Option<f64>,
+
+    /// Fixed wall-clock time requested for every batch, e.g. 30m or 01:00:00.
+    #[cfg_attr(feature = "cli", arg(long, value_parser = units::parse_duration))]
+    time_base: Option<u64>,
+
+    /// Upper limit for the computed time request, e.g. 2-00:00:00.
+    #[cfg_attr(feature = "cli", arg(long, value_parser = units::parse_duration))]
+    time_cap: Option<u64>,
+
+    /// Submit every job in held state; start them with `batchelor release`.
+    #[cfg_attr(feature = "cli", arg(long))]
+    hold: bool,
+
+    /// Scheduler to generate directives and flags for (default: guessed from
+    /// --submit; needed to tell SGE's qsub from PBS's).
+    #[cfg_attr(feature = "cli", arg(long, value_enum))]
+    scheduler: Option<Scheduler>,
+
+    /// Mail address for scheduler notifications.
+    #[cfg_attr(feature = "cli", arg(long))]
+    notify: Option<String>,
+
+    /// Events that trigger a --notify mail (comma-separated).
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_enum,
+            value_delimiter = ',',
+            default_value = "end,fail",
+            requires = "notify"
+        )
+    )]
+    notify_on: Vec<NotifyEvent>,
+
+    /// Send one mail for the whole run instead of one per job: attach the
+    /// notification to the last batch only, or to a no-op job that runs
+    /// after every batch has ended (the default).
+    #[cfg_attr(feature = "cli", arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        default_missing_value = "sentinel",
+        requires = "notify"
+    ))]
+    notify_once: Option<NotifyOnce>,
+
+    /// Feed each batch script to the submit command on stdin instead of
+    /// passing its path as an argument (bsub, some site wrappers).
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with = "wrap"))]
+    submit_stdin: bool,
+
+    /// Kill a submit command still running after this long, e.g. 2m, and
+    /// count the batch as not submitted.
+    #[cfg_attr(feature = "cli", arg(long, value_parser = units::parse_duration))]
+    submit_timeout: Option<u64>,
+
+    /// Record each submission as a JSON file in this directory (argv, script
+    /// and its contents) and fabricate job IDs instead of running --submit.
+    #[cfg_attr(feature = "cli", arg(long, env = "BATCHELOR_SUBMIT_RECORD"))]
+    submit_record: Option<PathBuf>,
+
+    /// Do not check that the --submit program exists before generating
+    /// scripts (e.g. when it only exists on another host).
+    #[cfg_attr(feature = "cli", arg(long))]
+    skip_submit_check: bool,
+
+    /// Generate every batch script but submit only these batches, e.g. 7,
+    /// 1-3,9 or 30-. Scripts of the other batches are kept.
+    #[cfg_attr(feature = "cli", arg(long))]
+    only_batch: Option<BatchSet>,
+
+    /// TOML file mapping batches to extra submit arguments or a replacement
+    /// submit string, e.g. `[overrides]` then `"5-8" = "--partition=bigmem"`
+    /// or `"9" = { submit = "sbatch -p gpu" }`.
+    #[cfg_attr(feature = "cli", arg(long))]
+    submit_overrides: Option<PathBuf>,
+
+    /// Add submit arguments to batches matching a condition over size
+    /// (total input bytes), count (inputs) and max_size (largest input),
+    /// e.g. 'size>200G => --partition=bigmem --mem=400G'. Conditions can be
+    /// joined with &&. Repeatable; every matching rule applies, in order.
+    #[cfg_attr(feature = "cli", arg(long = "resource-rule", value_name = "RULE"))]
+    resource_rules: Vec<ResourceRule>,
+
+    /// Also write the end-of-run submission report to this file.
+    #[cfg_attr(feature = "cli", arg(long))]
+    report: Option<PathBuf>,
+
+    /// Show progress bars while generating scripts and submitting (the
+    /// default when stderr is a terminal).
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with = "no_progress"))]
+    progress: bool,
+
+    /// Print plain progress lines instead of progress bars.
+    #[cfg_attr(feature = "cli", arg(long))]
+    no_progress: bool,
+
+    /// Format of the --report file.
+    #[cfg_attr(feature = "cli", arg(long, value_enum, default_value = "tsv"))]
+    report_format: ReportFormat,
+
+    /// Write which inputs went into which batch to this file, right after
+    /// generating the scripts (also with --dry-run).
+    #[cfg_attr(feature = "cli", arg(long))]
+    manifest: Option<PathBuf>,
+
+    /// Format of the --manifest file.
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, value_enum, default_value = "tsv", requires = "manifest")
+    )]
+    manifest_format: ManifestFormat,
+
+    /// Write the computed plan (inputs, batches, script contents, submit
+    /// invocations) as JSON to this file, or `-` for stdout, and stop
+    /// without writing scripts or submitting.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "FILE"))]
+    plan_json: Option<PathBuf>,
+
+    /// With --plan-json, go on to write the scripts and submit.
+    #[cfg_attr(feature = "cli", arg(long, requires = "plan_json"))]
+    execute: bool,
+
+    /// How --dry-run shows the would-be submissions. json and ndjson print
+    /// job_name, script, submit_argv, input_count and inputs per
+    /// submission, and nothing else on stdout.
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, value_enum, default_value = "human", requires = "dry_run")
+    )]
+    output_format: OutputFormat,
+
+    /// Write the plan for another workflow tool to PATH instead of
+    /// generating scripts and submitting. FORMAT `make` writes a Makefile
+    /// with one target per input, `snakemake` a Snakefile with one rule per
+    /// batch; targets are stamp files under <out-dir>/stamps. `nextflow`
+    /// writes samplesheet.csv and a main.nf stub into the directory PATH.
+    #[cfg_attr(feature = "cli", arg(long, num_args = 2, value_names = ["FORMAT", "PATH"]))]
+    emit: Vec<String>,
+
+    /// With --emit snakemake, one rule per input instead of per batch.
+    #[cfg_attr(feature = "cli", arg(long, requires = "emit"))]
+    per_input: bool,
+
+    /// After submitting, wait for every job to finish, print a summary of
+    /// job states and exit codes, and write the inputs that failed to the
+    /// run's failed_inputs.txt. Exits 1 if any input failed, 2 if any job
+    /// failed as a whole (timed out, ran out of memory, ...).
+    #[cfg_attr(feature = "cli", arg(long))]
+    wait: bool,
+
+    /// How often --wait polls the scheduler, e.g. 30s or 5m.
+    #[cfg_attr(feature = "cli", arg(long, default_value = "30s", value_parser = units::parse_duration, requires = "wait"))]
+    wait_interval: u64,
+
+    /// Command --wait runs instead of sacct/squeue. It gets the
+    /// comma-separated job IDs as last argument and must print
+    /// `sacct --parsable2 -o JobID,State,Elapsed,ExitCode,NodeList` lines,
+    /// optionally followed by Start and End.
+    #[cfg_attr(feature = "cli", arg(long, requires = "wait"))]
+    status_command: Option<String>,
+
+    /// With --wait, warn about jobs pending longer than this, with the
+    /// scheduler's reason (e.g. ReqNodeNotAvail).
+    #[cfg_attr(feature = "cli", arg(long, default_value = "30m", value_parser = units::parse_duration, requires = "wait"))]
+    pending_warn: u64,
+
+    /// With --wait, fail the run when a job stays pending longer than this.
+    /// The jobs stay queued unless --cancel-on-failure is given.
+    #[cfg_attr(feature = "cli", arg(long, value_parser = units::parse_duration, requires = "wait"))]
+    pending_fail: Option<u64>,
+
+    /// With --wait, write Prometheus gauges for the run to FILE (e.g. for
+    /// node-exporter's textfile collector), replaced on every poll.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "FILE", requires = "wait"))]
+    metrics_out: Option<PathBuf>,
+
+    /// Command to run once the run has finished (after --wait, if given),
+    /// with the JSON run summary on stdin and BATCHELOR_RUN_ID,
+    /// BATCHELOR_STATUS, BATCHELOR_JOBS_FAILED, ... in its environment.
+    /// Its failure is reported but does not change the exit code.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "CMD"))]
+    on_complete: Option<String>,
+
+    /// Like --on-complete, but only runs when a submission or (with
+    /// --wait) a job failed.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "CMD"))]
+    on_failure: Option<String>,
+
+    /// POST the JSON run summary to this URL once the run has finished,
+    /// e.g. a Slack workflow webhook. Needs the `webhook` build feature.
+    #[cfg_attr(feature = "cli", arg(long, value_name = "URL"))]
+    webhook_url: Option<String>,
+
+    /// Take options from the `[profile.NAME]` tables of the config files,
+    /// over the rest of the files. Recorded in the run state.
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, value_name = "NAME", env = "BATCHELOR_PROFILE")
+    )]
+    profile: Option<String>,
+
+    /// Say more on stderr: -v for how many inputs each pattern and list
+    /// gave, what each batch holds and the argv of each submission, -vv for
+    /// every input as well.
+    #[cfg_attr(feature = "cli", arg(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet"))]
+    verbose: u8,
+
+    /// Print only errors and the final report: no status lines, warnings
+    /// or progress, and not what the submit command printed.
+    #[cfg_attr(feature = "cli", arg(long))]
+    quiet: bool,
+
+    /// Color markers, errors and the final report: on a terminal unless
+    /// NO_COLOR is set (auto), always or never. Output that is not to a
+    /// terminal is plain text unless always.
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, value_enum, value_name = "WHEN", default_value = "auto")
+    )]
+    color: ColorChoice,
+
+    #[cfg_attr(feature = "cli", arg(skip))]
+    interrupt: Interrupt,
+}
+
+impl Cli {
+    /// What stops a run of this command line early; see
+    /// [`CliBuilder::interrupt`].
+    pub fn interrupt(&self) -> &Interrupt {
+        &self.interrupt
+    }
+
+    /// How much [`run_and_print`] reports, as -v and --quiet ask.
+    pub fn verbosity(&self) -> Verbosity {
+        match (self.quiet, self.verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, _) => Verbosity::Verbose,
+        }
+    }
+
+    /// `--color`.
+    pub fn color(&self) -> ColorChoice {
+        self.color
+    }
+
+    /// The log level asked for with -v/-vv and --quiet.
+    pub fn log_level(&self) -> log::LevelFilter {
+        match (self.quiet, self.verbose) {
+            (true, _) => log::LevelFilter::Error,
+            (false, 0) => log::LevelFilter::Warn,
+            (false, 1) => log::LevelFilter::Debug,
+            (false, _) => log::LevelFilter::Trace,
+        }
+    }
+}
+
+/// What `--on-clamp` does when `--batch` is more than the inputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum OnClamp {
+    /// Create one batch per input, with a warning.
+    #[default]
+    Warn,
+    /// Fail before anything is written.
+    Error,
+    /// Create all the batches; the extra ones have no inputs.
+    Pad,
+}
+
+/// How `--dry-run` shows the would-be submissions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+enum OutputFormat {
+    /// `[dry-run] <submit command line>` lines.
+    Human,
+    /// One JSON array of submissions at the end; other messages go to
+    /// stderr.
+    Json,
+    /// One JSON object per submission and line; other messages go to
+    /// stderr.
+    Ndjson,
+}
+
+/// A would-be submission in `--output-format json|ndjson`.
+#[derive(Serialize)]
+struct DryRunSubmission {
+    job_name: String,
+    script: Option<PathBuf>,
+    submit_argv: Vec<String>,
+    input_count: usize,
+    inputs: Vec<String>,
+}
+
+/// How `--notify-once` collapses notifications.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+// Only the command line sets it.
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+enum NotifyOnce {
+    /// Notify only for the last submitted batch.
+    Last,
+    /// Notify from a no-op job that depends on all batches.
+    Sentinel,
+}
+
+/// Wrapped command blocks above this size are likely to hit scheduler limits.
+const WRAP_WARN_BYTES: usize = 64 * 1024;
+
+/// How often the run state is rewritten while submitting; it is always
+/// written when submission starts and ends.
+const STATE_SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What gets handed to the submit command for one job.
+pub(crate) enum JobPayload<'a> {
+    /// Path to a generated batch script.
+    Script(&'a Path),
+    /// Command block passed inline via `--wrap`.
+    Wrap(&'a str),
+    /// Generated batch script fed to the submit command's stdin.
+    Stdin(&'a Path),
+}
+
+impl JobPayload<'_> {
+    fn args(&self) -> Vec<OsString> {
+        match self {
+            JobPayload::Script(path) => vec![path.as_os_str().to_os_string()],
+            JobPayload::Stdin(_) => Vec::new(),
+            JobPayload::Wrap(commands) => vec!["--wrap".into(), (*commands).into()],
+        }
+    }
+
+    /// Appends the payload's arguments, quoted, to `line`.
+    fn write_shell_args(&self, line: &mut String) {
+        use std::fmt::Write as _;
+        let _ = match self {
+            JobPayload::Script(path) => write!(line, "{}", shellgen::quote_path(path)),
+            JobPayload::Stdin(path) => write!(line, "< {}", shellgen::quote_path(path)),
+            JobPayload::Wrap(commands) => write!(line, "--wrap {}", shellgen::quote(commands)),
+        };
+    }
+}
+
+/// One job handed to the submit command.
+pub(crate) struct Submission<'a> {
+    pub(crate) job_name: &'a str,
+    /// Scheduler flags appended after the arguments of `--submit`.
+    pub(crate) extra_args: Vec<String>,
+    pub(crate) payload: JobPayload<'a>,
+}
+
+impl Submission<'_> {
+    pub(crate) fn args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = self.extra_args.iter().map(OsString::from).collect();
+        args.extend(self.payload.args());
+        args
+    }
+
+    /// The full argv of the submit invocation: `--submit` split into
+    /// words, then [`Submission::args`].
+    pub(crate) fn argv(&self, submit: &str) -> Vec<String> {
+        let mut argv = shlex::split(submit).unwrap_or_default();
+        argv.extend(
+            self.args()
+                .into_iter()
+                .map(|a| a.to_string_lossy().into_owned()),
+        );
+        argv
+    }
+
+    /// The submit invocation as it would be typed into a shell.
+    pub(crate) fn shell_line(&self, submit: &str) -> String {
+        let mut line = submit.to_string();
+        for arg in &self.extra_args {
+            line.push(' ');
+            line.push_str(&shellgen::quote(arg));
+        }
+        line.push(' ');
+        self.payload.write_shell_args(&mut line);
+        line
+    }
+}
+
+/// Generates and submits the batches described by `cli` and reports what
+/// happened to each of them: [`plan`], then [`execute`]; `batchelor
+/// submit`. Prints nothing; see [`run_with`] and [`run_and_print`].
+pub fn run(cli: Cli) -> Result<RunReport, BatchelorError> {
+    run_with(cli, &Quiet, None)
+}
+
+/// [`run`], printing like the binary does: progress and messages to
+/// stderr, and submissions, `--plan-json -`, structured output and the
+/// final report to stdout.
+pub fn run_and_print(cli: Cli) -> Result<RunReport, BatchelorError> {
+    cli.interrupt.install_handler()?;
+    let reporter = StreamReporter::stdio()
+        .with_verbosity(cli.verbosity())
+        .with_color(cli.color);
+    run_with(cli, &reporter, None)
+}
+
+/// [`run`], reporting what it does to `reporter` and submitting through
+/// `submitter`, by default a [`CommandSubmitter`] for `--submit`.
+pub fn run_with(
+    cli: Cli,
+    reporter: &dyn Reporter,
+    submitter: Option<&dyn Submitter>,
+) -> Result<RunReport, BatchelorError> {
+    Ok(plan_and_execute(cli, reporter, submitter)?)
+}
+
+fn plan_and_execute(
+    cli: Cli,
+    reporter: &dyn Reporter,
+    submitter: Option<&dyn Submitter>,
+) -> Result<RunReport, Box<dyn std::error::Error>> {
+    if !cli.emit.is_empty() {
+        let scheduler = check_cli(&cli, true)?;
+        let script_abs = fs::canonicalize(&cli.script)?;
+        let found =
+            collect_inputs(&cli, true, &mut |warning| reporter.diagnostic(&warning))?.inputs;
+        let sizes = found
+            .iter()
+            .map(|input| input.metadata.as_ref().map_or(0, |m| m.len()))
+            .collect::<Vec<_>>();
+        let inputs = found
+            .into_iter()
+            .map(|input| input.path)
+            .collect::<Vec<_>>();
+        return emit_plan(&cli, scheduler, &script_abs, &inputs, &sizes, reporter);
+    }
+
+    let scheduler = check_cli(&cli, true)?;
+    if (cli.plan_json.is_none() || cli.execute) && !cli.no_write {
+        // Before the inputs are expanded, which may take long. The
+        // directory --out-dir-timestamp picks is made in --out-dir's parent.
+        let dir = if cli.out_dir_timestamp {
+            match timestamp_base(&cli.out_dir).parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            }
+        } else {
+            cli.out_dir.clone()
+        };
+        probe_out_dir(&dir, &permissions(&cli)?)?;
+    }
+    let plan = plan_batches(&cli, scheduler, reporter)?;
+    if let Some(path) = &cli.plan_json {
+        write_plan(&plan, path, reporter)?;
+        if !cli.execute {
+            let prepared = plan
+                .selected()
+                .map(PreparedBatch::from_spec)
+                .collect::<Vec<_>>();
+            let state = initial_state(
+                &plan.submit,
+                plan.scheduler,
+                plan.run_id.clone(),
+                cli.profile.clone(),
+                &prepared,
+                plan.marker_dir().as_deref(),
+            );
+            return Ok(RunReport { state, wait: None });
+        }
+    }
+    let command_submitter;
+    let submitter = match submitter {
+        Some(submitter) => submitter,
+        None => {
+            command_submitter = CommandSubmitter::from_cli(&cli, plan.scheduler);
+            &command_submitter
+        }
+    };
+    execute_plan(&plan, &ExecOptions::new(&cli), reporter, submitter)
+}
+
+/// Works out the plan of `cli` and writes it as JSON to `--plan-json`, or
+/// to stdout without it; `batchelor plan`. Nothing else is written or
+/// submitted. Messages go to stderr.
+pub fn plan_and_print(cli: Cli) -> Result<Plan, BatchelorError> {
+    cli.interrupt.install_handler()?;
+    let reporter = StreamReporter::stdio()
+        .with_verbosity(cli.verbosity())
+        .with_color(cli.color);
+    if cli.execute {
+        return Err(
+            "batchelor plan does not submit; use batchelor submit --plan-json FILE --execute"
+                .into(),
+        );
+    }
+    if cli.clean_only {
+        return Err("batchelor plan does not clean up; use batchelor clean".into());
+    }
+    let plan = build_plan(&cli, &reporter)?;
+    write_plan(
+        &plan,
+        cli.plan_json.as_deref().unwrap_or(Path::new("-")),
+        &reporter,
+    )?;
+    Ok(plan)
+}
+
+/// Writes `plan` as JSON to `path`, or to the data stream for `-`.
+fn write_plan(
+    plan: &Plan,
+    path: &Path,
+    reporter: &dyn Reporter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if path == Path::new("-") {
+        reporter.data(&plan.to_json_pretty());
+    } else {
+        fs::write(path, plan.to_json_pretty())
+            .map_err(|e| format!("could not write --plan-json {}: {}", path.display(), e))?;
+        reporter.message(&format!("Plan written to {}", path.display()));
+    }
+    Ok(())
+}
+
+/// How [`execute`] runs a plan: the options of a command line beyond what
+/// went into the plan, such as confirmation, `--keep`, `--keep-going`,
+/// `--wait`, reports and hooks.
+#[derive(Clone, Debug)]
+pub struct ExecOptions {
+    cli: Cli,
+}
+
+impl ExecOptions {
+    pub fn new(cli: &Cli) -> ExecOptions {
+        ExecOptions { cli: cli.clone() }
+    }
+}
+
+/// Checks the options of `cli` that do not depend on its inputs and
+/// returns the scheduler submissions go to. The script is only checked
+/// (see [`validate_script`]) with `check_script`.
+fn check_cli(cli: &Cli, check_script: bool) -> Result<Scheduler, Box<dyn std::error::Error>> {
+    if cli.batch == 0 {
+        return Err(BatchelorError::InvalidBatchCount.into());
+    }
+
+    if check_script {
+        validate_script(cli)?;
+    }
+
+    let scheduler = cli
+        .scheduler
+        .unwrap_or_else(|| Scheduler::detect(&cli.submit));
+    // Before anything is generated or cleaned up; the prefix names
+    // --singleton jobs and the --notify-once job as is.
+    if let (Err(e), true) = (naming::check_prefix(&cli.job_name_prefix), cli.strict_names) {
+        return Err(BatchelorError::InvalidOptions(format!("{} (--strict-names)", e)).into());
+    }
+    let prefix = job_name_prefix(cli);
+    scheduler
+        .check_job_name(&prefix)
+        .map_err(|e| format!("--job-name-prefix: {}", e))?;
+    if let Some(max) = scheduler
+        .max_job_name_len()
+        .filter(|max| prefix.len() > *max)
+    {
+        return Err(format!("--job-name-prefix is longer than {} characters", max).into());
+    }
+    if cli.hold {
+        require_scheduler(cli, scheduler, "--hold")?;
+    }
+    if cli.notify.is_some() {
+        require_scheduler(cli, scheduler, "--notify")?;
+    }
+    if cli.preflight {
+        require_scheduler(cli, scheduler, "--preflight")?;
+    }
+    if cfg!(not(feature = "webhook")) && cli.webhook_url.is_some() {
+        return Err("--webhook-url needs batchelor built with the `webhook` feature".into());
+    }
+    if cli.wrap {
+        validate_wrap(cli, scheduler)?;
+    }
+    shellgen::check_slot(&input_flag(cli)?, cli.script_args.len(), slot_overflow(cli))
+        .map_err(BatchelorError::InvalidOptions)?;
+    Ok(scheduler)
+}
+
+fn slot_overflow(cli: &Cli) -> shellgen::SlotOverflow {
+    if cli.pad_positional {
+        shellgen::SlotOverflow::Pad
+    } else if cli.clamp_positional {
+        shellgen::SlotOverflow::Clamp
+    } else {
+        shellgen::SlotOverflow::Error
+    }
+}
+
+/// `--job-name-prefix` as batchelor uses it: characters
+/// [`naming::check_prefix`] refuses are replaced by `_`, since
+/// `--strict-names` refuses them in [`check_cli`].
+fn job_name_prefix(cli: &Cli) -> std::borrow::Cow<'_, str> {
+    naming::sanitize_prefix(&cli.job_name_prefix)
+}
+
+/// The command of a batch reading its `inputs` from the file at `list`.
+fn list_command(
+    cli: &Cli,
+    spec: &shellgen::CommandSpec,
+    inputs: &[String],
+    list: &Path,
+) -> Result<String, String> {
+    if let Some(input) = inputs.iter().find(|input| input.contains('\n')) {
+        return Err(format!(
+            "input {:?} contains a newline, so it cannot be listed in {}",
+            input,
+            list.display()
+        ));
+    }
+    shellgen::list_command_line(spec, list, cli.input_list_flag.as_deref(), cli.multi_input)
+        .map_err(|e| format!("{}: {}", list.display(), e))
+}
+
+fn input_flag(cli: &Cli) -> Result<shellgen::InputFlag, BatchelorError> {
+    let parsed = if cli.raw_template {
+        shellgen::InputFlag::parse_raw(&cli.input_flag)
+    } else {
+        cli.input_flag.parse()
+    };
+    parsed.map_err(|e| {
+        BatchelorError::InvalidOptions(format!("--input-flag {:?}: {}", cli.input_flag, e))
+    })
+}
+
+/// The inputs of `cli`, sorted, warning about entries skipped with
+/// `--glob-errors warn` and inputs `--on-missing-input` skipped or kept.
+fn collect_inputs(
+    cli: &Cli,
+    metadata: bool,
+    warn: &mut dyn FnMut(String),
+) -> Result<Expansion, BatchelorError> {
+    let meta_cache_path = cli.out_dir.join(meta_cache::META_CACHE_FILE);
+    let options = InputOptions {
+        metadata,
+        glob_errors: cli.glob_errors,
+        on_missing: cli.on_missing_input,
+        interrupt: cli.interrupt.clone(),
+        threads: cli.expand_threads.map_or(0, usize::from),
+        io_threads: cli.io_threads.map_or(0, usize::from),
+        meta_cache: cli
+            .meta_cache
+            .then(|| Arc::new(MetaCache::load(&meta_cache_path))),
+        ..InputOptions::default()
+    };
+    let expansion = inputs::collect_expansion(&cli.glob, cli.input_list.as_deref(), &options)?;
+    if let (Some(cache), false) = (&options.meta_cache, cli.no_write) {
+        if let Err(e) = cache.save_with(&meta_cache_path, &permissions(cli)?) {
+            warn(format!(
+                "warning: could not write {}: {}",
+                meta_cache_path.display(),
+                e
+            ));
+        }
+    }
+    if cli.glob_errors == GlobErrors::Warn {
+        for skipped in &expansion.skipped {
+            warn(format!("warning: skipping {}", skipped));
+        }
+    }
+    let action = match cli.on_missing_input {
+        OnMissingInput::KeepRaw => "kept as given",
+        _ => "skipped",
+    };
+    for missing in &expansion.missing {
+        warn(format!("warning: {}; {}", missing, action));
+    }
+    Ok(expansion)
+}
+
+/// How many of `inputs` each `--glob` pattern and the `--input-list`
+/// gave, in the order given.
+fn input_counts(cli: &Cli, inputs: &[Input]) -> Vec<(String, usize)> {
+    let mut counts = HashMap::<&str, usize>::new();
+    for input in inputs {
+        *counts.entry(input.source.as_str()).or_default() += 1;
+    }
+    let sources = cli
+        .glob
+        .iter()
+        .map(|pattern| format!("--glob {}", pattern))
+        .chain(
+            cli.input_list
+                .iter()
+                .map(|list| format!("--input-list {}", list.display())),
+        );
+    let mut seen = HashSet::new();
+    sources
+        .filter(|source| seen.insert(source.clone()))
+        .map(|source| {
+            let count = counts.get(source.as_str()).copied().unwrap_or(0);
+            (source, count)
+        })
+        .collect()
+}
+
+fn show_progress(cli: &Cli) -> bool {
+    !cli.dry_run && !cli.quiet && !cli.no_progress && (cli.progress || io::stderr().is_terminal())
+}
+
+/// `--out-dir` without a trailing slash, so `--out-dir-timestamp` appends
+/// to its name.
+fn timestamp_base(out_dir: &Path) -> PathBuf {
+    out_dir.components().as_path().to_path_buf()
+}
+
+/// Works out the batches `cli` describes: their inputs, job names,
+/// commands, directives and submit invocations. Nothing is written but the
+/// `--meta-cache`; inputs are only listed and stat'ed.
+pub fn plan(cli: &Cli, reporter: &dyn Reporter) -> Result<Plan, BatchelorError> {
+    Ok(build_plan(cli, reporter)?)
+}
+
+fn build_plan(cli: &Cli, reporter: &dyn Reporter) -> Result<Plan, Box<dyn std::error::Error>> {
+    let scheduler = check_cli(cli, true)?;
+    plan_batches(cli, scheduler, reporter)
+}
+
+/// [`build_plan`] for a `cli` [`check_cli`] accepted.
+fn plan_batches(
+    cli: &Cli,
+    scheduler: Scheduler,
+    reporter: &dyn Reporter,
+) -> Result<Plan, Box<dyn std::error::Error>> {
+    let mut output = Output::new(show_progress(cli), reporter);
+    let prefix = job_name_prefix(cli);
+    if let Err(e) = naming::check_prefix(&cli.job_name_prefix) {
+        output.eprintln(format!("warning: {}; using {}", e, prefix));
+    }
+    if let Some(warning) = script_warning(cli) {
+        output.eprintln(warning);
+    }
+    let scales_resources = cli.mem_base.is_some()
+        || cli.mem_per_byte.is_some()
+        || cli.time_base.is_some()
+        || cli.time_per_byte.is_some();
+    if scales_resources {
+        require_scheduler(cli, scheduler, "--mem-*/--time-*")?;
+    }
+    let singleton_args = if cli.singleton {
+        Some(scheduler.singleton_args().ok_or_else(|| {
+            format!(
+                "--singleton needs SLURM's --dependency=singleton, got --submit {:?}",
+                cli.submit
+            )
+        })?)
+    } else {
+        None
+    };
+    let overrides = match &cli.submit_overrides {
+        Some(path) => SubmitOverrides::load(path)?,
+        None => SubmitOverrides::default(),
+    };
+    let out_dir = if cli.out_dir_timestamp {
+        let mut dir = timestamp_base(&cli.out_dir).into_os_string();
+        dir.push(chrono::Local::now().format("-%Y%m%d-%H%M%S").to_string());
+        let dir = PathBuf::from(dir);
+        output.eprintln(format!("Output directory: {}", dir.display()));
+        dir
+    } else {
+        cli.out_dir.clone()
+    };
+
+    let script_abs = fs::canonicalize(&cli.script)?;
+    // Sizes are only stat'ed when something uses them: scaled resources,
+    // resource rules or the submission report.
+    let needs_sizes = scales_resources
+        || !cli.resource_rules.is_empty()
+        || !cli.dry_run
+        || cli.plan_json.is_some()
+        || cli.incremental;
+    let Expansion {
+        inputs: found,
+        skipped,
+        ..
+    } = collect_inputs(cli, needs_sizes, &mut |warning| output.eprintln(warning))?;
+    for (source, count) in input_counts(cli, &found) {
+        output.detail(format_args!("{}: {} input(s)", source, count));
+    }
+    let mut notes = Vec::new();
+    if !skipped.is_empty() {
+        notes.push(format!("{} unreadable entries skipped", skipped.len()));
+    }
+    let (found, fingerprints, unchanged) = if cli.incremental {
+        let previous = incremental::Previous::load(&cli.out_dir)?;
+        let selection = incremental::select(
+            found,
+            previous.as_ref(),
+            cli.incremental_key,
+            cli.io_threads.map_or(0, usize::from),
+            &cli.interrupt,
+        )?;
+        notes.push(match &previous {
+            Some(previous) => format!(
+                "{} unchanged since run {} left out",
+                selection.skipped, previous.run_id
+            ),
+            None => "no earlier run to compare with".to_string(),
+        });
+        let (found, fingerprints) = selection.scheduled.into_iter().unzip();
+        (found, fingerprints, selection.unchanged)
+    } else {
+        let fingerprints = vec![None; found.len()];
+        (found, fingerprints, Vec::new())
+    };
+    let inputs = found
+        .iter()
+        .map(|input| input.path.clone())
+        .collect::<Vec<_>>();
+    // Dry runs get a run ID for their scripts and the report, but nothing
+    // is recorded.
+    let run_id = cli.run_id.clone().unwrap_or_else(runs::new_run_id);
+    // Scripts, and the markers where they record finished and failed
+    // inputs, go to the run's own directory, so concurrent runs and runs
+    // reusing job names do not mix. --wrap jobs have no scripts and are only
+    // tracked per job. Dry runs preview theirs apart from real runs.
+    let script_dir = std::path::absolute(if cli.dry_run {
+        out_dir.join(runs::DRY_RUN_DIR).join(&run_id)
+    } else if cli.flat_out_dir {
+        out_dir.clone()
+    } else {
+        runs::run_dir(&out_dir, &run_id)
+    })?;
+    let marker_dir = (!cli.wrap).then(|| script_dir.clone());
+
+    let submit_dir = std::env::current_dir()?;
+    let job_log_dir = cli
+        .job_log_dir
+        .as_deref()
+        .map(std::path::absolute)
+        .transpose()?;
+    let job_log_template = placeholder::parse(&cli.job_log_template, LOG_PLACEHOLDERS)
+        .map_err(|e| format!("--job-log-template: {}", e))?;
+
+    // Nothing new for --incremental is no reason to warn.
+    let batch_count = if cli.incremental && inputs.is_empty() {
+        0
+    } else {
+        clamp_batches(cli, inputs.len(), true, &mut |warning| {
+            output.eprintln(warning)
+        })?
+    };
+    if let Some(only) = &cli.only_batch {
+        only.check_bounds(batch_count)
+            .map_err(|e| BatchelorError::InvalidOptions(format!("--only-batch {}: {}", only, e)))?;
+    }
+    overrides
+        .check_bounds(batch_count)
+        .map_err(BatchelorError::InvalidOptions)?;
+    output.println(format!(
+        "Found {} input files{}. Creating {} job(s).",
+        inputs.len(),
+        match notes.is_empty() {
+            true => String::new(),
+            false => format!(" ({})", notes.join("; ")),
+        },
+        batch_count
+    ));
+    let is_selected = |idx: usize| cli.only_batch.as_ref().is_none_or(|o| o.contains(idx));
+    let last_selected = (1..=batch_count).rev().find(|i| is_selected(*i));
+    if let Some(only) = &cli.only_batch {
+        let selected = (1..=batch_count).filter(|i| is_selected(*i)).count();
+        output.println(format!(
+            "Submitting {} of {} batch(es) (--only-batch {}); scripts of the others are kept.",
+            selected, batch_count, only
+        ));
+    }
+
+    let sizes = found
+        .iter()
+        .map(|input| input.metadata.as_ref().map_or(0, |m| m.len()))
+        .collect::<Vec<_>>();
+
+    let groups = split_evenly(&inputs, batch_count);
+    let size_groups = split_evenly(&sizes, batch_count);
+    let job_names = naming::job_names(
+        &cli.job_name_format,
+        &prefix,
+        &groups,
+        scheduler,
+        cli.strict_names,
+        &mut |warning| output.eprintln(warning),
+    )
+    .map_err(BatchelorError::InvalidOptions)?;
+    output.start_phase("planning", batch_count);
+    let command_spec = shellgen::CommandSpec {
+        script: &script_abs,
+        input_flag: &input_flag(cli)?,
+        script_args: &cli.script_args,
+        overflow: slot_overflow(cli),
+    };
+    // Scripts say when their command lines were not quoted by batchelor.
+    let header = if cli.raw_template {
+        vec!["# batchelor-input-flag: raw".to_string()]
+    } else {
+        Vec::new()
+    };
+    let mut batches = Vec::new();
+    for (idx, chunk) in groups.iter().enumerate() {
+        cli.interrupt.check()?;
+        let batch_idx = idx + 1;
+        let batch_bytes: u64 = size_groups[idx].iter().sum();
+        let job_name = job_names[idx].clone();
+        // Name the scheduler sees; shared by all batches with --singleton.
+        let scheduler_job_name = if cli.singleton {
+            prefix.as_ref()
+        } else {
+            &job_name
+        };
+        let mut input_list = None;
+        let commands = if chunk.is_empty() {
+            vec!["# No inputs (--on-clamp pad).".to_string()]
+        } else {
+            let mut commands = shellgen::command_lines(&command_spec, chunk, cli.multi_input);
+            let length = if cli.wrap {
+                wrap_commands(&commands).len()
+            } else {
+                commands.iter().map(String::len).max().unwrap_or(0)
+            };
+            if length as u64 > cli.max_command_bytes && cli.wrap {
+                // Reading the inputs from a list would leave a file on disk,
+                // which --wrap is there to avoid.
+                return Err(BatchelorError::InvalidOptions(format!(
+                    "{} would need a {} byte --wrap command (over --max-command-bytes {}); use a smaller --batch, or drop --wrap to read its inputs from a file",
+                    job_name, length, cli.max_command_bytes
+                ))
+                .into());
+            }
+            if length as u64 > cli.max_command_bytes {
+                let path = script_dir.join(format!("{}.inputs", job_name));
+                commands = vec![list_command(cli, &command_spec, chunk, &path)?];
+                output.eprintln(format!(
+                    "notice: {} would need a {} byte command line (over --max-command-bytes {}); its {} inputs are read from {}",
+                    job_name,
+                    length,
+                    cli.max_command_bytes,
+                    chunk.len(),
+                    path.display()
+                ));
+                input_list = Some(path);
+            }
+            match (&marker_dir, &input_list) {
+                (Some(dir), Some(list)) => commands
+                    .iter()
+                    .map(|command| failures::track_input_list(command, list, dir, &job_name))
+                    .collect(),
+                (Some(dir), None) => {
+                    failures::track_inputs(commands, chunk, cli.multi_input, dir, &job_name)
+                }
+                (None, _) => commands,
+            }
+        };
+
+        let submit = overrides.submit_for(&cli.submit, batch_idx);
+        let submit_parts = shlex::split(&submit).unwrap_or_default();
+        let pass_job_name = !cli.no_auto_job_name && !scheduler.has_job_name_arg(&submit_parts);
+        let mut extra_args = parsable_args(scheduler, &submit_parts);
+        if cli.hold {
+            extra_args.extend(scheduler.hold_args());
+        }
+        if pass_job_name {
+            extra_args.extend(scheduler.job_name_args(scheduler_job_name));
+        }
+        if let Some(args) = &singleton_args {
+            extra_args.extend(args.iter().cloned());
+        }
+        let stats = BatchStats {
+            size: batch_bytes,
+            count: chunk.len() as u64,
+            max_size: size_groups[idx].iter().copied().max().unwrap_or(0),
+        };
+        for rule in cli.resource_rules.iter().filter(|r| r.matches(&stats)) {
+            if cli.dry_run {
+                output.println(format!("[dry-run] {}: rule '{}' matched", job_name, rule));
+            }
+            extra_args.extend(rule.args().iter().cloned());
+        }
+
+        let mut directives = Vec::new();
+        let mut resources = Vec::new();
+        if let Some(mem) = scaled_request(cli.mem_base, cli.mem_per_byte, cli.mem_cap, batch_bytes)
+        {
+            resources.extend(scheduler.mem_args(mem));
+        }
+        if let Some(secs) =
+            scaled_request(cli.time_base, cli.time_per_byte, cli.time_cap, batch_bytes)
+        {
+            resources.extend(scheduler.time_args(secs));
+        }
+        if cli.dry_run && !resources.is_empty() {
+            output.println(format!(
+                "[dry-run] {}: {} input(s), {} -> {}",
+                job_name,
+                chunk.len(),
+                units::format_size(batch_bytes),
+                resources.join(" ")
+            ));
+        }
+        directives.extend(resources);
+        if let Some(email) = &cli.notify {
+            let notify_here = match cli.notify_once {
+                None => true,
+                Some(NotifyOnce::Last) => Some(batch_idx) == last_selected,
+                Some(NotifyOnce::Sentinel) => false,
+            };
+            if notify_here {
+                directives.extend(scheduler.notify_args(email, &cli.notify_on));
+            }
+        }
+        let mut log = None;
+        if let Some(dir) = &job_log_dir {
+            let (stdout, stderr) = job_log_paths(
+                dir,
+                &job_log_template,
+                &job_name,
+                batch_idx,
+                cli.split_stderr,
+            );
+            directives.extend(scheduler.log_args(
+                &stdout.to_string_lossy(),
+                stderr.as_ref().map(|p| p.to_string_lossy()).as_deref(),
+            ));
+            log = Some(stdout);
+        } else if scheduler == Scheduler::Slurm {
+            // sbatch's default: slurm-<job id>.out in the submit directory.
+            log = Some(submit_dir.join("slurm-%j.out"));
+        }
+
+        let (script, directives) = if cli.wrap {
+            // No script to carry directives, so they become submit flags.
+            extra_args.extend(directives);
+            let wrapped = wrap_commands(&commands);
+            if wrapped.len() > WRAP_WARN_BYTES {
+                output.eprintln(format!(
+                    "warning: wrapped command for {} is {} bytes; sbatch may reject it (consider dropping --wrap)",
+                    job_name,
+                    wrapped.len()
+                ));
+            }
+            (None, Vec::new())
+        } else {
+            if !cli.no_auto_job_name {
+                directives.splice(0..0, scheduler.job_name_args(scheduler_job_name));
+            }
+            let path = script_dir.join(format!("{}.batch.sh", job_name));
+            (Some(path), directive_lines(scheduler, &directives))
+        };
+        output.advance();
+        output.detail(format_args!(
+            "batch {} ({}): {} input(s), {}{}",
+            batch_idx,
+            job_name,
+            chunk.len(),
+            units::format_size(batch_bytes),
+            match chunk {
+                [] => String::new(),
+                [only] => format!(": {}", only),
+                [first, .., last] => format!(": {} .. {}", first, last),
+            }
+        ));
+        for input in chunk.iter() {
+            log::trace!("batch {}: {}", batch_idx, input);
+        }
+
+        let mut spec = JobSpec {
+            batch_index: batch_idx,
+            job_name,
+            inputs: chunk.to_vec(),
+            input_bytes: batch_bytes,
+            header: header.clone(),
+            directives,
+            commands,
+            script,
+            input_list,
+            log,
+            submit_command: submit,
+            submit_args: extra_args,
+            submit: String::new(),
+            selected: is_selected(batch_idx),
+        };
+        let batch = PreparedBatch::from_spec(&spec);
+        spec.submit = batch.submission(cli).shell_line(&batch.submit);
+        batches.push(spec);
+    }
+    output.finish_phase();
+
+    Ok(Plan {
+        schema_version: plan::SCHEMA_VERSION,
+        scheduler,
+        submit: cli.submit.clone(),
+        run_id,
+        out_dir,
+        script_dir,
+        inputs: found
+            .into_iter()
+            .zip(&sizes)
+            .zip(fingerprints)
+            .map(|((input, size), fingerprint)| PlanInput {
+                path: input.path,
+                size: *size,
+                source: input.source,
+                fingerprint,
+            })
+            .collect(),
+        batches,
+        unchanged,
+    })
+}
+
+/// Writes the scripts of `plan` and submits its selected batches through
+/// `submitter`, handling failures, waiting and reporting as `options` say.
+pub fn execute(
+    plan: &Plan,
+    options: &ExecOptions,
+    reporter: &dyn Reporter,
+    submitter: &dyn Submitter,
+) -> Result<RunReport, BatchelorError> {
+    Ok(execute_plan(plan, options, reporter, submitter)?)
+}
+
+fn execute_plan(
+    plan: &Plan,
+    options: &ExecOptions,
+    reporter: &dyn Reporter,
+    submitter: &dyn Submitter,
+) -> Result<RunReport, Box<dyn std::error::Error>> {
+    // Everything below goes to the plan's directories, which
+    // --out-dir-timestamp may have chosen.
+    let mut cli = options.cli.clone();
+    cli.out_dir = plan.out_dir.clone();
+    let scheduler = plan.scheduler;
+    let run_id = plan.run_id.clone();
+    let script_dir = plan.script_dir.clone();
+    let marker_dir = plan.marker_dir();
+    let submit_dir = std::env::current_dir()?;
+    let structured = cli.output_format != OutputFormat::Human;
+    let recorded = !cli.dry_run;
+
+    if recorded && !cli.skip_submit_check && cli.submit_record.is_none() {
+        let mut submits = plan
+            .selected()
+            .map(|b| b.submit_command.as_str())
+            .collect::<Vec<_>>();
+        submits.sort();
+        submits.dedup();
+        for submit in submits {
+            check_submit_program(submit)?;
+        }
+    }
+    let perms = permissions(&cli)?;
+    if !cli.no_write {
+        probe_out_dir(&cli.out_dir, &perms)?;
+    }
+    // Held until the scripts are submitted and cleaned up, not while
+    // waiting for the jobs.
+    let lock = if cli.no_lock || cli.no_write {
+        None
+    } else {
+        let wait = cli.wait_for_lock.map(Duration::from_secs);
+        Some(lock::acquire(&cli.out_dir, wait, &cli.interrupt)?)
+    };
+    if recorded && runs::run_ids(&cli.out_dir)?.contains(&run_id) {
+        return Err(format!(
+            "run {} already exists under {}; pick another --run-id",
+            run_id,
+            cli.out_dir.join(runs::RUNS_DIR).display()
+        )
+        .into());
+    }
+    if !cli.no_write {
+        perms.create_dir_all(&script_dir)?;
+        if let Some(dir) = &cli.job_log_dir {
+            perms.create_dir_all(dir)?;
+        }
+    }
+
+    let mut output = Output::new(show_progress(&cli), reporter);
+    if !cfg!(unix)
+        && (cli.script_mode.is_some() || cli.artifact_mode.is_some() || cli.group.is_some())
+    {
+        output.eprintln(
+            "notice: --script-mode, --artifact-mode and --group are ignored on this platform",
+        );
+    }
+    output.start_phase("generating scripts", plan.batches.len());
+    let mut selected: Vec<PreparedBatch> = Vec::new();
+    let mut manifest = Vec::new();
+    let mut dry_run_submissions = Vec::new();
+    let scripts = plan.batches.iter().filter(|b| b.script.is_some()).count();
+    let mut written = 0;
+    // Scripts already there as they would be written: not written again,
+    // nor removed once submitted, as they are not this run's.
+    let mut unchanged = HashSet::new();
+    for spec in &plan.batches {
+        if cli.interrupt.is_interrupted() {
+            return Err(stopped_before_submitting(&cli, &script_dir, &mut output));
+        }
+        // A dry run replaces nothing, even when it runs a plan whose
+        // scripts are not in a preview directory.
+        let write = |path: &Path| !(cli.no_write || cli.dry_run && path.exists());
+        if let Some(path) = spec.input_list.as_deref().filter(|path| write(path)) {
+            let text = |out: &mut dyn Write| write_input_list_text(out, &spec.inputs);
+            if cli.force_rewrite || !has_contents(path, text) {
+                write_input_list(path, &spec.inputs, &perms)
+                    .map_err(|e| out_of_space(e, written, scripts))?;
+            } else {
+                keep_permissions(path, perms::Kind::Artifact, &perms)?;
+            }
+        }
+        if let Some(path) = spec.script.as_deref().filter(|path| write(path)) {
+            let text = |out: &mut dyn Write| {
+                write_job_script_text(out, &spec.header, &spec.directives, &spec.commands)
+            };
+            if cli.force_rewrite || !is_generated_script(path) || !has_contents(path, text) {
+                write_job_script(path, &spec.header, &spec.directives, &spec.commands, &perms)
+                    .map_err(|e| out_of_space(e, written, scripts))?;
+                written += 1;
+            } else {
+                keep_permissions(path, perms::Kind::Script, &perms)?;
+                unchanged.insert(path.to_path_buf());
+            }
+        }
+        output.advance();
+        if cli.manifest.is_some() {
+            manifest.push(ManifestBatch {
+                batch_index: spec.batch_index,
+                job_name: spec.job_name.clone(),
+                script: spec.script.clone(),
+                inputs: spec.inputs.clone(),
+            });
+        }
+        if !spec.selected {
+            continue;
+        }
+        let batch = PreparedBatch::from_spec(spec);
+        if cli.dry_run {
+            let submission = batch.submission(&cli);
+            if !structured {
+                output.data(format_args!(
+                    "[dry-run] {}\n",
+                    submission.shell_line(&batch.submit)
+                ));
+            } else {
+                let record = DryRunSubmission {
+                    job_name: batch.job_name.clone(),
+                    script: batch.script().map(Path::to_path_buf),
+                    submit_argv: submission.argv(&batch.submit),
+                    input_count: batch.inputs.len(),
+                    inputs: batch.inputs.clone(),
+                };
+                if cli.output_format == OutputFormat::Ndjson {
+                    output.data(format_args!("{}\n", serde_json::to_string(&record)?));
+                } else {
+                    dry_run_submissions.push(record);
+                }
+            }
+        }
+        selected.push(batch);
+    }
+    // Dry runs report their batches, but submit none of them.
+    let prepared: &[PreparedBatch] = if cli.dry_run { &[] } else { &selected };
+    output.finish_phase();
+    if !unchanged.is_empty() {
+        output.println(format!(
+            "{} script(s) unchanged, {} rewritten (--force-rewrite rewrites all)",
+            unchanged.len(),
+            written
+        ));
+    }
+    if cli.dry_run && written > 0 {
+        output.println(format!(
+            "[dry-run] {} preview script(s) written to {}",
+            written,
+            script_dir.display()
+        ));
+    }
+    if cli.output_format == OutputFormat::Json {
+        output.data(format_args!(
+            "{}\n",
+            serde_json::to_string_pretty(&dry_run_submissions)?
+        ));
+    }
+    if let Some(path) = &cli.manifest {
+        perms
+            .write(
+                path,
+                manifest::render(&manifest, cli.manifest_format).as_bytes(),
+                perms::Kind::Artifact,
+            )
+            .map_err(|e| format!("could not write --manifest {}: {}", path.display(), e))?;
+        output.println(format!("Manifest written to {}", path.display()));
+    }
+
+    // Asked only now, so the generated scripts can be inspected before
+    // answering.
+    if !cli.dry_run && needs_confirmation(&cli, prepared.len()) {
+        output.println(format!(
+            "About to submit {} job(s) covering {} input(s)",
+            prepared.len(),
+            prepared.iter().map(|b| b.inputs.len()).sum::<usize>()
+        ));
+        output.println(format!("  submit:  {}", cli.submit));
+        output.println(format!("  scripts: {}", script_dir.display()));
+        if !io::stdin().is_terminal() {
+            return Err(
+                "stdin is not a terminal, so submission cannot be confirmed; pass --yes to submit anyway"
+                    .into(),
+            );
+        }
+        if !confirm(&format!("Submit {} jobs?", prepared.len())) {
+            if !cli.wrap {
+                output.eprintln(format!(
+                    "Generated scripts kept in {}",
+                    script_dir.display()
+                ));
+            }
+            return Err("submission aborted".into());
+        }
+    }
+
+    let run_preflight =
+        !cli.no_preflight && (cli.preflight || scheduler.test_only_args().is_some());
+    if let (false, true, Some(first)) = (cli.dry_run, run_preflight, plan.selected().next()) {
+        preflight(submitter, first, &output)?;
+    }
+
+    if cli.interrupt.is_interrupted() {
+        return Err(stopped_before_submitting(&cli, &script_dir, &mut output));
+    }
+
+    let mut state = initial_state(
+        &plan.submit,
+        scheduler,
+        run_id.clone(),
+        cli.profile.clone(),
+        &selected,
+        marker_dir.as_deref(),
+    );
+    incremental::record(&mut state, plan);
+    let save_state = |state: &RunState| -> Result<(), Box<dyn std::error::Error>> {
+        if recorded {
+            state.save_with(&cli.out_dir, &perms)?;
+        }
+        Ok(())
+    };
+    save_state(&state)?;
+    if recorded && cli.out_dir_timestamp {
+        let base = timestamp_base(&options.cli.out_dir);
+        if let Err(e) = runs::link_latest(&base, &cli.out_dir) {
+            output.eprintln(format!("warning: could not update the latest link: {}", e));
+        }
+    }
+    let mut last_save = Instant::now();
+
+    let mut submitted: Vec<SubmittedJob> = Vec::new();
+    let mut pending_removal: Vec<PathBuf> = Vec::new();
+    let mut failures: Vec<SubmitFailure> = Vec::new();
+    output.start_phase("submitting", prepared.len());
+    let jobs = plan.selected().take(prepared.len()).collect::<Vec<_>>();
+    let shared = submissions::Shared::default();
+    thread::scope(|scope| -> Result<(), Box<dyn std::error::Error>> {
+        let mut submissions = Submissions::new(
+            scope,
+            &shared,
+            &jobs,
+            submitter,
+            usize::from(cli.submit_parallel),
+            !cli.keep_going,
+            &cli.interrupt,
+        );
+        for (idx, batch) in prepared.iter().enumerate() {
+            let result = if cli.interrupt.is_interrupted() {
+                None
+            } else {
+                submissions.next(&mut |job, line| submit_stderr(&output, job, line))
+            };
+            let Some(result) = result else {
+                output.finish_phase();
+                record_stopped(
+                    &mut submissions,
+                    prepared,
+                    &mut state,
+                    &mut submitted,
+                    &output,
+                );
+                mark_not_submitted(&mut state, "interrupted");
+                save_state(&state)?;
+                return Err(interrupted(
+                    &cli,
+                    submitter,
+                    &state,
+                    &submitted,
+                    &script_dir,
+                    &output,
+                ));
+            };
+            let result = result.map_err(Box::<dyn std::error::Error>::from);
+            output.advance();
+            output.detail(format_args!(
+                "{}: argv {:?}",
+                batch.job_name,
+                batch.submission(&cli).argv(&batch.submit)
+            ));
+            // Scripts of failed or untracked submissions are always kept.
+            let mut keep_script = cli.keep;
+            match result {
+                Ok(Submitted { job_id, stdout }) => {
+                    output.job_output(&stdout);
+                    if job_id.is_none() && scheduler != Scheduler::Generic {
+                        output.eprintln(untracked_warning(&batch.job_name, &stdout));
+                        if let Some(path) = batch.script() {
+                            output.eprintln(format!("  script kept: {}", path.display()));
+                        }
+                        keep_script = true;
+                    }
+                    state.jobs[idx].record_submission(job_id.clone(), stdout);
+                    if last_save.elapsed() >= STATE_SAVE_INTERVAL {
+                        save_state(&state)?;
+                        last_save = Instant::now();
+                    }
+                    submitted.push(SubmittedJob {
+                        job_name: batch.job_name.clone(),
+                        job_id,
+                    });
+                }
+                Err(e) if cli.interrupt.is_interrupted() => {
+                    output.finish_phase();
+                    output.error(&e);
+                    record_stopped(
+                        &mut submissions,
+                        prepared,
+                        &mut state,
+                        &mut submitted,
+                        &output,
+                    );
+                    mark_not_submitted(&mut state, "interrupted");
+                    save_state(&state)?;
+                    return Err(interrupted(
+                        &cli,
+                        submitter,
+                        &state,
+                        &submitted,
+                        &script_dir,
+                        &output,
+                    ));
+                }
+                Err(e) if cli.cancel_on_failure => {
+                    output.finish_phase();
+                    state.jobs[idx].submit_error = Some(e.to_string());
+                    record_stopped(
+                        &mut submissions,
+                        prepared,
+                        &mut state,
+                        &mut submitted,
+                        &output,
+                    );
+                    save_state(&state)?;
+                    cancel_submitted(submitter, &submitted);
+                    if !cli.wrap {
+                        output.eprintln(format!(
+                            "Generated scripts kept in {}",
+                            script_dir.display()
+                        ));
+                    }
+                    return Err(e);
+                }
+                Err(e) if cli.keep_going => {
+                    output.error(&e);
+                    state.jobs[idx].submit_error = Some(e.to_string());
+                    failures.push(SubmitFailure {
+                        batch_index: batch.batch_index,
+                        job_name: batch.job_name.clone(),
+                        script: batch.script().map(Path::to_path_buf),
+                        error: e.to_string(),
+                    });
+                    if cli
+                        .max_submit_failures
+                        .is_some_and(|max| failures.len() > max)
+                    {
+                        let remaining = prepared.len() - submitted.len() - failures.len();
+                        output.error(format!(
+                        "Giving up after {} failed submission(s) (--max-submit-failures); {} batch(es) not attempted, scripts kept in {}",
+                        failures.len(),
+                        remaining,
+                        script_dir.display()
+                    ));
+                        break;
+                    }
+                    // The failed batch's script is kept for resubmission.
+                    continue;
+                }
+                Err(e) => {
+                    output.finish_phase();
+                    state.jobs[idx].submit_error = Some(e.to_string());
+                    record_stopped(
+                        &mut submissions,
+                        prepared,
+                        &mut state,
+                        &mut submitted,
+                        &output,
+                    );
+                    save_state(&state)?;
+                    return Err(e);
+                }
+            }
+
+            // Removed once every batch is in, so a failure can still be
+            // inspected against the scripts that were already submitted.
+            if let (false, Some(path)) = (keep_script, batch.script()) {
+                if !unchanged.contains(path) {
+                    pending_removal.push(path.to_path_buf());
+                }
+            }
+        }
+        // After --max-submit-failures gave up.
+        record_stopped(
+            &mut submissions,
+            prepared,
+            &mut state,
+            &mut submitted,
+            &output,
+        );
+        Ok(())
+    })?;
+    output.finish_phase();
+
+    if let (Some(email), Some(NotifyOnce::Sentinel)) = (&cli.notify, cli.notify_once) {
+        submit_notify_sentinel(
+            &cli,
+            scheduler,
+            submitter,
+            email,
+            &submitted,
+            &script_dir,
+            &output,
+        )?;
+    }
+
+    remove_scripts(&pending_removal, &cli, &output)?;
+
+    if recorded {
+        mark_not_submitted(&mut state, "not submitted");
+    }
+    save_state(&state)?;
+    drop(lock);
+    if recorded {
+        output.println(format!(
+            "Run state recorded in {}",
+            RunState::path(&cli.out_dir, &state.run_id).display()
+        ));
+        if cli.out_dir_timestamp {
+            output.println(format!(
+                "Pass --out-dir {} to later subcommands",
+                cli.out_dir.display()
+            ));
+        }
+    }
+
+    let wait = if recorded && cli.wait {
+        Some(wait_and_summarize(
+            &cli, scheduler, submitter, &state, &submitted, &output,
+        )?)
+    } else {
+        None
+    };
+    if let Some(summary) = &wait {
+        state.apply_wait(summary);
+        save_state(&state)?;
+    }
+
+    let report = RunReport { state, wait };
+    if !cli.dry_run {
+        reporter.report(&report);
+    }
+    if let Some(path) = &cli.report {
+        perms
+            .write(
+                path,
+                report.render(cli.report_format).as_bytes(),
+                perms::Kind::Artifact,
+            )
+            .map_err(|e| format!("could not write --report {}: {}", path.display(), e))?;
+        output.println(format!("Report written to {}", path.display()));
+    }
+
+    // Inputs of batches that failed to submit or, with --wait, whose
+    // command failed; written next to the run state with a script that
+    // reruns just them.
+    let mut failed_inputs = prepared
+        .iter()
+        .filter(|b| failures.iter().any(|f| f.batch_index == b.batch_index))
+        .flat_map(|b| &b.inputs)
+        .chain(report.wait.iter().flat_map(|w| &w.failed_inputs))
+        .cloned()
+        .collect::<Vec<_>>();
+    failed_inputs.sort();
+    failed_inputs.dedup();
+    if recorded && !failed_inputs.is_empty() {
+        let path = runs::run_dir(&cli.out_dir, &run_id).join(FAILED_INPUTS_FILE);
+        let lines = failed_inputs
+            .iter()
+            .map(|input| escape::encode(input))
+            .collect::<Vec<_>>();
+        perms
+            .write(
+                &path,
+                (lines.join("\n") + "\n").as_bytes(),
+                perms::Kind::Artifact,
+            )
+            .map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+        output.println(format!(
+            "{} failed input(s) written to {}",
+            failed_inputs.len(),
+            path.display()
+        ));
+        if let Some(rerun) = rerun::write(&cli.out_dir, &report.state, &submit_dir, &path, &perms)?
+        {
+            output.println(format!("Rerun them with {}", rerun.display()));
+        }
+    }
+
+    if !failures.is_empty() {
+        output.eprintln(format!("{} submission(s) failed:", failures.len()));
+        for failure in &failures {
+            output.eprintln(format!("  {}: {}", failure.job_name, failure.error));
+        }
+        if recorded {
+            output.eprintln(format!(
+                "Failed submissions recorded in {}; `batchelor resubmit` picks them up",
+                RunState::path(&cli.out_dir, &report.state.run_id).display()
+            ));
+        }
+    }
+    if !cli.dry_run {
+        hooks::run_hooks(
+            cli.on_complete.as_deref(),
+            cli.on_failure.as_deref(),
+            cli.webhook_url.as_deref(),
+            &report,
+            &output,
+        );
+    }
+
+    if failures.is_empty() {
+        return Ok(report);
+    }
+    Err(BatchelorError::PartialFailure {
+        failed: failures.len(),
+        attempted: prepared.len(),
+    }
+    .into())
+}
+
+/// Shows a line a submit command wrote to stderr, as it does.
+fn submit_stderr(output: &Output, job_name: &str, line: &str) {
+    output.eprintln(format!("[{}] {}", job_name, line));
+}
+
+/// Records what `--submit-parallel` submitted ahead of where the run
+/// stopped, so those jobs are tracked and cancelled like the others. Their
+/// scripts are kept.
+fn record_stopped(
+    submissions: &mut Submissions,
+    prepared: &[PreparedBatch],
+    state: &mut RunState,
+    submitted: &mut Vec<SubmittedJob>,
+    output: &Output,
+) {
+    for (idx, result) in submissions.stop(&mut |job, line| submit_stderr(output, job, line)) {
+        match result {
+            Ok(Submitted { job_id, stdout }) => {
+                output.job_output(&stdout);
+                state.jobs[idx].record_submission(job_id.clone(), stdout);
+                submitted.push(SubmittedJob {
+                    job_name: prepared[idx].job_name.clone(),
+                    job_id,
+                });
+            }
+            Err(e) => state.jobs[idx].submit_error = Some(e.to_string()),
+        }
+    }
+}
+
+/// A generated batch waiting to be submitted.
+struct PreparedBatch {
+    batch_index: usize,
+    job_name: String,
+    inputs: Vec<String>,
+    input_bytes: u64,
+    /// Stdout log as recorded in the run manifest.
+    log: Option<PathBuf>,
+    /// `--submit`, or its `--submit-overrides` replacement for this batch.
+    submit: String,
+    extra_args: Vec<String>,
+    body: BatchBody,
+}
+
+enum BatchBody {
+    Script(PathBuf),
+    /// Command block for `--wrap`; nothing is written to disk.
+    Wrap(String),
+}
+
+impl PreparedBatch {
+    fn from_spec(spec: &JobSpec) -> PreparedBatch {
+        PreparedBatch {
+            batch_index: spec.batch_index,
+            job_name: spec.job_name.clone(),
+            inputs: spec.inputs.clone(),
+            input_bytes: spec.input_bytes,
+            log: spec.log.clone(),
+            submit: spec.submit_command.clone(),
+            extra_args: spec.submit_args.clone(),
+            body: match &spec.script {
+                Some(path) => BatchBody::Script(path.clone()),
+                None => BatchBody::Wrap(wrap_commands(&spec.commands)),
+            },
+        }
+    }
+
+    fn script(&self) -> Option<&Path> {
+        match &self.body {
+            BatchBody::Script(path) => Some(path),
+            BatchBody::Wrap(_) => None,
+        }
+    }
+
+    fn submission<'a>(&'a self, cli: &Cli) -> Submission<'a> {
+        let payload = match &self.body {
+            BatchBody::Script(path) => script_payload(cli, path),
+            BatchBody::Wrap(commands) => JobPayload::Wrap(commands),
+        };
+        Submission {
+            job_name: &self.job_name,
+            extra_args: self.extra_args.clone(),
+            payload,
+        }
+    }
+}
+
+/// Writes the `--emit` artifacts for `inputs`; nothing is submitted.
+fn emit_plan(
+    cli: &Cli,
+    scheduler: Scheduler,
+    script: &Path,
+    inputs: &[String],
+    sizes: &[u64],
+    reporter: &dyn Reporter,
+) -> Result<RunReport, Box<dyn std::error::Error>> {
+    let command_spec = shellgen::CommandSpec {
+        script,
+        input_flag: &input_flag(cli)?,
+        script_args: &cli.script_args,
+        overflow: slot_overflow(cli),
+    };
+    let job = |name: String, inputs: &[String], sizes: &[u64], multi_input: bool| {
+        let bytes = sizes.iter().sum();
+        EmitJob {
+            name,
+            inputs: inputs.to_vec(),
+            commands: shellgen::command_lines(&command_spec, inputs, multi_input),
+            mem: scaled_request(cli.mem_base, cli.mem_per_byte, cli.mem_cap, bytes),
+            time: scaled_request(cli.time_base, cli.time_per_byte, cli.time_cap, bytes),
+        }
+    };
+    let per_input = || {
+        inputs
+            .iter()
+            .zip(sizes)
+            .enumerate()
+            .map(|(idx, (input, size))| {
+                job(
+                    format!("input_{:06}", idx + 1),
+                    std::slice::from_ref(input),
+                    std::slice::from_ref(size),
+                    false,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let batch_count = clamp_batches(cli, inputs.len(), false, &mut |warning| {
+        reporter.diagnostic(&warning)
+    })?;
+    let batches = || {
+        split_evenly(inputs, batch_count)
+            .into_iter()
+            .zip(split_evenly(sizes, batch_count))
+            .enumerate()
+            .map(|(idx, (chunk, sizes))| {
+                job(
+                    format!("batch_{:04}", idx + 1),
+                    chunk,
+                    sizes,
+                    cli.multi_input,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let stamp_dir = std::path::absolute(cli.out_dir.join("stamps"))?;
+    for (format, path) in emit::parse_emit(&cli.emit)? {
+        let (files, summary) = match format {
+            EmitFormat::Make => {
+                let jobs = per_input();
+                let (text, warnings) = emit::makefile(&jobs, &stamp_dir);
+                for warning in warnings {
+                    reporter.diagnostic(&format!("warning: {}", warning));
+                }
+                (
+                    vec![(path.clone(), text)],
+                    format!("{} target(s)", jobs.len()),
+                )
+            }
+            EmitFormat::Snakemake => {
+                let jobs = if cli.per_input {
+                    per_input()
+                } else {
+                    batches()
+                };
+                let text = emit::snakefile(&jobs, &stamp_dir);
+                (
+                    vec![(path.clone(), text)],
+                    format!("{} rule(s)", jobs.len()),
+                )
+            }
+            EmitFormat::Nextflow => {
+                let command = shellgen::build_command_line(
+                    &command_spec,
+                    &[emit::INPUT_PLACEHOLDER.to_string()],
+                );
+                fs::create_dir_all(&path)?;
+                let files = vec![
+                    (path.join("samplesheet.csv"), emit::samplesheet(inputs)),
+                    (path.join("main.nf"), emit::main_nf(&command)),
+                ];
+                (files, format!("{} sample(s)", inputs.len()))
+            }
+        };
+        for (file, text) in files {
+            fs::write(&file, text)
+                .map_err(|e| format!("could not write --emit {}: {}", file.display(), e))?;
+        }
+        reporter.message(&format!(
+            "--emit {}: wrote {} ({})",
+            format.name(),
+            path.display(),
+            summary
+        ));
+    }
+    let state = initial_state(
+        &cli.submit,
+        scheduler,
+        cli.run_id.clone().unwrap_or_else(runs::new_run_id),
+        cli.profile.clone(),
+        &[],
+        None,
+    );
+    Ok(RunReport { state, wait: None })
+}
+
+/// The run state before anything is submitted: one job per prepared batch.
+fn initial_state(
+    submit: &str,
+    scheduler: Scheduler,
+    run_id: String,
+    profile: Option<String>,
+    prepared: &[PreparedBatch],
+    marker_dir: Option<&Path>,
+) -> RunState {
+    let jobs = prepared
+        .iter()
+        .map(|batch| {
+            let markers = marker_dir.map(|dir| failures::marker_paths(dir, &batch.job_name));
+            JobState {
+                batch_index: batch.batch_index,
+                job_name: batch.job_name.clone(),
+                script: batch.script().map(Path::to_path_buf),
+                log: batch.log.clone(),
+                done_file: markers.as_ref().map(|(done, _)| done.clone()),
+                failed_file: markers.map(|(_, failed)| failed),
+                input_bytes: batch.input_bytes,
+                inputs: batch
+                    .inputs
+                    .iter()
+                    .map(|path| InputState {
+                        path: path.clone(),
+                        status: None,
+                        fingerprint: None,
+                    })
+                    .collect(),
+                ..JobState::default()
+            }
+        })
+        .collect();
+    RunState {
+        schema_version: state::SCHEMA_VERSION,
+        run_id,
+        timestamp: Some(chrono::Local::now().to_rfc3339()),
+        args: std::env::args_os()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect(),
+        profile,
+        submit: submit.to_string(),
+        scheduler,
+        script_format: script_format::CURRENT,
+        jobs,
+        unchanged: Vec::new(),
+    }
+}
+
+/// Waits for the submitted jobs (`--wait`) and prints the summary.
+fn wait_and_summarize(
+    cli: &Cli,
+    scheduler: Scheduler,
+    submitter: &dyn Submitter,
+    state: &RunState,
+    submitted: &[SubmittedJob],
+    output: &Output,
+) -> Result<WaitSummary, Box<dyn std::error::Error>> {
+    let job_ids = submitted
+        .iter()
+        .filter_map(|j| Some((j.job_name.as_str(), j.job_id.as_deref()?)))
+        .collect::<HashMap<_, _>>();
+    let untracked = submitted.len() - job_ids.len();
+    if untracked > 0 {
+        output.eprintln(format!(
+            "warning: {} job(s) have no job ID and are not waited for",
+            untracked
+        ));
+    }
+    let jobs = state
+        .jobs
+        .iter()
+        .filter_map(|job| {
+            Some(WaitedJob {
+                job,
+                job_id: job_ids.get(job.job_name.as_str())?,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let summary = wait::wait_for_jobs(
+        scheduler,
+        &cli.out_dir,
+        &jobs,
+        wait::Polling {
+            interval: Duration::from_secs(cli.wait_interval.max(1)),
+            status_command: cli.status_command.as_deref(),
+            interrupt: &cli.interrupt,
+        },
+        wait::PendingLimits {
+            warn: cli.pending_warn,
+            fail: cli.pending_fail,
+        },
+        &mut |statuses| {
+            let Some(path) = &cli.metrics_out else {
+                return;
+            };
+            let text = metrics::render(state, statuses, &cli.out_dir, chrono::Utc::now());
+            if let Err(e) = metrics::write(path, &text) {
+                output.eprintln(format!("warning: {}", e));
+            }
+        },
+        output.reporter(),
+    )?;
+    if !summary.stuck.is_empty() {
+        output.eprintln("Stopped waiting: jobs pending longer than --pending-fail");
+        for line in &summary.stuck {
+            output.eprintln(format!("  {}", line));
+        }
+        if cli.cancel_on_failure {
+            let unfinished = submitted
+                .iter()
+                .filter(|j| {
+                    j.job_id
+                        .as_ref()
+                        .and_then(|id| summary.jobs.get(id))
+                        .is_some_and(|s| !scheduler::is_final_state(&s.state))
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            cancel_submitted(submitter, &unfinished);
+        } else {
+            output.eprintln("The jobs are still queued; `batchelor cancel` removes them");
+        }
+    }
+    output.println(summary.line());
+    Ok(summary)
+}
+
+/// Whether to ask before submitting `jobs` jobs: always with --confirm,
+/// otherwise only for large runs started from a terminal.
+fn needs_confirmation(cli: &Cli, jobs: usize) -> bool {
+    if cli.yes || jobs == 0 {
+        return false;
+    }
+    cli.confirm || (jobs > cli.confirm_above && io::stdout().is_terminal())
+}
+
+/// Has the submitter check `batch` so that scheduler validation errors
+/// (missing account, unknown partition, ...) surface before anything real
+/// is queued.
+fn preflight(
+    submitter: &dyn Submitter,
+    batch: &JobSpec,
+    output: &Output,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let checked = submitter
+        .preflight(batch)
+        .map_err(|e| format!("preflight check failed, nothing was submitted: {}", e))?;
+    match checked {
+        Preflight::NotChecked => {}
+        Preflight::Accepted => {
+            output.println(format!("Preflight check passed ({})", batch.job_name));
+        }
+        Preflight::ProbeCancelled(job_id) => output.println(format!(
+            "Preflight check passed ({}, probe job {} cancelled)",
+            batch.job_name, job_id
+        )),
+    }
+    Ok(())
+}
+
+/// The scheduler's `--parsable`-style flags, unless `--submit` already
+/// passes them.
+pub(crate) fn parsable_args(scheduler: Scheduler, submit_parts: &[String]) -> Vec<String> {
+    let args = scheduler.parsable_args();
+    if args.iter().any(|a| submit_parts.contains(a)) {
+        return Vec::new();
+    }
+    args
+}
+
+pub(crate) fn untracked_warning(job_name: &str, stdout: &str) -> String {
+    format!(
+        "warning: no job ID found in the submit output for {} ({:?}); it is recorded as untracked",
+        job_name,
+        stdout.trim()
+    )
+}
+
+/// Ends a run interrupted before it submitted anything.
+fn stopped_before_submitting(
+    cli: &Cli,
+    script_dir: &Path,
+    output: &mut Output,
+) -> Box<dyn std::error::Error> {
+    output.finish_phase();
+    output.eprintln("Interrupted; nothing was submitted.");
+    if !cli.wrap {
+        output.eprintln(format!(
+            "Generated scripts kept in {}",
+            script_dir.display()
+        ));
+    }
+    BatchelorError::Interrupted.into()
+}
+
+/// Marks the batches of `state` that were neither submitted nor failed,
+/// so `batchelor resubmit` picks them up.
+fn mark_not_submitted(state: &mut RunState, reason: &str) {
+    for job in &mut state.jobs {
+        if !job.submitted() && job.submit_error.is_none() {
+            job.submit_error = Some(reason.to_string());
+        }
+    }
+}
+
+/// Says how far an interrupted run got, with `--cancel-on-failure` offers
+/// to cancel what was already submitted, and returns the error that ends
+/// the run. The run's state is saved.
+fn interrupted(
+    cli: &Cli,
+    submitter: &dyn Submitter,
+    state: &RunState,
+    submitted: &[SubmittedJob],
+    script_dir: &Path,
+    output: &Output,
+) -> Box<dyn std::error::Error> {
+    let pending = state.jobs.iter().filter(|j| !j.submitted()).count();
+    output.eprintln(format!(
+        "Interrupted after submitting {} of {} job(s); {} not submitted.",
+        submitted.len(),
+        state.jobs.len(),
+        pending
+    ));
+    output.eprintln(format!(
+        "Run state recorded in {}; `batchelor resubmit` submits the rest",
+        RunState::path(&cli.out_dir, &state.run_id).display()
+    ));
+    if cli.cancel_on_failure
+        && !submitted.is_empty()
+        && (cli.yes || confirm(&format!("Cancel the {} submitted job(s)?", submitted.len())))
+    {
+        cancel_submitted(submitter, submitted);
+    }
+    if !cli.wrap {
+        output.eprintln(format!(
+            "Generated scripts kept in {}",
+            script_dir.display()
+        ));
+    }
+    BatchelorError::Interrupted.into()
+}
+
+/// Asks a yes/no question on stderr and reads the answer from stdin.
+/// Anything other than an explicit yes counts as no.
+fn confirm(question: &str) -> bool {
+    eprint!("{} [y/N] ", question);
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
 
-    /// Directory where generated batch scripts are stored.
-    #[arg(long, default_value = ".batchelor")]
-    out_dir: PathBuf,
+/// Fails fast when the --submit program cannot be found, before any
+/// previous scripts are cleaned up or new ones are written.
+fn check_submit_program(submit: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let parts = shlex::split(submit).ok_or_else(|| {
+        format!(
+            "could not parse --submit command string (check shell quoting): {}",
+            submit
+        )
+    })?;
+    let program = parts.first().ok_or("--submit cannot be empty")?;
+    if which::which(program).is_some() {
+        return Ok(());
+    }
+    let hint = match which::closest_program(program) {
+        Some(suggestion) => format!(" (did you mean {}?)", suggestion),
+        None => String::new(),
+    };
+    Err(format!(
+        "submit command not found: {}{}; use --skip-submit-check if it exists only where jobs are submitted",
+        program, hint
+    )
+    .into())
+}
 
-    /// Submission command, e.g. "sbatch --mem=50G --mincpus 1" or "bash".
-    #[arg(long, default_value = "sbatch")]
-    submit: String,
+/// Errors out when `what` needs a scheduler but --submit is a plain command.
+fn require_scheduler(
+    cli: &Cli,
+    scheduler: Scheduler,
+    what: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if scheduler != Scheduler::Generic {
+        return Ok(());
+    }
+    Err(BatchelorError::InvalidOptions(format!(
+        "{} needs a scheduler submit command (sbatch, qsub, bsub) or --scheduler, got --submit {:?}",
+        what, cli.submit
+    ))
+    .into())
+}
 
-    /// Prefix for generated job names.
-    #[arg(long, default_value = "batch")]
-    job_name_prefix: String,
+/// Submits a no-op job that depends on every submitted batch and carries
+/// the `--notify` settings, so the run produces a single mail.
+fn submit_notify_sentinel(
+    cli: &Cli,
+    scheduler: Scheduler,
+    submitter: &dyn Submitter,
+    email: &str,
+    submitted: &[SubmittedJob],
+    script_dir: &Path,
+    output: &Output,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let prefix = job_name_prefix(cli);
+    let job_name = format!("{}-notify", prefix);
+    let ids = if cli.dry_run {
+        vec!["<job-ids>"]
+    } else {
+        submitted
+            .iter()
+            .filter_map(|j| j.job_id.as_deref())
+            .collect::<Vec<_>>()
+    };
+    if ids.is_empty() {
+        output.eprintln("warning: no job IDs were captured; skipping the --notify-once job");
+        return Ok(());
+    }
+    if !cli.dry_run && ids.len() < submitted.len() {
+        output.eprintln(format!(
+            "warning: only {} of {} job IDs were captured; the --notify-once job may run early",
+            ids.len(),
+            submitted.len()
+        ));
+    }
 
-    /// Additional args passed to your script for each invocation.
-    #[arg(long, num_args = 1.., trailing_var_arg = true)]
-    script_args: Vec<String>,
+    let mut directives = scheduler.job_name_args(&job_name);
+    directives.extend(scheduler.notify_args(email, &cli.notify_on));
+    let mut extra_args = parsable_args(scheduler, &shlex::split(&cli.submit).unwrap_or_default());
+    if !cli.no_auto_job_name {
+        extra_args.extend(scheduler.job_name_args(&job_name));
+    }
+    extra_args.extend(scheduler.after_all_args(&ids));
 
-    /// Print what would be submitted without running the submit command.
-    #[arg(long)]
-    dry_run: bool,
+    let commands = vec![format!(
+        "echo {}",
+        shellgen::quote(&format!("all batches of {} have ended", prefix))
+    )];
+    let (script, directives) = if cli.wrap {
+        extra_args.extend(scheduler.notify_args(email, &cli.notify_on));
+        (None, Vec::new())
+    } else {
+        let path = script_dir.join(format!("{}.batch.sh", job_name));
+        let directives = directive_lines(scheduler, &directives);
+        write_job_script(&path, &[], &directives, &commands, &permissions(cli)?)?;
+        (Some(path), directives)
+    };
+    let mut spec = JobSpec {
+        batch_index: 0,
+        job_name,
+        inputs: Vec::new(),
+        input_bytes: 0,
+        header: Vec::new(),
+        directives,
+        commands,
+        script,
+        input_list: None,
+        log: None,
+        submit_command: cli.submit.clone(),
+        submit_args: extra_args,
+        submit: String::new(),
+        selected: true,
+    };
+    let batch = PreparedBatch::from_spec(&spec);
+    spec.submit = batch.submission(cli).shell_line(&cli.submit);
 
-    /// Keep generated intermediate batch scripts after successful submission.
-    #[arg(long)]
-    keep: bool,
+    if cli.dry_run {
+        output.println(format!("[dry-run] {}", spec.submit));
+        return Ok(());
+    }
+    let accepted = submitter.submit(&spec)?;
+    output.job_output(&accepted.stdout);
+    if let (Some(path), false) = (&spec.script, cli.keep) {
+        remove_scripts(std::slice::from_ref(path), cli, output)?;
+    }
+    Ok(())
+}
 
-    /// Call script once per batch with all inputs instead of once per input.
-    #[arg(long)]
-    multi_input: bool,
+/// Removes the scripts of submitted batches. The jobs are in by now, so a
+/// script that cannot be removed (e.g. in a shared `--out-dir`) is only
+/// warned about, unless `strict` (`--clean-strict`); one that is no longer
+/// batchelor's is left alone, and so are all scripts in a directory
+/// [`safety`] protects, unless `--force-clean`.
+fn remove_scripts(
+    paths: &[PathBuf],
+    cli: &Cli,
+    output: &Output,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let strict = cli.clean_strict;
+    let mut failed = 0;
+    // Per directory, why removing from it is refused and how many scripts
+    // were kept there.
+    let mut protected = BTreeMap::<&Path, Option<(String, usize)>>::new();
+    for path in paths {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let refused = protected.entry(dir).or_insert_with(|| {
+            safety::check_cleanup(dir, cli.force_clean)
+                .err()
+                .map(|reason| (reason, 0))
+        });
+        if let Some((_, kept)) = refused {
+            *kept += 1;
+            continue;
+        }
+        if !is_generated_script(path) {
+            log::debug!(
+                "not removing {}: not generated by batchelor",
+                path.display()
+            );
+            continue;
+        }
+        if let Err(e) = fs::remove_file(path) {
+            let message = format!("could not remove {}: {}", path.display(), e);
+            if strict {
+                return Err(format!("{} (--clean-strict)", message).into());
+            }
+            output.eprintln(format!("warning: {}", message));
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        output.eprintln(format!(
+            "warning: {} script(s) were left behind; `batchelor clean` removes them later",
+            failed
+        ));
+    }
+    for (reason, kept) in protected.values().flatten() {
+        output.eprintln(format!(
+            "warning: {} submitted script(s) NOT removed: {}",
+            kept, reason
+        ));
+    }
+    Ok(())
 }
 
-pub fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
-    if cli.batch == 0 {
-        return Err("--batch must be >= 1".into());
+/// Refuses a `--script` the jobs could not run: one that is not a regular
+/// file, cannot be read or is empty, and with `--check-script` one
+/// `bash -n` finds a syntax error in. Scripts with a shebang naming
+/// another interpreter are not syntax-checked; see [`script_warning`].
+fn validate_script(cli: &Cli) -> Result<(), BatchelorError> {
+    let invalid = |reason: String| BatchelorError::ScriptInvalid {
+        path: cli.script.clone(),
+        reason,
+    };
+    let metadata = match fs::metadata(&cli.script) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(BatchelorError::ScriptMissing(cli.script.clone()));
+        }
+        Err(e) => return Err(invalid(format!("cannot be checked: {}", e))),
+    };
+    if metadata.is_dir() {
+        return Err(invalid("is a directory, not a script".to_string()));
+    }
+    if !metadata.is_file() {
+        return Err(invalid("is not a regular file".to_string()));
     }
+    fs::File::open(&cli.script).map_err(|e| invalid(format!("cannot be read: {}", e)))?;
+    if metadata.len() == 0 {
+        return Err(invalid("is empty".to_string()));
+    }
+    if cli.check_script && foreign_interpreter(&cli.script).is_none() {
+        let checked = Command::new("bash")
+            .arg("-n")
+            .arg(&cli.script)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| invalid(format!("could not run bash -n: {}", e)))?;
+        if !checked.status.success() {
+            return Err(invalid(format!(
+                "bash -n found a syntax error (--check-script):\n{}",
+                String::from_utf8_lossy(&checked.stderr).trim_end()
+            )));
+        }
+    }
+    Ok(())
+}
 
-    if !cli.script.exists() {
-        return Err(format!("script does not exist: {}", cli.script.display()).into());
+/// The interpreter a script's shebang names when it is not bash or sh.
+fn foreign_interpreter(script: &Path) -> Option<String> {
+    let file = fs::File::open(script).ok()?;
+    let mut first = String::new();
+    io::BufReader::new(file).read_line(&mut first).ok()?;
+    let shebang = first.strip_prefix("#!")?;
+    let mut words = shebang.split_whitespace();
+    let mut program = words.next()?;
+    if Path::new(program)
+        .file_name()
+        .is_some_and(|name| name == "env")
+    {
+        program = words.find(|word| !word.starts_with('-'))?;
     }
+    let name = Path::new(program)
+        .file_name()?
+        .to_string_lossy()
+        .into_owned();
+    (!matches!(name.as_str(), "bash" | "sh")).then_some(name)
+}
 
-    let script_abs = fs::canonicalize(&cli.script)?;
-    let mut inputs = expand_inputs(&cli.glob)?;
+/// A warning about a script the jobs run with bash although its shebang
+/// names another interpreter.
+fn script_warning(cli: &Cli) -> Option<String> {
+    foreign_interpreter(&cli.script).map(|name| {
+        format!(
+            "warning: --script {} starts with a {} shebang, but the jobs run it with bash",
+            cli.script.display(),
+            name
+        )
+    })
+}
+
+fn validate_wrap(cli: &Cli, scheduler: Scheduler) -> Result<(), Box<dyn std::error::Error>> {
+    if scheduler != Scheduler::Slurm {
+        return Err(format!(
+            "--wrap requires an sbatch submit command, got --submit {:?}",
+            cli.submit
+        )
+        .into());
+    }
+    if cli.keep {
+        return Err("--wrap does not write batch scripts, so --keep has nothing to keep".into());
+    }
+    Ok(())
+}
 
-    if inputs.is_empty() {
-        return Err(format!("no inputs matched from --glob {:?}", cli.glob).into());
+/// Hands a generated script to the submitter as an argument or, with
+/// `--submit-stdin`, on stdin.
+fn script_payload<'a>(cli: &Cli, path: &'a Path) -> JobPayload<'a> {
+    if cli.submit_stdin {
+        JobPayload::Stdin(path)
+    } else {
+        JobPayload::Script(path)
     }
+}
 
-    inputs.sort();
-    fs::create_dir_all(&cli.out_dir)?;
-    cleanup_old_batch_scripts(&cli.out_dir, &cli.job_name_prefix)?;
+/// Joins rendered command lines into the block passed to `sbatch --wrap`.
+fn wrap_commands(commands: &[String]) -> String {
+    let mut text = String::from("set -eu");
+    for command in commands {
+        text.push('\n');
+        text.push_str(command);
+    }
+    text
+}
 
-    let batch_count = cli.batch.min(inputs.len());
-    println!(
-        "Found {} input files. Creating {} job(s).",
-        inputs.len(),
-        batch_count
-    );
+/// The placeholders of `--job-log-template`.
+const LOG_PLACEHOLDERS: &[&str] = &["job_name", "batch_index"];
 
-    let groups = split_evenly(&inputs, batch_count);
-    for (idx, chunk) in groups.iter().enumerate() {
-        let batch_idx = idx + 1;
-        let job_name = format!("{}-{:04}", cli.job_name_prefix, batch_idx);
-        let job_script_path = cli.out_dir.join(format!("{}.batch.sh", job_name));
-
-        write_job_script(
-            &job_script_path,
-            &script_abs,
-            &cli.input_flag,
-            chunk,
-            &cli.script_args,
-            cli.multi_input,
-        )?;
+/// Expands a parsed `--job-log-template` for one batch, returning the
+/// stdout log path and, with `split_stderr`, the matching stderr path.
+fn job_log_paths(
+    dir: &Path,
+    template: &[placeholder::Segment],
+    job_name: &str,
+    batch_idx: usize,
+    split_stderr: bool,
+) -> (PathBuf, Option<PathBuf>) {
+    let name = placeholder::render(template, |name| match name {
+        "job_name" => job_name.to_string(),
+        _ => batch_idx.to_string(),
+    });
+    let stderr = split_stderr.then(|| {
+        let err_name = match name.strip_suffix(".out") {
+            Some(stem) => format!("{}.err", stem),
+            None => format!("{}.err", name),
+        };
+        dir.join(err_name)
+    });
+    (dir.join(name), stderr)
+}
 
-        if cli.dry_run {
-            println!(
-                "[dry-run] {} {}",
-                cli.submit,
-                shell_quote_path(&job_script_path)
-            );
-        } else {
-            submit_job(&cli.submit, &job_script_path)?;
-            if !cli.keep {
-                fs::remove_file(&job_script_path)?;
+/// Renders scheduler submit flags as `#SBATCH ...`-style header lines, one
+/// line per option (a flag plus any values that follow it).
+fn directive_lines(scheduler: Scheduler, directives: &[String]) -> Vec<String> {
+    let Some(prefix) = scheduler.directive_prefix() else {
+        return Vec::new();
+    };
+    let mut lines: Vec<String> = Vec::new();
+    for arg in directives {
+        match lines.last_mut() {
+            Some(line) if !arg.starts_with('-') => {
+                line.push(' ');
+                line.push_str(&shellgen::quote(arg));
             }
+            _ => lines.push(format!("{} {}", prefix, shellgen::quote(arg))),
         }
     }
+    lines
+}
 
-    Ok(())
+/// Computes `base + ratio × bytes`, capped at `cap`. Returns `None` when
+/// neither a base nor a ratio was given.
+fn scaled_request(
+    base: Option<u64>,
+    ratio: Option<f64>,
+    cap: Option<u64>,
+    bytes: u64,
+) -> Option<u64> {
+    if base.is_none() && ratio.is_none() {
+        return None;
+    }
+    let scaled = (ratio.unwrap_or(0.0) * bytes as f64).ceil() as u64;
+    let value = base.unwrap_or(0).saturating_add(scaled);
+    Some(cap.map_or(value, |cap| value.min(cap)))
 }
 
+/// The number of batches for `inputs` inputs: `--batch`, or fewer as
+/// `--on-clamp` says when there are not enough inputs. Padding needs
+/// `can_pad`; `--emit` has no use for batches without inputs.
+fn clamp_batches(
+    cli: &Cli,
+    inputs: usize,
+    can_pad: bool,
+    warn: &mut dyn FnMut(String),
+) -> Result<usize, BatchelorError> {
+    if cli.batch <= inputs {
+        return Ok(cli.batch);
+    }
+    match cli.on_clamp {
+        OnClamp::Warn => {
+            warn(format!(
+                "warning: --batch {} is more than the {} input(s); creating {} batch(es) (--on-clamp pad keeps all {})",
+                cli.batch, inputs, inputs, cli.batch
+            ));
+            Ok(inputs)
+        }
+        OnClamp::Error => Err(BatchelorError::InvalidOptions(format!(
+            "--batch {} is more than the {} input(s) (--on-clamp error)",
+            cli.batch, inputs
+        ))),
+        OnClamp::Pad if can_pad => Ok(cli.batch),
+        OnClamp::Pad => Err(BatchelorError::InvalidOptions(format!(
+            "--batch {} is more than the {} input(s), and --emit cannot pad with empty batches",
+            cli.batch, inputs
+        ))),
+    }
+}
+
+/// `items` in `groups` consecutive slices whose lengths differ by at most
+/// one; the last ones are empty when there are fewer items than groups.
 fn split_evenly<T>(items: &[T], groups: usize) -> Vec<&[T]> {
+    if groups == 0 {
+        return Vec::new();
+    }
     let mut out = Vec::new();
     let base = items.len() / groups;
     let remainder = items.len() % groups;
@@ -128,157 +3073,280 @@ fn split_evenly<T>(items: &[T], groups: usize) -> Vec<&[T]> {
     out
 }
 
-fn cleanup_old_batch_scripts(out_dir: &Path, job_name_prefix: &str) -> io::Result<()> {
-    let prefix = format!("{}-", job_name_prefix);
-    for entry in fs::read_dir(out_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-        if !path.is_file() {
-            continue;
-        }
-        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
-            continue;
-        };
-        if name.starts_with(&prefix) && name.ends_with(".batch.sh") {
-            fs::remove_file(path)?;
-        }
+/// Start of the comment line marking a script as generated by batchelor;
+/// files without it are never overwritten or cleaned up.
+pub(crate) const GENERATED_MARKER: &str = "# generated-by: batchelor";
+
+/// The text of a batch script with the comment lines of `header`,
+/// `directives` and `commands`.
+pub(crate) fn render_job_script(
+    header: &[String],
+    directives: &[String],
+    commands: &[String],
+) -> String {
+    let mut text = Vec::new();
+    // Writing to a Vec does not fail.
+    let _ = write_job_script_text(&mut text, header, directives, commands);
+    String::from_utf8_lossy(&text).into_owned()
+}
+
+/// Writes the text [`render_job_script`] returns to `out`, line by line.
+fn write_job_script_text(
+    out: &mut dyn Write,
+    header: &[String],
+    directives: &[String],
+    commands: &[String],
+) -> io::Result<()> {
+    writeln!(out, "#!/usr/bin/env bash")?;
+    writeln!(out, "{} {}", GENERATED_MARKER, env!("CARGO_PKG_VERSION"))?;
+    writeln!(out, "{}", script_format::header_line())?;
+    for line in header.iter().chain(directives) {
+        writeln!(out, "{}", line)?;
+    }
+    writeln!(out, "set -euo pipefail\n")?;
+    for command in commands {
+        writeln!(out, "{}", command)?;
     }
     Ok(())
 }
 
-fn expand_inputs(patterns: &[String]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    let mut out = Vec::new();
+/// Fails with [`BatchelorError::OutDirNotWritable`] unless `dir` can be
+/// created and written to: a small file is written, synced and removed.
+fn probe_out_dir(dir: &Path, perms: &Permissions) -> Result<(), BatchelorError> {
+    let probe = dir.join(format!(".batchelor-probe-{}", std::process::id()));
+    let write = || -> io::Result<()> {
+        perms.create_dir_all(dir)?;
+        let mut file = fs::File::create(&probe)?;
+        file.write_all(b"batchelor\n")?;
+        file.sync_all()?;
+        drop(file);
+        fs::remove_file(&probe)
+    };
+    write().map_err(|source| {
+        let _ = fs::remove_file(&probe);
+        BatchelorError::OutDirNotWritable {
+            dir: dir.to_path_buf(),
+            source,
+        }
+    })
+}
 
-    for pattern in patterns {
-        if has_glob_meta(pattern) {
-            for entry in glob(pattern)? {
-                match entry {
-                    Ok(path) => {
-                        let normalized = if path.exists() {
-                            fs::canonicalize(path)?
-                                .to_string_lossy()
-                                .into_owned()
-                        } else {
-                            path.to_string_lossy().into_owned()
-                        };
-                        out.push(normalized);
-                    }
-                    Err(e) => return Err(Box::new(e)),
-                }
-            }
-        } else {
-            let path = Path::new(pattern);
-            if path.exists() {
-                out.push(fs::canonicalize(path)?.to_string_lossy().into_owned());
-            } else {
-                out.push(pattern.to_string());
+/// Makes a failure to write a script (or its input list) for lack of space
+/// or quota a [`BatchelorError::OutOfSpace`], saying how many of the
+/// `total` scripts were `written`.
+fn out_of_space(e: BatchelorError, written: usize, total: usize) -> BatchelorError {
+    match e {
+        BatchelorError::ScriptWriteError { path, source }
+            if matches!(
+                source.kind(),
+                io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded
+            ) =>
+        {
+            BatchelorError::OutOfSpace {
+                path,
+                written,
+                total,
+                source,
             }
         }
+        e => e,
     }
+}
 
-    Ok(out)
+/// The permissions `--script-mode`, `--artifact-mode` and `--group` ask
+/// for.
+fn permissions(cli: &Cli) -> Result<Permissions, String> {
+    Permissions::new(cli.script_mode, cli.artifact_mode, cli.group.as_deref())
 }
 
 fn write_job_script(
     output_path: &Path,
-    script: &Path,
-    input_flag: &str,
+    header: &[String],
+    directives: &[String],
+    commands: &[String],
+    perms: &Permissions,
+) -> Result<(), BatchelorError> {
+    let failed = |source| BatchelorError::ScriptWriteError {
+        path: output_path.to_path_buf(),
+        source,
+    };
+    if output_path.exists() && !is_generated_script(output_path) {
+        return Err(failed(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "refusing to overwrite it: it was not generated by batchelor",
+        )));
+    }
+    // Streamed, so a batch of a million inputs is not held twice.
+    write_file_atomic_with(output_path, perms::Kind::Script, perms, |out| {
+        write_job_script_text(out, header, directives, commands)
+    })
+    .map_err(failed)
+}
+
+/// Writes the `--max-command-bytes` list of a batch's inputs, one per line.
+fn write_input_list(
+    path: &Path,
     inputs: &[String],
-    script_args: &[String],
-    multi_input: bool,
-) -> io::Result<()> {
-    let mut text = String::new();
-    text.push_str("#!/usr/bin/env bash\n");
-    text.push_str("set -euo pipefail\n\n");
+    perms: &Permissions,
+) -> Result<(), BatchelorError> {
+    write_file_atomic_with(path, perms::Kind::Artifact, perms, |out| {
+        write_input_list_text(out, inputs)
+    })
+    .map_err(|source| BatchelorError::ScriptWriteError {
+        path: path.to_path_buf(),
+        source,
+    })
+}
 
-    let script_q = shell_quote_os(script.as_os_str());
-    let input_flag_q = shell_quote(input_flag);
-    let script_args_q = script_args
+fn write_input_list_text(out: &mut dyn Write, inputs: &[String]) -> io::Result<()> {
+    inputs
         .iter()
-        .map(|a| shell_quote(a))
-        .collect::<Vec<_>>();
-    let template_tokens = parse_template_tokens(input_flag);
-    let has_template = template_tokens.iter().any(|t| t.contains("$1"));
-    let positional_slot = parse_positional_slot(input_flag);
-
-    if multi_input {
-        let mut args: Vec<String> = Vec::new();
-
-        if let Some(slot) = positional_slot {
-            args = script_args_q.clone();
-            let mut idx = slot.saturating_sub(1).min(args.len());
-            for input in inputs {
-                args.insert(idx, shell_quote(input));
-                idx += 1;
-            }
-        } else if has_template {
-            for input in inputs {
-                args.extend(
-                    template_tokens
-                        .iter()
-                        .map(|t| shell_quote(&t.replace("$1", input))),
-                );
+        .try_for_each(|input| writeln!(out, "{}", input))
+}
+
+/// Whether `path` holds exactly what `write` writes. The file is read
+/// alongside, so neither is held in memory and the first difference ends
+/// the comparison.
+fn has_contents(path: &Path, write: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> bool {
+    struct Compare {
+        file: io::BufReader<fs::File>,
+        buf: Vec<u8>,
+    }
+    impl Write for Compare {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.buf.resize(data.len(), 0);
+            self.file.read_exact(&mut self.buf)?;
+            if self.buf != data {
+                return Err(io::Error::other("contents differ"));
             }
-            args.extend(script_args_q.iter().cloned());
-        } else {
-            args.push(input_flag_q);
-            args.extend(inputs.iter().map(|i| shell_quote(i)));
-            args.extend(script_args_q.iter().cloned());
+            Ok(data.len())
         }
 
-        if args.is_empty() {
-            text.push_str(&format!("bash {}\n", script_q));
-        } else {
-            text.push_str(&format!("bash {} {}\n", script_q, args.join(" ")));
-        }
-    } else {
-        for input in inputs {
-            let input_q = shell_quote(input);
-            if let Some(slot) = positional_slot {
-                let mut args = script_args_q.clone();
-                let idx = slot.saturating_sub(1).min(args.len());
-                args.insert(idx, input_q);
-
-                if args.is_empty() {
-                    text.push_str(&format!("bash {}\n", script_q));
-                } else {
-                    text.push_str(&format!("bash {} {}\n", script_q, args.join(" ")));
-                }
-            } else if has_template {
-                let mut args = template_tokens
-                    .iter()
-                    .map(|t| shell_quote(&t.replace("$1", input)))
-                    .collect::<Vec<_>>();
-                args.extend(script_args_q.iter().cloned());
-                text.push_str(&format!("bash {} {}\n", script_q, args.join(" ")));
-            } else if script_args_q.is_empty() {
-                text.push_str(&format!("bash {} {} {}\n", script_q, input_flag_q, input_q));
-            } else {
-                text.push_str(&format!(
-                    "bash {} {} {} {}\n",
-                    script_q,
-                    input_flag_q,
-                    input_q,
-                    script_args_q.join(" ")
-                ));
-            }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
         }
     }
+    let Ok(file) = fs::File::open(path) else {
+        return false;
+    };
+    let mut compare = Compare {
+        file: io::BufReader::with_capacity(1 << 16, file),
+        buf: Vec::new(),
+    };
+    write(&mut compare).is_ok() && compare.file.read(&mut [0]).is_ok_and(|n| n == 0)
+}
+
+/// Gives a file left as it was the permissions asked for, if any.
+fn keep_permissions(
+    path: &Path,
+    kind: perms::Kind,
+    perms: &Permissions,
+) -> Result<(), BatchelorError> {
+    if !perms.is_set() {
+        return Ok(());
+    }
+    perms
+        .apply(path, kind)
+        .map_err(|source| BatchelorError::ScriptWriteError {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+/// Writes `contents` to `path` so that it only ever appears complete: the
+/// data goes to a temporary file in the same directory, which is synced,
+/// given the permissions of `kind` and renamed over `path`; the directory
+/// is synced last. The scheduler may read the file from another host (e.g.
+/// over NFS) right after submission.
+pub(crate) fn write_file_atomic(
+    path: &Path,
+    contents: &[u8],
+    kind: perms::Kind,
+    perms: &Permissions,
+) -> io::Result<()> {
+    write_file_atomic_with(path, kind, perms, |out| out.write_all(contents))
+}
 
-    fs::write(output_path, text)?;
+/// [`write_file_atomic`] with the contents written by `write`, through a
+/// buffer.
+pub(crate) fn write_file_atomic_with(
+    path: &Path,
+    kind: perms::Kind,
+    perms: &Permissions,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<()> {
+    // Named independently of `path`, which may already be as long as the
+    // file system allows.
+    static TMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+    let tmp = path.with_file_name(format!(
+        ".batchelor-{}-{}.tmp",
+        std::process::id(),
+        TMP_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let write = || -> io::Result<()> {
+        let mut out = io::BufWriter::with_capacity(64 * 1024, perms.create(&tmp, kind)?);
+        write(&mut out)?;
+        let file = out.into_inner().map_err(io::IntoInnerError::into_error)?;
+        perms.apply(&tmp, kind)?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&tmp, path)
+    };
+    // A partial copy would only take up the space that ran out.
+    write().inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })?;
 
     #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        let mut perms = fs::metadata(output_path)?.permissions();
-        perms.set_mode(0o755);
-        fs::set_permissions(output_path, perms)?;
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        fs::File::open(dir)?.sync_all()?;
     }
-
     Ok(())
 }
 
-fn submit_job(submit: &str, job_script: &Path) -> Result<(), Box<dyn std::error::Error>> {
+/// Whether `path` carries [`GENERATED_MARKER`] in its first lines.
+pub(crate) fn is_generated_script(path: &Path) -> bool {
+    let Ok(file) = fs::File::open(path) else {
+        return false;
+    };
+    io::BufReader::new(file)
+        .lines()
+        .take(5)
+        .map_while(Result::ok)
+        .any(|line| line.starts_with(GENERATED_MARKER))
+}
+
+/// Runs the submit command, or only records the submission when
+/// `record_dir` is set (`--submit-record`).
+pub(crate) fn dispatch_submission(
+    submit: &str,
+    submission: &Submission,
+    scheduler: Scheduler,
+    record_dir: Option<&Path>,
+    timeout: Option<Duration>,
+    on_stderr: &mut dyn FnMut(&str),
+) -> Result<String, Box<dyn std::error::Error>> {
+    match record_dir {
+        Some(dir) => record::record_submission(dir, submit, submission, scheduler),
+        None => submit_job(submit, submission, timeout, on_stderr),
+    }
+}
+
+/// Runs the submit command for `submission` and returns its stdout. Its
+/// stderr lines go to `on_stderr` as they are written; stdin is closed
+/// unless the script is fed on it. A command still running after
+/// `timeout` is killed and fails the submission.
+pub(crate) fn submit_job(
+    submit: &str,
+    submission: &Submission,
+    timeout: Option<Duration>,
+    on_stderr: &mut dyn FnMut(&str),
+) -> Result<String, Box<dyn std::error::Error>> {
     let parts = shlex::split(submit).ok_or_else(|| {
         format!(
             "could not parse --submit command string (check shell quoting): {}",
@@ -290,56 +3358,609 @@ fn submit_job(submit: &str, job_script: &Path) -> Result<(), Box<dyn std::error:
         .split_first()
         .ok_or_else(|| "--submit cannot be empty".to_string())?;
 
-    let output = Command::new(program).args(args).arg(job_script).output()?;
+    let mut command = Command::new(program);
+    command.args(args).args(submission.args());
+    if let JobPayload::Stdin(path) = submission.payload {
+        // The script was synced and renamed into place by
+        // write_job_script, so the submitter reads it in full.
+        command.stdin(fs::File::open(path)?);
+    } else {
+        // A submit command that prompts fails instead of waiting forever.
+        command.stdin(Stdio::null());
+    }
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
 
-    if output.status.success() {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        print!("{}", stdout);
-        Ok(())
+    // Both pipes are read from their own threads, so neither fills up
+    // while the other is waited on.
+    let (sender, received) = mpsc::channel();
+    for (is_stderr, pipe) in [
+        (
+            false,
+            child
+                .stdout
+                .take()
+                .map(|p| Box::new(p) as Box<dyn io::Read + Send>),
+        ),
+        (
+            true,
+            child
+                .stderr
+                .take()
+                .map(|p| Box::new(p) as Box<dyn io::Read + Send>),
+        ),
+    ] {
+        let (Some(pipe), sender) = (pipe, sender.clone()) else {
+            continue;
+        };
+        thread::spawn(move || {
+            let mut pipe = io::BufReader::new(pipe);
+            let mut line = Vec::new();
+            while pipe.read_until(b'\n', &mut line).is_ok_and(|n| n > 0) {
+                if sender.send((is_stderr, std::mem::take(&mut line))).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(sender);
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    let mut timed_out = false;
+    loop {
+        let next = match deadline {
+            Some(deadline) => received
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .map_err(|e| e == mpsc::RecvTimeoutError::Timeout),
+            None => received.recv().map_err(|_| false),
+        };
+        match next {
+            Ok((true, line)) => {
+                on_stderr(String::from_utf8_lossy(&line).trim_end_matches(['\n', '\r']));
+                stderr.extend(line);
+            }
+            Ok((false, line)) => stdout.extend(line),
+            // Both pipes are closed.
+            Err(false) => break,
+            Err(true) => {
+                // Children of the command may keep the pipes open, so
+                // they are not read to the end.
+                let _ = child.kill();
+                timed_out = true;
+                break;
+            }
+        }
+    }
+    let status = child.wait()?;
+
+    if status.success() && !timed_out {
+        Ok(String::from_utf8_lossy(&stdout).into_owned())
     } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        Err(format!(
-            "{} failed for {}: {}",
-            program,
-            job_script.display(),
-            stderr.trim()
-        )
+        let mut stderr = String::from_utf8_lossy(&stderr).into_owned();
+        if let (true, Some(timeout)) = (timed_out, timeout) {
+            stderr = format!(
+                "{}\nkilled after --submit-timeout {}",
+                stderr.trim_end(),
+                units::format_duration(timeout.as_secs())
+            );
+        }
+        Err(BatchelorError::SubmitFailed {
+            program: program.clone(),
+            script: match submission.payload {
+                JobPayload::Script(path) | JobPayload::Stdin(path) => Some(path.to_path_buf()),
+                JobPayload::Wrap(_) => None,
+            },
+            job_name: submission.job_name.to_string(),
+            stderr,
+            status: if timed_out { None } else { status.code() },
+        }
         .into())
     }
 }
 
-fn shell_quote_path(path: &Path) -> String {
-    shell_quote_os(path.as_os_str())
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn parse_positional_slot(input_flag: &str) -> Option<usize> {
-    let idx = input_flag.strip_prefix('$')?.parse::<usize>().ok()?;
-    if idx == 0 {
-        None
-    } else {
-        Some(idx)
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
     }
-}
 
-fn parse_template_tokens(input_flag: &str) -> Vec<String> {
-    shlex::split(input_flag).unwrap_or_else(|| vec![input_flag.to_string()])
-}
+    #[test]
+    fn atomic_write_replaces_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("batch-0001.batch.sh");
+        fs::write(&path, "old\n").unwrap();
+        write_file_atomic(
+            &path,
+            b"new\n",
+            perms::Kind::Script,
+            &Permissions::default(),
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        assert_eq!(entries(dir.path()), ["batch-0001.batch.sh"]);
+    }
 
-fn has_glob_meta(s: &str) -> bool {
-    s.contains('*') || s.contains('?') || s.contains('[')
-}
+    #[test]
+    fn atomic_write_never_shows_a_partial_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("batch-0001.batch.sh");
+        fs::write(&path, "old\n").unwrap();
+        write_file_atomic_with(&path, perms::Kind::Script, &Permissions::default(), |out| {
+            out.write_all(b"#!/usr/bin/env bash\n")?;
+            out.flush()?;
+            // Half written: the script is still the old one.
+            assert_eq!(fs::read_to_string(&path).unwrap(), "old\n");
+            assert_eq!(entries(dir.path()).len(), 2);
+            out.write_all(b"echo new\n")
+        })
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "#!/usr/bin/env bash\necho new\n"
+        );
+        assert_eq!(entries(dir.path()), ["batch-0001.batch.sh"]);
+    }
 
-fn shell_quote_os(s: &OsStr) -> String {
-    shell_quote(&s.to_string_lossy())
-}
+    #[test]
+    fn failed_atomic_write_keeps_the_old_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("batch-0001.batch.sh");
+        fs::write(&path, "old\n").unwrap();
+        let e =
+            write_file_atomic_with(&path, perms::Kind::Script, &Permissions::default(), |out| {
+                out.write_all(b"#!/usr/bin/env bash\n")?;
+                out.flush()?;
+                Err(io::Error::new(io::ErrorKind::StorageFull, "no space left"))
+            })
+            .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::StorageFull);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old\n");
+        assert_eq!(entries(dir.path()), ["batch-0001.batch.sh"]);
+    }
+
+    #[test]
+    fn failed_atomic_write_of_a_new_file_leaves_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("batch-0001.batch.sh");
+        let result =
+            write_file_atomic_with(&path, perms::Kind::Script, &Permissions::default(), |_| {
+                Err(io::Error::other("interrupted"))
+            });
+        assert!(result.is_err());
+        assert!(entries(dir.path()).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn atomic_write_sets_the_mode_before_the_rename() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("batch-0001.batch.sh");
+        let perms = Permissions::new(Some(0o750), None, None).unwrap();
+        write_file_atomic(&path, b"echo\n", perms::Kind::Script, &perms).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o7777;
+        assert_eq!(mode, 0o750);
+    }
+
+    #[test]
+    fn job_log_paths_expand_the_template() {
+        let dir = Path::new("/logs");
+        let template =
+            placeholder::parse("{job_name}.{batch_index}.%j.out", LOG_PLACEHOLDERS).unwrap();
+        assert_eq!(
+            job_log_paths(dir, &template, "batch-0007", 7, false),
+            (PathBuf::from("/logs/batch-0007.7.%j.out"), None)
+        );
+        assert_eq!(
+            job_log_paths(dir, &template, "batch-0007", 7, true),
+            (
+                PathBuf::from("/logs/batch-0007.7.%j.out"),
+                Some(PathBuf::from("/logs/batch-0007.7.%j.err"))
+            )
+        );
+        let template = placeholder::parse("{{{job_name}}}.log", LOG_PLACEHOLDERS).unwrap();
+        assert_eq!(
+            job_log_paths(dir, &template, "a", 1, true),
+            (
+                PathBuf::from("/logs/{a}.log"),
+                Some(PathBuf::from("/logs/{a}.log.err"))
+            )
+        );
+        assert_eq!(
+            placeholder::parse("{job}.%j.out", LOG_PLACEHOLDERS).unwrap_err(),
+            "unknown placeholder {job} (expected job_name or batch_index)"
+        );
+    }
 
-fn shell_quote(s: &str) -> String {
-    if s.is_empty() {
-        return "''".to_string();
+    /// A run of `script`, which is not checked up front.
+    fn script_cli(script: &Path, check_script: bool) -> Cli {
+        let mut cli = Cli::builder()
+            .script(script)
+            .glob("*.fq")
+            .check_script(false)
+            .build()
+            .unwrap();
+        cli.check_script = check_script;
+        cli
     }
-    if s.bytes().all(|b| b.is_ascii_alphanumeric() || b"@%_+=:,./-".contains(&b)) {
-        return s.to_string();
+
+    /// Why [`validate_script`] refuses `script`.
+    fn script_refused(script: &Path, check_script: bool) -> String {
+        match validate_script(&script_cli(script, check_script)) {
+            Err(BatchelorError::ScriptInvalid { path, reason }) => {
+                assert_eq!(path, script);
+                reason
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn scripts_jobs_cannot_run_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.sh");
+        let e = validate_script(&script_cli(&missing, false)).unwrap_err();
+        assert!(matches!(&e, BatchelorError::ScriptMissing(path) if *path == missing));
+        assert_eq!(e.exit_code(), 2);
+
+        assert_eq!(
+            script_refused(dir.path(), false),
+            "is a directory, not a script"
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            script_refused(Path::new("/dev/null"), false),
+            "is not a regular file"
+        );
+        let empty = dir.path().join("empty.sh");
+        fs::write(&empty, "").unwrap();
+        assert_eq!(script_refused(&empty, false), "is empty");
+
+        let script = dir.path().join("script.sh");
+        fs::write(&script, "#!/bin/bash\necho \"$@\"\n").unwrap();
+        validate_script(&script_cli(&script, true)).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_scripts_are_refused() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("script.sh");
+        fs::write(&script, "echo\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o200)).unwrap();
+        if fs::File::open(&script).is_ok() {
+            eprintln!("skipped: unreadable files can be read by this user");
+            return;
+        }
+        let reason = script_refused(&script, false);
+        assert!(reason.starts_with("cannot be read: "), "{}", reason);
+    }
+
+    #[test]
+    fn check_script_refuses_syntax_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("script.sh");
+        fs::write(&script, "#!/bin/bash\nif true; then\necho\n").unwrap();
+        validate_script(&script_cli(&script, false)).unwrap();
+        let reason = script_refused(&script, true);
+        assert!(
+            reason.starts_with("bash -n found a syntax error (--check-script):\n"),
+            "{}",
+            reason
+        );
+        assert!(reason.contains("syntax error"), "{}", reason);
+        let e = validate_script(&script_cli(&script, true)).unwrap_err();
+        assert!(e
+            .to_string()
+            .starts_with(&format!("--script {}: bash -n found", script.display())));
+    }
+
+    #[test]
+    fn shebangs_of_other_interpreters_are_warned_about_not_checked() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("script");
+        for (shebang, interpreter) in [
+            ("#!/bin/bash", None),
+            ("#!/bin/sh -e", None),
+            ("#!/usr/bin/env bash", None),
+            ("#!/usr/bin/env -S bash -eu", None),
+            ("#!/usr/bin/python3", Some("python3")),
+            ("#!/usr/bin/env -S python3 -u", Some("python3")),
+            ("#! /usr/bin/perl -w", Some("perl")),
+            ("# no shebang", None),
+        ] {
+            // Not bash syntax, so --check-script refuses it when it is
+            // checked.
+            fs::write(&script, format!("{}\ndef main(:\n", shebang)).unwrap();
+            let cli = script_cli(&script, true);
+            assert_eq!(
+                foreign_interpreter(&script).as_deref(),
+                interpreter,
+                "{}",
+                shebang
+            );
+            match interpreter {
+                Some(name) => {
+                    validate_script(&cli).unwrap();
+                    assert_eq!(
+                        script_warning(&cli).unwrap(),
+                        format!(
+                            "warning: --script {} starts with a {} shebang, but the jobs run it with bash",
+                            script.display(),
+                            name
+                        )
+                    );
+                }
+                None => {
+                    assert!(validate_script(&cli).is_err(), "{}", shebang);
+                    assert_eq!(script_warning(&cli), None);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn probing_creates_the_out_dir_and_leaves_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let out_dir = dir.path().join("a/b");
+        probe_out_dir(&out_dir, &Permissions::default()).unwrap();
+        assert!(out_dir.is_dir());
+        assert!(entries(&out_dir).is_empty());
+    }
+
+    #[test]
+    fn out_dirs_that_cannot_be_created_are_not_writable() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("file"), "").unwrap();
+        let out_dir = dir.path().join("file/out");
+        let e = probe_out_dir(&out_dir, &Permissions::default()).unwrap_err();
+        assert!(
+            matches!(&e, BatchelorError::OutDirNotWritable { dir, .. } if *dir == out_dir),
+            "{:?}",
+            e
+        );
+        assert_eq!(e.exit_code(), 4);
+        assert!(e.to_string().starts_with(&format!(
+            "--out-dir {} is not writable: ",
+            out_dir.display()
+        )));
+        assert!(e
+            .to_string()
+            .ends_with(" (pick another directory with --out-dir)"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_only_out_dirs_are_not_writable() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let out_dir = dir.path().join("out");
+        fs::create_dir(&out_dir).unwrap();
+        fs::set_permissions(&out_dir, fs::Permissions::from_mode(0o555)).unwrap();
+        let probed = probe_out_dir(&out_dir, &Permissions::default());
+        fs::set_permissions(&out_dir, fs::Permissions::from_mode(0o755)).unwrap();
+        if probed.is_ok() {
+            eprintln!("skipped: read-only directories do not stop this user");
+            return;
+        }
+        match probed.unwrap_err() {
+            BatchelorError::OutDirNotWritable { dir, source } => {
+                assert_eq!(dir, out_dir);
+                assert_eq!(source.kind(), io::ErrorKind::PermissionDenied);
+            }
+            e => panic!("{:?}", e),
+        }
+        assert!(entries(&out_dir).is_empty());
+    }
+
+    #[test]
+    fn contents_compare_to_the_end_of_both() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("batch-0001.batch.sh");
+        let lines = |lines: &'static [&'static str]| {
+            move |out: &mut dyn Write| lines.iter().try_for_each(|line| writeln!(out, "{}", line))
+        };
+        assert!(!has_contents(&path, lines(&["echo a"])));
+        fs::write(&path, "echo a\necho b\n").unwrap();
+        assert!(has_contents(&path, lines(&["echo a", "echo b"])));
+        assert!(!has_contents(&path, lines(&["echo a"])));
+        assert!(!has_contents(&path, lines(&["echo a", "echo b", "echo c"])));
+        assert!(!has_contents(&path, lines(&["echo a", "echo c"])));
+    }
+
+    /// The script text as it was built before scripts were streamed: one
+    /// String, written at once.
+    fn script_as_one_string(job: &JobSpec) -> String {
+        let mut text = format!(
+            "#!/usr/bin/env bash\n{} {}\n{}\n",
+            GENERATED_MARKER,
+            env!("CARGO_PKG_VERSION"),
+            script_format::header_line()
+        );
+        for line in job.header.iter().chain(&job.directives) {
+            text.push_str(line);
+            text.push('\n');
+        }
+        text.push_str("set -euo pipefail\n\n");
+        for command in &job.commands {
+            text.push_str(command);
+            text.push('\n');
+        }
+        text
+    }
+
+    #[test]
+    fn streamed_scripts_and_lists_are_byte_identical() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("script.sh");
+        fs::write(&script, "#!/bin/bash\necho \"$@\"\n").unwrap();
+        // Not files, so passed on as given; some need quoting.
+        let mut inputs = (0..3000)
+            .map(|i| match i % 3 {
+                0 => format!("in/{:05}.fq", i),
+                1 => format!("in/with space {:05}.fq", i),
+                _ => format!("in/it's {:05}.fq", i),
+            })
+            .collect::<Vec<_>>();
+        fs::write(dir.path().join("inputs.txt"), inputs.join("\n")).unwrap();
+        // As the plan orders them.
+        inputs.sort();
+        let cli = |out: &str, multi_input| {
+            let mut cli = Cli::builder()
+                .script(&script)
+                .input_list(dir.path().join("inputs.txt"))
+                .out_dir(dir.path().join(out))
+                .flat_out_dir(true)
+                .keep(true)
+                .multi_input(multi_input)
+                .skip_submit_check(true)
+                .preflight(false)
+                .build()
+                .unwrap();
+            cli.max_command_bytes = 1024;
+            cli
+        };
+
+        // Each input on a command line of its own.
+        let planned = plan(&cli("lines", false), &Quiet)
+            .unwrap()
+            .batches
+            .remove(0);
+        assert_eq!(planned.commands.len(), 3000);
+        for (input, command) in inputs.iter().zip(&planned.commands) {
+            assert!(
+                command.contains(shellgen::quote(input).as_ref()),
+                "{}",
+                command
+            );
+        }
+        let mock = crate::testing::MockSubmitter::new();
+        run_with(cli("lines", false), &Quiet, Some(&mock)).unwrap();
+        let written = mock.submitted().remove(0);
+        assert_eq!(written.commands, planned.commands);
+        let text = fs::read_to_string(written.script.as_ref().unwrap()).unwrap();
+        assert_eq!(text, script_as_one_string(&written));
+        assert_eq!(Some(text), written.script_text());
+
+        // One command for them all, too long for --max-command-bytes:
+        // it reads them from a list.
+        let mock = crate::testing::MockSubmitter::new();
+        run_with(cli("list", true), &Quiet, Some(&mock)).unwrap();
+        let written = mock.submitted().remove(0);
+        let list = fs::read_to_string(written.input_list.as_ref().unwrap()).unwrap();
+        assert_eq!(list, inputs.join("\n") + "\n");
+        let text = fs::read_to_string(written.script.as_ref().unwrap()).unwrap();
+        assert_eq!(text, script_as_one_string(&written));
+    }
+
+    /// The batches `--batch 8 --on-clamp on_clamp` makes of `inputs`
+    /// inputs, and the warnings given.
+    fn clamped(
+        on_clamp: OnClamp,
+        inputs: usize,
+        can_pad: bool,
+    ) -> (Result<usize, BatchelorError>, Vec<String>) {
+        let cli = Cli::builder()
+            .script("script.sh")
+            .glob("*.fq")
+            .check_script(false)
+            .batch(8)
+            .on_clamp(on_clamp)
+            .build()
+            .unwrap();
+        let mut warnings = Vec::new();
+        let batches = clamp_batches(&cli, inputs, can_pad, &mut |w| warnings.push(w));
+        (batches, warnings)
+    }
+
+    #[test]
+    fn enough_inputs_are_never_clamped() {
+        for on_clamp in [OnClamp::Warn, OnClamp::Error, OnClamp::Pad] {
+            for inputs in [8, 9] {
+                let (batches, warnings) = clamped(on_clamp, inputs, false);
+                assert_eq!(batches.unwrap(), 8);
+                assert!(warnings.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn warn_clamps_to_one_batch_per_input() {
+        let (batches, warnings) = clamped(OnClamp::Warn, 3, true);
+        assert_eq!(batches.unwrap(), 3);
+        assert_eq!(
+            warnings,
+            ["warning: --batch 8 is more than the 3 input(s); creating 3 batch(es) (--on-clamp pad keeps all 8)"]
+        );
+    }
+
+    #[test]
+    fn error_refuses_too_few_inputs() {
+        let (batches, warnings) = clamped(OnClamp::Error, 3, true);
+        let e = batches.unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "--batch 8 is more than the 3 input(s) (--on-clamp error)"
+        );
+        assert_eq!(e.exit_code(), 2);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn pad_keeps_every_batch_silently() {
+        let (batches, warnings) = clamped(OnClamp::Pad, 3, true);
+        assert_eq!(batches.unwrap(), 8);
+        assert!(warnings.is_empty());
+        // --emit has nothing to run empty batches with.
+        let (batches, _) = clamped(OnClamp::Pad, 3, false);
+        let e = batches.unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "--batch 8 is more than the 3 input(s), and --emit cannot pad with empty batches"
+        );
+        assert_eq!(e.exit_code(), 2);
+    }
+
+    #[test]
+    fn full_disks_are_out_of_space() {
+        let write_error = |kind: io::ErrorKind| BatchelorError::ScriptWriteError {
+            path: "out/batch-0003.batch.sh".into(),
+            source: io::Error::from(kind),
+        };
+        for kind in [io::ErrorKind::StorageFull, io::ErrorKind::QuotaExceeded] {
+            let e = out_of_space(write_error(kind), 2, 5);
+            assert!(
+                matches!(
+                    &e,
+                    BatchelorError::OutOfSpace {
+                        written: 2,
+                        total: 5,
+                        ..
+                    }
+                ),
+                "{:?}",
+                e
+            );
+            assert!(e
+                .to_string()
+                .starts_with("out of space writing out/batch-0003.batch.sh: "));
+            assert!(e.to_string().ends_with(
+                " (2 of 5 script(s) written; free some space or pick another --out-dir)"
+            ));
+        }
+        let e = out_of_space(write_error(io::ErrorKind::PermissionDenied), 2, 5);
+        assert!(matches!(e, BatchelorError::ScriptWriteError { .. }));
     }
-    let escaped = s.replace('\'', "'\\''");
-    format!("'{}'", escaped)
 }
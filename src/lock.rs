@@ -0,0 +1,269 @@
+//! The lock a run holds on its `--out-dir` while it writes scripts, submits
+//! and cleans up, so two runs sharing the directory (and maybe job names)
+//! cannot submit or remove each other's scripts. It is an OS lock on
+//! `<out-dir>/.batchelor.lock`, released when the run is done or its
+//! process dies; the file records the holder's PID and host for the
+//! message of the run that finds it locked.
+//!
+//! On file systems that keep locks of dead processes (some NFS setups), a
+//! lock whose holder ran on this host and no longer exists is stale: the
+//! file is replaced and locked anew.
+
+use crate::Interrupt;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Seek, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub(crate) const LOCK_FILE: &str = ".batchelor.lock";
+
+/// How often a run waiting for the lock tries again.
+const POLL: Duration = Duration::from_millis(200);
+
+/// A held lock; dropping it releases it.
+#[derive(Debug)]
+pub(crate) struct OutDirLock {
+    file: File,
+}
+
+impl Drop for OutDirLock {
+    fn drop(&mut self) {
+        // The holder is only meaningful while it holds the lock.
+        let _ = self.file.set_len(0);
+        let _ = self.file.unlock();
+    }
+}
+
+/// Locks `out_dir`, creating it. When another run holds the lock, waits up
+/// to `wait` (`--wait-for-lock`) for it, then fails naming the holder.
+pub(crate) fn acquire(
+    out_dir: &Path,
+    wait: Option<Duration>,
+    interrupt: &Interrupt,
+) -> Result<OutDirLock, Box<dyn std::error::Error>> {
+    fs::create_dir_all(out_dir)?;
+    let path = out_dir.join(LOCK_FILE);
+    let deadline = wait.map(|wait| Instant::now() + wait);
+    let mut recovered = false;
+    loop {
+        let file = open(&path)?;
+        match file.try_lock() {
+            Ok(()) => return hold(file, &path),
+            Err(TryLockError::WouldBlock) => {}
+            Err(TryLockError::Error(e)) => {
+                return Err(format!("could not lock {}: {}", path.display(), e).into());
+            }
+        }
+        let holder = Holder::read(&path);
+        if !recovered && holder.as_ref().is_some_and(Holder::is_stale) {
+            log::warn!(
+                "replacing stale lock {} of {}",
+                path.display(),
+                holder.as_ref().map_or_else(String::new, Holder::describe)
+            );
+            fs::remove_file(&path)
+                .map_err(|e| format!("could not remove stale lock {}: {}", path.display(), e))?;
+            recovered = true;
+            continue;
+        }
+        if deadline.is_none_or(|deadline| Instant::now() >= deadline) {
+            let by = holder.map_or_else(|| "another run".to_string(), |holder| holder.describe());
+            let hint = if wait.is_some() {
+                "it was not released in time"
+            } else {
+                "--wait-for-lock waits for it, --no-lock skips locking"
+            };
+            return Err(format!(
+                "{} is locked by {} ({}; {})",
+                out_dir.display(),
+                by,
+                path.display(),
+                hint
+            )
+            .into());
+        }
+        interrupt.check()?;
+        thread::sleep(POLL);
+    }
+}
+
+fn open(path: &Path) -> Result<File, String> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(|e| format!("could not open {}: {}", path.display(), e))
+}
+
+/// Records this process as the holder of the locked `file`.
+fn hold(mut file: File, path: &Path) -> Result<OutDirLock, Box<dyn std::error::Error>> {
+    let record = format!("{} {}\n", std::process::id(), hostname());
+    file.set_len(0)
+        .and_then(|_| file.rewind())
+        .and_then(|_| file.write_all(record.as_bytes()))
+        .map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+    Ok(OutDirLock { file })
+}
+
+/// Who holds a lock, as its file records.
+struct Holder {
+    pid: u32,
+    host: String,
+}
+
+impl Holder {
+    fn read(path: &Path) -> Option<Holder> {
+        let text = fs::read_to_string(path).ok()?;
+        let (pid, host) = text.trim().split_once(' ')?;
+        Some(Holder {
+            pid: pid.parse().ok()?,
+            host: host.to_string(),
+        })
+    }
+
+    fn describe(&self) -> String {
+        format!("PID {} on {}", self.pid, self.host)
+    }
+
+    /// Whether it ran on this host and is gone. Unknown on platforms
+    /// without `/proc`, where no lock is stale.
+    fn is_stale(&self) -> bool {
+        self.host == hostname()
+            && Path::new("/proc/self").exists()
+            && !Path::new("/proc").join(self.pid.to_string()).exists()
+    }
+}
+
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|name| name.trim().to_string())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown host".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locked_message(dir: &Path, holder: &str, hint: &str) -> String {
+        format!(
+            "{} is locked by {} ({}; {})",
+            dir.display(),
+            holder,
+            dir.join(LOCK_FILE).display(),
+            hint
+        )
+    }
+
+    #[test]
+    fn a_second_lock_fails_naming_the_holder() {
+        let dir = tempfile::tempdir().unwrap();
+        let out_dir = dir.path().join("out");
+        let interrupt = Interrupt::default();
+        let lock = acquire(&out_dir, None, &interrupt).unwrap();
+        let holder = format!("PID {} on {}", std::process::id(), hostname());
+        assert_eq!(
+            fs::read_to_string(out_dir.join(LOCK_FILE)).unwrap(),
+            format!("{} {}\n", std::process::id(), hostname())
+        );
+        assert_eq!(
+            acquire(&out_dir, None, &interrupt).unwrap_err().to_string(),
+            locked_message(
+                &out_dir,
+                &holder,
+                "--wait-for-lock waits for it, --no-lock skips locking"
+            )
+        );
+        let waited = Instant::now();
+        assert_eq!(
+            acquire(&out_dir, Some(Duration::from_millis(300)), &interrupt)
+                .unwrap_err()
+                .to_string(),
+            locked_message(&out_dir, &holder, "it was not released in time")
+        );
+        assert!(waited.elapsed() >= Duration::from_millis(300));
+
+        drop(lock);
+        assert_eq!(fs::read_to_string(out_dir.join(LOCK_FILE)).unwrap(), "");
+        acquire(&out_dir, None, &interrupt).unwrap();
+    }
+
+    #[test]
+    fn a_waiting_lock_is_taken_once_released() {
+        let dir = tempfile::tempdir().unwrap();
+        let interrupt = Interrupt::default();
+        let lock = acquire(dir.path(), None, &interrupt).unwrap();
+        let released = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            drop(lock);
+        });
+        acquire(dir.path(), Some(Duration::from_secs(30)), &interrupt).unwrap();
+        released.join().unwrap();
+    }
+
+    /// Locks `dir` the way a run that has since died left it locked on
+    /// file systems that keep such locks, as held by `pid` on `host`.
+    fn left_locked(dir: &Path, pid: u32, host: &str) -> File {
+        let path = dir.join(LOCK_FILE);
+        let file = open(&path).unwrap();
+        file.try_lock().unwrap();
+        fs::write(&path, format!("{} {}\n", pid, host)).unwrap();
+        file
+    }
+
+    /// The PID of a process that has exited.
+    #[cfg(unix)]
+    fn dead_pid() -> u32 {
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        pid
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn stale_locks_of_dead_processes_are_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let interrupt = Interrupt::default();
+        let pid = dead_pid();
+        let _dead = left_locked(dir.path(), pid, &hostname());
+        assert!(Holder::read(&dir.path().join(LOCK_FILE))
+            .unwrap()
+            .is_stale());
+        let _lock = acquire(dir.path(), None, &interrupt).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join(LOCK_FILE)).unwrap(),
+            format!("{} {}\n", std::process::id(), hostname())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn live_or_remote_holders_are_not_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let interrupt = Interrupt::default();
+        let pid = dead_pid();
+        // Another host's PIDs say nothing about this one.
+        let _remote = left_locked(dir.path(), pid, "elsewhere");
+        assert!(!Holder::read(&dir.path().join(LOCK_FILE))
+            .unwrap()
+            .is_stale());
+        let e = acquire(dir.path(), None, &interrupt).unwrap_err();
+        assert!(e
+            .to_string()
+            .contains(&format!("locked by PID {} on elsewhere", pid)));
+
+        let dir = tempfile::tempdir().unwrap();
+        let _live = left_locked(dir.path(), std::process::id(), &hostname());
+        assert!(!Holder::read(&dir.path().join(LOCK_FILE))
+            .unwrap()
+            .is_stale());
+        assert!(acquire(dir.path(), None, &interrupt).is_err());
+    }
+}
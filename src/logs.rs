@@ -0,0 +1,215 @@
+use crate::reporter::{ColorChoice, Reporter, StreamReporter, Verbosity};
+use crate::scheduler::Scheduler;
+use crate::state::{JobState, RunState};
+use clap::{Parser, ValueHint};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "batchelor logs",
+    about = "Print, follow or search the output logs of a previous run's jobs"
+)]
+pub struct LogsCli {
+    /// Job name (e.g. batch-0007) or batch index (e.g. 7).
+    #[arg(required_unless_present = "all", conflicts_with = "all")]
+    job: Option<String>,
+
+    /// Output directory the run was submitted from.
+    #[arg(long, default_value = ".batchelor", value_hint = ValueHint::DirPath)]
+    out_dir: PathBuf,
+
+    /// Run ID to look in (default: the most recent run).
+    #[arg(long)]
+    run: Option<String>,
+
+    /// Keep printing new output until the job leaves the queue.
+    #[arg(long, short = 'f')]
+    follow: bool,
+
+    /// Look at the logs of every job of the run.
+    #[arg(long, conflicts_with = "follow")]
+    all: bool,
+
+    /// Only print log lines containing PATTERN, prefixed with the job name.
+    #[arg(long, value_name = "PATTERN", conflicts_with = "follow")]
+    grep: Option<String>,
+
+    /// Print only the logs and errors: no notes on what is missing or
+    /// was not found.
+    #[arg(long)]
+    quiet: bool,
+
+    /// Color warnings and errors: on a terminal unless NO_COLOR is set
+    /// (auto), always or never.
+    #[arg(long, value_enum, value_name = "WHEN", default_value = "auto")]
+    color: ColorChoice,
+}
+
+pub fn logs(cli: LogsCli) -> Result<(), Box<dyn std::error::Error>> {
+    let reporter = StreamReporter::stdio()
+        .with_verbosity(Verbosity::from_quiet(cli.quiet))
+        .with_color(cli.color);
+    print_logs(cli, &reporter)
+}
+
+/// The logs and the lines found in them are data; logs that cannot be
+/// read with --all are errors, and notes are diagnostics.
+fn print_logs(cli: LogsCli, reporter: &dyn Reporter) -> Result<(), Box<dyn std::error::Error>> {
+    let state = RunState::load_run(&cli.out_dir, cli.run.as_deref())?;
+    let jobs = state.jobs.iter().collect::<Vec<_>>();
+    let selected = match &cli.job {
+        Some(wanted) => vec![find_job(&jobs, wanted)
+            .ok_or_else(|| format!("no job {:?} in run {}", wanted, state.run_id))?],
+        None => jobs,
+    };
+
+    if let Some(pattern) = &cli.grep {
+        return grep_logs(&selected, pattern, reporter);
+    }
+    if cli.follow {
+        let job = selected[0];
+        return follow(state.scheduler, job, &log_path(job)?, reporter);
+    }
+    for job in selected {
+        let path = match log_path(job) {
+            Ok(path) => path,
+            Err(e) if cli.all => {
+                reporter.error(&e.to_string());
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        if cli.all {
+            reporter.data(&format!("==> {} ({}) <==\n", job.job_name, path.display()));
+        }
+        match print_from(&path, 0, reporter) {
+            Ok(_) => {}
+            Err(e) if cli.all => reporter.error(&format!("{}: {}", path.display(), e)),
+            Err(e) => return Err(format!("could not read log {}: {}", path.display(), e).into()),
+        }
+    }
+    Ok(())
+}
+
+/// Finds a job by name, or by batch index when `wanted` is a number.
+fn find_job<'a>(jobs: &[&'a JobState], wanted: &str) -> Option<&'a JobState> {
+    if let Some(job) = jobs.iter().find(|j| j.job_name == wanted) {
+        return Some(job);
+    }
+    let index: usize = wanted.parse().ok()?;
+    jobs.iter().find(|j| j.batch_index == index).copied()
+}
+
+fn log_path(job: &JobState) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if job.log.is_none() {
+        return Err(format!(
+            "no log path recorded for {} (submit with --job-log-dir to record one)",
+            job.job_name
+        )
+        .into());
+    }
+    job.log_path().ok_or_else(|| {
+        format!(
+            "log path of {} depends on its job ID, which was not recorded",
+            job.job_name
+        )
+        .into()
+    })
+}
+
+fn grep_logs(
+    jobs: &[&JobState],
+    pattern: &str,
+    reporter: &dyn Reporter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut matches = 0usize;
+    let mut missing = 0usize;
+    for job in jobs {
+        let Ok(path) = log_path(job) else {
+            missing += 1;
+            continue;
+        };
+        let Ok(file) = File::open(&path) else {
+            missing += 1;
+            continue;
+        };
+        for (lineno, line) in BufReader::new(file).lines().enumerate() {
+            // Logs are not always valid UTF-8; skip what cannot be read.
+            let Ok(line) = line else { continue };
+            if line.contains(pattern) {
+                matches += 1;
+                reporter.data(&format!(
+                    "{}: {}:{}: {}\n",
+                    job.job_name,
+                    path.display(),
+                    lineno + 1,
+                    line
+                ));
+            }
+        }
+    }
+    if missing > 0 {
+        reporter.diagnostic(&format!("{} log(s) missing or not recorded", missing));
+    }
+    if matches == 0 {
+        reporter.diagnostic(&format!("no log line contains {:?}", pattern));
+    }
+    Ok(())
+}
+
+/// Prints the log as it grows, like `tail -f`, until the job has left the
+/// queue. Follows until interrupted when the queue cannot be queried.
+fn follow(
+    scheduler: Scheduler,
+    job: &JobState,
+    path: &Path,
+    reporter: &dyn Reporter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    reporter.message(&format!(
+        "Following {} until {} leaves the queue (Ctrl-C to stop)",
+        path.display(),
+        job.job_name
+    ));
+    let mut offset = 0;
+    loop {
+        // Asked before reading, so the last read sees everything the job
+        // wrote before it left the queue.
+        let queued = job
+            .job_id
+            .as_deref()
+            .and_then(|id| scheduler.queue_states(&[id]))
+            .map(|states| !states.is_empty());
+        offset = match print_from(path, offset, reporter) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => offset,
+            result => result?,
+        };
+        if queued == Some(false) {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// Prints `path` from byte `offset` on and returns the new end offset. A
+/// truncated file is printed from the start. Bytes that are not UTF-8 are
+/// printed as U+FFFD, except a character cut off at the end, which is left
+/// for the next read.
+fn print_from(path: &Path, offset: u64, reporter: &dyn Reporter) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let start = if len < offset { 0 } else { offset };
+    file.seek(SeekFrom::Start(start))?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    if let Err(e) = std::str::from_utf8(&buf) {
+        if e.error_len().is_none() {
+            buf.truncate(e.valid_up_to());
+        }
+    }
+    reporter.data(&String::from_utf8_lossy(&buf));
+    Ok(start + buf.len() as u64)
+}
@@ -0,0 +1,163 @@
+//! `batchelor man`: the manual pages, rendered from the command line
+//! definitions. Hidden; for packagers.
+
+use clap::{Command, Parser, ValueHint};
+use clap_mangen::roff::{bold, roman, Roff};
+use clap_mangen::Man;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Invocations shown under EXAMPLES in batchelor(1). Each must parse, or
+/// rendering fails.
+const EXAMPLES: &[(&str, &str)] = &[
+    (
+        "batchelor --script align.sh --glob 'reads/*.fq.gz' --batch 20",
+        "Submit align.sh for every matching input with sbatch, 20 inputs per job.",
+    ),
+    (
+        "batchelor --script align.sh --glob 'reads/*.fq.gz' --dry-run",
+        "Print the submit commands and write the scripts without submitting.",
+    ),
+    (
+        "batchelor --script align.sh --input-list samples.txt --submit 'sbatch --partition=short' --wait",
+        "Submit the inputs listed in samples.txt and wait for the jobs to finish.",
+    ),
+    (
+        "batchelor --profile hawk --script align.sh --glob 'reads/*.fq.gz'",
+        "Take the options of [profile.hawk] from the config files.",
+    ),
+    ("batchelor status", "Show the jobs of the latest run."),
+    (
+        "batchelor logs --grep error --all",
+        "Print the lines with \"error\" in the logs of every job of the latest run.",
+    ),
+    (
+        "batchelor resubmit --with=--mem=64G",
+        "Resubmit the failed batches of the latest run with more memory.",
+    ),
+];
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "batchelor man",
+    about = "Render the manual pages of batchelor and its subcommands"
+)]
+pub struct ManCli {
+    /// Write batchelor.1 and a batchelor-SUBCOMMAND.1 per subcommand into
+    /// this directory, instead of batchelor.1 to stdout.
+    #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+    dir: Option<PathBuf>,
+}
+
+pub fn man(cli: ManCli) -> Result<(), Box<dyn std::error::Error>> {
+    let mut command = crate::command_tree();
+    // Names the subcommands' pages, e.g. batchelor-status.
+    command.build();
+    let Some(dir) = cli.dir else {
+        return render(&command, &mut io::stdout().lock());
+    };
+    fs::create_dir_all(&dir).map_err(|e| format!("could not create {}: {}", dir.display(), e))?;
+    write_page(&dir, "batchelor.1", &command)?;
+    for subcommand in command.get_subcommands().filter(|c| !c.is_hide_set()) {
+        let name = subcommand
+            .get_display_name()
+            .unwrap_or(subcommand.get_name());
+        write_page(&dir, &format!("{}.1", name), subcommand)?;
+    }
+    Ok(())
+}
+
+fn write_page(dir: &Path, file: &str, command: &Command) -> Result<(), Box<dyn std::error::Error>> {
+    let path = dir.join(file);
+    let mut page = Vec::new();
+    render(command, &mut page)?;
+    fs::write(&path, page).map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+/// The page of `command`; batchelor(1) gets the EXAMPLES section.
+fn render(command: &Command, out: &mut dyn Write) -> Result<(), Box<dyn std::error::Error>> {
+    let man = Man::new(command.clone());
+    man.render_title(out)?;
+    man.render_name_section(out)?;
+    man.render_synopsis_section(out)?;
+    man.render_description_section(out)?;
+    man.render_options_section(out)?;
+    if command.has_subcommands() {
+        man.render_subcommands_section(out)?;
+    }
+    if command.get_after_help().is_some() {
+        man.render_extra_section(out)?;
+    }
+    if command.get_name() == "batchelor" {
+        examples(command)?.to_writer(out)?;
+    }
+    if command.get_version().is_some() {
+        man.render_version_section(out)?;
+    }
+    Ok(())
+}
+
+/// EXAMPLES, checking that each example still parses.
+fn examples(command: &Command) -> Result<Roff, String> {
+    let mut roff = Roff::new();
+    roff.control("SH", ["EXAMPLES"]);
+    for (example, description) in EXAMPLES {
+        let args = shlex::split(example).ok_or_else(|| format!("example {:?}", example))?;
+        command
+            .clone()
+            .try_get_matches_from(args)
+            .map_err(|e| format!("example {:?} does not parse: {}", example, e))?;
+        roff.control("TP", [])
+            .text([bold(*example)])
+            .text([roman(*description)]);
+    }
+    Ok(roff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How roff spells `--long`.
+    fn roff_option(long: &str) -> String {
+        format!("--{}", long).replace('-', "\\-")
+    }
+
+    #[test]
+    fn batchelor_1_lists_every_option_and_the_examples() {
+        let mut command = crate::command_tree();
+        command.build();
+        let mut page = Vec::new();
+        render(&command, &mut page).unwrap();
+        let page = String::from_utf8(page).unwrap();
+        assert!(page.starts_with(".ie"), "{}", &page[..100]);
+        assert!(page.contains(".TH batchelor 1"));
+        for arg in command.get_arguments().filter(|arg| !arg.is_hide_set()) {
+            if let Some(long) = arg.get_long() {
+                assert!(page.contains(&roff_option(long)), "no --{}", long);
+            }
+        }
+        assert!(page.contains(".SH EXAMPLES"));
+        assert!(page.contains("batchelor status"));
+    }
+
+    #[test]
+    fn subcommand_pages_list_their_options() {
+        let mut command = crate::command_tree();
+        command.build();
+        let status = command.find_subcommand("status").unwrap();
+        let mut page = Vec::new();
+        render(status, &mut page).unwrap();
+        let page = String::from_utf8(page).unwrap();
+        assert!(page.contains(".TH batchelor-status 1"), "{}", page);
+        for arg in status.get_arguments().filter(|arg| !arg.is_hide_set()) {
+            if let Some(long) = arg.get_long() {
+                assert!(page.contains(&roff_option(long)), "no --{}", long);
+            }
+        }
+        assert!(!page.contains(".SH EXAMPLES"));
+    }
+}
@@ -0,0 +1,70 @@
+//! `--manifest`: which inputs went into which batch, written right after
+//! the scripts are generated (dry runs included), before anything is
+//! submitted.
+
+use crate::escape;
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+// Only the command line sets it.
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+pub(crate) enum ManifestFormat {
+    /// `batch_index  job_name  script_path  input_path`, one row per input.
+    Tsv,
+    /// An array of objects with the same fields.
+    Json,
+}
+
+/// One generated batch.
+pub(crate) struct ManifestBatch {
+    pub(crate) batch_index: usize,
+    pub(crate) job_name: String,
+    /// `None` with --wrap.
+    pub(crate) script: Option<PathBuf>,
+    pub(crate) inputs: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Row<'a> {
+    batch_index: usize,
+    job_name: &'a str,
+    script_path: Option<&'a Path>,
+    input_path: &'a str,
+}
+
+pub(crate) fn render(batches: &[ManifestBatch], format: ManifestFormat) -> String {
+    let rows = batches.iter().flat_map(|batch| {
+        batch.inputs.iter().map(move |input| Row {
+            batch_index: batch.batch_index,
+            job_name: &batch.job_name,
+            script_path: batch.script.as_deref(),
+            input_path: input,
+        })
+    });
+    match format {
+        ManifestFormat::Tsv => {
+            let mut out = "batch_index\tjob_name\tscript_path\tinput_path\n".to_string();
+            for row in rows {
+                out.push_str(&format!(
+                    "{}\t{}\t{}\t{}\n",
+                    row.batch_index,
+                    row.job_name,
+                    row.script_path.map_or_else(
+                        || "-".to_string(),
+                        |p| escape::encode(&p.display().to_string()).into_owned()
+                    ),
+                    escape::encode(row.input_path)
+                ));
+            }
+            out
+        }
+        // Plain strings and numbers always serialize.
+        ManifestFormat::Json => {
+            serde_json::to_string_pretty(&rows.collect::<Vec<_>>()).unwrap_or_default() + "\n"
+        }
+    }
+}
@@ -0,0 +1,267 @@
+//! `--meta-cache`: where the symlinks among the inputs led, kept in
+//! `<out-dir>/meta-cache.json` for the next run over the same inputs.
+//! Inputs are often links into a tree of raw data, and resolving a link
+//! (following it and every link and directory above its target) is what
+//! is slow on network file systems. Other inputs are resolved through
+//! their directory, once per directory, and are not cached.
+//!
+//! An entry is used only while the link and its target are the files they
+//! were: same size, modification time and (on Unix) inode and device.
+//! Checking that takes an `lstat` of the link and a `stat` through it,
+//! which a run needs anyway. The file holds the links of the last run that
+//! used it; those no longer matched are dropped.
+
+use crate::inputs::fs_calls;
+use crate::perms::{self, Permissions};
+use crate::{write_file_atomic, BatchelorError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+pub const META_CACHE_FILE: &str = "meta-cache.json";
+
+/// Version of the file written by this batchelor; files of other versions
+/// are ignored.
+const VERSION: u32 = 1;
+
+/// What tells one version of a file from another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Stamp {
+    size: u64,
+    modified_ns: u64,
+    inode: u64,
+    device: u64,
+}
+
+impl Stamp {
+    /// `None` for files without a usable modification time, which are
+    /// never cached.
+    fn of(metadata: &fs::Metadata) -> Option<Stamp> {
+        let modified_ns = metadata
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_nanos()
+            .try_into()
+            .ok()?;
+        #[cfg(unix)]
+        let (inode, device) = {
+            use std::os::unix::fs::MetadataExt;
+            (metadata.ino(), metadata.dev())
+        };
+        #[cfg(not(unix))]
+        let (inode, device) = (0, 0);
+        Some(Stamp {
+            size: metadata.len(),
+            modified_ns,
+            inode,
+            device,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Entry {
+    /// The link's absolute path, as matched or listed.
+    path: String,
+    /// Of the link itself.
+    stamp: Stamp,
+    /// The canonical path of its target.
+    resolved: String,
+    /// Of its target.
+    target: Stamp,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Document {
+    version: u32,
+    entries: Vec<Entry>,
+}
+
+/// The links an earlier run resolved, and those of this run to save.
+#[derive(Debug, Default)]
+pub struct MetaCache {
+    earlier: HashMap<PathBuf, Entry>,
+    current: Mutex<HashMap<PathBuf, Entry>>,
+}
+
+impl MetaCache {
+    /// The cache in `path`; empty when there is none, or when it cannot be
+    /// read, which is logged.
+    pub fn load(path: &Path) -> MetaCache {
+        let document = match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str::<Document>(&text)
+                .map_err(|e| log::warn!("ignoring {}: {}", path.display(), e))
+                .ok(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                log::warn!("ignoring {}: {}", path.display(), e);
+                None
+            }
+        };
+        let earlier = document
+            .filter(|document| document.version == VERSION)
+            .map(|document| {
+                document
+                    .entries
+                    .into_iter()
+                    .map(|entry| (PathBuf::from(&entry.path), entry))
+                    .collect()
+            })
+            .unwrap_or_default();
+        MetaCache {
+            earlier,
+            current: Mutex::default(),
+        }
+    }
+
+    /// The canonical path of the symlink `path` and the metadata of its
+    /// target, when an earlier run resolved it and neither has changed
+    /// since. `lstat` is the metadata of the link itself.
+    pub(crate) fn get(&self, path: &Path, lstat: &fs::Metadata) -> Option<(PathBuf, fs::Metadata)> {
+        let key = std::path::absolute(path).ok()?;
+        let entry = self.earlier.get(&key)?;
+        if Stamp::of(lstat)? != entry.stamp {
+            return None;
+        }
+        // Through the link, so a retargeted link or directory above its
+        // target shows as another file.
+        let target = fs_calls::metadata(path).ok()?;
+        if Stamp::of(&target)? != entry.target {
+            return None;
+        }
+        self.current().insert(key, entry.clone());
+        Some((PathBuf::from(&entry.resolved), target))
+    }
+
+    /// Records that the symlink `path`, whose own metadata is `lstat`,
+    /// resolved to `resolved` with metadata `target`. Paths that are not
+    /// valid UTF-8 are not cached.
+    pub(crate) fn insert(
+        &self,
+        path: &Path,
+        lstat: &fs::Metadata,
+        resolved: &Path,
+        target: &fs::Metadata,
+    ) {
+        let entry = || {
+            let key = std::path::absolute(path).ok()?;
+            let entry = Entry {
+                path: key.to_str()?.to_string(),
+                stamp: Stamp::of(lstat)?,
+                resolved: resolved.to_str()?.to_string(),
+                target: Stamp::of(target)?,
+            };
+            Some((key, entry))
+        };
+        if let Some((key, entry)) = entry() {
+            self.current().insert(key, entry);
+        }
+    }
+
+    /// Writes the links of this run to `path`.
+    pub fn save(&self, path: &Path) -> Result<(), BatchelorError> {
+        Ok(self.save_with(path, &Permissions::default())?)
+    }
+
+    /// [`MetaCache::save`] with the artifact permissions of `perms`.
+    pub(crate) fn save_with(
+        &self,
+        path: &Path,
+        perms: &Permissions,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut entries = self.current().values().cloned().collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        let document = Document {
+            version: VERSION,
+            entries,
+        };
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            perms.create_dir_all(dir)?;
+        }
+        write_file_atomic(
+            path,
+            serde_json::to_string(&document)?.as_bytes(),
+            perms::Kind::Artifact,
+            perms,
+        )?;
+        Ok(())
+    }
+
+    fn current(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, Entry>> {
+        self.current.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::inputs::{expand, fs_calls, InputOptions};
+    use std::sync::Arc;
+
+    #[test]
+    fn cached_links_are_stated_once_and_not_followed() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        fs::create_dir(root.join("raw")).unwrap();
+        for i in 0..3 {
+            fs::write(root.join(format!("raw/{}.fq", i)), "x").unwrap();
+            std::os::unix::fs::symlink(
+                root.join(format!("raw/{}.fq", i)),
+                root.join(format!("{}.fq", i)),
+            )
+            .unwrap();
+        }
+        let cache_file = root.join("cache/meta-cache.json");
+        let patterns = [root.join("*.fq").to_string_lossy().into_owned()];
+        // The inputs of a run with the cache saved last, and the calls
+        // it made.
+        let run = || {
+            let cache = Arc::new(MetaCache::load(&cache_file));
+            let options = InputOptions {
+                metadata: true,
+                meta_cache: Some(cache.clone()),
+                ..InputOptions::default()
+            };
+            let before = fs_calls::under(&root).len();
+            let inputs = expand(&patterns, &options).unwrap();
+            cache.save(&cache_file).unwrap();
+            let paths = inputs
+                .into_iter()
+                .map(|input| input.path)
+                .collect::<Vec<_>>();
+            (paths, fs_calls::under(&root).split_off(before))
+        };
+        let on = |calls: &[(&'static str, PathBuf)], path: &str| {
+            calls
+                .iter()
+                .filter(|(_, p)| *p == root.join(path))
+                .map(|(call, _)| *call)
+                .collect::<Vec<_>>()
+        };
+
+        let (first, calls) = run();
+        assert_eq!(on(&calls, "0.fq"), ["lstat", "realpath"]);
+        assert_eq!(on(&calls, "raw/0.fq"), ["stat"]);
+
+        // A hit takes the link's lstat and one stat through it.
+        let (second, calls) = run();
+        assert_eq!(second, first);
+        for i in 0..3 {
+            assert_eq!(on(&calls, &format!("{}.fq", i)), ["lstat", "stat"]);
+            assert!(on(&calls, &format!("raw/{}.fq", i)).is_empty());
+        }
+
+        // A changed target is resolved again; the others still hit.
+        fs::write(root.join("raw/1.fq"), "longer").unwrap();
+        let (third, calls) = run();
+        assert_eq!(third, first);
+        assert_eq!(on(&calls, "0.fq"), ["lstat", "stat"]);
+        assert_eq!(on(&calls, "1.fq"), ["lstat", "stat", "realpath"]);
+        assert_eq!(on(&calls, "raw/1.fq"), ["stat"]);
+    }
+}
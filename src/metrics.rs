@@ -0,0 +1,224 @@
+//! Prometheus textfile export (`--metrics-out`), for node-exporter's
+//! textfile collector. The file is replaced atomically on every poll.
+//!
+//! Metrics, all gauges labelled with `run="<run_id>"`:
+//!
+//! ```text
+//! batchelor_jobs{run,state}          jobs per state; besides scheduler states
+//!                                    SUBMIT_FAILED, UNTRACKED and UNKNOWN
+//! batchelor_inputs{run}              inputs covered by the run
+//! batchelor_inputs_done{run}         inputs whose command succeeded
+//! batchelor_inputs_failed{run}       inputs whose command failed
+//! batchelor_run_duration_seconds{run} seconds since the run was submitted
+//! ```
+//!
+//! Names and labels are stable; new metrics may be added.
+
+use crate::failures::marker_counts;
+use crate::scheduler::JobStatus;
+use crate::state::RunState;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+/// Renders the metrics for `state` at `now`, with `statuses` (by job ID)
+/// as the scheduler's current view of its jobs.
+pub(crate) fn render(
+    state: &RunState,
+    statuses: &HashMap<String, JobStatus>,
+    out_dir: &Path,
+    now: chrono::DateTime<chrono::Utc>,
+) -> String {
+    let run = label_value(&state.run_id);
+    let mut jobs = BTreeMap::new();
+    let (mut done, mut failed) = (0, 0);
+    for job in &state.jobs {
+        let job_state = match &job.job_id {
+            _ if job.submit_error.is_some() => "SUBMIT_FAILED",
+            None => "UNTRACKED",
+            Some(id) => statuses
+                .get(id)
+                .map(|s| s.state.as_str())
+                .or(job.state.as_deref())
+                .unwrap_or("UNKNOWN"),
+        };
+        *jobs.entry(job_state).or_insert(0usize) += 1;
+        if let Ok((d, f)) = marker_counts(out_dir, job) {
+            done += d;
+            failed += f;
+        }
+    }
+
+    let mut out = String::new();
+    gauge(&mut out, "batchelor_jobs", "Jobs of the run by state.");
+    for (job_state, count) in &jobs {
+        let _ = writeln!(
+            out,
+            "batchelor_jobs{{run=\"{}\",state=\"{}\"}} {}",
+            run,
+            label_value(job_state),
+            count
+        );
+    }
+    let inputs = state.jobs.iter().map(|j| j.inputs.len()).sum::<usize>();
+    for (name, help, value) in [
+        ("batchelor_inputs", "Inputs covered by the run.", inputs),
+        (
+            "batchelor_inputs_done",
+            "Inputs whose command succeeded.",
+            done,
+        ),
+        (
+            "batchelor_inputs_failed",
+            "Inputs whose command failed.",
+            failed,
+        ),
+    ] {
+        gauge(&mut out, name, help);
+        let _ = writeln!(out, "{}{{run=\"{}\"}} {}", name, run, value);
+    }
+    let started = state
+        .timestamp
+        .as_deref()
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok());
+    if let Some(started) = started {
+        let secs = (now - started.to_utc()).num_seconds().max(0);
+        gauge(
+            &mut out,
+            "batchelor_run_duration_seconds",
+            "Seconds since the run was submitted.",
+        );
+        let _ = writeln!(
+            out,
+            "batchelor_run_duration_seconds{{run=\"{}\"}} {}",
+            run, secs
+        );
+    }
+    out
+}
+
+/// Replaces `path` with `text` by renaming a complete temporary copy over
+/// it, so a scrape never sees a partial file.
+pub(crate) fn write(path: &Path, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let write = || -> std::io::Result<()> {
+        fs::write(&tmp, text)?;
+        fs::rename(&tmp, path)
+    };
+    write().map_err(|e| format!("could not write metrics to {}: {}", path.display(), e).into())
+}
+
+fn gauge(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge", name, help, name);
+}
+
+/// Escapes a label value per the exposition format.
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::{parse_sacct, Scheduler};
+    use crate::state::{InputState, JobState};
+
+    #[test]
+    fn exposition_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let done = dir.path().join("batch-0001.done");
+        let failed = dir.path().join("batch-0001.failed");
+        fs::write(&done, "/in/a.fq\n/in/b.fq\n").unwrap();
+        fs::write(&failed, "/in/c.fq\n").unwrap();
+        let inputs = |paths: &[&str]| {
+            paths
+                .iter()
+                .map(|path| InputState {
+                    path: path.to_string(),
+                    status: None,
+                    fingerprint: None,
+                })
+                .collect()
+        };
+        let job = |index: usize, id: Option<&str>| JobState {
+            batch_index: index,
+            job_name: format!("batch-{:04}", index),
+            job_id: id.map(str::to_string),
+            ..JobState::default()
+        };
+        let state = RunState {
+            schema_version: crate::state::SCHEMA_VERSION,
+            run_id: "run \"a\\b\"\nc".to_string(),
+            timestamp: Some("2024-01-31T14:25:01+01:00".to_string()),
+            args: Vec::new(),
+            profile: None,
+            submit: "sbatch".to_string(),
+            scheduler: Scheduler::Slurm,
+            script_format: crate::script_format::CURRENT,
+            jobs: vec![
+                JobState {
+                    done_file: Some(done),
+                    failed_file: Some(failed),
+                    inputs: inputs(&["/in/a.fq", "/in/b.fq", "/in/c.fq"]),
+                    ..job(1, Some("101"))
+                },
+                JobState {
+                    inputs: inputs(&["/in/d.fq"]),
+                    ..job(2, Some("102"))
+                },
+                job(3, Some("103")),
+                job(4, None),
+                JobState {
+                    submit_error: Some("rejected".to_string()),
+                    ..job(5, None)
+                },
+            ],
+            unchanged: Vec::new(),
+        };
+        let statuses = parse_sacct("101|COMPLETED|00:10:00|0:0|n1\n102|RUNNING|00:01:00|0:0|n2\n")
+            .into_iter()
+            .map(|s| (s.job_id.clone(), s))
+            .collect::<HashMap<_, _>>();
+        let now = chrono::DateTime::parse_from_rfc3339("2024-01-31T15:25:31+01:00")
+            .unwrap()
+            .to_utc();
+        assert_eq!(
+            render(&state, &statuses, dir.path(), now),
+            r#"# HELP batchelor_jobs Jobs of the run by state.
+# TYPE batchelor_jobs gauge
+batchelor_jobs{run="run \"a\\b\"\nc",state="COMPLETED"} 1
+batchelor_jobs{run="run \"a\\b\"\nc",state="RUNNING"} 1
+batchelor_jobs{run="run \"a\\b\"\nc",state="SUBMIT_FAILED"} 1
+batchelor_jobs{run="run \"a\\b\"\nc",state="UNKNOWN"} 1
+batchelor_jobs{run="run \"a\\b\"\nc",state="UNTRACKED"} 1
+# HELP batchelor_inputs Inputs covered by the run.
+# TYPE batchelor_inputs gauge
+batchelor_inputs{run="run \"a\\b\"\nc"} 4
+# HELP batchelor_inputs_done Inputs whose command succeeded.
+# TYPE batchelor_inputs_done gauge
+batchelor_inputs_done{run="run \"a\\b\"\nc"} 2
+# HELP batchelor_inputs_failed Inputs whose command failed.
+# TYPE batchelor_inputs_failed gauge
+batchelor_inputs_failed{run="run \"a\\b\"\nc"} 1
+# HELP batchelor_run_duration_seconds Seconds since the run was submitted.
+# TYPE batchelor_run_duration_seconds gauge
+batchelor_run_duration_seconds{run="run \"a\\b\"\nc"} 3630
+"#
+        );
+    }
+
+    #[test]
+    fn write_replaces_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("batchelor.prom");
+        fs::write(&path, "old\n").unwrap();
+        write(&path, "new\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
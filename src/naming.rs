@@ -0,0 +1,529 @@
+use crate::placeholder::{self, Segment};
+use crate::scheduler::Scheduler;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Today's naming: `batch-0001`, `batch-0002`, ...
+pub const DEFAULT_JOB_NAME_FORMAT: &str = "{prefix}-{index:04}";
+
+const PLACEHOLDERS: &[&str] = &["prefix", "index", "total", "date", "time", "stem"];
+
+/// Names also name files such as `<name>.batch.sh`, which most file
+/// systems limit to 255 bytes.
+const MAX_NAME_LEN: usize = 255 - ".batch.sh".len();
+
+/// A `--job-name-format` template such as `{prefix}-{date}-{index:05}`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JobNameFormat {
+    template: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Prefix,
+    /// 1-based batch index, zero-padded to `width`.
+    Index {
+        width: usize,
+    },
+    Total {
+        width: usize,
+    },
+    /// YYYYMMDD
+    Date,
+    /// HHMMSS
+    Time,
+    /// The file stem of the batch's only input.
+    Stem,
+}
+
+/// What a job name is rendered from.
+pub struct NameContext<'a> {
+    pub prefix: &'a str,
+    pub index: usize,
+    pub total: usize,
+    pub inputs: &'a [String],
+    pub now: chrono::DateTime<chrono::Local>,
+}
+
+impl JobNameFormat {
+    /// Renders the name of one batch. Characters that are not allowed in
+    /// file or scheduler job names are replaced with `_`; the second value
+    /// tells whether that happened.
+    pub fn render(&self, ctx: &NameContext) -> Result<(String, bool), String> {
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => name.push_str(text),
+                Part::Prefix => name.push_str(ctx.prefix),
+                Part::Index { width } => name.push_str(&pad(ctx.index, *width)),
+                Part::Total { width } => name.push_str(&pad(ctx.total, *width)),
+                Part::Date => name.push_str(&ctx.now.format("%Y%m%d").to_string()),
+                Part::Time => name.push_str(&ctx.now.format("%H%M%S").to_string()),
+                Part::Stem => match ctx.inputs {
+                    [input] => name.push_str(
+                        &Path::new(input)
+                            .file_stem()
+                            .map(|s| s.to_string_lossy().into_owned())
+                            .unwrap_or_else(|| input.clone()),
+                    ),
+                    _ => {
+                        return Err(format!(
+                            "--job-name-format {}: {{stem}} needs one input per batch, but batch {} has {}",
+                            self.template,
+                            ctx.index,
+                            ctx.inputs.len()
+                        ))
+                    }
+                },
+            }
+        }
+        if name.is_empty() {
+            return Err(format!(
+                "--job-name-format {}: empty job name for batch {}",
+                self.template, ctx.index
+            ));
+        }
+        let sanitized = name
+            .chars()
+            .map(|c| if name_char_allowed(c) { c } else { '_' })
+            .collect::<String>();
+        let changed = sanitized != name;
+        Ok((sanitized, changed))
+    }
+}
+
+/// Whether `c` may appear in a job name, and so in `--job-name-prefix`.
+fn name_char_allowed(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "._-".contains(c)
+}
+
+/// Refuses a `--job-name-prefix` with characters other than ASCII letters,
+/// digits, `.`, `_` and `-`, naming them. Such a prefix ends up in script
+/// paths and in the names the scheduler is queried by.
+pub(crate) fn check_prefix(prefix: &str) -> Result<(), String> {
+    let mut bad = Vec::new();
+    for c in prefix.chars().filter(|c| !name_char_allowed(*c)) {
+        if !bad.contains(&c) {
+            bad.push(c);
+        }
+    }
+    if prefix.is_empty() {
+        Err("--job-name-prefix cannot be empty".to_string())
+    } else if bad.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "--job-name-prefix {:?} contains {}; only letters, digits, '.', '_' and '-' are allowed",
+            prefix,
+            bad.iter()
+                .map(|c| format!("{:?}", c))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    }
+}
+
+/// `prefix` with each character [`check_prefix`] refuses replaced by `_`.
+pub(crate) fn sanitize_prefix(prefix: &str) -> Cow<'_, str> {
+    if prefix.chars().all(name_char_allowed) {
+        Cow::Borrowed(prefix)
+    } else {
+        Cow::Owned(
+            prefix
+                .chars()
+                .map(|c| if name_char_allowed(c) { c } else { '_' })
+                .collect(),
+        )
+    }
+}
+
+/// Renders the names of all batches and fits them to `scheduler`: names
+/// over its length limit are truncated and end in a hash of the full name,
+/// so they stay distinct. Sanitized and truncated names are warned about,
+/// or refused with `strict`. Names must be unique, since they also name
+/// the scripts.
+pub(crate) fn job_names(
+    format: &JobNameFormat,
+    prefix: &str,
+    groups: &[&[String]],
+    scheduler: Scheduler,
+    strict: bool,
+    warn: &mut dyn FnMut(String),
+) -> Result<Vec<String>, String> {
+    let now = chrono::Local::now();
+    let mut names = Vec::with_capacity(groups.len());
+    let mut seen = HashMap::new();
+    for (idx, inputs) in groups.iter().enumerate() {
+        let ctx = NameContext {
+            prefix,
+            index: idx + 1,
+            total: groups.len(),
+            inputs,
+            now,
+        };
+        let (mut name, sanitized) = format.render(&ctx)?;
+        if sanitized {
+            let message = format!(
+                "job name of batch {} contains characters not allowed in file or job names",
+                idx + 1
+            );
+            if strict {
+                return Err(format!("{} (--strict-names)", message));
+            }
+            warn(format!("warning: {}; using {}", message, name));
+        }
+        let max = scheduler
+            .max_job_name_len()
+            .map_or(MAX_NAME_LEN, |max| max.min(MAX_NAME_LEN));
+        if name.len() > max {
+            if strict {
+                return Err(format!(
+                    "job name {} of batch {} is longer than {} characters (--strict-names)",
+                    name,
+                    idx + 1,
+                    max
+                ));
+            }
+            let truncated = truncate(&name, max);
+            warn(format!(
+                "warning: job name {} is longer than {} characters; using {}",
+                name, max, truncated
+            ));
+            name = truncated;
+        }
+        scheduler
+            .check_job_name(&name)
+            .map_err(|e| format!("--job-name-format {}: {}", format.template, e))?;
+        if let Some(other) = seen.insert(name.clone(), idx + 1) {
+            return Err(format!(
+                "--job-name-format {}: batches {} and {} are both named {}",
+                format.template,
+                other,
+                idx + 1,
+                name
+            ));
+        }
+        names.push(name);
+    }
+    Ok(names)
+}
+
+/// Cuts `name` to `max` characters, the last 9 being `-` and a hash of
+/// the full name.
+fn truncate(name: &str, max: usize) -> String {
+    // FNV-1a, which unlike std's hasher is stable across releases.
+    let hash = name.bytes().fold(0x811c9dc5u32, |hash, b| {
+        (hash ^ u32::from(b)).wrapping_mul(0x01000193)
+    });
+    let suffix = format!("-{:08x}", hash);
+    let keep = max.saturating_sub(suffix.len());
+    // Sanitized names are ASCII, so any byte index is a boundary.
+    format!("{}{}", &name[..keep.min(name.len())], suffix)
+}
+
+fn pad(value: usize, width: usize) -> String {
+    format!("{:0width$}", value, width = width)
+}
+
+impl FromStr for JobNameFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<JobNameFormat, String> {
+        let mut parts = Vec::new();
+        for segment in placeholder::parse(s, PLACEHOLDERS)? {
+            let (key, spec) = match segment {
+                Segment::Literal(text) => {
+                    parts.push(Part::Literal(text));
+                    continue;
+                }
+                Segment::Placeholder { name, spec } => (name, spec),
+            };
+            // Only zero padding: spaces are not allowed in names.
+            let width = |spec: Option<&str>| -> Result<usize, String> {
+                let Some(spec) = spec else {
+                    return Ok(0);
+                };
+                match spec.parse::<usize>() {
+                    Ok(width) if spec.starts_with('0') => Ok(width),
+                    Ok(_) => Err(format!(
+                        "width {:?} in {{{}:{}}} must start with 0 (e.g. {{{}:0{}}}); names are zero-padded",
+                        spec, key, spec, key, spec
+                    )),
+                    Err(_) => Err(format!(
+                        "invalid width {:?} in {{{}:{}}} (expected e.g. {{{}:04}})",
+                        spec, key, spec, key
+                    )),
+                }
+            };
+            parts.push(match key {
+                "index" => Part::Index {
+                    width: width(spec)?,
+                },
+                "total" => Part::Total {
+                    width: width(spec)?,
+                },
+                _ if spec.is_some() => {
+                    return Err(format!("{{{}}} takes no width", key));
+                }
+                "prefix" => Part::Prefix,
+                "date" => Part::Date,
+                "time" => Part::Time,
+                _ => Part::Stem,
+            });
+        }
+        Ok(JobNameFormat {
+            template: s.to_string(),
+            parts,
+        })
+    }
+}
+
+impl fmt::Display for JobNameFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(format: &str, index: usize, inputs: &[&str]) -> Result<(String, bool), String> {
+        let inputs = inputs.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let format = format.parse::<JobNameFormat>()?;
+        format.render(&NameContext {
+            prefix: "batch",
+            index,
+            total: 12,
+            inputs: &inputs,
+            now: chrono::Local::now(),
+        })
+    }
+
+    #[test]
+    fn default_format() {
+        assert_eq!(
+            render(DEFAULT_JOB_NAME_FORMAT, 7, &[]).unwrap(),
+            ("batch-0007".to_string(), false)
+        );
+    }
+
+    #[test]
+    fn widths_are_zero_padded() {
+        assert_eq!(
+            render("{index:03}of{total:03}", 7, &[]).unwrap().0,
+            "007of012"
+        );
+        assert_eq!(render("{index}-{total}", 7, &[]).unwrap().0, "7-12");
+        assert_eq!(render("{index:0}", 7, &[]).unwrap().0, "7");
+    }
+
+    #[test]
+    fn widths_without_a_zero_are_refused() {
+        let e = "{index:4}".parse::<JobNameFormat>().unwrap_err();
+        assert!(e.contains("must start with 0 (e.g. {index:04})"), "{}", e);
+        let e = "{total:x}".parse::<JobNameFormat>().unwrap_err();
+        assert!(e.contains("invalid width \"x\""), "{}", e);
+    }
+
+    #[test]
+    fn bad_templates_are_refused() {
+        for (format, error) in [
+            ("{prefix", "unclosed {"),
+            ("prefix}", "unmatched }"),
+            ("{nope}", "unknown placeholder {nope}"),
+            ("{date:04}", "{date} takes no width"),
+        ] {
+            let e = format.parse::<JobNameFormat>().unwrap_err();
+            assert!(e.contains(error), "{}: {}", format, e);
+        }
+    }
+
+    #[test]
+    fn braces_and_stems() {
+        assert_eq!(
+            render("{{{stem}}}", 1, &["in/sample.R1.fq"]).unwrap(),
+            ("_sample.R1_".to_string(), true)
+        );
+        let e = render("{stem}", 2, &["a.fq", "b.fq"]).unwrap_err();
+        assert!(e.contains("batch 2 has 2"), "{}", e);
+    }
+
+    #[test]
+    fn names_use_the_prefix_character_set() {
+        let (name, sanitized) = render("{prefix}+x y/é", 1, &[]).unwrap();
+        assert_eq!(name, "batch_x_y__");
+        assert!(sanitized);
+        assert!(check_prefix(&name).is_ok());
+        assert!(check_prefix("a+b").is_err());
+        assert_eq!(sanitize_prefix("a+b"), "a_b");
+    }
+
+    #[test]
+    fn good_and_bad_prefixes() {
+        for prefix in ["batch", "run-2", "align_v1.2", "A-Z.0_9", "-", "."] {
+            assert_eq!(check_prefix(prefix), Ok(()), "{:?}", prefix);
+            assert!(matches!(sanitize_prefix(prefix), Cow::Borrowed(_)));
+        }
+        let only = "only letters, digits, '.', '_' and '-' are allowed";
+        for (prefix, bad, sanitized) in [
+            ("my run", "' '", "my_run"),
+            ("a/b", "'/'", "a_b"),
+            ("../x", "'/'", ".._x"),
+            ("x+y", "'+'", "x_y"),
+            ("$(rm)", "'$', '(', ')'", "__rm_"),
+            ("a b c/d", "' ', '/'", "a_b_c_d"),
+            ("tab\there", "'\\t'", "tab_here"),
+            ("né", "'é'", "n_"),
+            ("a*b?", "'*', '?'", "a_b_"),
+        ] {
+            assert_eq!(
+                check_prefix(prefix).unwrap_err(),
+                format!("--job-name-prefix {:?} contains {}; {}", prefix, bad, only)
+            );
+            assert_eq!(sanitize_prefix(prefix), sanitized);
+            assert_eq!(check_prefix(&sanitize_prefix(prefix)), Ok(()));
+        }
+        assert_eq!(
+            check_prefix("").unwrap_err(),
+            "--job-name-prefix cannot be empty"
+        );
+    }
+
+    const SCHEDULERS: [Scheduler; 5] = [
+        Scheduler::Slurm,
+        Scheduler::Pbs,
+        Scheduler::Sge,
+        Scheduler::Lsf,
+        Scheduler::Generic,
+    ];
+
+    /// The names `job_names` gives one batch per input in `stems`, and its
+    /// warnings.
+    fn name_batches(
+        format: &str,
+        prefix: &str,
+        stems: &[String],
+        scheduler: Scheduler,
+        strict: bool,
+    ) -> (Result<Vec<String>, String>, Vec<String>) {
+        let format = format.parse::<JobNameFormat>().unwrap();
+        let groups = stems.iter().map(std::slice::from_ref).collect::<Vec<_>>();
+        let mut warnings = Vec::new();
+        let names = job_names(&format, prefix, &groups, scheduler, strict, &mut |w| {
+            warnings.push(w)
+        });
+        (names, warnings)
+    }
+
+    fn max_len(scheduler: Scheduler) -> usize {
+        scheduler
+            .max_job_name_len()
+            .map_or(MAX_NAME_LEN, |max| max.min(MAX_NAME_LEN))
+    }
+
+    #[test]
+    fn names_at_the_limit_are_kept() {
+        for scheduler in SCHEDULERS {
+            let prefix = "a".repeat(max_len(scheduler));
+            for strict in [false, true] {
+                let (names, warnings) =
+                    name_batches("{prefix}", &prefix, &[String::new()], scheduler, strict);
+                assert_eq!(names.unwrap(), [prefix.as_str()], "{:?}", scheduler);
+                assert!(warnings.is_empty(), "{:?}", warnings);
+            }
+        }
+    }
+
+    #[test]
+    fn names_over_the_limit_are_truncated_with_a_hash() {
+        for scheduler in SCHEDULERS {
+            let max = max_len(scheduler);
+            let prefix = "a".repeat(max + 1);
+            let (names, warnings) =
+                name_batches("{prefix}", &prefix, &[String::new()], scheduler, false);
+            let name = &names.unwrap()[0];
+            assert_eq!(name.len(), max, "{:?}", scheduler);
+            assert!(name.starts_with(&"a".repeat(max - 9)));
+            assert!(name[max - 9..].starts_with('-'));
+            assert!(name[max - 8..].chars().all(|c| c.is_ascii_hexdigit()));
+            assert_eq!(warnings.len(), 1);
+            assert!(
+                warnings[0].contains(&format!("longer than {} characters; using {}", max, name))
+            );
+        }
+    }
+
+    #[test]
+    fn strict_names_refuse_names_over_the_limit() {
+        for scheduler in SCHEDULERS {
+            let max = max_len(scheduler);
+            let (names, warnings) = name_batches(
+                "{prefix}",
+                &"a".repeat(max + 1),
+                &[String::new()],
+                scheduler,
+                true,
+            );
+            let e = names.unwrap_err();
+            assert!(
+                e.contains(&format!("longer than {} characters (--strict-names)", max)),
+                "{:?}: {}",
+                scheduler,
+                e
+            );
+            assert!(warnings.is_empty());
+        }
+    }
+
+    #[test]
+    fn strict_names_refuse_sanitized_names() {
+        for scheduler in SCHEDULERS {
+            let stems = ["a b.fq".to_string()];
+            let (names, _) = name_batches("{prefix}-{stem}", "batch", &stems, scheduler, true);
+            assert!(names.unwrap_err().contains("(--strict-names)"));
+            let (names, warnings) =
+                name_batches("{prefix}-{stem}", "batch", &stems, scheduler, false);
+            assert_eq!(names.unwrap(), ["batch-a_b"]);
+            assert_eq!(warnings.len(), 1);
+        }
+    }
+
+    #[test]
+    fn truncated_names_differing_past_the_cut_stay_distinct() {
+        for scheduler in SCHEDULERS {
+            let prefix = "a".repeat(max_len(scheduler));
+            let stems = ["x", "y", "z"].map(String::from);
+            let (names, warnings) =
+                name_batches("{prefix}{stem}", &prefix, &stems, scheduler, false);
+            let names = names.unwrap();
+            assert_eq!(names.len(), 3);
+            assert!(names[0] != names[1] && names[1] != names[2] && names[0] != names[2]);
+            assert_eq!(warnings.len(), 3);
+        }
+    }
+
+    #[test]
+    fn colliding_hash_suffixes_are_refused() {
+        let scheduler = Scheduler::Pbs;
+        let prefix = "a".repeat(max_len(scheduler));
+        // Finds two stems whose truncated names collide (a birthday search
+        // over the 32-bit hash).
+        let mut seen = HashMap::new();
+        let stems = (0u32..)
+            .find_map(|i| {
+                let stem = format!("{:x}", i);
+                let name = truncate(&format!("{}{}", prefix, stem), max_len(scheduler));
+                seen.insert(name, stem.clone()).map(|other| [other, stem])
+            })
+            .unwrap();
+        let (names, _) = name_batches("{prefix}{stem}", &prefix, &stems, scheduler, false);
+        let e = names.unwrap_err();
+        assert!(e.contains("batches 1 and 2 are both named"), "{}", e);
+    }
+}
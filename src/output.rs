@@ -0,0 +1,176 @@
+//! User-facing output of a run. Long phases (generating scripts,
+//! submitting) show a progress bar on a terminal, with messages printed
+//! above it; otherwise they print a progress line every few seconds.
+//! Lines go to the run's [`Reporter`].
+
+use crate::reporter::Reporter;
+use crate::units;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+/// How often plain mode reports progress within a phase.
+const PLAIN_INTERVAL: Duration = Duration::from_secs(10);
+
+pub(crate) struct Output<'a> {
+    progress_bars: bool,
+    reporter: &'a dyn Reporter,
+    phase: Option<Phase>,
+}
+
+struct Phase {
+    name: &'static str,
+    total: usize,
+    done: usize,
+    started: Instant,
+    last_report: Instant,
+    reported: bool,
+    bar: Option<ProgressBar>,
+}
+
+impl<'a> Output<'a> {
+    /// Progress bars are only drawn when `reporter` allows them.
+    pub(crate) fn new(progress_bars: bool, reporter: &'a dyn Reporter) -> Output<'a> {
+        Output {
+            progress_bars: progress_bars && reporter.progress_bars(),
+            reporter,
+            phase: None,
+        }
+    }
+
+    pub(crate) fn reporter(&self) -> &'a dyn Reporter {
+        self.reporter
+    }
+
+    /// Reports a status line, above the progress bar if one is shown.
+    pub(crate) fn println(&self, line: impl Display) {
+        self.suspend(|| self.reporter.message(&line.to_string()));
+    }
+
+    /// Reports a diagnostic, above the progress bar if one is shown.
+    pub(crate) fn eprintln(&self, line: impl Display) {
+        self.suspend(|| self.reporter.diagnostic(&line.to_string()));
+    }
+
+    /// Reports an error the run carries on after, above the progress bar if
+    /// one is shown.
+    pub(crate) fn error(&self, line: impl Display) {
+        self.suspend(|| self.reporter.error(&line.to_string()));
+    }
+
+    /// Reports a `-v` detail, above the progress bar if one is shown.
+    pub(crate) fn detail(&self, line: impl Display) {
+        self.suspend(|| self.reporter.detail(&line.to_string()));
+    }
+
+    /// Writes `text` to the data stream, above the progress bar if one is
+    /// shown.
+    pub(crate) fn data(&self, text: impl Display) {
+        self.suspend(|| self.reporter.data(&text.to_string()));
+    }
+
+    /// Reports the submit command's own output for one job. The progress
+    /// bar replaces these lines, so they are only shown without it.
+    pub(crate) fn job_output(&self, text: &str) {
+        if self.bar().is_none() {
+            self.reporter.job_output(text);
+        }
+    }
+
+    pub(crate) fn start_phase(&mut self, name: &'static str, total: usize) {
+        self.finish_phase();
+        let bar = self.progress_bars.then(|| {
+            let bar =
+                ProgressBar::with_draw_target(Some(total as u64), ProgressDrawTarget::stderr());
+            bar.set_style(
+                ProgressStyle::with_template(
+                    "{prefix:>18} [{bar:30}] {pos}/{len} ({rate}, ETA {eta})",
+                )
+                .expect("valid progress template")
+                .with_key("rate", |state: &ProgressState, w: &mut dyn fmt::Write| {
+                    let _ = write!(w, "{:.1}/s", state.per_sec());
+                })
+                .progress_chars("=> "),
+            );
+            bar.set_prefix(name);
+            bar
+        });
+        let now = Instant::now();
+        self.phase = Some(Phase {
+            name,
+            total,
+            done: 0,
+            started: now,
+            last_report: now,
+            reported: false,
+            bar,
+        });
+    }
+
+    /// Counts one item of the current phase as done.
+    pub(crate) fn advance(&mut self) {
+        let Some(phase) = self.phase.as_mut() else {
+            return;
+        };
+        phase.done += 1;
+        if let Some(bar) = &phase.bar {
+            bar.inc(1);
+        } else if phase.last_report.elapsed() >= PLAIN_INTERVAL {
+            phase.last_report = Instant::now();
+            phase.reported = true;
+            let status = phase.status();
+            self.println(status);
+        }
+    }
+
+    /// Ends the current phase, clearing its progress bar. In plain mode a
+    /// final line is printed if progress was reported along the way.
+    pub(crate) fn finish_phase(&mut self) {
+        let Some(phase) = self.phase.take() else {
+            return;
+        };
+        match &phase.bar {
+            Some(bar) => bar.finish_and_clear(),
+            None if phase.reported => self.println(phase.status()),
+            None => {}
+        }
+    }
+
+    fn bar(&self) -> Option<&ProgressBar> {
+        self.phase.as_ref().and_then(|p| p.bar.as_ref())
+    }
+
+    fn suspend(&self, f: impl FnOnce()) {
+        match self.bar() {
+            Some(bar) => bar.suspend(f),
+            None => f(),
+        }
+    }
+}
+
+impl Drop for Output<'_> {
+    fn drop(&mut self) {
+        // Leaves a clean terminal when a run ends early with an error.
+        self.finish_phase();
+    }
+}
+
+impl Phase {
+    fn status(&self) -> String {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let rate = self.done as f64 / elapsed.max(0.001);
+        let eta = if rate > 0.0 {
+            ((self.total - self.done) as f64 / rate).ceil() as u64
+        } else {
+            0
+        };
+        format!(
+            "{}: {}/{} ({:.1}/s, ETA {})",
+            self.name,
+            self.done,
+            self.total,
+            rate,
+            units::format_duration(eta)
+        )
+    }
+}
@@ -0,0 +1,237 @@
+//! `--submit-overrides`: per-batch changes to the submit command, read from
+//! a TOML file such as
+//!
+//! ```toml
+//! [overrides]
+//! "5-8" = "--partition=bigmem --mem=500G"           # appended to --submit
+//! "9" = { submit = "sbatch --partition=gpu" }        # replaces --submit
+//! "10-" = { submit = "sbatch", args = "--qos=long" } # both
+//! ```
+//!
+//! No batch may be covered by two entries. The arguments of matching
+//! `--resource-rule`s come after the submit string, overridden or not.
+
+use crate::selection::BatchSet;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OverridesFile {
+    overrides: BTreeMap<String, OverrideValue>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OverrideValue {
+    Args(String),
+    Table(Override),
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Override {
+    /// Replacement for the whole `--submit` string.
+    submit: Option<String>,
+    /// Extra arguments appended to the submit string.
+    args: Option<String>,
+}
+
+/// Submit command overrides keyed by the batches they apply to. No batch
+/// is covered by more than one entry.
+#[derive(Clone, Debug, Default)]
+pub struct SubmitOverrides {
+    entries: Vec<(BatchSet, Override)>,
+}
+
+impl SubmitOverrides {
+    pub fn load(path: &Path) -> Result<SubmitOverrides, Box<dyn std::error::Error>> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let file: OverridesFile =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let mut entries: Vec<(BatchSet, Override)> = Vec::new();
+        for (key, value) in file.overrides {
+            let batches: BatchSet = key
+                .parse()
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            if let Some((other, _)) = entries.iter().find(|(other, _)| other.overlaps(&batches)) {
+                return Err(format!(
+                    "{}: overrides {:?} and {:?} cover the same batch",
+                    path.display(),
+                    other.to_string(),
+                    key
+                )
+                .into());
+            }
+            let entry = match value {
+                OverrideValue::Args(args) => Override {
+                    args: Some(args),
+                    ..Override::default()
+                },
+                OverrideValue::Table(entry) => entry,
+            };
+            for text in [&entry.submit, &entry.args].into_iter().flatten() {
+                if shlex::split(text).is_none() {
+                    return Err(format!(
+                        "{}: could not parse {:?} for batches {} (check shell quoting)",
+                        path.display(),
+                        text,
+                        key
+                    )
+                    .into());
+                }
+            }
+            if entry.submit.as_deref().is_some_and(|s| s.trim().is_empty()) {
+                return Err(format!(
+                    "{}: empty submit string for batches {}",
+                    path.display(),
+                    key
+                )
+                .into());
+            }
+            entries.push((batches, entry));
+        }
+        Ok(SubmitOverrides { entries })
+    }
+
+    /// The submit string to use for batch `index`.
+    pub fn submit_for(&self, base: &str, index: usize) -> String {
+        let Some((_, entry)) = self.entries.iter().find(|(set, _)| set.contains(index)) else {
+            return base.to_string();
+        };
+        let submit = entry.submit.as_deref().unwrap_or(base);
+        match &entry.args {
+            Some(args) => format!("{} {}", submit, args),
+            None => submit.to_string(),
+        }
+    }
+
+    /// Replacement submit strings, so their programs can be checked up front.
+    pub fn replacement_submits(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().filter_map(|(_, e)| e.submit.as_deref())
+    }
+
+    /// Checks that every batch named explicitly exists.
+    pub fn check_bounds(&self, count: usize) -> Result<(), String> {
+        for (set, _) in &self.entries {
+            set.check_bounds(count)
+                .map_err(|e| format!("--submit-overrides {}: {}", set, e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(toml: &str) -> Result<SubmitOverrides, String> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("overrides.toml");
+        fs::write(&path, toml).unwrap();
+        SubmitOverrides::load(&path).map_err(|e| {
+            e.to_string()
+                .replace(&path.display().to_string(), "overrides.toml")
+        })
+    }
+
+    #[test]
+    fn entries_append_replace_or_both() {
+        let overrides = load(
+            r#"
+            [overrides]
+            "2-3" = "--mem=500G"
+            "5" = { submit = "sbatch -p gpu" }
+            "7-" = { submit = "sbatch", args = "--qos=long" }
+            "#,
+        )
+        .unwrap();
+        let submits = (1..=8)
+            .map(|index| overrides.submit_for("sbatch -p short", index))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            submits,
+            [
+                "sbatch -p short",
+                "sbatch -p short --mem=500G",
+                "sbatch -p short --mem=500G",
+                "sbatch -p short",
+                "sbatch -p gpu",
+                "sbatch -p short",
+                "sbatch --qos=long",
+                "sbatch --qos=long",
+            ]
+        );
+        assert_eq!(
+            overrides.replacement_submits().collect::<Vec<_>>(),
+            ["sbatch -p gpu", "sbatch"]
+        );
+    }
+
+    #[test]
+    fn overlapping_entries_conflict() {
+        for (a, b) in [("1-5", "5"), ("3", "1,3"), ("10-", "20-30"), ("2-", "1-2")] {
+            let error = load(&format!(
+                "[overrides]\n\"{}\" = \"-a\"\n\"{}\" = \"-b\"\n",
+                a, b
+            ))
+            .unwrap_err();
+            assert!(
+                error.starts_with("overrides.toml: overrides ")
+                    && error.ends_with(" cover the same batch"),
+                "{}",
+                error
+            );
+        }
+        // Apart, they do not.
+        load("[overrides]\n\"1-4\" = \"-a\"\n\"5-\" = \"-b\"\n").unwrap();
+    }
+
+    #[test]
+    fn a_batch_given_twice_is_a_toml_error() {
+        let error = load("[overrides]\n\"3\" = \"-a\"\n\"3\" = \"-b\"\n").unwrap_err();
+        assert!(error.starts_with("overrides.toml: "), "{}", error);
+        assert!(error.contains("duplicate key"), "{}", error);
+    }
+
+    #[test]
+    fn malformed_entries_are_refused() {
+        let cases = [
+            (
+                "[overrides]\n\"0\" = \"-a\"\n",
+                "overrides.toml: batch indices start at 1, got \"0\"",
+            ),
+            (
+                "[overrides]\n\"2\" = \"--comment='open\"\n",
+                "overrides.toml: could not parse \"--comment='open\" for batches 2 (check shell quoting)",
+            ),
+            (
+                "[overrides]\n\"2\" = { submit = \" \" }\n",
+                "overrides.toml: empty submit string for batches 2",
+            ),
+        ];
+        for (toml, expected) in cases {
+            assert_eq!(load(toml).unwrap_err(), expected);
+        }
+        for toml in [
+            "[overrides]\n\"2\" = { sbumit = \"sbatch\" }\n",
+            "[overides]\n\"2\" = \"-a\"\n",
+        ] {
+            assert!(load(toml).is_err(), "{}", toml);
+        }
+    }
+
+    #[test]
+    fn every_batch_named_must_exist() {
+        let overrides = load("[overrides]\n\"2-4\" = \"-a\"\n\"5-\" = \"-b\"\n").unwrap();
+        assert_eq!(overrides.check_bounds(5), Ok(()));
+        assert_eq!(
+            overrides.check_bounds(3),
+            Err("--submit-overrides 2-4: batch 4 is out of range; valid batches are 1-3".into())
+        );
+    }
+}
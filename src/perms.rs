@@ -0,0 +1,334 @@
+//! The permissions and group of the files and directories a run writes:
+//! scripts (`--script-mode`), the run state, manifests, reports, input
+//! lists and log directories (`--artifact-mode`), all given `--group`.
+//! Without a mode the defaults below apply less the umask; a mode given
+//! is applied exactly. Directories get the artifact mode plus search
+//! permission wherever it grants read permission (640 becomes 750), and
+//! only directories a run creates are changed.
+//!
+//! Only Unix has modes and groups; elsewhere these options do nothing.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// The mode of scripts without `--script-mode`, less the umask.
+pub(crate) const DEFAULT_SCRIPT_MODE: u32 = 0o755;
+
+/// The mode of other files without `--artifact-mode`, less the umask.
+pub(crate) const DEFAULT_ARTIFACT_MODE: u32 = 0o644;
+
+/// Parses an octal mode such as `750`, `0750` or `0o750`.
+pub fn parse_mode(s: &str) -> Result<u32, String> {
+    let t = s.trim();
+    let digits = t
+        .strip_prefix("0o")
+        .or_else(|| t.strip_prefix("0O"))
+        .unwrap_or(t);
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if !digits.is_empty() && mode <= 0o7777 => Ok(mode),
+        _ => Err(format!(
+            "invalid mode {:?} (expected an octal mode such as 750 or 0640)",
+            s
+        )),
+    }
+}
+
+/// What a file is, for its mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Script,
+    Artifact,
+}
+
+/// The modes and group a run gives what it writes.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Permissions {
+    script_mode: Option<u32>,
+    artifact_mode: Option<u32>,
+    group: Option<Group>,
+}
+
+#[derive(Clone, Debug)]
+struct Group {
+    name: String,
+    gid: u32,
+}
+
+impl Permissions {
+    /// The permissions `--script-mode`, `--artifact-mode` and `--group`
+    /// ask for. Fails for a group that does not exist.
+    pub(crate) fn new(
+        script_mode: Option<u32>,
+        artifact_mode: Option<u32>,
+        group: Option<&str>,
+    ) -> Result<Permissions, String> {
+        if !cfg!(unix) {
+            return Ok(Permissions::default());
+        }
+        let group = group
+            .map(|name| {
+                Ok::<_, String>(Group {
+                    name: name.to_string(),
+                    gid: gid(name)?,
+                })
+            })
+            .transpose()?;
+        Ok(Permissions {
+            script_mode,
+            artifact_mode,
+            group,
+        })
+    }
+
+    /// The mode `path` has, to keep it when the file is replaced. Its
+    /// group is not kept: whoever replaces it may not belong to it. The
+    /// defaults for a file that does not exist.
+    pub(crate) fn like(path: &Path) -> Permissions {
+        #[cfg(unix)]
+        if let Ok(metadata) = fs::metadata(path) {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = metadata.permissions().mode() & 0o7777;
+            return Permissions {
+                script_mode: Some(mode),
+                artifact_mode: Some(mode),
+                group: None,
+            };
+        }
+        let _ = path;
+        Permissions::default()
+    }
+
+    /// Whether a mode or group was asked for.
+    pub(crate) fn is_set(&self) -> bool {
+        self.script_mode.is_some() || self.artifact_mode.is_some() || self.group.is_some()
+    }
+
+    fn mode(&self, kind: Kind) -> Option<u32> {
+        match kind {
+            Kind::Script => self.script_mode,
+            Kind::Artifact => self.artifact_mode,
+        }
+    }
+
+    /// Creates (or truncates) `path` for writing. A new file gets the mode
+    /// of `kind` less the umask, so it is never more open than asked for;
+    /// [`Permissions::apply`] sets the mode exactly.
+    pub(crate) fn create(&self, path: &Path, kind: Kind) -> io::Result<File> {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(self.mode(kind).unwrap_or(match kind {
+                Kind::Script => DEFAULT_SCRIPT_MODE,
+                Kind::Artifact => DEFAULT_ARTIFACT_MODE,
+            }));
+        }
+        options.open(path)
+    }
+
+    /// Writes `contents` to `path` with the permissions of `kind`.
+    pub(crate) fn write(&self, path: &Path, contents: &[u8], kind: Kind) -> io::Result<()> {
+        let mut file = self.create(path, kind)?;
+        file.write_all(contents)?;
+        self.apply(path, kind)
+    }
+
+    /// Gives `path` the mode asked for `kind`, ignoring the umask, and the
+    /// group.
+    pub(crate) fn apply(&self, path: &Path, kind: Kind) -> io::Result<()> {
+        self.apply_mode(path, self.mode(kind))
+    }
+
+    fn apply_mode(&self, path: &Path, mode: Option<u32>) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if let Some(group) = &self.group {
+                std::os::unix::fs::chown(path, None, Some(group.gid)).map_err(|e| {
+                    io::Error::new(
+                        e.kind(),
+                        format!(
+                            "could not change the group of {} to {}: {}",
+                            path.display(),
+                            group.name,
+                            e
+                        ),
+                    )
+                })?;
+            }
+            // After the group: changing it may clear the setgid bit.
+            if let Some(mode) = mode {
+                fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = (path, mode);
+        Ok(())
+    }
+
+    /// Creates `dir` and its missing parents, giving the ones it creates
+    /// the artifact mode (with search permission) and the group.
+    pub(crate) fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        let missing = dir
+            .ancestors()
+            .take_while(|d| !d.as_os_str().is_empty() && !d.exists())
+            .map(Path::to_path_buf)
+            .collect::<Vec<_>>();
+        fs::create_dir_all(dir)?;
+        let mode = self.artifact_mode.map(|mode| mode | (mode & 0o444) >> 2);
+        for created in missing.iter().rev() {
+            self.apply_mode(created, mode)?;
+        }
+        Ok(())
+    }
+}
+
+/// The ID of group `name`, which may be numeric. Asks `getent` so groups
+/// from LDAP and the like are found, falling back to `/etc/group`.
+fn gid(name: &str) -> Result<u32, String> {
+    if let Ok(gid) = name.parse() {
+        return Ok(gid);
+    }
+    let entry = std::process::Command::new("getent")
+        .args(["group", name])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .map(|out| String::from_utf8_lossy(&out.stdout).into_owned())
+        .or_else(|| {
+            fs::read_to_string("/etc/group")
+                .ok()?
+                .lines()
+                .find_map(|line| (line.split(':').next() == Some(name)).then(|| line.to_string()))
+        });
+    entry
+        .and_then(|entry| entry.trim().split(':').nth(2)?.parse().ok())
+        .ok_or_else(|| format!("--group {}: no such group", name))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o7777
+    }
+
+    /// The permission bits the umask leaves of a new file.
+    fn umask_allows(dir: &Path) -> u32 {
+        use std::os::unix::fs::OpenOptionsExt;
+        let probe = dir.join("probe");
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o777)
+            .open(&probe)
+            .unwrap();
+        let allowed = mode(&probe);
+        fs::remove_file(probe).unwrap();
+        allowed
+    }
+
+    #[test]
+    fn modes_parse_as_octal() {
+        for (text, expected) in [("750", 0o750), ("0640", 0o640), ("0o2775", 0o2775)] {
+            assert_eq!(parse_mode(text), Ok(expected), "{}", text);
+        }
+        for text in ["", "0o", "789", "17777", "rwx"] {
+            assert_eq!(
+                parse_mode(text),
+                Err(format!(
+                    "invalid mode {:?} (expected an octal mode such as 750 or 0640)",
+                    text
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn defaults_are_less_the_umask() {
+        let dir = tempfile::tempdir().unwrap();
+        let allowed = umask_allows(dir.path());
+        let perms = Permissions::default();
+        let (script, artifact) = (dir.path().join("a.sh"), dir.path().join("a.json"));
+        perms.write(&script, b"", Kind::Script).unwrap();
+        perms.write(&artifact, b"", Kind::Artifact).unwrap();
+        assert_eq!(mode(&script), DEFAULT_SCRIPT_MODE & allowed);
+        assert_eq!(mode(&artifact), DEFAULT_ARTIFACT_MODE & allowed);
+        assert!(!perms.is_set());
+    }
+
+    #[test]
+    fn modes_given_are_applied_exactly() {
+        let dir = tempfile::tempdir().unwrap();
+        let perms = Permissions::new(Some(0o770), Some(0o664), None).unwrap();
+        assert!(perms.is_set());
+        let (script, artifact) = (dir.path().join("a.sh"), dir.path().join("a.json"));
+        perms.write(&script, b"#!/bin/sh\n", Kind::Script).unwrap();
+        perms.write(&artifact, b"{}", Kind::Artifact).unwrap();
+        // Group write is on even under the usual umask of 022.
+        assert_eq!(mode(&script), 0o770);
+        assert_eq!(mode(&artifact), 0o664);
+        // Rewriting an existing file sets its mode too.
+        Permissions::new(None, Some(0o600), None)
+            .unwrap()
+            .write(&artifact, b"{}", Kind::Artifact)
+            .unwrap();
+        assert_eq!(mode(&artifact), 0o600);
+        assert_eq!(Permissions::like(&artifact).mode(Kind::Script), Some(0o600));
+        assert_eq!(
+            Permissions::like(&dir.path().join("missing")).mode(Kind::Artifact),
+            None
+        );
+    }
+
+    #[test]
+    fn created_directories_can_be_searched() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("existing");
+        fs::create_dir(&existing).unwrap();
+        fs::set_permissions(&existing, fs::Permissions::from_mode(0o700)).unwrap();
+        let perms = Permissions::new(None, Some(0o640), None).unwrap();
+        perms.create_dir_all(&existing.join("runs/run")).unwrap();
+        assert_eq!(mode(&existing.join("runs")), 0o750);
+        assert_eq!(mode(&existing.join("runs/run")), 0o750);
+        assert_eq!(mode(&existing), 0o700);
+        let perms = Permissions::new(None, Some(0o604), None).unwrap();
+        perms.create_dir_all(&dir.path().join("other")).unwrap();
+        assert_eq!(mode(&dir.path().join("other")), 0o705);
+    }
+
+    #[test]
+    fn files_get_the_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        fs::write(&path, "").unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        // Any group for root; otherwise the group new files already get.
+        let gid = if metadata.uid() == 0 {
+            metadata.gid() + 1
+        } else {
+            metadata.gid()
+        };
+        let perms = Permissions::new(None, Some(0o2640), Some(&gid.to_string())).unwrap();
+        perms.write(&path, b"{}", Kind::Artifact).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().gid(), gid);
+        // The mode is set after the group, which keeps setgid.
+        assert_eq!(mode(&path), 0o2640);
+        perms.create_dir_all(&dir.path().join("logs")).unwrap();
+        assert_eq!(fs::metadata(dir.path().join("logs")).unwrap().gid(), gid);
+        // Replacing the file keeps its mode, not its group.
+        assert!(Permissions::like(&path).group.is_none());
+    }
+
+    #[test]
+    fn unknown_groups_are_refused() {
+        assert_eq!(
+            Permissions::new(None, None, Some("no-such-group-batchelor")).unwrap_err(),
+            "--group no-such-group-batchelor: no such group"
+        );
+    }
+}
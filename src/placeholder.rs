@@ -0,0 +1,155 @@
+//! `{placeholder}` templates: `--job-name-format` and `--job-log-template`.
+//! `{name}` and `{name:spec}` are placeholders, `{{` and `}}` literal braces.
+//! Each template knows its own placeholders and refuses any other, so a
+//! typo is an error rather than text in a file name.
+
+/// A piece of a template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Segment<'a> {
+    Literal(String),
+    Placeholder {
+        name: &'a str,
+        /// What follows a `:`, e.g. `04` in `{index:04}`.
+        spec: Option<&'a str>,
+    },
+}
+
+/// Splits `template` into literal text and the placeholders of `known`.
+pub(crate) fn parse<'a>(template: &'a str, known: &[&str]) -> Result<Vec<Segment<'a>>, String> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut rest = template;
+    while let Some(at) = rest.find(['{', '}']) {
+        literal.push_str(&rest[..at]);
+        if rest[at..].starts_with("{{") || rest[at..].starts_with("}}") {
+            literal.push_str(&rest[at..at + 1]);
+            rest = &rest[at + 2..];
+            continue;
+        }
+        if rest[at..].starts_with('}') {
+            return Err(format!("unmatched }} in {:?}", template));
+        }
+        let end = rest[at..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed {{ in {:?}", template))?
+            + at;
+        let inside = &rest[at + 1..end];
+        let (name, spec) = match inside.split_once(':') {
+            Some((name, spec)) => (name, Some(spec)),
+            None => (inside, None),
+        };
+        if !known.contains(&name) {
+            return Err(format!(
+                "unknown placeholder {{{}}} (expected {})",
+                name,
+                one_of(known)
+            ));
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(std::mem::take(&mut literal)));
+        }
+        segments.push(Segment::Placeholder { name, spec });
+        rest = &rest[end + 1..];
+    }
+    literal.push_str(rest);
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+/// `segments` with each placeholder replaced by `value` of its name.
+pub(crate) fn render(segments: &[Segment], value: impl Fn(&str) -> String) -> String {
+    segments
+        .iter()
+        .map(|segment| match segment {
+            Segment::Literal(text) => text.clone(),
+            Segment::Placeholder { name, .. } => value(name),
+        })
+        .collect()
+}
+
+/// `a, b or c`.
+fn one_of(names: &[&str]) -> String {
+    match names {
+        [] => "none".to_string(),
+        [name] => name.to_string(),
+        [init @ .., last] => format!("{} or {}", init.join(", "), last),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KNOWN: &[&str] = &["job_name", "batch_index"];
+
+    fn placeholder(name: &str) -> Segment<'_> {
+        Segment::Placeholder { name, spec: None }
+    }
+
+    #[test]
+    fn segments() {
+        let literal = |s: &str| Segment::Literal(s.to_string());
+        for (template, segments) in [
+            ("", vec![]),
+            ("%j.out", vec![literal("%j.out")]),
+            ("{job_name}", vec![placeholder("job_name")]),
+            (
+                "{job_name}.{batch_index}.%j.out",
+                vec![
+                    placeholder("job_name"),
+                    literal("."),
+                    placeholder("batch_index"),
+                    literal(".%j.out"),
+                ],
+            ),
+            (
+                "{{{job_name}}}",
+                vec![literal("{"), placeholder("job_name"), literal("}")],
+            ),
+            ("{{job_name}}", vec![literal("{job_name}")]),
+            (
+                "{batch_index:04}",
+                vec![Segment::Placeholder {
+                    name: "batch_index",
+                    spec: Some("04"),
+                }],
+            ),
+        ] {
+            assert_eq!(parse(template, KNOWN).unwrap(), segments, "{:?}", template);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        for (template, error) in [
+            ("{job_name", "unclosed { in \"{job_name\""),
+            ("job_name}", "unmatched } in \"job_name}\""),
+            (
+                "{jobname}.out",
+                "unknown placeholder {jobname} (expected job_name or batch_index)",
+            ),
+            (
+                "{}",
+                "unknown placeholder {} (expected job_name or batch_index)",
+            ),
+        ] {
+            assert_eq!(parse(template, KNOWN).unwrap_err(), error, "{:?}", template);
+        }
+        assert_eq!(
+            parse("{x}", &["a", "b", "c"]).unwrap_err(),
+            "unknown placeholder {x} (expected a, b or c)"
+        );
+    }
+
+    #[test]
+    fn rendering() {
+        let segments = parse("{{{job_name}}}-{batch_index}.%j", KNOWN).unwrap();
+        let rendered = render(&segments, |name| match name {
+            "job_name" => "batch-0001".to_string(),
+            _ => "1".to_string(),
+        });
+        assert_eq!(rendered, "{batch-0001}-1.%j");
+    }
+}
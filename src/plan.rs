@@ -0,0 +1,294 @@
+//! The computed plan of a run, as printed by `--plan-json`: every input
+//! with where it came from, how inputs were grouped into batches, and for
+//! each batch its script and submit invocation.
+//!
+//! The scripts are fully determined by a plan: [`JobSpec::script_text`]
+//! renders exactly what a run writes, so a plan read back with
+//! [`Plan::from_json`] reproduces them, and [`crate::execute`] runs it.
+//!
+//! The JSON field names are a public contract: fields are only ever added
+//! (older plans read with the new fields empty), anything else bumps
+//! [`SCHEMA_VERSION`]. Paths are strings; see the `serde_path` module for
+//! paths that are not valid UTF-8.
+
+use crate::scheduler::Scheduler;
+use crate::state::{Fingerprint, InputState};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Version of the plan document written by this batchelor.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    /// Plans written before the field existed are version 1.
+    #[serde(default = "first_schema_version")]
+    pub schema_version: u32,
+    pub scheduler: Scheduler,
+    /// `--submit`; batches may use a `--submit-overrides` replacement.
+    pub submit: String,
+    #[serde(default)]
+    pub run_id: String,
+    /// `--out-dir`, with the timestamp of `--out-dir-timestamp`.
+    #[serde(default, with = "crate::serde_path")]
+    pub out_dir: PathBuf,
+    /// Where the scripts and markers go: the run's directory, or `out_dir`
+    /// with `--flat-out-dir`.
+    #[serde(default, with = "crate::serde_path")]
+    pub script_dir: PathBuf,
+    /// Every input, sorted, as batched.
+    pub inputs: Vec<PlanInput>,
+    pub batches: Vec<JobSpec>,
+    /// With `--incremental`: the inputs earlier runs handled that are not
+    /// batched again, recorded in the run's state with its own.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unchanged: Vec<InputState>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanInput {
+    /// Absolute path, or the token as given when it is not a file.
+    pub path: String,
+    /// Size in bytes; 0 for tokens that are not files.
+    pub size: u64,
+    /// `--glob <pattern>` or `--input-list <file>` the input came from.
+    pub source: String,
+    /// With `--incremental`, what the run records of the input.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<Fingerprint>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobSpec {
+    pub batch_index: usize,
+    pub job_name: String,
+    pub inputs: Vec<String>,
+    pub input_bytes: u64,
+    /// Comment lines at the top of the script, after batchelor's stamps.
+    #[serde(default)]
+    pub header: Vec<String>,
+    /// Scheduler directive lines of the script, e.g. `#SBATCH --mem=4G`.
+    pub directives: Vec<String>,
+    /// Command lines of the script (or of the `--wrap` command).
+    pub commands: Vec<String>,
+    /// `None` with `--wrap`.
+    #[serde(default, with = "crate::serde_path::option")]
+    pub script: Option<PathBuf>,
+    /// File listing the inputs one per line, which the commands read
+    /// instead of taking them as arguments when their command line would
+    /// exceed `--max-command-bytes`.
+    #[serde(default, with = "crate::serde_path::option")]
+    pub input_list: Option<PathBuf>,
+    /// The scheduler log, when known before submission.
+    #[serde(default, with = "crate::serde_path::option")]
+    pub log: Option<PathBuf>,
+    /// The submit command: `--submit`, or its `--submit-overrides`
+    /// replacement.
+    #[serde(default)]
+    pub submit_command: String,
+    /// Arguments given to the submit command before the script.
+    #[serde(default)]
+    pub submit_args: Vec<String>,
+    /// The submit invocation as it would be typed into a shell.
+    pub submit: String,
+    /// Whether the batch is submitted (see `--only-batch`).
+    pub selected: bool,
+}
+
+fn first_schema_version() -> u32 {
+    1
+}
+
+impl Plan {
+    pub fn to_json_pretty(&self) -> String {
+        // Plain strings, numbers and enums always serialize.
+        serde_json::to_string_pretty(self).unwrap_or_default() + "\n"
+    }
+
+    pub fn from_json(text: &str) -> Result<Plan, Box<dyn std::error::Error>> {
+        let plan: Plan = serde_json::from_str(text)?;
+        if plan.schema_version > SCHEMA_VERSION {
+            return Err(format!(
+                "plan has schema version {}, this batchelor reads up to {}",
+                plan.schema_version, SCHEMA_VERSION
+            )
+            .into());
+        }
+        Ok(plan)
+    }
+
+    /// The batches that are submitted.
+    pub fn selected(&self) -> impl Iterator<Item = &JobSpec> {
+        self.batches.iter().filter(|b| b.selected)
+    }
+
+    /// Where jobs record finished and failed inputs; `None` with `--wrap`,
+    /// whose jobs have no scripts.
+    pub(crate) fn marker_dir(&self) -> Option<PathBuf> {
+        self.batches
+            .iter()
+            .any(|b| b.script.is_some())
+            .then(|| self.script_dir.clone())
+    }
+}
+
+impl JobSpec {
+    /// The batch script exactly as a run writes it; `None` with `--wrap`.
+    pub fn script_text(&self) -> Option<String> {
+        self.script
+            .as_ref()
+            .map(|_| crate::render_job_script(&self.header, &self.directives, &self.commands))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::InputStatus;
+
+    fn sample() -> Plan {
+        Plan {
+            schema_version: SCHEMA_VERSION,
+            scheduler: Scheduler::Slurm,
+            submit: "sbatch -p short".to_string(),
+            run_id: "20240131-142501-3fa2".to_string(),
+            out_dir: PathBuf::from("/abs/.batchelor"),
+            script_dir: PathBuf::from("/abs/.batchelor/runs/20240131-142501-3fa2"),
+            inputs: vec![
+                PlanInput {
+                    path: "/abs/a b.fq".to_string(),
+                    size: 1024,
+                    source: "--glob /abs/*.fq".to_string(),
+                    fingerprint: Some(Fingerprint {
+                        size: 1024,
+                        modified_ns: Some(1_706_707_501_000_000_000),
+                        crc32: None,
+                    }),
+                },
+                PlanInput {
+                    path: "sample-7".to_string(),
+                    size: 0,
+                    source: "--input-list ids.txt".to_string(),
+                    fingerprint: None,
+                },
+            ],
+            batches: vec![JobSpec {
+                batch_index: 1,
+                job_name: "batch-0001".to_string(),
+                inputs: vec!["/abs/a b.fq".to_string(), "sample-7".to_string()],
+                input_bytes: 1024,
+                header: vec!["# generated".to_string()],
+                directives: vec!["#SBATCH --mem=4G".to_string()],
+                commands: vec!["bash /abs/a.sh --input '/abs/a b.fq'".to_string()],
+                script: Some(PathBuf::from("/abs/.batchelor/batch-0001.batch.sh")),
+                input_list: Some(PathBuf::from("/abs/.batchelor/batch-0001.inputs")),
+                log: Some(PathBuf::from("/abs/logs/batch-0001.%j.out")),
+                submit_command: "sbatch -p short".to_string(),
+                submit_args: vec!["--job-name=batch-0001".to_string()],
+                submit: "sbatch -p short --job-name=batch-0001 /abs/.batchelor/batch-0001.batch.sh"
+                    .to_string(),
+                selected: true,
+            }],
+            unchanged: vec![InputState {
+                path: "/abs/old.fq".to_string(),
+                status: Some(InputStatus::Done),
+                fingerprint: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let plan = sample();
+        let json = plan.to_json_pretty();
+        assert!(json.ends_with("}\n"));
+        assert_eq!(Plan::from_json(&json).unwrap(), plan);
+    }
+
+    #[test]
+    fn field_names() {
+        let json: serde_json::Value = serde_json::from_str(&sample().to_json_pretty()).unwrap();
+        assert_eq!(json["schema_version"], 1);
+        assert_eq!(json["scheduler"], "slurm");
+        assert_eq!(json["out_dir"], "/abs/.batchelor");
+        assert_eq!(json["inputs"][1]["source"], "--input-list ids.txt");
+        let batch = &json["batches"][0];
+        assert_eq!(batch["script"], "/abs/.batchelor/batch-0001.batch.sh");
+        assert_eq!(batch["submit_args"][0], "--job-name=batch-0001");
+        assert_eq!(batch["selected"], true);
+    }
+
+    #[test]
+    fn wrap_batches_have_no_script() {
+        let mut plan = sample();
+        plan.batches[0].script = None;
+        plan.batches[0].input_list = None;
+        let json = plan.to_json_pretty();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert!(value["batches"][0]["script"].is_null());
+        assert_eq!(Plan::from_json(&json).unwrap(), plan);
+        assert_eq!(plan.marker_dir(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn round_trip_of_non_utf8_paths() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let path = |bytes: &[u8]| PathBuf::from(OsStr::from_bytes(bytes));
+        let mut plan = sample();
+        plan.out_dir = path(b"/abs/caf\xe9");
+        plan.script_dir = path(b"/abs/caf\xe9/runs/1");
+        plan.batches[0].script = Some(path(b"/abs/caf\xe9/batch-0001.batch.sh"));
+        let json = plan.to_json_pretty();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["out_dir"]["path"], "/abs/caf\u{fffd}");
+        assert_eq!(value["out_dir"]["raw_bytes"], "L2Ficy9jYWbp");
+        assert_eq!(Plan::from_json(&json).unwrap(), plan);
+    }
+
+    #[test]
+    fn future_versions_are_rejected() {
+        let mut plan = sample();
+        plan.schema_version = SCHEMA_VERSION + 1;
+        let e = Plan::from_json(&plan.to_json_pretty()).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "plan has schema version 2, this batchelor reads up to 1"
+        );
+    }
+
+    #[test]
+    fn fields_added_later_may_be_missing() {
+        let mut value: serde_json::Value =
+            serde_json::from_str(&sample().to_json_pretty()).unwrap();
+        let object = value.as_object_mut().unwrap();
+        for key in [
+            "schema_version",
+            "run_id",
+            "out_dir",
+            "script_dir",
+            "unchanged",
+        ] {
+            object.remove(key);
+        }
+        let batch = value["batches"][0].as_object_mut().unwrap();
+        for key in [
+            "header",
+            "input_list",
+            "log",
+            "submit_command",
+            "submit_args",
+        ] {
+            batch.remove(key);
+        }
+        // Unknown fields, e.g. of a newer batchelor, are ignored.
+        batch.insert("priority".to_string(), 3.into());
+        let plan = Plan::from_json(&value.to_string()).unwrap();
+        assert_eq!(plan.schema_version, 1);
+        assert_eq!(plan.out_dir, PathBuf::new());
+        assert!(plan.unchanged.is_empty());
+        assert!(plan.batches[0].header.is_empty());
+        assert_eq!(plan.batches[0].input_list, None);
+    }
+}
@@ -0,0 +1,71 @@
+//! `--submit-record`: instead of running the submit command, write each
+//! submission to a JSON file and hand back a fabricated job ID, so the rest
+//! of the pipeline (job manifests, dependencies, ...) runs as it would on a
+//! cluster.
+
+use crate::scheduler::Scheduler;
+use crate::{JobPayload, Submission};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+#[derive(Serialize)]
+struct RecordedSubmission<'a> {
+    job_id: String,
+    job_name: &'a str,
+    /// Full argv of the submit command, program first.
+    argv: Vec<String>,
+    script: Option<String>,
+    script_contents: Option<String>,
+    /// True when the script would have been fed on stdin.
+    stdin: bool,
+}
+
+/// Records `submission` under `dir` and returns the stdout the scheduler
+/// would have printed for it.
+pub(crate) fn record_submission(
+    dir: &Path,
+    submit: &str,
+    submission: &Submission,
+    scheduler: Scheduler,
+) -> Result<String, Box<dyn std::error::Error>> {
+    fs::create_dir_all(dir)?;
+    let previous = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+        .count();
+    let job_id = (previous + 1).to_string();
+
+    let mut argv = shlex::split(submit).ok_or_else(|| {
+        format!(
+            "could not parse --submit command string (check shell quoting): {}",
+            submit
+        )
+    })?;
+    argv.extend(
+        submission
+            .args()
+            .iter()
+            .map(|a| a.to_string_lossy().into_owned()),
+    );
+
+    let (script, stdin) = match submission.payload {
+        JobPayload::Script(path) => (Some(path), false),
+        JobPayload::Stdin(path) => (Some(path), true),
+        JobPayload::Wrap(_) => (None, false),
+    };
+    let script_contents = script.map(fs::read_to_string).transpose()?;
+
+    let record = RecordedSubmission {
+        job_id: job_id.clone(),
+        job_name: submission.job_name,
+        argv,
+        script: script.map(|p| p.to_string_lossy().into_owned()),
+        script_contents,
+        stdin,
+    };
+    let path = dir.join(format!("{:06}-{}.json", previous + 1, submission.job_name));
+    fs::write(&path, serde_json::to_string_pretty(&record)? + "\n")?;
+
+    Ok(scheduler.fake_submit_output(&job_id))
+}
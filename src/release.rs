@@ -0,0 +1,121 @@
+use crate::cancel::{per_job_outcomes, print_group};
+use crate::reporter::{ColorChoice, Reporter, StreamReporter, Verbosity};
+use crate::runs::resolve_jobs;
+use crate::scheduler::Scheduler;
+use clap::{Parser, ValueHint};
+use std::path::PathBuf;
+use std::process::Command;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "batchelor release",
+    about = "Release the held jobs of a previous run (see --hold)"
+)]
+pub struct ReleaseCli {
+    /// Output directory the run was submitted from.
+    #[arg(long, default_value = ".batchelor", value_hint = ValueHint::DirPath)]
+    out_dir: PathBuf,
+
+    /// Run ID to release (default: the most recent run).
+    #[arg(long, conflicts_with = "job_ids")]
+    run: Option<String>,
+
+    /// File with one job ID per line, instead of a recorded run.
+    #[arg(long)]
+    job_ids: Option<PathBuf>,
+
+    /// Scheduler the IDs in --job-ids belong to.
+    #[arg(long, value_enum, default_value = "slurm", requires = "job_ids")]
+    scheduler: Scheduler,
+
+    /// Number of job IDs passed to each release call.
+    #[arg(long, default_value_t = 100)]
+    chunk_size: usize,
+
+    /// Print only what happened to each job and errors: not the run or
+    /// jobs without an ID.
+    #[arg(long)]
+    quiet: bool,
+
+    /// Color warnings and errors: on a terminal unless NO_COLOR is set
+    /// (auto), always or never.
+    #[arg(long, value_enum, value_name = "WHEN", default_value = "auto")]
+    color: ColorChoice,
+}
+
+pub fn release(cli: ReleaseCli) -> Result<(), Box<dyn std::error::Error>> {
+    let reporter = StreamReporter::stdio()
+        .with_verbosity(Verbosity::from_quiet(cli.quiet))
+        .with_color(cli.color);
+    release_jobs(cli, &reporter)
+}
+
+/// Like `cancel`: what happened to each job is data, the rest status lines.
+fn release_jobs(
+    cli: ReleaseCli,
+    reporter: &dyn Reporter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (scheduler, jobs) = resolve_jobs(
+        &cli.out_dir,
+        cli.run.as_deref(),
+        cli.job_ids.as_deref(),
+        cli.scheduler,
+        reporter,
+    )?;
+    let (program, leading_args) = scheduler
+        .release_command()
+        .ok_or("no release command known for this run's submitter")?;
+
+    for job in jobs.iter().filter(|j| j.job_id.is_none()) {
+        reporter.diagnostic(&format!("untracked: {} (no job ID recorded)", job.job_name));
+    }
+    let ids = jobs
+        .iter()
+        .filter_map(|j| j.job_id.as_deref())
+        .collect::<Vec<_>>();
+
+    let outcomes = per_job_outcomes(&ids, cli.chunk_size, |chunk| {
+        let mut command = Command::new(program);
+        command.args(leading_args);
+        if scheduler == Scheduler::Slurm {
+            command.arg(chunk.join(","));
+        } else {
+            command.args(chunk);
+        }
+        command
+    });
+
+    let mut released = Vec::new();
+    let mut gone = Vec::new();
+    let mut failed = Vec::new();
+    for (id, result) in outcomes {
+        match result {
+            Ok(()) => released.push(id.to_string()),
+            Err(e) if is_unknown_job_error(&e) => gone.push(id.to_string()),
+            Err(e) => failed.push(format!("{} ({})", id, e)),
+        }
+    }
+
+    print_group(reporter, "released", &released);
+    print_group(reporter, "no longer exist", &gone);
+    print_group(reporter, "failed to release", &failed);
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} job(s) could not be released", failed.len()).into())
+    }
+}
+
+/// Recognizes the "no such job" messages of scontrol, qrls and bresume.
+fn is_unknown_job_error(stderr: &str) -> bool {
+    let stderr = stderr.to_ascii_lowercase();
+    [
+        "invalid job id",
+        "unknown job",
+        "does not exist",
+        "no matching job",
+    ]
+    .iter()
+    .any(|needle| stderr.contains(needle))
+}
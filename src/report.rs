@@ -0,0 +1,296 @@
+//! End-of-run submission report: one row per submitted (or attempted)
+//! batch, printed after submitting and optionally written with `--report`.
+
+use crate::reporter::Style;
+use crate::state::{JobState, RunState};
+use crate::units;
+use crate::wait::WaitSummary;
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum ReportFormat {
+    Tsv,
+    /// The run state document (see the `state` module).
+    Json,
+    Md,
+}
+
+/// What happened to the batches of one `run()`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RunReport {
+    /// The run as recorded in its state file. Dry runs get a run ID too,
+    /// but nothing is recorded under it.
+    pub state: RunState,
+    /// How the jobs ended, with `--wait`.
+    pub wait: Option<WaitSummary>,
+}
+
+/// How one batch of a run went, as listed by [`RunReport::jobs`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JobResult {
+    pub batch_index: usize,
+    pub job_name: String,
+    /// `None` with `--wrap`.
+    pub script: Option<PathBuf>,
+    pub outcome: SubmitOutcome,
+    /// What the submit command printed.
+    pub submit_stdout: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubmitOutcome {
+    /// The submit command accepted the job; `None` when no job ID could be
+    /// read from its output.
+    Submitted(Option<String>),
+    /// Why the submission failed, or was never attempted.
+    Failed(String),
+    /// Dry runs and plans submit nothing.
+    NotSubmitted,
+}
+
+const COLUMNS: [&str; 8] = [
+    "batch",
+    "job_name",
+    "job_id",
+    "state",
+    "elapsed",
+    "inputs",
+    "input_bytes",
+    "script",
+];
+
+impl RunReport {
+    /// Process exit code for the run: the `--wait` outcome, 0 without it.
+    pub fn exit_code(&self) -> i32 {
+        self.wait.as_ref().map_or(0, WaitSummary::exit_code)
+    }
+
+    /// Every batch of the run, with its submission outcome.
+    pub fn jobs(&self) -> Vec<JobResult> {
+        self.state.jobs.iter().map(JobResult::from).collect()
+    }
+
+    /// Number of inputs covered by the run's batches.
+    pub fn inputs_total(&self) -> usize {
+        self.state.jobs.iter().map(|j| j.inputs.len()).sum()
+    }
+
+    /// Number of batches whose submission failed.
+    pub fn failed(&self) -> usize {
+        self.state
+            .jobs
+            .iter()
+            .filter(|j| j.submit_error.is_some())
+            .count()
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Tsv => {
+                let mut out = COLUMNS.join("\t") + "\n";
+                for row in self.rows() {
+                    out.push_str(&row.join("\t"));
+                    out.push('\n');
+                }
+                out
+            }
+            ReportFormat::Md => {
+                let mut out = format!("| {} |\n", COLUMNS.join(" | "));
+                out.push_str(&format!("|{}\n", "---|".repeat(COLUMNS.len())));
+                for row in self.rows() {
+                    let cells = row
+                        .iter()
+                        .map(|c| c.replace('|', "\\|"))
+                        .collect::<Vec<_>>();
+                    out.push_str(&format!("| {} |\n", cells.join(" | ")));
+                }
+                out
+            }
+            ReportFormat::Json => self.state.to_json(),
+        }
+    }
+
+    /// The TSV report styled for a terminal: the header in bold, job names
+    /// in cyan and failures in red, with the columns `aligned` by spaces
+    /// rather than separated by tabs.
+    pub(crate) fn render_table(&self, style: Style, aligned: bool) -> String {
+        let rows = self.rows().collect::<Vec<_>>();
+        let mut widths = COLUMNS.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        let line = |cells: [Cow<'_, str>; 8], plain: [&str; 8]| {
+            let mut out = String::new();
+            for (i, (cell, plain)) in cells.iter().zip(plain).enumerate() {
+                out.push_str(cell);
+                if i + 1 == COLUMNS.len() {
+                    break;
+                }
+                match aligned {
+                    true => out.extend(std::iter::repeat_n(
+                        ' ',
+                        widths[i] - plain.chars().count() + 2,
+                    )),
+                    false => out.push('\t'),
+                }
+            }
+            out.push('\n');
+            out
+        };
+        let mut out = line(COLUMNS.map(|c| style.bold(c)), COLUMNS);
+        for row in &rows {
+            let plain = row.each_ref().map(String::as_str);
+            let cells = std::array::from_fn(|i| match i {
+                1 => style.cyan(plain[1]),
+                2 if plain[2].starts_with("failed:") => style.red(plain[2]),
+                _ => Cow::Borrowed(plain[i]),
+            });
+            out.push_str(&line(cells, plain));
+        }
+        out
+    }
+
+    /// Table cells per batch; the job ID column carries the failure reason
+    /// for batches that were not submitted, and scripts are only listed
+    /// while they are still on disk.
+    fn rows(&self) -> impl Iterator<Item = [String; 8]> + '_ {
+        self.state.jobs.iter().map(|job| {
+            let job_id = match (&job.job_id, &job.submit_error) {
+                (_, Some(error)) => format!("failed: {}", one_line(error)),
+                (Some(id), None) => id.clone(),
+                (None, None) => "-".to_string(),
+            };
+            [
+                job.batch_index.to_string(),
+                job.job_name.clone(),
+                job_id,
+                job.state.clone().unwrap_or_else(|| "-".to_string()),
+                job.elapsed_secs
+                    .map_or_else(|| "-".to_string(), units::format_clock),
+                job.inputs.len().to_string(),
+                job.input_bytes.to_string(),
+                job.script
+                    .as_ref()
+                    .filter(|p| p.exists())
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+            ]
+        })
+    }
+}
+
+impl From<&JobState> for JobResult {
+    fn from(job: &JobState) -> JobResult {
+        let outcome = match &job.submit_error {
+            Some(error) => SubmitOutcome::Failed(error.clone()),
+            None if job.submitted() => SubmitOutcome::Submitted(job.job_id.clone()),
+            None => SubmitOutcome::NotSubmitted,
+        };
+        JobResult {
+            batch_index: job.batch_index,
+            job_name: job.job_name.clone(),
+            script: job.script.clone(),
+            outcome,
+            submit_stdout: job.submit_stdout.clone(),
+        }
+    }
+}
+
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::{JobStatus, Scheduler};
+    use crate::script_format;
+    use crate::state::{InputState, SCHEMA_VERSION};
+
+    fn sample() -> RunReport {
+        let job = |batch_index: usize, job_id: &str| JobState {
+            batch_index,
+            job_name: format!("batch-{:04}", batch_index),
+            job_id: Some(job_id.to_string()),
+            submit_stdout: Some(format!("{}\n", job_id)),
+            script: Some(PathBuf::from(format!(
+                "/abs/.batchelor/batch-{:04}.batch.sh",
+                batch_index
+            ))),
+            inputs: vec![InputState {
+                path: format!("/abs/{}.fq", batch_index),
+                status: None,
+                fingerprint: None,
+            }],
+            ..JobState::default()
+        };
+        let status = |job_id: &str, state: &str| JobStatus {
+            job_id: job_id.to_string(),
+            state: state.to_string(),
+            elapsed: Some("00:01:00".to_string()),
+            exit_code: Some("0:0".to_string()),
+            node: Some("node1".to_string()),
+            start: None,
+            end: None,
+        };
+        RunReport {
+            state: RunState {
+                schema_version: SCHEMA_VERSION,
+                run_id: "20240131-142501-3fa2".to_string(),
+                timestamp: None,
+                args: vec!["batchelor".to_string()],
+                profile: None,
+                submit: "sbatch".to_string(),
+                scheduler: Scheduler::Slurm,
+                script_format: script_format::CURRENT,
+                jobs: vec![job(1, "123"), job(2, "124")],
+                unchanged: Vec::new(),
+            },
+            wait: Some(WaitSummary {
+                jobs: [
+                    ("123".to_string(), status("123", "COMPLETED")),
+                    ("124".to_string(), status("124", "FAILED")),
+                ]
+                .into(),
+                inputs_total: 2,
+                failed_inputs: vec!["/abs/2.fq".to_string()],
+                failed_jobs: Vec::new(),
+                stuck: Vec::new(),
+            }),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let report = sample();
+        let json = serde_json::to_string(&report).unwrap();
+        let read: RunReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.state, report.state);
+        assert_eq!(serde_json::to_string(&read).unwrap(), json);
+        assert_eq!(read.exit_code(), 1);
+        assert_eq!(read.jobs(), report.jobs());
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["wait"]["jobs"]["124"]["state"], "FAILED");
+        assert_eq!(value["wait"]["failed_inputs"][0], "/abs/2.fq");
+    }
+
+    #[test]
+    fn round_trip_without_wait() {
+        let report = RunReport {
+            wait: None,
+            ..sample()
+        };
+        let json = serde_json::to_string(&report).unwrap();
+        let read: RunReport = serde_json::from_str(&json).unwrap();
+        assert!(read.wait.is_none());
+        assert_eq!(read.exit_code(), 0);
+    }
+}
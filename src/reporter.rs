@@ -0,0 +1,289 @@
+//! Where a run reports what it is doing. The binary writes with
+//! [`StreamReporter::stdio`]; library callers get a [`RunReport`] back and
+//! can pass their own reporter to see the lines as well.
+
+use crate::report::{ReportFormat, RunReport};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+
+pub trait Reporter {
+    /// A status line: what was found, written, submitted or recorded.
+    fn message(&self, line: &str);
+
+    /// A warning, a failure or a note kept apart from the status lines.
+    fn diagnostic(&self, line: &str);
+
+    /// An error the run carries on after, such as a failed submission with
+    /// `--keep-going`. By default a [`Reporter::diagnostic`].
+    fn error(&self, line: &str) {
+        self.diagnostic(line);
+    }
+
+    /// Detail for `-v`: how the inputs were matched and batched, and the
+    /// argv of each submission.
+    fn detail(&self, _line: &str) {}
+
+    /// Output for the data stream, verbatim: the submissions of a dry run,
+    /// what the submit command printed, `--plan-json -` and structured
+    /// `--output-format` output.
+    fn data(&self, _text: &str) {}
+
+    /// What the submit command printed for one job. By default
+    /// [`Reporter::data`].
+    fn job_output(&self, text: &str) {
+        self.data(text);
+    }
+
+    /// The end-of-run table, once every batch was submitted or attempted.
+    fn report(&self, _report: &RunReport) {}
+
+    /// Whether long phases may draw progress bars on the terminal.
+    fn progress_bars(&self) -> bool {
+        false
+    }
+}
+
+/// Reports nothing.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Quiet;
+
+impl Reporter for Quiet {
+    fn message(&self, _line: &str) {}
+
+    fn diagnostic(&self, _line: &str) {}
+}
+
+/// `--color`: whether a [`StreamReporter`] styles what it writes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ColorChoice {
+    /// On a terminal, unless `NO_COLOR` is set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether a stream gets colors; `terminal` says whether it is one.
+    pub fn enabled(self, terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                terminal && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// ANSI styles for one stream; text is left as it is without colors.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Style {
+    color: bool,
+}
+
+impl Style {
+    pub(crate) fn new(color: bool) -> Style {
+        Style { color }
+    }
+
+    pub(crate) fn color(self) -> bool {
+        self.color
+    }
+
+    pub(crate) fn bold(self, text: &str) -> Cow<'_, str> {
+        self.paint("1", text)
+    }
+
+    pub(crate) fn red(self, text: &str) -> Cow<'_, str> {
+        self.paint("31", text)
+    }
+
+    pub(crate) fn cyan(self, text: &str) -> Cow<'_, str> {
+        self.paint("36", text)
+    }
+
+    fn paint<'a>(self, code: &str, text: &'a str) -> Cow<'a, str> {
+        if !self.color || text.is_empty() {
+            return Cow::Borrowed(text);
+        }
+        Cow::Owned(format!("\x1b[{}m{}\x1b[0m", code, text))
+    }
+
+    /// `line` with the marker it starts with styled: `[dry-run]` dimmed,
+    /// `warning:` in yellow and `error:` in red.
+    pub(crate) fn line(self, line: &str) -> Cow<'_, str> {
+        let markers = [("[dry-run]", "2"), ("warning:", "33"), ("error:", "31")];
+        for (marker, code) in markers {
+            if let (true, Some(rest)) = (self.color, line.strip_prefix(marker)) {
+                return Cow::Owned(format!("{}{}", self.paint(code, marker), rest));
+            }
+        }
+        Cow::Borrowed(line)
+    }
+}
+
+/// How much a [`StreamReporter`] writes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Errors, the data stream and the report, but no status lines,
+    /// diagnostics or submit command output (`--quiet`).
+    Quiet,
+    /// Everything but details.
+    #[default]
+    Normal,
+    /// Details as well (`-v`).
+    Verbose,
+}
+
+#[cfg(feature = "cli")]
+impl Verbosity {
+    /// [`Verbosity::Quiet`] for the `--quiet` of a subcommand, otherwise
+    /// [`Verbosity::Normal`].
+    pub(crate) fn from_quiet(quiet: bool) -> Verbosity {
+        if quiet {
+            Verbosity::Quiet
+        } else {
+            Verbosity::Normal
+        }
+    }
+}
+
+/// Writes status lines and diagnostics to an info stream, and the data
+/// stream — dry-run submissions, submit command output, structured output
+/// and the report — to another, as much of them as its [`Verbosity`]
+/// says. [`StreamReporter::stdio`] is what the binary uses; tests can hand
+/// in writers over a shared buffer and read back what was written.
+///
+/// On a terminal, markers, errors and the report are styled as
+/// [`ColorChoice`] says, and the report is a table aligned with spaces.
+/// Anywhere else it writes plain text, the report tab-separated, unless
+/// colors are asked for `always`.
+pub struct StreamReporter {
+    info: RefCell<Box<dyn Write>>,
+    data: RefCell<Box<dyn Write>>,
+    progress_bars: bool,
+    verbosity: Verbosity,
+    /// Whether each stream is a terminal.
+    terminals: (bool, bool),
+    info_style: Style,
+    data_style: Style,
+}
+
+impl StreamReporter {
+    pub fn new(info: impl Write + 'static, data: impl Write + 'static) -> StreamReporter {
+        StreamReporter {
+            info: RefCell::new(Box::new(info)),
+            data: RefCell::new(Box::new(data)),
+            progress_bars: false,
+            verbosity: Verbosity::Normal,
+            terminals: (false, false),
+            info_style: Style::default(),
+            data_style: Style::default(),
+        }
+    }
+
+    /// Info to stderr and data to stdout, with progress bars and
+    /// [`ColorChoice::Auto`] colors on a terminal.
+    pub fn stdio() -> StreamReporter {
+        let mut reporter = StreamReporter::new(io::stderr(), io::stdout());
+        reporter.terminals = (io::stderr().is_terminal(), io::stdout().is_terminal());
+        reporter
+            .with_progress_bars(true)
+            .with_color(ColorChoice::Auto)
+    }
+
+    /// Whether long phases may draw progress bars, on stderr (default: no).
+    pub fn with_progress_bars(mut self, progress_bars: bool) -> StreamReporter {
+        self.progress_bars = progress_bars;
+        self
+    }
+
+    /// Whether it styles what it writes (default: no colors, as the streams
+    /// of [`StreamReporter::new`] are not known to be terminals).
+    pub fn with_color(mut self, color: ColorChoice) -> StreamReporter {
+        self.info_style = Style::new(color.enabled(self.terminals.0));
+        self.data_style = Style::new(color.enabled(self.terminals.1));
+        self
+    }
+
+    /// How much it writes (default: [`Verbosity::Normal`]).
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> StreamReporter {
+        self.verbosity = verbosity;
+        self
+    }
+
+    fn write_info(&self, line: &str) {
+        let mut info = self.info.borrow_mut();
+        // Like println!, minus the panic on a closed stream.
+        let _ = writeln!(info, "{}", line);
+        let _ = info.flush();
+    }
+}
+
+impl fmt::Debug for StreamReporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamReporter")
+            .field("progress_bars", &self.progress_bars)
+            .field("verbosity", &self.verbosity)
+            .field("terminals", &self.terminals)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Reporter for StreamReporter {
+    fn message(&self, line: &str) {
+        if self.verbosity > Verbosity::Quiet {
+            self.write_info(&self.info_style.line(line));
+        }
+    }
+
+    fn diagnostic(&self, line: &str) {
+        if self.verbosity > Verbosity::Quiet {
+            self.write_info(&self.info_style.line(line));
+        }
+    }
+
+    fn error(&self, line: &str) {
+        self.write_info(&self.info_style.red(line));
+    }
+
+    fn detail(&self, line: &str) {
+        if self.verbosity >= Verbosity::Verbose {
+            self.write_info(line);
+        }
+    }
+
+    fn data(&self, text: &str) {
+        let text = self.data_style.line(text);
+        let mut data = self.data.borrow_mut();
+        let _ = data.write_all(text.as_bytes());
+        let _ = data.flush();
+    }
+
+    fn job_output(&self, text: &str) {
+        if self.verbosity > Verbosity::Quiet {
+            self.data(text);
+        }
+    }
+
+    fn report(&self, report: &RunReport) {
+        if self.verbosity > Verbosity::Quiet {
+            self.write_info("");
+        }
+        let text = match (self.terminals.1, self.data_style.color()) {
+            (false, false) => report.render(ReportFormat::Tsv),
+            (aligned, _) => report.render_table(self.data_style, aligned),
+        };
+        let mut data = self.data.borrow_mut();
+        let _ = data.write_all(text.as_bytes());
+        let _ = data.flush();
+    }
+
+    fn progress_bars(&self) -> bool {
+        self.progress_bars
+    }
+}
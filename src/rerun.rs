@@ -0,0 +1,104 @@
+//! `rerun.sh`: the command line of a run again, over only the inputs that
+//! failed, written into the run's directory at the end of the run.
+
+use crate::perms::{self, Permissions};
+use crate::state::RunState;
+use crate::{runs, script_format, shellgen, write_file_atomic, GENERATED_MARKER};
+use std::path::{Path, PathBuf};
+
+pub(crate) const RERUN_FILE: &str = "rerun.sh";
+
+/// Options dropped from the original command line, with whether they take
+/// one value (`false`: one or more). Inputs come from the input list
+/// instead; a new run gets a new ID and submits every batch.
+const DROPPED: &[(&str, bool)] = &[
+    ("--glob", false),
+    ("--input-list", true),
+    ("--run-id", true),
+    ("--only-batch", true),
+];
+
+/// Writes `rerun.sh` for `state`, running its command line from
+/// `submit_dir` with `--input-list input_list`. Extra arguments given to
+/// the script are passed on. It gets the script permissions of `perms`.
+/// Returns its path, or `None` when the run
+/// does not record its command line.
+pub(crate) fn write(
+    out_dir: &Path,
+    state: &RunState,
+    submit_dir: &Path,
+    input_list: &Path,
+    perms: &Permissions,
+) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    if state.args.is_empty() {
+        return Ok(None);
+    }
+    let mut args = rerun_args(&state.args);
+    // Everything after `--` is the script's, so the input list goes before
+    // it; arguments given to rerun.sh go after it too.
+    if !args.iter().any(|a| a == "--") {
+        args.push("--".to_string());
+    }
+    let at = args.iter().position(|a| a == "--").unwrap_or(args.len());
+    args.splice(
+        at..at,
+        [
+            "--input-list".to_string(),
+            input_list.to_string_lossy().into_owned(),
+        ],
+    );
+
+    let command = args
+        .iter()
+        .map(|a| shellgen::quote(a))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let text = format!(
+        "#!/usr/bin/env bash\n{} {}\n{}\n# Reruns the failed inputs of run {}.\nset -euo pipefail\n\ncd {}\n{} \"$@\"\n",
+        GENERATED_MARKER,
+        env!("CARGO_PKG_VERSION"),
+        script_format::header_line(),
+        state.run_id,
+        shellgen::quote(&submit_dir.to_string_lossy()),
+        command
+    );
+    let path = runs::run_dir(out_dir, &state.run_id).join(RERUN_FILE);
+    write_file_atomic(&path, text.as_bytes(), perms::Kind::Script, perms)
+        .map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+    Ok(Some(path))
+}
+
+/// The original command line without the options in [`DROPPED`]. The
+/// values of `--script-args`, and everything after `--`, are the script's
+/// and kept as they are.
+fn rerun_args(args: &[String]) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut iter = args.iter().peekable();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            out.push(arg.clone());
+            out.extend(iter.cloned());
+            break;
+        }
+        if arg == "--script-args" {
+            out.push(arg.clone());
+            out.extend(iter.next().cloned());
+            continue;
+        }
+        let dropped = DROPPED.iter().find(|(name, _)| {
+            arg == name
+                || arg
+                    .strip_prefix(name)
+                    .is_some_and(|rest| rest.starts_with('='))
+        });
+        match dropped {
+            None => out.push(arg.clone()),
+            Some((name, _)) if arg != name => {}
+            Some((_, true)) => {
+                iter.next();
+            }
+            Some((_, false)) => while iter.next_if(|next| !next.starts_with("--")).is_some() {},
+        }
+    }
+    out
+}
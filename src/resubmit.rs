@@ -0,0 +1,192 @@
+use crate::scheduler::Scheduler;
+use crate::script_format;
+use crate::state::{JobState, RunState};
+use crate::submitter::Submitted;
+use crate::{dispatch_submission, parsable_args, untracked_warning, JobPayload, Submission};
+use clap::{Parser, ValueHint};
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+
+/// State shown for batches whose submission failed under `--keep-going`.
+const SUBMIT_FAILED: &str = "SUBMIT_FAILED";
+
+/// The new job ID (if readable) and the submit command's output.
+type Resubmitted = (Option<String>, String);
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "batchelor resubmit",
+    about = "Resubmit the batches of a previous run whose jobs failed"
+)]
+pub struct ResubmitCli {
+    /// Output directory the run was submitted from.
+    #[arg(long, default_value = ".batchelor", value_hint = ValueHint::DirPath)]
+    out_dir: PathBuf,
+
+    /// Run ID to resubmit from (default: the most recent run).
+    #[arg(long)]
+    run: Option<String>,
+
+    /// Final job states that count as failed.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "FAILED,TIMEOUT,OUT_OF_MEMORY"
+    )]
+    states: Vec<String>,
+
+    /// Extra submit arguments for the resubmitted jobs, e.g. --with=--mem=64G.
+    /// Repeatable; each value is split like a shell word list.
+    #[arg(long = "with", allow_hyphen_values = true)]
+    with_args: Vec<String>,
+
+    /// Print what would be resubmitted without submitting.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+pub fn resubmit(cli: ResubmitCli) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = RunState::load_run(&cli.out_dir, cli.run.as_deref())?;
+    script_format::check(state.script_format)
+        .map_err(|e| format!("cannot resubmit run {}: {}", state.run_id, e))?;
+
+    let wanted = cli
+        .states
+        .iter()
+        .map(|s| normalize_state(s))
+        .collect::<Vec<_>>();
+    let mut extra_args = Vec::new();
+    for with in &cli.with_args {
+        extra_args.extend(
+            shlex::split(with).ok_or_else(|| format!("could not parse --with {:?}", with))?,
+        );
+    }
+
+    let ids = state.job_ids();
+    let states = if ids.is_empty() {
+        HashMap::new()
+    } else {
+        state.scheduler.accounting_states(&ids).ok_or_else(|| {
+            format!(
+                "cannot look up job states for run {} (needs sacct on a SLURM cluster)",
+                state.run_id
+            )
+        })?
+    };
+
+    // Failed jobs, and batches that never reached the scheduler
+    // (--keep-going) and have not been resubmitted since.
+    let failed = state
+        .jobs
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, job)| {
+            if job.submit_error.is_some() {
+                return Some((idx, SUBMIT_FAILED.to_string()));
+            }
+            let job_state = states.get(job.job_id.as_deref()?)?;
+            wanted
+                .contains(&normalize_state(job_state))
+                .then(|| (idx, job_state.clone()))
+        })
+        .collect::<Vec<_>>();
+    if failed.is_empty() {
+        println!(
+            "Run {}: no jobs in state {}",
+            state.run_id,
+            wanted.join(",")
+        );
+        return Ok(());
+    }
+
+    println!("batch\tjob_name\told_job_id\tstate\tnew_job_id");
+    let mut errors = 0usize;
+    for (idx, job_state) in failed {
+        let job = &state.jobs[idx];
+        let (batch_index, job_name) = (job.batch_index, job.job_name.clone());
+        let old_id = job.job_id.clone().unwrap_or_else(|| "-".to_string());
+        let new_id = match resubmit_one(&state, job, &extra_args, cli.dry_run) {
+            Ok(None) => "(dry-run)".to_string(),
+            Ok(Some((job_id, stdout))) => {
+                let shown = job_id.clone().unwrap_or_else(|| "-".to_string());
+                state.jobs[idx].record_submission(job_id, stdout);
+                state.save(&cli.out_dir)?;
+                shown
+            }
+            Err(e) => {
+                errors += 1;
+                format!("error: {}", e)
+            }
+        };
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            batch_index, job_name, old_id, job_state, new_id
+        );
+    }
+
+    if errors == 0 {
+        Ok(())
+    } else {
+        Err(format!("{} batch(es) could not be resubmitted", errors).into())
+    }
+}
+
+/// Resubmits one batch's script; `None` for a dry run.
+fn resubmit_one(
+    state: &RunState,
+    job: &JobState,
+    extra_args: &[String],
+    dry_run: bool,
+) -> Result<Option<Resubmitted>, Box<dyn std::error::Error>> {
+    let script = job
+        .script
+        .as_ref()
+        .ok_or("batch was submitted without a script (--wrap)")?;
+    if !script.is_file() {
+        return Err(format!(
+            "script {} no longer exists (submit with --keep to allow resubmission)",
+            script.display()
+        )
+        .into());
+    }
+    // The script may have been rewritten by another batchelor since.
+    let version = script_format::read(script)?;
+    script_format::check(version).map_err(|e| format!("{}: {}", script.display(), e))?;
+
+    let submit_parts = shlex::split(&state.submit).unwrap_or_default();
+    let mut args = parsable_args(state.scheduler, &submit_parts);
+    args.extend_from_slice(extra_args);
+    let submission = Submission {
+        job_name: &job.job_name,
+        extra_args: args,
+        payload: JobPayload::Script(script),
+    };
+    if dry_run {
+        println!("[dry-run] {}", submission.shell_line(&state.submit));
+        return Ok(None);
+    }
+
+    let record_dir = env::var_os("BATCHELOR_SUBMIT_RECORD").map(PathBuf::from);
+    let stdout = dispatch_submission(
+        &state.submit,
+        &submission,
+        state.scheduler,
+        record_dir.as_deref(),
+        None,
+        &mut |line| eprintln!("[{}] {}", job.job_name, line),
+    )?;
+    let Submitted { job_id, stdout } = Submitted::from_stdout(state.scheduler, stdout);
+    if job_id.is_none() && state.scheduler != Scheduler::Generic {
+        eprintln!("{}", untracked_warning(&job.job_name, &stdout));
+    }
+    Ok(Some((job_id, stdout)))
+}
+
+/// Upper-cases a state name and maps squeue's short OOM to sacct's name.
+fn normalize_state(state: &str) -> String {
+    match state.trim().to_ascii_uppercase().as_str() {
+        "OOM" => "OUT_OF_MEMORY".to_string(),
+        other => other.to_string(),
+    }
+}
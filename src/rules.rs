@@ -0,0 +1,293 @@
+//! `--resource-rule`: extra submit arguments for batches matching a
+//! condition over their inputs, e.g.
+//! `size>200G => --partition=bigmem --mem=400G` or
+//! `count>=100 && max_size<1G => --time=12:00:00`.
+
+use crate::units;
+use std::fmt;
+use std::str::FromStr;
+
+/// What a rule condition can look at for one batch.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BatchStats {
+    /// Total size of the batch's inputs in bytes.
+    pub size: u64,
+    /// Number of inputs.
+    pub count: u64,
+    /// Size of the largest input in bytes.
+    pub max_size: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Property {
+    Size,
+    Count,
+    MaxSize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+#[derive(Clone, Debug)]
+struct Comparison {
+    property: Property,
+    op: Op,
+    value: u64,
+}
+
+/// One `--resource-rule`: all comparisons must hold for `args` to apply.
+#[derive(Clone, Debug)]
+pub struct ResourceRule {
+    text: String,
+    conditions: Vec<Comparison>,
+    args: Vec<String>,
+}
+
+impl ResourceRule {
+    pub fn matches(&self, stats: &BatchStats) -> bool {
+        self.conditions.iter().all(|c| {
+            let actual = match c.property {
+                Property::Size => stats.size,
+                Property::Count => stats.count,
+                Property::MaxSize => stats.max_size,
+            };
+            match c.op {
+                Op::Gt => actual > c.value,
+                Op::Ge => actual >= c.value,
+                Op::Lt => actual < c.value,
+                Op::Le => actual <= c.value,
+                Op::Eq => actual == c.value,
+                Op::Ne => actual != c.value,
+            }
+        })
+    }
+
+    /// Submit arguments added to matching batches.
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+}
+
+impl fmt::Display for ResourceRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl FromStr for ResourceRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ResourceRule, String> {
+        let (condition, args) = s
+            .split_once("=>")
+            .ok_or_else(|| format!("invalid rule {:?} (expected CONDITION => ARGS)", s))?;
+        let args = shlex::split(args).ok_or_else(|| {
+            format!(
+                "could not parse the arguments at column {} of rule {:?}",
+                column(s, args.trim_start()),
+                s
+            )
+        })?;
+        if args.is_empty() {
+            return Err(format!("rule {:?} has no arguments after =>", s));
+        }
+        let conditions = condition
+            .split("&&")
+            .map(|part| parse_comparison(part.trim(), s))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ResourceRule {
+            text: s.trim().to_string(),
+            conditions,
+            args,
+        })
+    }
+}
+
+/// The 1-based column in `rule` where `part`, a slice of it, starts.
+fn column(rule: &str, part: &str) -> usize {
+    let offset = part.as_ptr() as usize - rule.as_ptr() as usize;
+    rule[..offset].chars().count() + 1
+}
+
+/// Parses `size>200G`, `count >= 10`, `max_size<1G`, ... Errors give
+/// the column of `part`, a slice of `rule`, in it.
+fn parse_comparison(part: &str, rule: &str) -> Result<Comparison, String> {
+    // Two-character operators first so `>=` is not read as `>`.
+    const OPS: [(&str, Op); 6] = [
+        (">=", Op::Ge),
+        ("<=", Op::Le),
+        ("==", Op::Eq),
+        ("!=", Op::Ne),
+        (">", Op::Gt),
+        ("<", Op::Lt),
+    ];
+    let (name, op, value) = OPS
+        .iter()
+        .find_map(|(token, op)| {
+            part.split_once(token)
+                .map(|(name, value)| (name.trim(), *op, value.trim()))
+        })
+        .ok_or_else(|| {
+            format!(
+                "invalid condition {:?} at column {} of rule {:?} (expected e.g. size>200G)",
+                part,
+                column(rule, part),
+                rule
+            )
+        })?;
+    let property = match name {
+        "size" => Property::Size,
+        "count" => Property::Count,
+        "max_size" => Property::MaxSize,
+        _ => {
+            return Err(format!(
+                "unknown property {:?} at column {} of rule {:?} (use size, count or max_size)",
+                name,
+                column(rule, part),
+                rule
+            ))
+        }
+    };
+    let value = match property {
+        Property::Size | Property::MaxSize => units::parse_size(value)
+            .map_err(|e| format!("{} at column {} of rule {:?}", e, column(rule, value), rule))?,
+        Property::Count => value.parse().map_err(|_| {
+            format!(
+                "invalid count {:?} at column {} of rule {:?}",
+                value,
+                column(rule, value),
+                rule
+            )
+        })?,
+    };
+    Ok(Comparison {
+        property,
+        op,
+        value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(text: &str) -> ResourceRule {
+        text.parse().unwrap()
+    }
+
+    fn error(text: &str) -> String {
+        text.parse::<ResourceRule>().unwrap_err()
+    }
+
+    fn stats(size: u64, count: u64, max_size: u64) -> BatchStats {
+        BatchStats {
+            size,
+            count,
+            max_size,
+        }
+    }
+
+    #[test]
+    fn rules_parse_conditions_and_args() {
+        let parsed = rule("  size > 2G && count>=10 &&max_size<1M =>  --mem=4G -p 'big mem' ");
+        assert_eq!(parsed.args(), ["--mem=4G", "-p", "big mem"]);
+        assert_eq!(
+            parsed.to_string(),
+            "size > 2G && count>=10 &&max_size<1M =>  --mem=4G -p 'big mem'"
+        );
+        let conditions = parsed
+            .conditions
+            .iter()
+            .map(|c| (c.property, c.op, c.value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            conditions,
+            [
+                (Property::Size, Op::Gt, 2 << 30),
+                (Property::Count, Op::Ge, 10),
+                (Property::MaxSize, Op::Lt, 1 << 20),
+            ]
+        );
+    }
+
+    #[test]
+    fn every_operator_compares() {
+        let cases = [
+            ("count>2", [false, false, true]),
+            ("count>=2", [false, true, true]),
+            ("count<2", [true, false, false]),
+            ("count<=2", [true, true, false]),
+            ("count==2", [false, true, false]),
+            ("count!=2", [true, false, true]),
+        ];
+        for (condition, expected) in cases {
+            let parsed = rule(&format!("{} => -x", condition));
+            let actual = [1, 2, 3].map(|count| parsed.matches(&stats(0, count, 0)));
+            assert_eq!(actual, expected, "{}", condition);
+        }
+    }
+
+    #[test]
+    fn two_character_operators_take_precedence() {
+        // Read as `>` the value would be `=1G`, which is not a size.
+        let ge = rule("size>=1G => -x");
+        assert_eq!(ge.conditions[0].op, Op::Ge);
+        assert!(ge.matches(&stats(1 << 30, 0, 0)));
+        assert_eq!(rule("count<=3 => -x").conditions[0].op, Op::Le);
+        assert_eq!(rule("count!=3 => -x").conditions[0].op, Op::Ne);
+        assert_eq!(rule("count==3 => -x").conditions[0].op, Op::Eq);
+    }
+
+    #[test]
+    fn every_condition_must_hold() {
+        let parsed = rule("size>1K && max_size<=100 => -x");
+        assert!(parsed.matches(&stats(2048, 30, 100)));
+        assert!(!parsed.matches(&stats(2048, 30, 101)));
+        assert!(!parsed.matches(&stats(1024, 30, 100)));
+    }
+
+    #[test]
+    fn the_first_arrow_ends_the_condition() {
+        let parsed = rule("count>1 => --comment=a=>b");
+        assert_eq!(parsed.args(), ["--comment=a=>b"]);
+    }
+
+    #[test]
+    fn malformed_rules_say_where() {
+        assert_eq!(
+            error("size>1G --mem=4G"),
+            r#"invalid rule "size>1G --mem=4G" (expected CONDITION => ARGS)"#
+        );
+        assert_eq!(
+            error("size>1G => "),
+            r#"rule "size>1G => " has no arguments after =>"#
+        );
+        assert_eq!(
+            error("size>1G => --comment='open"),
+            r#"could not parse the arguments at column 12 of rule "size>1G => --comment='open""#
+        );
+        assert_eq!(
+            error("size>1G && count 3 => -x"),
+            r#"invalid condition "count 3" at column 12 of rule "size>1G && count 3 => -x" (expected e.g. size>200G)"#
+        );
+        assert_eq!(
+            error("count>1 && sise>1G => -x"),
+            r#"unknown property "sise" at column 12 of rule "count>1 && sise>1G => -x" (use size, count or max_size)"#
+        );
+        assert_eq!(
+            error("count >= ten => -x"),
+            r#"invalid count "ten" at column 10 of rule "count >= ten => -x""#
+        );
+        assert_eq!(
+            error("max_size<1Q => -x"),
+            r#"invalid size unit in "1Q" (use K, M, G or T) at column 10 of rule "max_size<1Q => -x""#
+        );
+    }
+}
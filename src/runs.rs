@@ -0,0 +1,277 @@
+//! Run IDs and the run directories under `--out-dir`. The per-run record
+//! itself is [`crate::state::RunState`]; this module still reads the
+//! `jobs.tsv`/`failures.tsv` manifests of runs from before state files.
+
+#[cfg(feature = "cli")]
+use crate::reporter::Reporter;
+use crate::scheduler::Scheduler;
+use crate::state::STATE_FILE;
+#[cfg(feature = "cli")]
+use crate::state::{JobState, RunState};
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Subdirectory of `--out-dir` holding one directory per run.
+pub const RUNS_DIR: &str = "runs";
+
+/// Subdirectory of `--out-dir` where `--dry-run` writes its preview
+/// scripts, one directory per dry run, away from the scripts of real runs.
+pub const DRY_RUN_DIR: &str = "dry-run";
+
+const JOBS_FILE: &str = "jobs.tsv";
+const JOBS_HEADER: &str = "batch_index\tjob_name\tjob_id\tscript\tlog";
+const FAILURES_FILE: &str = "failures.tsv";
+const FAILURES_HEADER: &str = "batch_index\tjob_name\tscript\terror";
+/// Inputs to redo, one per line, as written by `--wait` and `batchelor
+/// failures`; ready for `--input-list`.
+pub const FAILED_INPUTS_FILE: &str = "failed_inputs.txt";
+
+/// Returns a new run ID: local timestamp plus a random suffix, so IDs sort
+/// chronologically and concurrent runs do not collide.
+pub fn new_run_id() -> String {
+    let suffix = RandomState::new().build_hasher().finish() & 0xffff;
+    format!(
+        "{}-{:04x}",
+        chrono::Local::now().format("%Y%m%d-%H%M%S"),
+        suffix
+    )
+}
+
+/// Parses `--run-id`: it names a directory, so only letters, digits, `-`,
+/// `_` and `.` are allowed, and it may not start with a dot.
+pub(crate) fn parse_run_id(id: &str) -> Result<String, String> {
+    if id.is_empty() || id.starts_with('.') {
+        return Err("must not be empty or start with '.'".to_string());
+    }
+    if let Some(c) = id
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && !"-_.".contains(*c))
+    {
+        return Err(format!(
+            "{:?} is not allowed; use letters, digits, '-', '_' and '.'",
+            c
+        ));
+    }
+    Ok(id.to_string())
+}
+
+pub fn run_dir(out_dir: &Path, run_id: &str) -> PathBuf {
+    out_dir.join(RUNS_DIR).join(run_id)
+}
+
+/// Points `<base>-latest` at `out_dir` (an `--out-dir-timestamp`
+/// directory), replacing the link atomically. Only on Unix.
+pub(crate) fn link_latest(base: &Path, out_dir: &Path) -> io::Result<()> {
+    let mut link = base.as_os_str().to_owned();
+    link.push("-latest");
+    let link = PathBuf::from(link);
+    // Relative, so the directories can be moved together.
+    let target = out_dir.file_name().map(Path::new).unwrap_or(out_dir);
+    #[cfg(unix)]
+    {
+        let mut tmp = link.clone().into_os_string();
+        tmp.push(format!(".{}.tmp", std::process::id()));
+        let _ = fs::remove_file(&tmp);
+        std::os::unix::fs::symlink(target, &tmp)?;
+        fs::rename(&tmp, &link)?;
+    }
+    #[cfg(not(unix))]
+    let _ = (link, target);
+    Ok(())
+}
+
+/// One submitted batch as recorded in a legacy job manifest.
+#[derive(Clone, Debug)]
+pub(crate) struct JobRecord {
+    pub(crate) batch_index: usize,
+    pub(crate) job_name: String,
+    /// `None` (written as `-`) when no job ID could be read from the submit
+    /// output.
+    pub(crate) job_id: Option<String>,
+    pub(crate) script: Option<PathBuf>,
+    pub(crate) log: Option<PathBuf>,
+}
+
+/// A batch whose submission failed under `--keep-going`.
+#[derive(Clone, Debug)]
+pub struct SubmitFailure {
+    pub batch_index: usize,
+    pub job_name: String,
+    pub script: Option<PathBuf>,
+    /// The submit command's error, on a single line.
+    pub error: String,
+}
+
+/// A legacy job manifest as read back from disk. Resubmissions appear as
+/// further lines for the same batch.
+#[derive(Clone, Debug)]
+pub(crate) struct RunRecord {
+    pub(crate) run_id: String,
+    pub(crate) submit: String,
+    pub(crate) scheduler: Scheduler,
+    pub(crate) jobs: Vec<JobRecord>,
+}
+
+impl RunRecord {
+    /// Loads the manifest of `run_id` from `out_dir`.
+    pub(crate) fn load(
+        out_dir: &Path,
+        run_id: &str,
+    ) -> Result<RunRecord, Box<dyn std::error::Error>> {
+        let path = run_dir(out_dir, run_id).join(JOBS_FILE);
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("could not read run state {}: {}", path.display(), e))?;
+
+        let mut record = RunRecord {
+            run_id: run_id.to_string(),
+            submit: String::new(),
+            scheduler: Scheduler::Generic,
+            jobs: Vec::new(),
+        };
+        for line in text.lines() {
+            if let Some(meta) = line.strip_prefix("# ") {
+                if let Some(submit) = meta.strip_prefix("submit: ") {
+                    record.submit = submit.to_string();
+                } else if let Some(name) = meta.strip_prefix("scheduler: ") {
+                    record.scheduler = name
+                        .parse::<Scheduler>()
+                        .map_err(|e| format!("{}: {}", path.display(), e))?;
+                }
+                continue;
+            }
+            if line.is_empty() || line == JOBS_HEADER {
+                continue;
+            }
+            record.jobs.push(
+                parse_job_line(line)
+                    .ok_or_else(|| format!("{}: malformed job line {:?}", path.display(), line))?,
+            );
+        }
+        Ok(record)
+    }
+
+    /// Loads the failed submissions recorded for this run, if any.
+    pub(crate) fn submit_failures(
+        &self,
+        out_dir: &Path,
+    ) -> Result<Vec<SubmitFailure>, Box<dyn std::error::Error>> {
+        let path = run_dir(out_dir, &self.run_id).join(FAILURES_FILE);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("could not read {}: {}", path.display(), e).into()),
+        };
+        text.lines()
+            .filter(|l| !l.is_empty() && *l != FAILURES_HEADER)
+            .map(|line| {
+                parse_failure_line(line).ok_or_else(|| {
+                    format!("{}: malformed failure line {:?}", path.display(), line).into()
+                })
+            })
+            .collect()
+    }
+}
+
+/// Picks the jobs a post-submission subcommand operates on: the IDs listed in
+/// `job_ids` (for `scheduler`), or the recorded jobs of `run` (default: the
+/// latest run under `out_dir`), which is reported to `reporter`.
+#[cfg(feature = "cli")]
+pub(crate) fn resolve_jobs(
+    out_dir: &Path,
+    run: Option<&str>,
+    job_ids: Option<&Path>,
+    scheduler: Scheduler,
+    reporter: &dyn Reporter,
+) -> Result<(Scheduler, Vec<JobState>), Box<dyn std::error::Error>> {
+    if let Some(path) = job_ids {
+        return Ok((scheduler, read_job_ids(path)?));
+    }
+    let state = RunState::load_run(out_dir, run)?;
+    reporter.message(&format!(
+        "Run {} ({} job(s))",
+        state.run_id,
+        state.jobs.len()
+    ));
+    Ok((state.scheduler, state.jobs))
+}
+
+/// Reads a file with one job ID per line (extra columns, blank lines and
+/// `#` comments are ignored).
+#[cfg(feature = "cli")]
+fn read_job_ids(path: &Path) -> Result<Vec<JobState>, Box<dyn std::error::Error>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("could not read --job-ids {}: {}", path.display(), e))?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter_map(|l| l.split_whitespace().next())
+        .enumerate()
+        .map(|(idx, id)| JobState {
+            batch_index: idx + 1,
+            job_name: id.to_string(),
+            job_id: Some(id.to_string()),
+            ..JobState::default()
+        })
+        .collect())
+}
+
+/// IDs of the runs recorded under `out_dir`, oldest first.
+pub fn run_ids(out_dir: &Path) -> io::Result<Vec<String>> {
+    let runs = out_dir.join(RUNS_DIR);
+    if !runs.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut ids = Vec::new();
+    for entry in fs::read_dir(runs)? {
+        let entry = entry?;
+        let dir = entry.path();
+        if !dir.join(STATE_FILE).is_file() && !dir.join(JOBS_FILE).is_file() {
+            continue;
+        }
+        if let Ok(name) = entry.file_name().into_string() {
+            ids.push(name);
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+/// Returns the newest run ID under `out_dir`, if any run was recorded.
+pub fn latest_run_id(out_dir: &Path) -> io::Result<Option<String>> {
+    Ok(run_ids(out_dir)?.pop())
+}
+
+fn parse_job_line(line: &str) -> Option<JobRecord> {
+    let mut fields = line.split('\t');
+    let batch_index = fields.next()?.parse().ok()?;
+    let job_name = fields.next()?.to_string();
+    let job_id = fields.next()?;
+    let script = fields.next()?;
+    // Manifests written before logs were recorded have no log column.
+    let log = fields.next().unwrap_or("-");
+    Some(JobRecord {
+        batch_index,
+        job_name,
+        job_id: (job_id != "-").then(|| job_id.to_string()),
+        script: (script != "-").then(|| PathBuf::from(script)),
+        log: (log != "-").then(|| PathBuf::from(log)),
+    })
+}
+
+fn parse_failure_line(line: &str) -> Option<SubmitFailure> {
+    let mut fields = line.splitn(4, '\t');
+    let batch_index = fields.next()?.parse().ok()?;
+    let job_name = fields.next()?.to_string();
+    let script = fields.next()?;
+    let error = fields.next()?.to_string();
+    Some(SubmitFailure {
+        batch_index,
+        job_name,
+        script: (script != "-").then(|| PathBuf::from(script)),
+        error,
+    })
+}
@@ -0,0 +1,155 @@
+//! Directories batchelor does not delete from without `--force-clean`: the
+//! current directory, the root of a repository (a directory holding `.git`)
+//! and `$HOME`. An `--out-dir .` with a common job name prefix would
+//! otherwise let cleanup remove matching files a project keeps there.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Why a directory is protected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Protected {
+    CurrentDir,
+    VcsRoot,
+    Home,
+}
+
+impl fmt::Display for Protected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Protected::CurrentDir => "the current directory",
+            Protected::VcsRoot => "a repository root",
+            Protected::Home => "the home directory",
+        })
+    }
+}
+
+/// The nearest directory from `dir` up that holds a `.git`.
+pub(crate) fn vcs_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|d| d.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Whether `dir` is protected, compared after resolving links and `..`. A
+/// directory that cannot be resolved is not.
+pub(crate) fn protected(dir: &Path) -> Option<Protected> {
+    protected_from(dir, env::current_dir().ok().as_deref(), home().as_deref())
+}
+
+/// [`protected`], with `cwd` as the current directory and `home` as `$HOME`.
+fn protected_from(dir: &Path, cwd: Option<&Path>, home: Option<&Path>) -> Option<Protected> {
+    let dir = fs::canonicalize(dir).ok()?;
+    let same = |other: &Path| fs::canonicalize(other).is_ok_and(|other| other == dir);
+    if cwd.is_some_and(same) {
+        Some(Protected::CurrentDir)
+    } else if vcs_root(&dir).as_deref() == Some(dir.as_path()) {
+        Some(Protected::VcsRoot)
+    } else if home.is_some_and(same) {
+        Some(Protected::Home)
+    } else {
+        None
+    }
+}
+
+/// Refuses deleting from `dir` when it is protected, unless `force`
+/// (`--force-clean`).
+pub(crate) fn check_cleanup(dir: &Path, force: bool) -> Result<(), String> {
+    match protected(dir) {
+        Some(reason) if !force => Err(format!(
+            "{} is {}; not deleting anything there without --force-clean",
+            dir.display(),
+            reason
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn home() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `home/project` is a repository with `sub/out` in it; `other` is in
+    /// none of its own.
+    fn tree() -> (tempfile::TempDir, PathBuf, PathBuf) {
+        let tmp = tempfile::tempdir().unwrap();
+        let home = tmp.path().join("home");
+        let project = home.join("project");
+        fs::create_dir_all(project.join(".git")).unwrap();
+        fs::create_dir_all(project.join("sub/out")).unwrap();
+        fs::create_dir_all(tmp.path().join("other")).unwrap();
+        (tmp, home, project)
+    }
+
+    #[test]
+    fn vcs_roots_are_the_nearest_directory_with_git() {
+        let (tmp, _, project) = tree();
+        assert_eq!(vcs_root(&project), Some(project.clone()));
+        assert_eq!(vcs_root(&project.join("sub/out")), Some(project.clone()));
+        // A submodule or nested checkout has a `.git` file.
+        let inner = project.join("sub/inner");
+        fs::create_dir_all(&inner).unwrap();
+        fs::write(inner.join(".git"), "gitdir: ../../.git/modules/inner\n").unwrap();
+        assert_eq!(vcs_root(&inner), Some(inner.clone()));
+        assert_eq!(vcs_root(&tmp.path().join("other")), vcs_root(tmp.path()));
+    }
+
+    #[test]
+    fn protected_directories() {
+        let (tmp, home, project) = tree();
+        let other = tmp.path().join("other");
+        let protected = |dir: &Path| protected_from(dir, Some(&other), Some(&home));
+        assert_eq!(protected(&other), Some(Protected::CurrentDir));
+        assert_eq!(protected(&project), Some(Protected::VcsRoot));
+        assert_eq!(protected(&home), Some(Protected::Home));
+        assert_eq!(protected(&project.join("sub/out")), None);
+        assert_eq!(protected(&project.join("sub")), None);
+        assert_eq!(protected(&tmp.path().join("missing")), None);
+        assert_eq!(protected_from(&home, None, None), None);
+        // The current directory wins over a repository root.
+        assert_eq!(
+            protected_from(&project, Some(&project), Some(&home)),
+            Some(Protected::CurrentDir)
+        );
+    }
+
+    #[test]
+    fn directories_are_compared_resolved() {
+        let (_tmp, home, project) = tree();
+        let cwd = project.join("sub");
+        let protected = |dir: &Path| protected_from(dir, Some(&cwd), Some(&home));
+        assert_eq!(protected(&cwd.join("out/..")), Some(Protected::CurrentDir));
+        assert_eq!(protected(&cwd.join("..")), Some(Protected::VcsRoot));
+        assert_eq!(protected(&project.join("..")), Some(Protected::Home));
+        #[cfg(unix)]
+        {
+            let link = home.join("link");
+            std::os::unix::fs::symlink(&project, &link).unwrap();
+            assert_eq!(protected(&link), Some(Protected::VcsRoot));
+            assert_eq!(protected(&link.join("sub")), Some(Protected::CurrentDir));
+        }
+    }
+
+    #[test]
+    fn cleanup_needs_force_in_protected_directories() {
+        let (_tmp, _, project) = tree();
+        assert_eq!(
+            check_cleanup(&project, false).unwrap_err(),
+            format!(
+                "{} is a repository root; not deleting anything there without --force-clean",
+                project.display()
+            )
+        );
+        assert_eq!(check_cleanup(&project, true), Ok(()));
+        assert_eq!(check_cleanup(&project.join("sub/out"), false), Ok(()));
+    }
+}
@@ -0,0 +1,925 @@
+use crate::units;
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+
+/// Batch system a submit command talks to, used to pick directive syntax and
+/// scheduler-specific submit flags.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Scheduler {
+    Slurm,
+    Pbs,
+    Sge,
+    Lsf,
+    /// Anything else (`bash`, site wrappers, ...): no directives or extra flags.
+    Generic,
+}
+
+/// Job ID as printed by the submit command (`123`, `123.headnode`, ...).
+pub type JobId = String;
+
+/// When `--notify` mails are sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum NotifyEvent {
+    End,
+    Fail,
+    /// Start, end and failure.
+    All,
+}
+
+impl FromStr for Scheduler {
+    type Err = String;
+
+    /// The lowercase name, as in `--scheduler`; case is ignored.
+    fn from_str(name: &str) -> Result<Scheduler, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "slurm" => Ok(Scheduler::Slurm),
+            "pbs" => Ok(Scheduler::Pbs),
+            "sge" => Ok(Scheduler::Sge),
+            "lsf" => Ok(Scheduler::Lsf),
+            "generic" => Ok(Scheduler::Generic),
+            _ => Err(format!("unknown scheduler {:?}", name)),
+        }
+    }
+}
+
+impl Scheduler {
+    /// Guesses the scheduler from the program named in a `--submit` string.
+    pub fn detect(submit: &str) -> Scheduler {
+        let program = shlex::split(submit).and_then(|parts| parts.into_iter().next());
+        let name = program
+            .as_deref()
+            .map(Path::new)
+            .and_then(Path::file_name)
+            .and_then(OsStr::to_str);
+        match name {
+            Some("sbatch") => Scheduler::Slurm,
+            Some("qsub") => Scheduler::Pbs,
+            Some("bsub") => Scheduler::Lsf,
+            _ => Scheduler::Generic,
+        }
+    }
+
+    /// Comment prefix for in-script directives, if the scheduler reads any.
+    pub fn directive_prefix(self) -> Option<&'static str> {
+        match self {
+            Scheduler::Slurm => Some("#SBATCH"),
+            Scheduler::Pbs => Some("#PBS"),
+            Scheduler::Sge => Some("#$"),
+            Scheduler::Lsf => Some("#BSUB"),
+            Scheduler::Generic => None,
+        }
+    }
+
+    /// Submit arguments that set the job name.
+    pub fn job_name_args(self, job_name: &str) -> Vec<String> {
+        match self {
+            Scheduler::Slurm => vec![format!("--job-name={}", job_name)],
+            Scheduler::Pbs | Scheduler::Sge => vec!["-N".to_string(), job_name.to_string()],
+            Scheduler::Lsf => vec!["-J".to_string(), job_name.to_string()],
+            Scheduler::Generic => Vec::new(),
+        }
+    }
+
+    /// Submit arguments that send the job's stdout (and optionally stderr)
+    /// to the given paths. Without `stderr` the two streams are joined.
+    pub fn log_args(self, stdout: &str, stderr: Option<&str>) -> Vec<String> {
+        match self {
+            Scheduler::Slurm => {
+                let mut args = vec![format!("--output={}", stdout)];
+                if let Some(stderr) = stderr {
+                    args.push(format!("--error={}", stderr));
+                }
+                args
+            }
+            Scheduler::Pbs | Scheduler::Sge | Scheduler::Lsf => {
+                let mut args = vec!["-o".to_string(), stdout.to_string()];
+                match (self, stderr) {
+                    (_, Some(stderr)) => args.extend(["-e".to_string(), stderr.to_string()]),
+                    (Scheduler::Pbs, None) => args.extend(["-j".to_string(), "oe".to_string()]),
+                    (Scheduler::Sge, None) => args.extend(["-j".to_string(), "y".to_string()]),
+                    _ => {}
+                }
+                args
+            }
+            Scheduler::Generic => Vec::new(),
+        }
+    }
+
+    /// Submit arguments that make the submit command print just the job
+    /// ID, so `parse_job_id` does not depend on the human-readable message.
+    pub fn parsable_args(self) -> Vec<String> {
+        match self {
+            Scheduler::Slurm => vec!["--parsable".to_string()],
+            Scheduler::Sge => vec!["-terse".to_string()],
+            Scheduler::Pbs | Scheduler::Lsf | Scheduler::Generic => Vec::new(),
+        }
+    }
+
+    /// What the submit command prints for a job with `job_id`, as understood
+    /// by [`parse_job_id`]. Used when submissions are only recorded.
+    pub fn fake_submit_output(self, job_id: &str) -> String {
+        match self {
+            Scheduler::Slurm => format!("Submitted batch job {}\n", job_id),
+            Scheduler::Pbs | Scheduler::Sge => format!("{}\n", job_id),
+            Scheduler::Lsf => format!("Job <{}> is submitted to queue <normal>.\n", job_id),
+            Scheduler::Generic => String::new(),
+        }
+    }
+
+    /// Program that cancels queued or running jobs given their IDs.
+    pub fn cancel_program(self) -> Option<&'static str> {
+        match self {
+            Scheduler::Slurm => Some("scancel"),
+            Scheduler::Pbs | Scheduler::Sge => Some("qdel"),
+            Scheduler::Lsf => Some("bkill"),
+            Scheduler::Generic => None,
+        }
+    }
+
+    /// Program and leading arguments that release held jobs; the IDs follow.
+    pub fn release_command(self) -> Option<(&'static str, &'static [&'static str])> {
+        match self {
+            Scheduler::Slurm => Some(("scontrol", &["release"])),
+            Scheduler::Pbs | Scheduler::Sge => Some(("qrls", &[])),
+            Scheduler::Lsf => Some(("bresume", &[])),
+            Scheduler::Generic => None,
+        }
+    }
+
+    /// Submit argument that queues a job in held state.
+    pub fn hold_args(self) -> Vec<String> {
+        let flag = match self {
+            Scheduler::Slurm => "--hold",
+            Scheduler::Pbs | Scheduler::Sge => "-h",
+            Scheduler::Lsf => "-H",
+            Scheduler::Generic => return Vec::new(),
+        };
+        vec![flag.to_string()]
+    }
+
+    /// Submit arguments that let only one job of a given name and user run
+    /// at a time.
+    pub fn singleton_args(self) -> Option<Vec<String>> {
+        match self {
+            Scheduler::Slurm => Some(vec!["--dependency=singleton".to_string()]),
+            Scheduler::Pbs | Scheduler::Sge | Scheduler::Lsf | Scheduler::Generic => None,
+        }
+    }
+
+    /// Submit arguments that make the submit command only validate the job
+    /// instead of queueing it, where the scheduler supports that.
+    pub fn test_only_args(self) -> Option<Vec<String>> {
+        match self {
+            Scheduler::Slurm => Some(vec!["--test-only".to_string()]),
+            Scheduler::Sge => Some(vec!["-w".to_string(), "v".to_string()]),
+            Scheduler::Pbs | Scheduler::Lsf | Scheduler::Generic => None,
+        }
+    }
+
+    /// Submit arguments that mail `email` on the given events.
+    pub fn notify_args(self, email: &str, events: &[NotifyEvent]) -> Vec<String> {
+        let has = |e: NotifyEvent| events.contains(&e) || events.contains(&NotifyEvent::All);
+        let begin = events.contains(&NotifyEvent::All);
+        match self {
+            Scheduler::Slurm => {
+                let mut types = Vec::new();
+                if begin {
+                    types.push("BEGIN");
+                }
+                if has(NotifyEvent::End) {
+                    types.push("END");
+                }
+                if has(NotifyEvent::Fail) {
+                    types.push("FAIL");
+                }
+                vec![
+                    format!("--mail-user={}", email),
+                    format!("--mail-type={}", types.join(",")),
+                ]
+            }
+            Scheduler::Pbs | Scheduler::Sge => {
+                let mut when = String::new();
+                if begin {
+                    when.push('b');
+                }
+                if has(NotifyEvent::End) {
+                    when.push('e');
+                }
+                if has(NotifyEvent::Fail) {
+                    when.push('a');
+                }
+                vec!["-M".to_string(), email.to_string(), "-m".to_string(), when]
+            }
+            Scheduler::Lsf => {
+                let mut args = vec!["-u".to_string(), email.to_string()];
+                if begin {
+                    args.push("-B".to_string());
+                }
+                // LSF mails the job report on completion, failed or not.
+                args.push("-N".to_string());
+                args
+            }
+            Scheduler::Generic => Vec::new(),
+        }
+    }
+
+    /// Submit arguments that start a job only after all of `ids` ended,
+    /// whatever their outcome.
+    pub fn after_all_args(self, ids: &[&str]) -> Vec<String> {
+        match self {
+            Scheduler::Slurm => vec![format!("--dependency=afterany:{}", ids.join(":"))],
+            Scheduler::Pbs => vec![
+                "-W".to_string(),
+                format!("depend=afterany:{}", ids.join(":")),
+            ],
+            Scheduler::Sge => vec!["-hold_jid".to_string(), ids.join(",")],
+            Scheduler::Lsf => {
+                let ended = ids
+                    .iter()
+                    .map(|id| format!("ended({})", id))
+                    .collect::<Vec<_>>();
+                vec!["-w".to_string(), ended.join(" && ")]
+            }
+            Scheduler::Generic => Vec::new(),
+        }
+    }
+
+    /// Submit arguments requesting `bytes` of memory, rounded up to whole
+    /// MiB or GiB.
+    pub fn mem_args(self, bytes: u64) -> Vec<String> {
+        let (amount, unit) = units::round_up_mem(bytes);
+        match self {
+            Scheduler::Slurm => vec![format!("--mem={}{}", amount, unit)],
+            Scheduler::Pbs => vec![
+                "-l".to_string(),
+                format!("mem={}{}b", amount, unit.to_ascii_lowercase()),
+            ],
+            Scheduler::Sge => vec!["-l".to_string(), format!("h_vmem={}{}", amount, unit)],
+            Scheduler::Lsf => vec!["-M".to_string(), format!("{}{}B", amount, unit)],
+            Scheduler::Generic => Vec::new(),
+        }
+    }
+
+    /// Submit arguments requesting a wall-clock limit of `secs`, rounded up
+    /// to whole minutes.
+    pub fn time_args(self, secs: u64) -> Vec<String> {
+        let secs = secs.div_ceil(60).max(1) * 60;
+        match self {
+            Scheduler::Slurm => vec![format!("--time={}", units::format_clock(secs))],
+            Scheduler::Pbs => vec![
+                "-l".to_string(),
+                format!("walltime={}", units::format_clock(secs)),
+            ],
+            Scheduler::Sge => vec![
+                "-l".to_string(),
+                format!("h_rt={}", units::format_clock(secs)),
+            ],
+            Scheduler::Lsf => vec![
+                "-W".to_string(),
+                format!("{}:{:02}", secs / 3_600, secs % 3_600 / 60),
+            ],
+            Scheduler::Generic => Vec::new(),
+        }
+    }
+
+    /// Longest job name the scheduler keeps in full, if it has a limit.
+    pub fn max_job_name_len(self) -> Option<usize> {
+        match self {
+            // slurmdbd stores names in a 255-byte column.
+            Scheduler::Slurm => Some(255),
+            Scheduler::Pbs => Some(236),
+            Scheduler::Sge => None,
+            Scheduler::Lsf => Some(4094),
+            Scheduler::Generic => None,
+        }
+    }
+
+    /// Checks the characters of a job name, ignoring its length.
+    pub fn check_job_name(self, name: &str) -> Result<(), String> {
+        let first = name.chars().next().ok_or("job names cannot be empty")?;
+        match self {
+            Scheduler::Pbs if !first.is_ascii_alphabetic() => {
+                return Err(format!(
+                    "{:?}: PBS job names must start with a letter",
+                    name
+                ));
+            }
+            Scheduler::Sge if first.is_ascii_digit() => {
+                return Err(format!(
+                    "{:?}: SGE job names must not start with a digit",
+                    name
+                ));
+            }
+            _ => {}
+        }
+        let forbidden: &[char] = match self {
+            Scheduler::Sge => &['/', ':', '@', '\\', '*', '?'],
+            _ => &['/'],
+        };
+        // Names also name the scripts, so `/` is never allowed.
+        let allowed = |c: char| {
+            !forbidden.contains(&c)
+                && (self == Scheduler::Generic || !(c.is_whitespace() || c.is_control()))
+        };
+        match name.chars().find(|c| !allowed(*c)) {
+            Some(c) => Err(format!("{:?}: {:?} is not allowed in job names", name, c)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns true if `args` already contain a flag that sets the job name.
+    pub fn has_job_name_arg(self, args: &[String]) -> bool {
+        args.iter().any(|arg| match self {
+            Scheduler::Slurm => {
+                arg == "--job-name" || arg.starts_with("--job-name=") || arg.starts_with("-J")
+            }
+            Scheduler::Pbs | Scheduler::Sge => arg.starts_with("-N"),
+            Scheduler::Lsf => arg.starts_with("-J"),
+            Scheduler::Generic => false,
+        })
+    }
+
+    /// Looks up the queue state (PENDING, RUNNING, ...) of each job that the
+    /// scheduler still lists. Jobs missing from the map have left the queue.
+    /// Returns `None` when the scheduler cannot be queried this way.
+    pub fn queue_states(self, ids: &[&str]) -> Option<HashMap<String, String>> {
+        if self != Scheduler::Slurm {
+            return None;
+        }
+        let mut states = HashMap::new();
+        if ids.is_empty() {
+            return Some(states);
+        }
+        match squeue(&ids.join(",")).ok()? {
+            Some(listed) => states.extend(listed),
+            // Older squeue versions fail outright if any ID is unknown, so
+            // fall back to asking about each job on its own.
+            None => {
+                for id in ids {
+                    states.extend(squeue(id).ok()?.unwrap_or_default());
+                }
+            }
+        }
+        Some(states)
+    }
+}
+
+impl Scheduler {
+    /// Looks up which of `ids` are pending, since when and why. Returns
+    /// `None` when the scheduler cannot be queried this way.
+    pub fn pending_jobs(self, ids: &[&str]) -> Option<Vec<PendingJob>> {
+        if self != Scheduler::Slurm {
+            return None;
+        }
+        if ids.is_empty() {
+            return Some(Vec::new());
+        }
+        let output = Command::new("squeue")
+            .args(["-h", "-t", "PENDING", "-o", "%i|%V|%R", "-j"])
+            .arg(ids.join(","))
+            .output()
+            .ok()
+            .filter(|o| o.status.success())?;
+        let mut pending = parse_squeue_pending(
+            &String::from_utf8_lossy(&output.stdout),
+            chrono::Local::now().naive_local(),
+        );
+        pending.retain(|job| ids.contains(&job.job_id.as_str()));
+        Some(pending)
+    }
+
+    /// Looks up the accounting state (COMPLETED, FAILED, TIMEOUT, ...) of
+    /// each job. Returns `None` when the scheduler cannot be queried this way.
+    pub fn accounting_states(self, ids: &[&str]) -> Option<HashMap<String, String>> {
+        let statuses = self.job_statuses(ids)?;
+        Some(
+            statuses
+                .into_iter()
+                .map(|(id, status)| (id, status.state))
+                .collect(),
+        )
+    }
+
+    /// Looks up state, elapsed time, exit code and node of each job, from
+    /// sacct where it knows the job and squeue otherwise. Jobs neither knows
+    /// are missing from the map. Returns `None` when the scheduler cannot be
+    /// queried this way.
+    pub fn job_statuses(self, ids: &[&str]) -> Option<HashMap<String, JobStatus>> {
+        if self != Scheduler::Slurm {
+            return None;
+        }
+        let mut statuses = HashMap::new();
+        if ids.is_empty() {
+            return Some(statuses);
+        }
+        let sacct = Command::new("sacct")
+            .args([
+                "-n",
+                "-X",
+                "--parsable2",
+                "-o",
+                "JobID,State,Elapsed,ExitCode,NodeList,Start,End",
+                "-j",
+                &ids.join(","),
+            ])
+            .output()
+            .ok();
+        if let Some(output) = sacct.as_ref().filter(|o| o.status.success()) {
+            statuses.extend(
+                parse_sacct(&String::from_utf8_lossy(&output.stdout))
+                    .into_iter()
+                    .map(|s| (s.job_id.clone(), s)),
+            );
+        }
+        // Accounting can lag behind the queue (or be disabled), so fall back
+        // to squeue for jobs sacct did not report.
+        let missing = ids
+            .iter()
+            .copied()
+            .filter(|id| !statuses.contains_key(*id))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            match self.queue_states(&missing) {
+                Some(states) => add_queue_states(&mut statuses, states),
+                // Neither sacct nor squeue could be asked.
+                None if sacct.is_none() => return None,
+                None => {}
+            }
+        }
+        Some(statuses)
+    }
+}
+
+/// Adds the jobs squeue listed in `states` that `statuses` (from sacct)
+/// does not know.
+fn add_queue_states(statuses: &mut HashMap<String, JobStatus>, states: HashMap<String, String>) {
+    for (id, state) in states {
+        statuses.entry(id.clone()).or_insert(JobStatus {
+            job_id: id,
+            state,
+            elapsed: None,
+            exit_code: None,
+            node: None,
+            start: None,
+            end: None,
+        });
+    }
+}
+
+/// States after which a job will not change any more.
+const FINAL_STATES: [&str; 9] = [
+    "COMPLETED",
+    "FAILED",
+    "CANCELLED",
+    "TIMEOUT",
+    "OUT_OF_MEMORY",
+    "NODE_FAIL",
+    "PREEMPTED",
+    "BOOT_FAIL",
+    "DEADLINE",
+];
+
+/// Returns true for job states that will not change any more.
+pub fn is_final_state(state: &str) -> bool {
+    FINAL_STATES.contains(&state)
+}
+
+/// One job as reported by sacct (or squeue, which knows less).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobStatus {
+    pub job_id: JobId,
+    /// PENDING, RUNNING, COMPLETED, FAILED, ... ("CANCELLED by N" is
+    /// shortened to CANCELLED).
+    pub state: String,
+    pub elapsed: Option<String>,
+    /// `exit:signal`, e.g. `0:0` or `1:0`.
+    pub exit_code: Option<String>,
+    pub node: Option<String>,
+    /// sacct's Start and End, e.g. `2024-01-31T14:25:07`.
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+/// Parses `sacct --parsable2 -o JobID,State,Elapsed,ExitCode,NodeList`
+/// output (with or without the header line), optionally followed by
+/// `Start,End` columns. Job steps such as `123.batch` are skipped.
+pub fn parse_sacct(stdout: &str) -> Vec<JobStatus> {
+    let field = |s: Option<&str>| {
+        s.map(str::trim)
+            .filter(|s| !s.is_empty() && *s != "None assigned" && *s != "Unknown")
+            .map(str::to_string)
+    };
+    stdout
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let mut fields = line.split('|');
+            let job_id = fields.next()?.trim();
+            if job_id == "JobID" || job_id.contains('.') {
+                return None;
+            }
+            // "CANCELLED by 1234" -> "CANCELLED"
+            let state = fields.next()?.split_whitespace().next().unwrap_or("");
+            Some(JobStatus {
+                job_id: job_id.to_string(),
+                state: state.to_string(),
+                elapsed: field(fields.next()),
+                exit_code: field(fields.next()),
+                node: field(fields.next()),
+                start: field(fields.next()),
+                end: field(fields.next()),
+            })
+        })
+        .collect()
+}
+
+/// A job waiting in the queue.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PendingJob {
+    pub job_id: JobId,
+    /// Seconds since submission, if squeue's submit time could be read.
+    pub pending_secs: Option<u64>,
+    /// Why the job is not running, e.g. `Resources`, `Priority` or
+    /// `ReqNodeNotAvail, UnavailableNodes:n[01-04]`.
+    pub reason: String,
+}
+
+/// Parses `squeue -o '%i|%V|%R'` output: job ID, submit time
+/// (`2024-01-31T14:25:01`, local time) and reason, which squeue wraps in
+/// parentheses for pending jobs. `now` is the current local time.
+pub fn parse_squeue_pending(stdout: &str, now: chrono::NaiveDateTime) -> Vec<PendingJob> {
+    stdout
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '|');
+            let job_id = fields.next()?.trim();
+            let submitted = fields.next()?.trim();
+            let reason = fields.next()?.trim();
+            let reason = reason
+                .strip_prefix('(')
+                .and_then(|r| r.strip_suffix(')'))
+                .unwrap_or(reason);
+            let pending_secs =
+                chrono::NaiveDateTime::parse_from_str(submitted, "%Y-%m-%dT%H:%M:%S")
+                    .ok()
+                    .map(|t| (now - t).num_seconds().max(0) as u64);
+            Some(PendingJob {
+                job_id: job_id.to_string(),
+                pending_secs,
+                reason: reason.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Extracts the job ID from a submit command's stdout, looking at each
+/// non-empty line in turn. Returns `None` when no line matches the
+/// scheduler's output formats; such jobs are recorded as untracked.
+pub fn parse_job_id(scheduler: Scheduler, stdout: &str) -> Option<JobId> {
+    stdout
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .find_map(|line| parse_job_id_line(scheduler, line))
+}
+
+fn parse_job_id_line(scheduler: Scheduler, line: &str) -> Option<JobId> {
+    match scheduler {
+        Scheduler::Slurm => {
+            // "Submitted batch job 123 [on cluster c]", or "123[;cluster]"
+            // with --parsable.
+            let id = match line.strip_prefix("Submitted batch job ") {
+                Some(rest) => rest.split_whitespace().next()?,
+                None => line.split(';').next()?,
+            };
+            is_numeric(id).then(|| id.to_string())
+        }
+        Scheduler::Pbs => {
+            // "123.headnode", or "123[].headnode" for job arrays.
+            let (number, _) = line.split_once(['.', '[']).unwrap_or((line, ""));
+            (is_numeric(number) && !line.contains(char::is_whitespace)).then(|| line.to_string())
+        }
+        Scheduler::Sge => {
+            // "123" or "123.1-10:1" with -terse, otherwise
+            // "Your job 123 ("name") has been submitted" (or "job-array").
+            let token = match line
+                .strip_prefix("Your job-array ")
+                .or_else(|| line.strip_prefix("Your job "))
+            {
+                Some(rest) => rest.split_whitespace().next()?,
+                None if !line.contains(char::is_whitespace) => line,
+                None => return None,
+            };
+            let id = token.split('.').next()?;
+            is_numeric(id).then(|| id.to_string())
+        }
+        Scheduler::Lsf => {
+            // "Job <123> is submitted to queue <normal>."
+            let rest = line.strip_prefix("Job <")?;
+            let (id, _) = rest.split_once('>')?;
+            is_numeric(id).then(|| id.to_string())
+        }
+        Scheduler::Generic => None,
+    }
+}
+
+fn is_numeric(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Runs `squeue` for the given comma-separated IDs. `Ok(None)` means squeue
+/// ran but rejected the query.
+fn squeue(ids: &str) -> io::Result<Option<Vec<(String, String)>>> {
+    let output = Command::new("squeue")
+        .args(["-h", "-o", "%i %T", "-j", ids])
+        .output()?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(parse_squeue_states(&String::from_utf8_lossy(
+        &output.stdout,
+    ))))
+}
+
+/// Parses `squeue -h -o '%i %T'` output: job ID and state.
+fn parse_squeue_states(stdout: &str) -> Vec<(String, String)> {
+    stdout
+        .lines()
+        .filter_map(|line| line.trim().split_once(' '))
+        .map(|(id, state)| (id.to_string(), state.trim().to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn job_ids_from_submit_output() {
+        let cases: &[(Scheduler, &str, Option<&str>)] = &[
+            (
+                Scheduler::Slurm,
+                "Submitted batch job 4815162\n",
+                Some("4815162"),
+            ),
+            (
+                Scheduler::Slurm,
+                "Submitted batch job 4815162 on cluster hawk\n",
+                Some("4815162"),
+            ),
+            (Scheduler::Slurm, "4815162\n", Some("4815162")),
+            (Scheduler::Slurm, "4815162;hawk\n", Some("4815162")),
+            (
+                Scheduler::Slurm,
+                "sbatch: Account set to lab\nSubmitted batch job 77\n",
+                Some("77"),
+            ),
+            (
+                Scheduler::Slurm,
+                "sbatch: error: Batch job submission failed: Invalid account\n",
+                None,
+            ),
+            (
+                Scheduler::Pbs,
+                "3141592.pbs01.cluster.local\n",
+                Some("3141592.pbs01.cluster.local"),
+            ),
+            (Scheduler::Pbs, "3141592[].pbs01\n", Some("3141592[].pbs01")),
+            (Scheduler::Pbs, "qsub: Unknown queue\n", None),
+            (Scheduler::Sge, "271828\n", Some("271828")),
+            (Scheduler::Sge, "271828.1-10:1\n", Some("271828")),
+            (
+                Scheduler::Sge,
+                "Your job 271828 (\"batch-0001\") has been submitted\n",
+                Some("271828"),
+            ),
+            (
+                Scheduler::Sge,
+                "Your job-array 271828.1-10:1 (\"batch-0001\") has been submitted\n",
+                Some("271828"),
+            ),
+            (
+                Scheduler::Lsf,
+                "Job <161803> is submitted to queue <normal>.\n",
+                Some("161803"),
+            ),
+            (
+                Scheduler::Lsf,
+                "Job <161803> is submitted to default queue <normal>.\n",
+                Some("161803"),
+            ),
+            (Scheduler::Lsf, "Request aborted by esub.\n", None),
+            (Scheduler::Generic, "4815162\n", None),
+            (Scheduler::Slurm, "", None),
+            (Scheduler::Slurm, "\n  \n", None),
+        ];
+        for (scheduler, stdout, expected) in cases {
+            assert_eq!(
+                parse_job_id(*scheduler, stdout).as_deref(),
+                *expected,
+                "{:?} {:?}",
+                scheduler,
+                stdout
+            );
+        }
+    }
+
+    fn status(id: &str, state: &str, rest: [Option<&str>; 5]) -> JobStatus {
+        let [elapsed, exit_code, node, start, end] = rest.map(|f| f.map(str::to_string));
+        JobStatus {
+            job_id: id.to_string(),
+            state: state.to_string(),
+            elapsed,
+            exit_code,
+            node,
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn sacct_with_start_and_end() {
+        // sacct -n -X --parsable2 -o JobID,State,Elapsed,ExitCode,NodeList,Start,End
+        let stdout = "\
+4815162|COMPLETED|00:12:31|0:0|node017|2024-01-31T14:25:07|2024-01-31T14:37:38
+4815163|FAILED|00:00:04|1:0|node018|2024-01-31T14:25:07|2024-01-31T14:25:11
+4815164|CANCELLED by 51234|00:00:00|0:15|None assigned|Unknown|2024-01-31T14:30:00
+4815165|PENDING|00:00:00|0:0|None assigned|Unknown|Unknown
+";
+        assert_eq!(
+            parse_sacct(stdout),
+            [
+                status(
+                    "4815162",
+                    "COMPLETED",
+                    [
+                        Some("00:12:31"),
+                        Some("0:0"),
+                        Some("node017"),
+                        Some("2024-01-31T14:25:07"),
+                        Some("2024-01-31T14:37:38")
+                    ]
+                ),
+                status(
+                    "4815163",
+                    "FAILED",
+                    [
+                        Some("00:00:04"),
+                        Some("1:0"),
+                        Some("node018"),
+                        Some("2024-01-31T14:25:07"),
+                        Some("2024-01-31T14:25:11")
+                    ]
+                ),
+                status(
+                    "4815164",
+                    "CANCELLED",
+                    [
+                        Some("00:00:00"),
+                        Some("0:15"),
+                        None,
+                        None,
+                        Some("2024-01-31T14:30:00")
+                    ]
+                ),
+                status(
+                    "4815165",
+                    "PENDING",
+                    [Some("00:00:00"), Some("0:0"), None, None, None]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn sacct_with_header_and_steps() {
+        // sacct --parsable2 -o JobID,State,Elapsed,ExitCode,NodeList
+        let stdout = "\
+JobID|State|Elapsed|ExitCode|NodeList
+4815162|COMPLETED|00:12:31|0:0|node017
+4815162.batch|COMPLETED|00:12:31|0:0|node017
+4815162.extern|COMPLETED|00:12:31|0:0|node017
+4815166|RUNNING|00:03:10|0:0|node[020-021]
+4815166.batch|RUNNING|00:03:10|0:0|node020
+
+";
+        assert_eq!(
+            parse_sacct(stdout),
+            [
+                status(
+                    "4815162",
+                    "COMPLETED",
+                    [Some("00:12:31"), Some("0:0"), Some("node017"), None, None]
+                ),
+                status(
+                    "4815166",
+                    "RUNNING",
+                    [
+                        Some("00:03:10"),
+                        Some("0:0"),
+                        Some("node[020-021]"),
+                        None,
+                        None
+                    ]
+                ),
+            ]
+        );
+        assert_eq!(parse_sacct(""), []);
+    }
+
+    #[test]
+    fn squeue_fills_in_what_sacct_lacks() {
+        let sacct =
+            "4815162|COMPLETED|00:12:31|0:0|node017|2024-01-31T14:25:07|2024-01-31T14:37:38\n";
+        // squeue -h -o '%i %T': it still lists 4815162 for a moment.
+        let squeue = "4815162 COMPLETING\n4815167 PENDING\n4815168 RUNNING\n";
+        let mut statuses = parse_sacct(sacct)
+            .into_iter()
+            .map(|s| (s.job_id.clone(), s))
+            .collect::<HashMap<_, _>>();
+        add_queue_states(
+            &mut statuses,
+            parse_squeue_states(squeue).into_iter().collect(),
+        );
+        let mut states = statuses
+            .values()
+            .map(|s| (s.job_id.as_str(), s.state.as_str(), s.node.is_some()))
+            .collect::<Vec<_>>();
+        states.sort();
+        assert_eq!(
+            states,
+            [
+                ("4815162", "COMPLETED", true),
+                ("4815167", "PENDING", false),
+                ("4815168", "RUNNING", false),
+            ]
+        );
+        assert!(!statuses.contains_key("4815169"));
+    }
+
+    #[test]
+    fn squeue_pending_reasons() {
+        // squeue -h -t PENDING -o '%i|%V|%R'
+        let stdout = "\
+4815170|2024-01-31T12:12:00|(ReqNodeNotAvail, UnavailableNodes:n[01-04])
+4815171|2024-01-31T14:20:00|(Priority)
+4815172|N/A|(Resources)
+4815173|2024-01-31T14:30:00|(BeginTime)
+4815174|2024-01-31T14:25:00|Dependency
+garbage
+";
+        let now = chrono::NaiveDate::from_ymd_opt(2024, 1, 31)
+            .unwrap()
+            .and_hms_opt(14, 25, 0)
+            .unwrap();
+        let pending = |id: &str, secs, reason: &str| PendingJob {
+            job_id: id.to_string(),
+            pending_secs: secs,
+            reason: reason.to_string(),
+        };
+        assert_eq!(
+            parse_squeue_pending(stdout, now),
+            [
+                pending(
+                    "4815170",
+                    Some(2 * 3600 + 13 * 60),
+                    "ReqNodeNotAvail, UnavailableNodes:n[01-04]"
+                ),
+                pending("4815171", Some(300), "Priority"),
+                pending("4815172", None, "Resources"),
+                // Submitted with a future begin time: not pending for long.
+                pending("4815173", Some(0), "BeginTime"),
+                pending("4815174", Some(0), "Dependency"),
+            ]
+        );
+    }
+
+    #[test]
+    fn fake_submit_output_parses_back() {
+        for scheduler in [
+            Scheduler::Slurm,
+            Scheduler::Pbs,
+            Scheduler::Sge,
+            Scheduler::Lsf,
+        ] {
+            let stdout = scheduler.fake_submit_output("42");
+            assert_eq!(
+                parse_job_id(scheduler, &stdout).as_deref(),
+                Some("42"),
+                "{:?}",
+                scheduler
+            );
+        }
+    }
+}
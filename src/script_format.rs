@@ -0,0 +1,137 @@
+//! Version of the generated batch scripts, stamped into each one as a
+//! `# batchelor-script-format: N` line and recorded in the run state.
+//! Subcommands that use the scripts of a run (`resubmit`, `clean`) refuse
+//! formats they do not know instead of guessing, e.g. with
+//! `scripts have format 3, this batchelor knows up to 2; upgrade batchelor
+//! to use them`.
+//!
+//! Formats:
+//!
+//! - 1: scripts written before the stamp existed. They differ from format
+//!   2 only by the missing line and are used as they are.
+//! - 2: adds the stamp.
+
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+/// Format of the scripts this batchelor writes.
+pub const CURRENT: u32 = 2;
+
+/// Format of scripts and run states without a stamp.
+pub const UNSTAMPED: u32 = 1;
+
+pub(crate) const HEADER: &str = "# batchelor-script-format:";
+
+/// The stamp line of [`CURRENT`].
+pub(crate) fn header_line() -> String {
+    format!("{} {}", HEADER, CURRENT)
+}
+
+/// The format stamped in the first lines of a script's `text`; `None`
+/// without a stamp.
+pub fn parse(text: &str) -> Result<Option<u32>, String> {
+    for line in text.lines().take(5) {
+        if let Some(version) = line.strip_prefix(HEADER) {
+            return match version.trim().parse() {
+                Ok(0) | Err(_) => Err(format!("unknown script format {:?}", version.trim())),
+                Ok(version) => Ok(Some(version)),
+            };
+        }
+    }
+    Ok(None)
+}
+
+/// The format of the script at `path`, [`UNSTAMPED`] without a stamp.
+pub fn read(path: &Path) -> Result<u32, String> {
+    let file =
+        fs::File::open(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let head = io::BufReader::new(file)
+        .lines()
+        .take(5)
+        .map_while(Result::ok)
+        .collect::<Vec<_>>()
+        .join("\n");
+    parse(&head)
+        .map(|version| version.unwrap_or(UNSTAMPED))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Whether this batchelor can use scripts of `version`.
+pub fn check(version: u32) -> Result<(), String> {
+    match version {
+        UNSTAMPED..=CURRENT => Ok(()),
+        0 => Err("unknown script format 0".to_string()),
+        _ => Err(format!(
+            "scripts have format {}, this batchelor knows up to {}; upgrade batchelor to use them",
+            version, CURRENT
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamps_are_read_from_the_first_lines() {
+        let script = format!(
+            "#!/usr/bin/env bash\n# generated-by: batchelor\n{}\n",
+            header_line()
+        );
+        assert_eq!(parse(&script), Ok(Some(CURRENT)));
+        assert_eq!(
+            parse("#!/bin/bash\n# batchelor-script-format:  7 \n"),
+            Ok(Some(7))
+        );
+        assert_eq!(parse("#!/usr/bin/env bash\necho hi\n"), Ok(None));
+        // Past the header, the line is the user's.
+        let late = format!("{}{}\n", "#\n".repeat(5), header_line());
+        assert_eq!(parse(&late), Ok(None));
+    }
+
+    #[test]
+    fn unreadable_stamps_are_refused() {
+        for (stamp, expected) in [
+            ("two", r#"unknown script format "two""#),
+            ("0", r#"unknown script format "0""#),
+            ("-1", r#"unknown script format "-1""#),
+            ("", r#"unknown script format """#),
+        ] {
+            let script = format!("#!/bin/bash\n{} {}\n", HEADER, stamp);
+            assert_eq!(parse(&script), Err(expected.to_string()), "{:?}", stamp);
+        }
+    }
+
+    #[test]
+    fn future_formats_are_refused() {
+        assert_eq!(check(UNSTAMPED), Ok(()));
+        assert_eq!(check(CURRENT), Ok(()));
+        assert_eq!(
+            check(CURRENT + 1),
+            Err(format!(
+                "scripts have format {}, this batchelor knows up to {}; upgrade batchelor to use them",
+                CURRENT + 1,
+                CURRENT
+            ))
+        );
+        assert_eq!(check(0), Err("unknown script format 0".to_string()));
+    }
+
+    #[test]
+    fn files_without_a_stamp_are_unstamped() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = dir.path().join("old.sh");
+        fs::write(&old, "#!/usr/bin/env bash\nset -euo pipefail\n").unwrap();
+        assert_eq!(read(&old), Ok(UNSTAMPED));
+        let bad = dir.path().join("bad.sh");
+        fs::write(&bad, format!("#!/bin/bash\n{} x\n", HEADER)).unwrap();
+        assert_eq!(
+            read(&bad),
+            Err(format!("{}: unknown script format \"x\"", bad.display()))
+        );
+        assert!(read(&dir.path().join("gone.sh"))
+            .unwrap_err()
+            .starts_with("could not read "));
+    }
+}
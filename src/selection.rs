@@ -0,0 +1,99 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A set of 1-based batch indices written as `7`, `1-3,9` or `30-`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BatchSet {
+    /// Inclusive ranges; an open end means "to the last batch".
+    ranges: Vec<(usize, Option<usize>)>,
+}
+
+impl BatchSet {
+    pub fn contains(&self, index: usize) -> bool {
+        self.ranges
+            .iter()
+            .any(|&(start, end)| index >= start && end.is_none_or(|end| index <= end))
+    }
+
+    /// Checks that every index named explicitly lies within `1..=count`.
+    pub fn check_bounds(&self, count: usize) -> Result<(), String> {
+        for &(start, end) in &self.ranges {
+            let highest = end.unwrap_or(start);
+            if highest > count {
+                return Err(format!(
+                    "batch {} is out of range; valid batches are 1-{}",
+                    highest, count
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true if some index is in both sets.
+    pub fn overlaps(&self, other: &BatchSet) -> bool {
+        self.ranges.iter().any(|&(a_start, a_end)| {
+            other.ranges.iter().any(|&(b_start, b_end)| {
+                a_end.is_none_or(|a_end| b_start <= a_end)
+                    && b_end.is_none_or(|b_end| a_start <= b_end)
+            })
+        })
+    }
+}
+
+impl FromStr for BatchSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<BatchSet, String> {
+        let invalid = |part: &str| {
+            format!(
+                "invalid batch selection {:?} in {:?} (expected e.g. 7, 1-3,9 or 30-)",
+                part, s
+            )
+        };
+        let index = |text: &str, part: &str| -> Result<usize, String> {
+            match text.trim().parse::<usize>() {
+                Ok(0) => Err(format!("batch indices start at 1, got {:?}", part)),
+                Ok(n) => Ok(n),
+                Err(_) => Err(invalid(part)),
+            }
+        };
+
+        let mut ranges = Vec::new();
+        for part in s.split(',').map(str::trim) {
+            if part.is_empty() {
+                return Err(invalid(part));
+            }
+            let range = match part.split_once('-') {
+                Some((start, "")) => (index(start, part)?, None),
+                Some((start, end)) => {
+                    let (start, end) = (index(start, part)?, index(end, part)?);
+                    if end < start {
+                        return Err(invalid(part));
+                    }
+                    (start, Some(end))
+                }
+                None => {
+                    let n = index(part, part)?;
+                    (n, Some(n))
+                }
+            };
+            ranges.push(range);
+        }
+        Ok(BatchSet { ranges })
+    }
+}
+
+impl fmt::Display for BatchSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts = self
+            .ranges
+            .iter()
+            .map(|&(start, end)| match end {
+                Some(end) if end == start => start.to_string(),
+                Some(end) => format!("{}-{}", start, end),
+                None => format!("{}-", start),
+            })
+            .collect::<Vec<_>>();
+        f.write_str(&parts.join(","))
+    }
+}
@@ -0,0 +1,102 @@
+//! How paths appear in the JSON documents (plans, run states): as plain
+//! strings. A path that is not valid UTF-8 cannot be a JSON string, so it
+//! is written as `{"path": "<lossy>", "raw_bytes": "<base64>"}` instead,
+//! where `path` is for display and `raw_bytes` (Unix only) restores the
+//! exact bytes when read back. Elsewhere such paths only keep their lossy
+//! form.
+//!
+//! Use with `#[serde(with = "crate::serde_path")]`, or
+//! `crate::serde_path::option` for `Option<PathBuf>`.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Repr {
+    Plain(String),
+    Lossy {
+        path: String,
+        #[serde(default)]
+        raw_bytes: Option<String>,
+    },
+}
+
+impl Repr {
+    fn new(path: &Path) -> Repr {
+        match path.to_str() {
+            Some(text) => Repr::Plain(text.to_string()),
+            None => Repr::Lossy {
+                path: path.to_string_lossy().into_owned(),
+                raw_bytes: raw_bytes(path),
+            },
+        }
+    }
+
+    fn into_path<E: serde::de::Error>(self) -> Result<PathBuf, E> {
+        match self {
+            Repr::Plain(text) => Ok(PathBuf::from(text)),
+            Repr::Lossy {
+                raw_bytes: Some(encoded),
+                ..
+            } => from_raw_bytes(&encoded).map_err(E::custom),
+            Repr::Lossy { path, .. } => Ok(PathBuf::from(path)),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn raw_bytes(path: &Path) -> Option<String> {
+    use base64::Engine;
+    use std::os::unix::ffi::OsStrExt;
+    Some(base64::engine::general_purpose::STANDARD.encode(path.as_os_str().as_bytes()))
+}
+
+#[cfg(not(unix))]
+fn raw_bytes(_path: &Path) -> Option<String> {
+    None
+}
+
+#[cfg(unix)]
+fn from_raw_bytes(encoded: &str) -> Result<PathBuf, String> {
+    use base64::Engine;
+    use std::os::unix::ffi::OsStringExt;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|e| format!("invalid raw_bytes of a path: {}", e))?;
+    Ok(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+fn from_raw_bytes(_encoded: &str) -> Result<PathBuf, String> {
+    Err("raw_bytes paths can only be read on Unix".to_string())
+}
+
+pub(crate) fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    Repr::new(path).serialize(serializer)
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+    Repr::deserialize(deserializer)?.into_path()
+}
+
+pub(crate) mod option {
+    use super::Repr;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::path::PathBuf;
+
+    pub(crate) fn serialize<S: Serializer>(
+        path: &Option<PathBuf>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        path.as_deref().map(Repr::new).serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<PathBuf>, D::Error> {
+        Option::<Repr>::deserialize(deserializer)?
+            .map(Repr::into_path)
+            .transpose()
+    }
+}
@@ -0,0 +1,956 @@
+//! How batchelor builds the command lines of its batch scripts: shell
+//! quoting, the `--input-flag` template and the per-input command line.
+//! Tools that preview what batchelor would run use these to get exactly
+//! the same text.
+//!
+//! The scripts are bash scripts on every platform, so quoting follows
+//! bash's rules on Windows too, where the scripts need a bash such as Git
+//! for Windows, MSYS2 or WSL; cmd.exe cannot run them. Paths put into the
+//! scripts, and the inputs that replace `$1`, use `/` there
+//! ([`script_path`]). Submit commands never go through a shell: their
+//! arguments are passed to the program as separate entries, which needs no
+//! quoting on any platform.
+//!
+//! `--input-flag` takes one of three forms ([`InputFlag`]):
+//!
+//! - a flag such as `--input`: `bash script.sh --input <inputs> <script-args>`
+//! - a positional slot such as `$2`: the inputs go at that position among
+//!   the script args. A slot past the end of the script args (`$5` with two)
+//!   is an error unless [`SlotOverflow`] says otherwise; `$0` is an error
+//! - a template containing `$1`, such as `--in=$1 --verbose`, for each
+//!   input followed by the script args
+//!
+//! In a template ([`Template`]), `$1` and `${1}` stand for the input, and
+//! `$$` for a literal `$` (so a raw template writes the shell's `$$` as
+//! `$$$$`). `$1` followed by a digit is ambiguous and an error: write
+//! `${1}2` for the input followed by `2`. Any other `$` is literal text.
+//! A flag without a placeholder is read the same way, so `$$1` is the flag
+//! `$1`.
+//!
+//! A template is split into words once, like a shell would (`"my file.cfg"`
+//! is one word, without the quotes); unbalanced quotes are an error. `$1`
+//! is then replaced by the input in every word, and each word is written
+//! back as exactly one shell word, quoted only when it needs to be. Words
+//! without `$1` come out as the same literal words, so shell syntax in a
+//! template is never interpreted: `>`, `2>&1` or `|` reach the script as
+//! arguments. Redirections and pipes belong in the script.
+//!
+//! With `--raw-template` ([`InputFlag::parse_raw`]) the template is written
+//! after `bash script.sh` as is instead, with only the input quoted where
+//! `$1` was, and the script args after it. Pipes,
+//! redirections and variables in it then work, and so does anything else:
+//! a raw template is shell code and must not come from untrusted text.
+//!
+//! A batch whose command line would be too long for the system or the
+//! scheduler reads its inputs from a file instead ([`list_command_line`]).
+
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Quotes `s` as one shell word. Words made only of characters the shell
+/// treats literally are returned as they are, anything else is put in
+/// single quotes; the empty string becomes `''`.
+pub fn quote(s: &str) -> Cow<'_, str> {
+    if s.is_empty() {
+        return Cow::Borrowed("''");
+    }
+    if s.bytes().all(|b| b.is_ascii_alphanumeric() || b"@%_+=:,./-".contains(&b)) {
+        return Cow::Borrowed(s);
+    }
+    // One allocation: each `'` in `s` grows by three bytes as `'\''`.
+    let quotes = s.bytes().filter(|&b| b == b'\'').count();
+    let mut quoted = String::with_capacity(s.len() + 3 * quotes + 2);
+    quoted.push('\'');
+    for (i, part) in s.split('\'').enumerate() {
+        if i > 0 {
+            quoted.push_str("'\\''");
+        }
+        quoted.push_str(part);
+    }
+    quoted.push('\'');
+    Cow::Owned(quoted)
+}
+
+/// Like [`quote`]; strings that are not valid UTF-8 are converted lossily.
+pub fn quote_os(s: &OsStr) -> Cow<'_, str> {
+    match s.to_string_lossy() {
+        Cow::Borrowed(s) => quote(s),
+        Cow::Owned(s) => Cow::Owned(quote(&s).into_owned()),
+    }
+}
+
+/// Like [`quote_os`], for paths, which are written as [`script_path`] says.
+pub fn quote_path(path: &Path) -> Cow<'_, str> {
+    match path.to_string_lossy() {
+        Cow::Borrowed(s) => match script_path(s) {
+            Cow::Borrowed(s) => quote(s),
+            Cow::Owned(s) => Cow::Owned(quote(&s).into_owned()),
+        },
+        Cow::Owned(s) => Cow::Owned(quote(&script_path(&s)).into_owned()),
+    }
+}
+
+/// How `path` is written into a script and recorded as an input. On
+/// Windows the prefix `\\?\` of canonical paths is dropped and `\` becomes
+/// `/`: `C:/data/a.fq` works for bash and for Windows programs alike.
+/// Elsewhere paths are written as they are.
+pub fn script_path(path: &str) -> Cow<'_, str> {
+    if cfg!(windows) {
+        forward_slashes(path)
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+/// [`script_path`] on Windows.
+fn forward_slashes(path: &str) -> Cow<'_, str> {
+    let path = if let Some(unc) = path.strip_prefix(r"\\?\UNC\") {
+        Cow::Owned(format!(r"\\{}", unc))
+    } else {
+        Cow::Borrowed(path.strip_prefix(r"\\?\").unwrap_or(path))
+    };
+    if path.contains('\\') {
+        Cow::Owned(path.replace('\\', "/"))
+    } else {
+        path
+    }
+}
+
+/// The 1-based slot of a positional `--input-flag` such as `$2`; `None`
+/// for anything else, including `$0` (see [`InputFlag`]).
+pub fn positional_slot(input_flag: &str) -> Option<usize> {
+    let idx = input_flag.strip_prefix('$')?.parse::<usize>().ok()?;
+    if idx == 0 {
+        None
+    } else {
+        Some(idx)
+    }
+}
+
+/// The words of an `--input-flag` template, split like shell words.
+pub fn template_tokens(input_flag: &str) -> Result<Vec<String>, String> {
+    shlex::split(input_flag).ok_or_else(|| "unbalanced quotes in template".to_string())
+}
+
+/// A piece of a [`Template`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Piece {
+    Text(String),
+    /// `$1` or `${1}`.
+    Input,
+}
+
+/// A template word, or a whole raw template, split into text and the
+/// places of the input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+impl Template {
+    pub fn pieces(&self) -> &[Piece] {
+        &self.pieces
+    }
+
+    /// Whether the input appears in it.
+    pub fn has_input(&self) -> bool {
+        self.pieces.contains(&Piece::Input)
+    }
+
+    /// The text with `input` in place of each `$1`.
+    pub fn render(&self, input: &str) -> String {
+        self.pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Text(text) => text.as_str(),
+                Piece::Input => input,
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Template, String> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut rest = s;
+        while let Some(at) = rest.find('$') {
+            text.push_str(&rest[..at]);
+            let after = &rest[at + 1..];
+            let len = if after.starts_with('$') {
+                text.push('$');
+                1
+            } else if after.starts_with("{1}") {
+                push_input(&mut pieces, &mut text);
+                3
+            } else if let Some(braced) = after
+                .strip_prefix('{')
+                .and_then(|a| a.split_once('}'))
+                .map(|(inside, _)| inside)
+                .filter(|inside| !inside.is_empty() && inside.bytes().all(|b| b.is_ascii_digit()))
+            {
+                return Err(format!(
+                    "${{{}}} is not a placeholder; only ${{1}} (the input) is",
+                    braced
+                ));
+            } else if let Some(next) = after.strip_prefix('1') {
+                if let Some(digit) = next.chars().next().filter(char::is_ascii_digit) {
+                    return Err(format!(
+                        "$1{} is ambiguous; write ${{1}}{} for the input followed by {}",
+                        digit, digit, digit
+                    ));
+                }
+                push_input(&mut pieces, &mut text);
+                1
+            } else {
+                text.push('$');
+                0
+            };
+            rest = &after[len..];
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(Template { pieces })
+    }
+}
+
+/// Ends the text so far and adds the input after it.
+fn push_input(pieces: &mut Vec<Piece>, text: &mut String) {
+    if !text.is_empty() {
+        pieces.push(Piece::Text(std::mem::take(text)));
+    }
+    pieces.push(Piece::Input);
+}
+
+/// A parsed `--input-flag`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InputFlag {
+    /// Written as one word before the inputs.
+    Flag(String),
+    /// The 1-based position of the inputs among the script args.
+    Positional(usize),
+    /// The words of a template, at least one containing the input.
+    Template(Vec<Template>),
+    /// A `--raw-template`: shell text containing the input.
+    RawTemplate(Template),
+}
+
+impl InputFlag {
+    /// Parses a `--raw-template` template, which is not split into words.
+    pub fn parse_raw(s: &str) -> Result<InputFlag, String> {
+        let template = s.parse::<Template>()?;
+        if !template.has_input() {
+            return Err("--raw-template needs a template containing $1".to_string());
+        }
+        Ok(InputFlag::RawTemplate(template))
+    }
+}
+
+impl FromStr for InputFlag {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<InputFlag, String> {
+        if let Some(slot) = positional_slot(s) {
+            return Ok(InputFlag::Positional(slot));
+        }
+        if s.strip_prefix('$')
+            .is_some_and(|n| n.parse::<usize>().ok() == Some(0))
+        {
+            return Err("$0 is not a positional slot; the first one is $1".to_string());
+        }
+        let template = s.parse::<Template>()?;
+        if !template.has_input() {
+            // Without an input there is only text, `$$` made a `$`.
+            return Ok(InputFlag::Flag(template.render("")));
+        }
+        let words = template_tokens(s)?
+            .iter()
+            .map(|word| word.parse::<Template>())
+            .collect::<Result<Vec<_>, _>>()?;
+        if !words.iter().any(Template::has_input) {
+            // Splitting drops words from an unquoted `#` on.
+            return Err(
+                "$1 is lost when the template is split into words (after a #?)".to_string(),
+            );
+        }
+        Ok(InputFlag::Template(words))
+    }
+}
+
+/// What a template is rendered for.
+pub struct Context<'a> {
+    /// Replaces `$1`.
+    pub input: &'a str,
+}
+
+/// The template `words` for one input, each a quoted shell word.
+pub fn render_template(words: &[Template], ctx: &Context) -> Vec<String> {
+    words
+        .iter()
+        .map(|word| quote(&word.render(ctx.input)).into_owned())
+        .collect()
+}
+
+/// A raw `template` for one input: the text as is, with the quoted input in
+/// place of `$1`.
+pub fn render_raw_template(template: &Template, ctx: &Context) -> String {
+    template.render(&quote(ctx.input))
+}
+
+/// What a positional slot past the end of the script args does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SlotOverflow {
+    /// Refused by [`check_slot`].
+    #[default]
+    Error,
+    /// The missing slots are filled with empty arguments (`--pad-positional`).
+    Pad,
+    /// The inputs follow the last script arg (`--clamp-positional`).
+    Clamp,
+}
+
+/// Whether a positional `input_flag` fits `script_args` script args.
+pub fn check_slot(
+    input_flag: &InputFlag,
+    script_args: usize,
+    overflow: SlotOverflow,
+) -> Result<(), String> {
+    match input_flag {
+        InputFlag::Positional(slot) if slot - 1 > script_args && overflow == SlotOverflow::Error => {
+            Err(format!(
+                "positional slot ${} requested but only {} script args provided (--pad-positional fills the gap with empty args, --clamp-positional puts the inputs after the last one)",
+                slot, script_args
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// How a batch runs the user's script.
+pub struct CommandSpec<'a> {
+    /// `--script`, as the job sees it.
+    pub script: &'a Path,
+    /// `--input-flag`.
+    pub input_flag: &'a InputFlag,
+    /// `--script-args`.
+    pub script_args: &'a [String],
+    /// Where a positional slot past the script args puts the inputs.
+    pub overflow: SlotOverflow,
+}
+
+impl CommandSpec<'_> {
+    /// Refuses slots past the script args, as [`check_slot`] does; a spec
+    /// that was not checked clamps them.
+    pub fn check(&self) -> Result<(), String> {
+        check_slot(self.input_flag, self.script_args.len(), self.overflow)
+    }
+}
+
+/// The command line running the script over `inputs`, e.g.
+/// `bash /abs/script.sh --input a.txt b.txt`.
+pub fn build_command_line(spec: &CommandSpec, inputs: &[String]) -> String {
+    // Quoted as the line is joined; args that need no quoting are not
+    // copied in between.
+    let mut args = spec
+        .script_args
+        .iter()
+        .map(|a| quote(a))
+        .collect::<Vec<_>>();
+    match spec.input_flag {
+        InputFlag::Positional(slot) => {
+            let idx = slot - 1;
+            if spec.overflow == SlotOverflow::Pad && idx > args.len() {
+                args.resize(idx, quote(""));
+            }
+            let idx = idx.min(args.len());
+            args.splice(idx..idx, inputs.iter().map(|i| quote(i)));
+        }
+        InputFlag::Template(tokens) => {
+            let mut templated = Vec::new();
+            for input in inputs {
+                templated.extend(
+                    render_template(tokens, &Context { input })
+                        .into_iter()
+                        .map(Cow::Owned),
+                );
+            }
+            args.splice(0..0, templated);
+        }
+        InputFlag::RawTemplate(template) => {
+            let raw = inputs
+                .iter()
+                .map(|input| Cow::Owned(render_raw_template(template, &Context { input })));
+            args.splice(0..0, raw);
+        }
+        InputFlag::Flag(flag) => {
+            let flagged = std::iter::once(quote(flag)).chain(inputs.iter().map(|i| quote(i)));
+            args.splice(0..0, flagged);
+        }
+    }
+
+    let script = quote_path(spec.script);
+    let mut line =
+        String::with_capacity(5 + script.len() + args.iter().map(|a| a.len() + 1).sum::<usize>());
+    line.push_str("bash ");
+    line.push_str(&script);
+    for arg in &args {
+        line.push(' ');
+        line.push_str(arg);
+    }
+    line
+}
+
+/// The command lines of a batch: one for all `inputs` with `--multi-input`,
+/// otherwise one per input. A batch without inputs has none.
+pub fn command_lines(spec: &CommandSpec, inputs: &[String], multi_input: bool) -> Vec<String> {
+    if multi_input && !inputs.is_empty() {
+        vec![build_command_line(spec, inputs)]
+    } else {
+        inputs
+            .iter()
+            .map(|input| build_command_line(spec, std::slice::from_ref(input)))
+            .collect()
+    }
+}
+
+/// Stands for the inputs in the command [`list_command_line`] hands to
+/// `xargs`. The space makes [`quote`] put it in single quotes wherever it
+/// ends up.
+const LISTED_INPUTS: &str = "__batchelor inputs__";
+
+/// The command line running the script over the inputs listed one per
+/// line in `list`, for batches whose command line would be too long. With
+/// `list_flag` (`--input-list-flag`) the script is called once with the
+/// list: `bash script.sh <list_flag> <list> <script-args>`. Otherwise
+/// `xargs` passes the inputs as the input flag would, in as many calls as
+/// the system's argument limit needs, or one call per input without
+/// `multi_input`. Templates take one input per call, so several inputs
+/// per call need `list_flag`.
+pub fn list_command_line(
+    spec: &CommandSpec,
+    list: &Path,
+    list_flag: Option<&str>,
+    multi_input: bool,
+) -> Result<String, String> {
+    if let Some(flag) = list_flag {
+        let listed = CommandSpec {
+            input_flag: &InputFlag::Flag(flag.to_string()),
+            ..*spec
+        };
+        let list = list.to_string_lossy().into_owned();
+        return Ok(build_command_line(&listed, &[list]));
+    }
+    let per_input = matches!(
+        spec.input_flag,
+        InputFlag::Template(_) | InputFlag::RawTemplate(_)
+    );
+    if multi_input && per_input {
+        return Err(
+            "a template cannot pass several inputs per call through xargs; give --input-list-flag"
+                .to_string(),
+        );
+    }
+    let inputs = if multi_input { "\"$@\"" } else { "\"$1\"" };
+    // The marker is always single-quoted: a whole word is replaced with the
+    // expansion, and in part of a word the quotes are closed around it.
+    let command = build_command_line(spec, &[LISTED_INPUTS.to_string()])
+        .replace(quote(LISTED_INPUTS).as_ref(), inputs)
+        .replace(LISTED_INPUTS, &format!("'{}'", inputs));
+    Ok(format!(
+        "tr '\\n' '\\0' < {} | xargs -0 {}bash -c {} bash",
+        quote_path(list),
+        if multi_input { "" } else { "-n 1 " },
+        quote(&command)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec<'a>(input_flag: &'a InputFlag, script_args: &'a [String]) -> CommandSpec<'a> {
+        CommandSpec {
+            script: Path::new("/abs/run me.sh"),
+            input_flag,
+            script_args,
+            overflow: SlotOverflow::Error,
+        }
+    }
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn quote_words() {
+        for (word, quoted) in [
+            ("", "''"),
+            ("plain", "plain"),
+            ("/abs/a-b_c.fq.gz", "/abs/a-b_c.fq.gz"),
+            ("k=v,w:x@y%z+1", "k=v,w:x@y%z+1"),
+            ("a b", "'a b'"),
+            ("it's", "'it'\\''s'"),
+            ("''", "''\\'''\\'''"),
+            ("$HOME", "'$HOME'"),
+            ("a\nb", "'a\nb'"),
+            ("*.fq", "'*.fq'"),
+            ("~", "'~'"),
+            ("café", "'café'"),
+        ] {
+            assert_eq!(quote(word), quoted, "{:?}", word);
+        }
+        assert!(matches!(quote("plain"), Cow::Borrowed(_)));
+        assert_eq!(quote_path(Path::new("/a b/c")), "'/a b/c'");
+    }
+
+    #[test]
+    fn flag_goes_before_the_inputs() {
+        let flag = InputFlag::Flag("--input".to_string());
+        let args = strings(&["-t", "4"]);
+        assert_eq!(
+            build_command_line(&spec(&flag, &args), &strings(&["a.fq", "b c.fq"])),
+            "bash '/abs/run me.sh' --input a.fq 'b c.fq' -t 4"
+        );
+    }
+
+    #[test]
+    fn positional_slots() {
+        let args = strings(&["x", "y"]);
+        let inputs = strings(&["a.fq", "b.fq"]);
+        for (slot, line) in [
+            (1, "bash '/abs/run me.sh' a.fq b.fq x y"),
+            (2, "bash '/abs/run me.sh' x a.fq b.fq y"),
+            (3, "bash '/abs/run me.sh' x y a.fq b.fq"),
+        ] {
+            let flag = InputFlag::Positional(slot);
+            assert_eq!(build_command_line(&spec(&flag, &args), &inputs), line);
+            assert!(spec(&flag, &args).check().is_ok());
+        }
+
+        let flag = InputFlag::Positional(5);
+        let e = spec(&flag, &args).check().unwrap_err();
+        assert!(e.starts_with("positional slot $5 requested but only 2 script args"));
+        let padded = CommandSpec {
+            overflow: SlotOverflow::Pad,
+            ..spec(&flag, &args)
+        };
+        assert!(padded.check().is_ok());
+        assert_eq!(
+            build_command_line(&padded, &inputs),
+            "bash '/abs/run me.sh' x y '' '' a.fq b.fq"
+        );
+        let clamped = CommandSpec {
+            overflow: SlotOverflow::Clamp,
+            ..spec(&flag, &args)
+        };
+        assert_eq!(
+            build_command_line(&clamped, &inputs),
+            "bash '/abs/run me.sh' x y a.fq b.fq"
+        );
+    }
+
+    #[test]
+    fn template_words_per_input() {
+        let flag = "--in=$1 \"my file.cfg\" >".parse::<InputFlag>().unwrap();
+        let args = strings(&["-v"]);
+        assert_eq!(
+            build_command_line(&spec(&flag, &args), &strings(&["a.fq", "b c.fq"])),
+            "bash '/abs/run me.sh' --in=a.fq 'my file.cfg' '>' '--in=b c.fq' 'my file.cfg' '>' -v"
+        );
+    }
+
+    #[test]
+    fn escaped_dollars_are_literal_in_flags_and_templates() {
+        let inputs = strings(&["a.fq"]);
+        let flag = "$$1".parse::<InputFlag>().unwrap();
+        assert_eq!(
+            build_command_line(&spec(&flag, &[]), &inputs),
+            "bash '/abs/run me.sh' '$1' a.fq"
+        );
+        let template = "$$1 $1".parse::<InputFlag>().unwrap();
+        assert_eq!(
+            build_command_line(&spec(&template, &[]), &inputs),
+            "bash '/abs/run me.sh' '$1' a.fq"
+        );
+    }
+
+    #[test]
+    fn raw_template_is_shell_text() {
+        let flag = InputFlag::parse_raw("--in $1 2>&1 | tee $1.log").unwrap();
+        assert_eq!(
+            build_command_line(&spec(&flag, &[]), &strings(&["b c.fq"])),
+            "bash '/abs/run me.sh' --in 'b c.fq' 2>&1 | tee 'b c.fq'.log"
+        );
+    }
+
+    #[test]
+    fn command_lines_per_input_or_per_batch() {
+        let flag = InputFlag::Flag("-i".to_string());
+        let inputs = strings(&["a", "b"]);
+        assert_eq!(
+            command_lines(&spec(&flag, &[]), &inputs, false),
+            ["bash '/abs/run me.sh' -i a", "bash '/abs/run me.sh' -i b"]
+        );
+        assert_eq!(
+            command_lines(&spec(&flag, &[]), &inputs, true),
+            ["bash '/abs/run me.sh' -i a b"]
+        );
+        assert!(command_lines(&spec(&flag, &[]), &[], true).is_empty());
+        assert!(command_lines(&spec(&flag, &[]), &[], false).is_empty());
+    }
+
+    #[test]
+    fn list_flag_passes_the_list() {
+        let flag = InputFlag::Flag("-i".to_string());
+        let args = strings(&["-v"]);
+        let line = list_command_line(
+            &spec(&flag, &args),
+            Path::new("/o/b 1.inputs"),
+            Some("--list"),
+            true,
+        )
+        .unwrap();
+        assert_eq!(line, "bash '/abs/run me.sh' --list '/o/b 1.inputs' -v");
+    }
+
+    #[test]
+    fn xargs_fallback() {
+        let flag = InputFlag::Flag("-i".to_string());
+        let list = Path::new("/o/b.inputs");
+        assert_eq!(
+            list_command_line(&spec(&flag, &[]), list, None, true).unwrap(),
+            "tr '\\n' '\\0' < /o/b.inputs | xargs -0 bash -c 'bash '\\''/abs/run me.sh'\\'' -i \"$@\"' bash"
+        );
+        assert_eq!(
+            list_command_line(&spec(&flag, &[]), list, None, false).unwrap(),
+            "tr '\\n' '\\0' < /o/b.inputs | xargs -0 -n 1 bash -c 'bash '\\''/abs/run me.sh'\\'' -i \"$1\"' bash"
+        );
+        let template = "--in=$1".parse::<InputFlag>().unwrap();
+        assert!(list_command_line(&spec(&template, &[]), list, None, true).is_err());
+        assert!(list_command_line(&spec(&template, &[]), list, None, false).is_ok());
+    }
+
+    /// The arguments of each call of a script when bash runs the
+    /// [`list_command_line`] of `input_flag` over `inputs`, one call per line.
+    #[cfg(unix)]
+    fn run_listed(input_flag: &InputFlag, inputs: &[&str], multi_input: bool) -> Vec<String> {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("args.sh");
+        std::fs::write(&script, "printf '<%s>' \"$@\"; echo\n").unwrap();
+        let list = dir.path().join("batch.inputs");
+        std::fs::write(&list, inputs.join("\n") + "\n").unwrap();
+        let spec = CommandSpec {
+            script: &script,
+            input_flag,
+            script_args: &strings(&["-v"]),
+            overflow: SlotOverflow::Error,
+        };
+        let line = list_command_line(&spec, &list, None, multi_input).unwrap();
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(&line)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn xargs_fallback_passes_the_inputs_like_the_input_flag() {
+        let inputs = ["a b.fq", "it's.fq", "$x.fq"];
+        let flag = InputFlag::Flag("--input".to_string());
+        assert_eq!(
+            run_listed(&flag, &inputs, true),
+            ["<--input><a b.fq><it's.fq><$x.fq><-v>"]
+        );
+        assert_eq!(
+            run_listed(&flag, &inputs, false),
+            [
+                "<--input><a b.fq><-v>",
+                "<--input><it's.fq><-v>",
+                "<--input><$x.fq><-v>"
+            ]
+        );
+        let template = "--in=$1.gz".parse::<InputFlag>().unwrap();
+        assert_eq!(
+            run_listed(&template, &inputs[..2], false),
+            ["<--in=a b.fq.gz><-v>", "<--in=it's.fq.gz><-v>"]
+        );
+        assert_eq!(
+            run_listed(&InputFlag::Positional(2), &inputs[..2], true),
+            ["<-v><a b.fq><it's.fq>"]
+        );
+    }
+
+    #[test]
+    fn template_pieces() {
+        use Piece::{Input, Text};
+        let text = |s: &str| Text(s.to_string());
+        for (template, pieces) in [
+            ("$1", vec![Input]),
+            ("${1}", vec![Input]),
+            ("--in=$1", vec![text("--in="), Input]),
+            ("${1}2", vec![Input, text("2")]),
+            ("$1.$1", vec![Input, text("."), Input]),
+            ("$$", vec![text("$")]),
+            ("$$1", vec![text("$1")]),
+            ("$$$1", vec![text("$"), Input]),
+            ("$HOME/$1", vec![text("$HOME/"), Input]),
+            ("${x}", vec![text("${x}")]),
+            ("${", vec![text("${")]),
+            ("${1", vec![text("${1")]),
+            ("$", vec![text("$")]),
+            ("$2", vec![text("$2")]),
+            ("--flag", vec![text("--flag")]),
+            ("", vec![]),
+        ] {
+            let parsed = template.parse::<Template>().unwrap();
+            assert_eq!(parsed.pieces(), pieces, "{:?}", template);
+        }
+    }
+
+    #[test]
+    fn template_errors() {
+        for (template, error) in [
+            (
+                "$12",
+                "$12 is ambiguous; write ${1}2 for the input followed by 2",
+            ),
+            (
+                "a$10b",
+                "$10 is ambiguous; write ${1}0 for the input followed by 0",
+            ),
+            (
+                "${2}",
+                "${2} is not a placeholder; only ${1} (the input) is",
+            ),
+            (
+                "${12}",
+                "${12} is not a placeholder; only ${1} (the input) is",
+            ),
+        ] {
+            assert_eq!(
+                template.parse::<Template>().unwrap_err(),
+                error,
+                "{:?}",
+                template
+            );
+            if positional_slot(template).is_none() {
+                assert_eq!(template.parse::<InputFlag>().unwrap_err(), error);
+            }
+        }
+        // A whole flag of `$` and digits is a slot, not a template.
+        assert_eq!("$12".parse(), Ok(InputFlag::Positional(12)));
+    }
+
+    #[test]
+    fn input_flag_forms() {
+        assert_eq!(
+            "--input".parse(),
+            Ok(InputFlag::Flag("--input".to_string()))
+        );
+        // Escapes mean the same in a flag as in a template.
+        assert_eq!("$$1".parse(), Ok(InputFlag::Flag("$1".to_string())));
+        assert_eq!(
+            "--cost=$$$$5".parse(),
+            Ok(InputFlag::Flag("--cost=$$5".to_string()))
+        );
+        assert_eq!("$HOME".parse(), Ok(InputFlag::Flag("$HOME".to_string())));
+        assert_eq!("$3".parse(), Ok(InputFlag::Positional(3)));
+        assert_eq!(
+            "$0".parse::<InputFlag>().unwrap_err(),
+            "$0 is not a positional slot; the first one is $1"
+        );
+        assert_eq!("$1".parse(), Ok(InputFlag::Positional(1)));
+        assert!(matches!("${1}".parse(), Ok(InputFlag::Template(words)) if words.len() == 1));
+        assert_eq!(
+            "--in $1 'a".parse::<InputFlag>().unwrap_err(),
+            "unbalanced quotes in template"
+        );
+        assert_eq!(
+            "--in # $1".parse::<InputFlag>().unwrap_err(),
+            "$1 is lost when the template is split into words (after a #?)"
+        );
+        assert_eq!(
+            InputFlag::parse_raw("--in").unwrap_err(),
+            "--raw-template needs a template containing $1"
+        );
+    }
+
+    #[test]
+    fn template_and_raw_template_quote_differently() {
+        let input = "a b.fq";
+        let words = match "--in=${1}.gz $$HOME '|' x".parse::<InputFlag>().unwrap() {
+            InputFlag::Template(words) => words,
+            other => panic!("{:?}", other),
+        };
+        // Each word is one quoted shell word: `$`, `|` stay literal.
+        assert_eq!(
+            render_template(&words, &Context { input }),
+            ["'--in=a b.fq.gz'", "'$HOME'", "'|'", "x"]
+        );
+        let raw = match InputFlag::parse_raw("--in=${1}.gz $$HOME | x").unwrap() {
+            InputFlag::RawTemplate(raw) => raw,
+            other => panic!("{:?}", other),
+        };
+        // Shell text: only the input is quoted.
+        assert_eq!(
+            render_raw_template(&raw, &Context { input }),
+            "--in='a b.fq'.gz $HOME | x"
+        );
+    }
+
+    /// `quote` before it wrote quoted words in one allocation; the output
+    /// must not have changed.
+    fn quote_before(s: &str) -> Cow<'_, str> {
+        if s.is_empty() {
+            return Cow::Borrowed("''");
+        }
+        if s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"@%_+=:,./-".contains(&b))
+        {
+            return Cow::Borrowed(s);
+        }
+        let escaped = s.replace('\'', "'\\''");
+        Cow::Owned(format!("'{}'", escaped))
+    }
+
+    /// xorshift64*: reproducible strings without a dependency.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        /// Up to 12 pieces: safe and unsafe ASCII, quotes, whitespace,
+        /// shell syntax, non-ASCII and invalid UTF-8 converted lossily.
+        fn string(&mut self) -> String {
+            const PIECES: &[&str] = &[
+                "a", "Z", "0", "_", "-", ".", "/", "@", "%", "+", "=", ":", ",", "'", "''", "\"",
+                "\\", " ", "\t", "\n", "\r", "$", "${1}", "`", "!", "*", "?", "[", "]", "{", "}",
+                "(", ")", "<", ">", "|", "&", ";", "#", "~", "é", "ß", "日本", "🦀", "\u{0}",
+                "\u{7f}", "\u{a0}", "\u{200b}",
+            ];
+            let mut s = String::new();
+            for _ in 0..self.below(13) {
+                if self.below(8) == 0 {
+                    let bytes = (0..1 + self.below(4))
+                        .map(|_| self.next() as u8)
+                        .collect::<Vec<_>>();
+                    s.push_str(&String::from_utf8_lossy(&bytes));
+                } else {
+                    s.push_str(PIECES[self.below(PIECES.len())]);
+                }
+            }
+            s
+        }
+    }
+
+    #[test]
+    fn quote_matches_the_previous_implementation() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let mut kinds = [0; 3];
+        for _ in 0..200_000 {
+            let s = rng.string();
+            let (new, old) = (quote(&s), quote_before(&s));
+            assert_eq!(new, old, "{:?}", s);
+            assert_eq!(
+                matches!(new, Cow::Borrowed(_)),
+                matches!(old, Cow::Borrowed(_))
+            );
+            kinds[match (s.is_empty(), new.starts_with('\'')) {
+                (true, _) => 0,
+                (false, false) => 1,
+                (false, true) => 2,
+            }] += 1;
+        }
+        // Empty, unquoted and quoted strings were all generated.
+        assert!(kinds.iter().all(|&n| n > 1000), "{:?}", kinds);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn quote_os_matches_the_previous_implementation() {
+        use std::os::unix::ffi::OsStrExt;
+        let mut rng = Rng(42);
+        for _ in 0..20_000 {
+            let bytes = (0..rng.below(16))
+                .map(|_| rng.next() as u8)
+                .collect::<Vec<_>>();
+            let s = OsStr::from_bytes(&bytes);
+            assert_eq!(
+                quote_os(s),
+                quote_before(&s.to_string_lossy()),
+                "{:?}",
+                bytes
+            );
+        }
+    }
+
+    /// bash reads each quoted word back as the string itself.
+    #[cfg(unix)]
+    #[test]
+    fn bash_reads_quoted_words_back() {
+        let mut rng = Rng(7);
+        let words = (0..500)
+            .map(|_| rng.string().replace('\0', ""))
+            .collect::<Vec<_>>();
+        let mut script = String::from("printf '%s\\0'");
+        for word in &words {
+            script.push(' ');
+            script.push_str(&quote(word));
+        }
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(&script)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        let read = String::from_utf8(output.stdout).unwrap();
+        let read = read.split_terminator('\0').collect::<Vec<_>>();
+        assert_eq!(read, words);
+    }
+
+    #[test]
+    fn windows_paths_use_forward_slashes() {
+        for (path, written) in [
+            (r"\\?\C:\data\a b.fq", "C:/data/a b.fq"),
+            (r"C:\data\a.fq", "C:/data/a.fq"),
+            (r"\\?\UNC\server\share\a.fq", "//server/share/a.fq"),
+            (r"\\server\share\a.fq", "//server/share/a.fq"),
+            ("C:/data/a.fq", "C:/data/a.fq"),
+            ("a.fq", "a.fq"),
+        ] {
+            assert_eq!(forward_slashes(path), written, "{:?}", path);
+        }
+        assert!(matches!(forward_slashes("C:/a"), Cow::Borrowed(_)));
+        assert!(matches!(forward_slashes(r"\\?\C:"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn script_paths_are_left_alone_off_windows() {
+        let path = r"/data/a\b.fq";
+        let written = if cfg!(windows) { "/data/a/b.fq" } else { path };
+        assert_eq!(script_path(path), written);
+        assert_eq!(quote_path(Path::new(path)), quote(written).into_owned());
+    }
+}
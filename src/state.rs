@@ -0,0 +1,579 @@
+//! The durable record of a run: `runs/<run_id>/state.json`. Written when
+//! submission starts, rewritten as each job is submitted (and resubmitted),
+//! and read by every post-submission subcommand. Also printed by
+//! `status --format json` and `--report-format json`.
+//!
+//! Schema (version 1):
+//!
+//! ```text
+//! {
+//!   "schema_version": 1,
+//!   "run_id": "20240131-142501-3fa2",
+//!   "timestamp": "2024-01-31T14:25:01+01:00",   // null if unknown
+//!   "args": ["batchelor", "--script", ...],       // command line of the run
+//!   "profile": "hawk",                             // --profile, null without
+//!   "submit": "sbatch",
+//!   "scheduler": "slurm",                          // slurm|pbs|sge|lsf|generic
+//!   "script_format": 2,                            // see `script_format`; 1 if missing
+//!   "jobs": [{
+//!     "batch_index": 1,
+//!     "job_name": "batch-0001",
+//!     "job_id": "123",                             // null until submitted, or untracked
+//!     "previous_job_ids": ["118"],                 // earlier submissions (resubmit)
+//!     "submit_stdout": "Submitted batch job 123\n",// null until submitted
+//!     "state": "COMPLETED",                        // null until known
+//!     "exit_code": "0:0",                          // null until known
+//!     "started": "2024-01-31T14:25:07",            // from sacct, null until known
+//!     "ended": "2024-01-31T14:31:40",
+//!     "elapsed_secs": 393,
+//!     "submit_error": null,                        // why submission failed
+//!     "script": ".batchelor/batch-0001.batch.sh",  // null with --wrap
+//!     "log": "/abs/logs/batch-0001.%j.out",
+//!     "done_file": "/abs/.batchelor/batch-0001.done",     // input markers,
+//!     "failed_file": "/abs/.batchelor/batch-0001.failed", // null with --wrap
+//!     "input_bytes": 2048,
+//!     "inputs": [{ "path": "/abs/a.txt", "status": "done" }]  // status: null|done|failed
+//!   }],
+//!   "unchanged": [{ "path": "/abs/b.txt", "status": "done" }]  // --incremental only
+//! }
+//! ```
+//!
+//! With `--incremental`, inputs also record a `"fingerprint"` (`{"size":
+//! 2048, "modified_ns": ..., "crc32": "..."}`, see the `incremental`
+//! module), and `unchanged` lists the inputs of earlier runs this run did
+//! not schedule again.
+//!
+//! Fields are only ever added (older files read with the new fields
+//! empty); anything else bumps `schema_version`. Paths that are not valid
+//! UTF-8 are written as `{"path": "<lossy>", "raw_bytes": "<base64>"}`
+//! instead of a string (see the `serde_path` module).
+
+use crate::perms::{Kind, Permissions};
+use crate::runs::{self, RunRecord, RUNS_DIR};
+use crate::scheduler::{is_final_state, JobStatus, Scheduler};
+use crate::script_format;
+use crate::units;
+use crate::wait::WaitSummary;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Name of the state file inside a run's directory.
+pub const STATE_FILE: &str = "state.json";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunState {
+    pub schema_version: u32,
+    pub run_id: String,
+    pub timestamp: Option<String>,
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub profile: Option<String>,
+    pub submit: String,
+    pub scheduler: Scheduler,
+    /// Format of the run's scripts (see [`crate::script_format`]); runs
+    /// recorded before it was stamped have format 1.
+    #[serde(default = "unstamped_script_format")]
+    pub script_format: u32,
+    pub jobs: Vec<JobState>,
+    /// With `--incremental`: the inputs earlier runs handled that this run
+    /// left out, as those runs recorded them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unchanged: Vec<InputState>,
+}
+
+fn unstamped_script_format() -> u32 {
+    script_format::UNSTAMPED
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobState {
+    pub batch_index: usize,
+    pub job_name: String,
+    pub job_id: Option<String>,
+    #[serde(default)]
+    pub previous_job_ids: Vec<String>,
+    #[serde(default)]
+    pub submit_stdout: Option<String>,
+    pub state: Option<String>,
+    pub exit_code: Option<String>,
+    #[serde(default)]
+    pub started: Option<String>,
+    #[serde(default)]
+    pub ended: Option<String>,
+    #[serde(default)]
+    pub elapsed_secs: Option<u64>,
+    pub submit_error: Option<String>,
+    #[serde(default, with = "crate::serde_path::option")]
+    pub script: Option<PathBuf>,
+    /// Where the job's stdout goes, possibly with scheduler patterns such as
+    /// `%j` left in (see [`JobState::log_path`]).
+    #[serde(default, with = "crate::serde_path::option")]
+    pub log: Option<PathBuf>,
+    #[serde(default, with = "crate::serde_path::option")]
+    pub done_file: Option<PathBuf>,
+    #[serde(default, with = "crate::serde_path::option")]
+    pub failed_file: Option<PathBuf>,
+    pub input_bytes: u64,
+    pub inputs: Vec<InputState>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputState {
+    pub path: String,
+    pub status: Option<InputStatus>,
+    /// Recorded with `--incremental`, to tell whether the input changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<Fingerprint>,
+}
+
+/// What an input was when it was scheduled (see [`crate::incremental`]).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub size: u64,
+    /// Modification time in nanoseconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_ns: Option<u64>,
+    /// CRC-32 of the contents, in hex; with `--incremental-key path+hash`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crc32: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputStatus {
+    Done,
+    Failed,
+}
+
+impl JobState {
+    /// Whether the job is known to have ended unsuccessfully.
+    pub fn failed(&self) -> bool {
+        self.state.as_deref().is_some_and(|state| {
+            is_final_state(state) && !crate::wait::completed_ok(state, self.exit_code.as_deref())
+        })
+    }
+
+    /// Whether the submit command accepted the job (with or without a
+    /// readable job ID).
+    pub fn submitted(&self) -> bool {
+        self.job_id.is_some() || self.submit_stdout.is_some()
+    }
+
+    /// Records a (re)submission, forgetting what was known about an earlier
+    /// job for this batch.
+    pub fn record_submission(&mut self, job_id: Option<String>, stdout: String) {
+        if let Some(old) = self.job_id.take() {
+            self.previous_job_ids.push(old);
+        }
+        self.job_id = job_id;
+        self.submit_stdout = Some(stdout);
+        self.submit_error = None;
+        self.set_outcome(None, None);
+        self.started = None;
+        self.ended = None;
+        self.elapsed_secs = None;
+    }
+
+    /// Records what the scheduler reports for the job. A job already
+    /// recorded as finished keeps its outcome unless the new report is
+    /// final too (the scheduler may since have lost it), and fields the
+    /// report lacks keep their recorded values.
+    pub fn record_status(&mut self, status: &JobStatus) {
+        let recorded_final = self.state.as_deref().is_some_and(is_final_state);
+        if recorded_final && !is_final_state(&status.state) {
+            return;
+        }
+        let exit_code = status.exit_code.clone().or_else(|| self.exit_code.clone());
+        self.set_outcome(Some(status.state.clone()), exit_code);
+        if let Some(start) = &status.start {
+            self.started = Some(start.clone());
+        }
+        if let Some(end) = &status.end {
+            self.ended = Some(end.clone());
+        }
+        if let Some(secs) = status
+            .elapsed
+            .as_deref()
+            .and_then(|e| units::parse_duration(e).ok())
+        {
+            self.elapsed_secs = Some(secs);
+        }
+    }
+
+    /// Sets the job's scheduler state and exit code; final states also
+    /// settle the status of its inputs.
+    pub fn set_outcome(&mut self, state: Option<String>, exit_code: Option<String>) {
+        let outcome = state.as_deref().filter(|s| is_final_state(s)).map(|s| {
+            if crate::wait::completed_ok(s, exit_code.as_deref()) {
+                InputStatus::Done
+            } else {
+                InputStatus::Failed
+            }
+        });
+        self.state = state;
+        self.exit_code = exit_code;
+        for input in &mut self.inputs {
+            input.status = outcome;
+        }
+    }
+
+    /// The job's stdout log with `%j` (job ID), `%x` (job name) and `%%`
+    /// expanded.
+    pub fn log_path(&self) -> Option<PathBuf> {
+        let template = self.log.as_ref()?.to_string_lossy().into_owned();
+        let mut path = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                path.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('j') => path.push_str(self.job_id.as_deref()?),
+                Some('x') => path.push_str(&self.job_name),
+                Some('%') => path.push('%'),
+                Some(other) => {
+                    path.push('%');
+                    path.push(other);
+                }
+                None => path.push('%'),
+            }
+        }
+        Some(PathBuf::from(path))
+    }
+}
+
+impl RunState {
+    pub fn to_json(&self) -> String {
+        // Plain strings, numbers and enums always serialize.
+        serde_json::to_string_pretty(self).unwrap_or_default() + "\n"
+    }
+
+    pub fn from_json(text: &str) -> Result<RunState, Box<dyn std::error::Error>> {
+        let state: RunState = serde_json::from_str(text)?;
+        if state.schema_version > SCHEMA_VERSION {
+            return Err(format!(
+                "run state has schema version {}, this batchelor reads up to {}",
+                state.schema_version, SCHEMA_VERSION
+            )
+            .into());
+        }
+        Ok(state)
+    }
+
+    /// Job IDs of the jobs that were submitted with a readable ID.
+    pub fn job_ids(&self) -> Vec<&str> {
+        self.jobs
+            .iter()
+            .filter_map(|j| j.job_id.as_deref())
+            .collect()
+    }
+
+    /// Fills in the scheduler's current view of each job.
+    pub fn refresh(&mut self, statuses: &HashMap<String, JobStatus>) {
+        for job in &mut self.jobs {
+            let Some(status) = job.job_id.as_ref().and_then(|id| statuses.get(id)) else {
+                continue;
+            };
+            job.record_status(status);
+        }
+    }
+
+    /// Records how the jobs ended according to `--wait`.
+    pub fn apply_wait(&mut self, summary: &WaitSummary) {
+        for job in &mut self.jobs {
+            if let Some(status) = job.job_id.as_ref().and_then(|id| summary.jobs.get(id)) {
+                job.record_status(status);
+            }
+        }
+    }
+
+    pub fn path(out_dir: &Path, run_id: &str) -> PathBuf {
+        runs::run_dir(out_dir, run_id).join(STATE_FILE)
+    }
+
+    /// Writes the state to `<out_dir>/runs/<run_id>/state.json`. The file
+    /// is replaced by renaming a complete temporary copy over it, so a
+    /// crash never leaves a truncated state behind. A replaced file keeps
+    /// its permissions and group.
+    pub fn save(&self, out_dir: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        self.save_with(out_dir, &Permissions::default())
+    }
+
+    /// [`RunState::save`] giving the file and the run's directories the
+    /// artifact permissions of `perms`, when it asks for any.
+    pub(crate) fn save_with(
+        &self,
+        out_dir: &Path,
+        perms: &Permissions,
+    ) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let path = RunState::path(out_dir, &self.run_id);
+        let kept;
+        let perms = if perms.is_set() {
+            perms
+        } else {
+            kept = Permissions::like(&path);
+            &kept
+        };
+        let write = || -> std::io::Result<()> {
+            perms.create_dir_all(&runs::run_dir(out_dir, &self.run_id))?;
+            let tmp = path.with_extension("json.tmp");
+            let _ = fs::remove_file(&tmp);
+            perms.write(&tmp, self.to_json().as_bytes(), Kind::Artifact)?;
+            fs::rename(&tmp, &path)
+        };
+        write().map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+        Ok(path)
+    }
+
+    /// Loads the state of `run_id`. Runs recorded before state files existed
+    /// are read from their job manifest instead.
+    pub fn load(out_dir: &Path, run_id: &str) -> Result<RunState, Box<dyn std::error::Error>> {
+        let path = RunState::path(out_dir, run_id);
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                RunState::from_json(&text).map_err(|e| format!("{}: {}", path.display(), e).into())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let record = RunRecord::load(out_dir, run_id)?;
+                Ok(RunState::from_record(&record, out_dir)?)
+            }
+            Err(e) => Err(format!("could not read {}: {}", path.display(), e).into()),
+        }
+    }
+
+    /// Loads the most recent run under `out_dir`.
+    pub fn load_latest(out_dir: &Path) -> Result<RunState, Box<dyn std::error::Error>> {
+        let run_id = runs::latest_run_id(out_dir)?.ok_or_else(|| {
+            format!(
+                "no run state found under {}; pass --run or --job-ids",
+                out_dir.join(RUNS_DIR).display()
+            )
+        })?;
+        RunState::load(out_dir, &run_id)
+    }
+
+    /// Loads `run` if given, otherwise the most recent run under `out_dir`.
+    pub fn load_run(
+        out_dir: &Path,
+        run: Option<&str>,
+    ) -> Result<RunState, Box<dyn std::error::Error>> {
+        match run {
+            Some(run_id) => RunState::load(out_dir, run_id),
+            None => RunState::load_latest(out_dir),
+        }
+    }
+
+    /// The state of a run recorded before state files existed, as far as
+    /// its `jobs.tsv` and `failures.tsv` tell.
+    fn from_record(
+        record: &RunRecord,
+        out_dir: &Path,
+    ) -> Result<RunState, Box<dyn std::error::Error>> {
+        let mut jobs: Vec<JobState> = Vec::new();
+        for job in &record.jobs {
+            let previous = match jobs.iter().position(|j| j.batch_index == job.batch_index) {
+                Some(idx) => {
+                    let mut earlier = jobs.remove(idx);
+                    earlier.previous_job_ids.extend(earlier.job_id.take());
+                    earlier.previous_job_ids
+                }
+                None => Vec::new(),
+            };
+            jobs.push(JobState {
+                batch_index: job.batch_index,
+                job_name: job.job_name.clone(),
+                job_id: job.job_id.clone(),
+                previous_job_ids: previous,
+                script: job.script.clone(),
+                log: job.log.clone(),
+                ..JobState::default()
+            });
+        }
+        for failure in record.submit_failures(out_dir)? {
+            if jobs.iter().any(|j| j.batch_index == failure.batch_index) {
+                continue;
+            }
+            jobs.push(JobState {
+                batch_index: failure.batch_index,
+                job_name: failure.job_name,
+                submit_error: Some(failure.error),
+                script: failure.script,
+                ..JobState::default()
+            });
+        }
+        jobs.sort_by_key(|j| j.batch_index);
+        Ok(RunState {
+            schema_version: SCHEMA_VERSION,
+            run_id: record.run_id.clone(),
+            timestamp: None,
+            args: Vec::new(),
+            profile: None,
+            submit: record.submit.clone(),
+            scheduler: record.scheduler,
+            script_format: script_format::UNSTAMPED,
+            jobs,
+            unchanged: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> RunState {
+        let input = |path: &str, status| InputState {
+            path: path.to_string(),
+            status,
+            fingerprint: None,
+        };
+        RunState {
+            schema_version: SCHEMA_VERSION,
+            run_id: "20240131-142501-3fa2".to_string(),
+            timestamp: Some("2024-01-31T14:25:01+01:00".to_string()),
+            args: vec![
+                "batchelor".to_string(),
+                "--script".to_string(),
+                "a.sh".to_string(),
+            ],
+            profile: Some("hawk".to_string()),
+            submit: "sbatch -p short".to_string(),
+            scheduler: Scheduler::Slurm,
+            script_format: script_format::CURRENT,
+            jobs: vec![
+                JobState {
+                    batch_index: 1,
+                    job_name: "batch-0001".to_string(),
+                    job_id: Some("123".to_string()),
+                    previous_job_ids: vec!["118".to_string()],
+                    submit_stdout: Some("123\n".to_string()),
+                    state: Some("COMPLETED".to_string()),
+                    exit_code: Some("0:0".to_string()),
+                    started: Some("2024-01-31T14:25:07".to_string()),
+                    ended: Some("2024-01-31T14:31:40".to_string()),
+                    elapsed_secs: Some(393),
+                    submit_error: None,
+                    script: Some(PathBuf::from("/abs/.batchelor/batch-0001.batch.sh")),
+                    log: Some(PathBuf::from("/abs/logs/batch-0001.%j.out")),
+                    done_file: Some(PathBuf::from("/abs/.batchelor/batch-0001.done")),
+                    failed_file: Some(PathBuf::from("/abs/.batchelor/batch-0001.failed")),
+                    input_bytes: 2048,
+                    inputs: vec![
+                        input("/abs/a.txt", Some(InputStatus::Done)),
+                        InputState {
+                            fingerprint: Some(Fingerprint {
+                                size: 1024,
+                                modified_ns: Some(1_706_707_501_000_000_000),
+                                crc32: Some("cbf43926".to_string()),
+                            }),
+                            ..input("/abs/b\ttab.txt", Some(InputStatus::Failed))
+                        },
+                    ],
+                },
+                JobState {
+                    batch_index: 2,
+                    job_name: "batch-0002".to_string(),
+                    submit_error: Some("sbatch failed: Invalid account".to_string()),
+                    inputs: vec![input("/abs/c.txt", None)],
+                    ..JobState::default()
+                },
+            ],
+            unchanged: vec![input("/abs/d.txt", Some(InputStatus::Done))],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let state = sample();
+        let json = state.to_json();
+        assert_eq!(RunState::from_json(&json).unwrap(), state);
+        // Stable field names are part of the schema.
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["schema_version"], SCHEMA_VERSION);
+        assert_eq!(value["scheduler"], "slurm");
+        assert_eq!(value["jobs"][0]["inputs"][1]["status"], "failed");
+        assert_eq!(
+            value["jobs"][0]["inputs"][1]["fingerprint"]["crc32"],
+            "cbf43926"
+        );
+        assert!(value["jobs"][0]["inputs"][0].get("fingerprint").is_none());
+    }
+
+    #[test]
+    fn round_trip_through_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = sample();
+        let path = state.save(dir.path()).unwrap();
+        assert_eq!(path, RunState::path(dir.path(), &state.run_id));
+        assert_eq!(RunState::load(dir.path(), &state.run_id).unwrap(), state);
+        assert!(!path.with_extension("json.tmp").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn round_trip_of_a_non_utf8_script_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let mut state = sample();
+        let script = PathBuf::from(OsStr::from_bytes(b"/abs/\xffbatch-0001.batch.sh"));
+        state.jobs[0].script = Some(script.clone());
+        let json = state.to_json();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            value["jobs"][0]["script"]["path"],
+            "/abs/\u{fffd}batch-0001.batch.sh"
+        );
+        assert_eq!(
+            RunState::from_json(&json).unwrap().jobs[0].script,
+            Some(script)
+        );
+    }
+
+    #[test]
+    fn future_versions_are_rejected() {
+        let mut state = sample();
+        state.schema_version = SCHEMA_VERSION + 1;
+        let e = RunState::from_json(&state.to_json()).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "run state has schema version {}, this batchelor reads up to {}",
+                SCHEMA_VERSION + 1,
+                SCHEMA_VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn fields_added_since_version_1_may_be_missing() {
+        let json = r#"{
+            "schema_version": 1,
+            "run_id": "20240131-142501-3fa2",
+            "timestamp": null,
+            "args": [],
+            "submit": "sbatch",
+            "scheduler": "slurm",
+            "jobs": [{
+                "batch_index": 1,
+                "job_name": "batch-0001",
+                "job_id": "123",
+                "state": null,
+                "exit_code": null,
+                "submit_error": null,
+                "input_bytes": 0,
+                "inputs": [{ "path": "/abs/a.txt", "status": null }]
+            }]
+        }"#;
+        let state = RunState::from_json(json).unwrap();
+        assert_eq!(state.script_format, script_format::UNSTAMPED);
+        assert_eq!(state.profile, None);
+        assert!(state.unchanged.is_empty());
+        assert_eq!(state.jobs[0].script, None);
+        assert!(state.jobs[0].previous_job_ids.is_empty());
+    }
+}
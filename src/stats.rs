@@ -0,0 +1,468 @@
+use crate::scheduler::Scheduler;
+use crate::state::RunState;
+use crate::units;
+use clap::{Parser, ValueEnum, ValueHint};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Fields asked of sacct, in the order `parse_usage` reads them.
+const SACCT_FORMAT: &str =
+    "JobID,Elapsed,MaxRSS,MaxVMSize,TotalCPU,State,ReqMem,Timelimit,AllocCPUS";
+
+/// Peak usage below this share of the request is worth a suggestion.
+const UNDERUSE: f64 = 0.5;
+/// Headroom added on top of the observed peak when suggesting a request.
+const HEADROOM: f64 = 1.25;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "batchelor stats",
+    about = "Summarize the resource usage of the finished jobs of a previous run"
+)]
+pub struct StatsCli {
+    /// Output directory the run was submitted from.
+    #[arg(long, default_value = ".batchelor", value_hint = ValueHint::DirPath)]
+    out_dir: PathBuf,
+
+    /// Run ID to summarize (default: the most recent run).
+    #[arg(long)]
+    run: Option<String>,
+
+    /// Output format; tsv and json list every job.
+    #[arg(long, value_enum, default_value = "table")]
+    format: StatsFormat,
+
+    /// Also record each job's final state, start/end times, elapsed time
+    /// and exit code in the run's state file.
+    #[arg(long)]
+    update: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum StatsFormat {
+    Table,
+    Tsv,
+    Json,
+}
+
+/// Resource usage of one job, with its steps folded in. Sizes are bytes,
+/// times seconds.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct JobUsage {
+    pub job_id: String,
+    pub state: String,
+    pub elapsed: Option<u64>,
+    pub max_rss: Option<u64>,
+    pub max_vmsize: Option<u64>,
+    pub total_cpu: Option<u64>,
+    /// Requested memory per node.
+    pub req_mem: Option<u64>,
+    pub timelimit: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Spread {
+    pub min: u64,
+    pub median: u64,
+    pub max: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct Summary<'a> {
+    run_id: &'a str,
+    jobs: usize,
+    elapsed: Option<Spread>,
+    max_rss: Option<Spread>,
+    max_vmsize: Option<Spread>,
+    total_cpu: u64,
+    suggestions: Vec<String>,
+    usage: &'a [JobUsage],
+}
+
+pub fn stats(cli: StatsCli) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = RunState::load_run(&cli.out_dir, cli.run.as_deref())?;
+    if state.scheduler != Scheduler::Slurm {
+        return Err(format!(
+            "stats needs sacct (SLURM); run {} was submitted with {:?}",
+            state.run_id, state.submit
+        )
+        .into());
+    }
+    let ids = state
+        .job_ids()
+        .into_iter()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if ids.is_empty() {
+        return Err(format!("run {} has no tracked jobs", state.run_id).into());
+    }
+
+    let output = Command::new("sacct")
+        .args(["-n", "--parsable2", "--format", SACCT_FORMAT, "-j"])
+        .arg(ids.join(","))
+        .output()
+        .map_err(|e| format!("could not run sacct: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "sacct failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    let usage = parse_usage(&String::from_utf8_lossy(&output.stdout));
+    if cli.update {
+        let ids = ids.iter().map(String::as_str).collect::<Vec<_>>();
+        let statuses = state
+            .scheduler
+            .job_statuses(&ids)
+            .ok_or("could not run sacct or squeue")?;
+        state.refresh(&statuses);
+        let path = state.save(&cli.out_dir)?;
+        eprintln!("Job outcomes recorded in {}", path.display());
+    }
+    let names = state
+        .jobs
+        .iter()
+        .filter_map(|j| Some((j.job_id.as_deref()?, j.job_name.as_str())))
+        .collect::<HashMap<_, _>>();
+
+    let spread = |field: fn(&JobUsage) -> Option<u64>| spread(usage.iter().filter_map(field));
+    let summary = Summary {
+        run_id: &state.run_id,
+        jobs: usage.len(),
+        elapsed: spread(|u| u.elapsed),
+        max_rss: spread(|u| u.max_rss),
+        max_vmsize: spread(|u| u.max_vmsize),
+        total_cpu: usage.iter().filter_map(|u| u.total_cpu).sum(),
+        suggestions: suggestions(&usage),
+        usage: &usage,
+    };
+
+    match cli.format {
+        StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&summary)?),
+        StatsFormat::Tsv => {
+            println!("job_name\tjob_id\tstate\telapsed\tmax_rss\tmax_vmsize\ttotal_cpu\treq_mem\ttimelimit");
+            let field = |v: Option<u64>| v.map_or_else(|| "-".to_string(), |v| v.to_string());
+            for u in &usage {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    names.get(u.job_id.as_str()).unwrap_or(&"-"),
+                    u.job_id,
+                    u.state,
+                    field(u.elapsed),
+                    field(u.max_rss),
+                    field(u.max_vmsize),
+                    field(u.total_cpu),
+                    field(u.req_mem),
+                    field(u.timelimit)
+                );
+            }
+        }
+        StatsFormat::Table => print_table(&summary, ids.len()),
+    }
+    Ok(())
+}
+
+fn print_table(summary: &Summary, tracked: usize) {
+    println!(
+        "Run {}: usage of {} of {} job(s)",
+        summary.run_id, summary.jobs, tracked
+    );
+    println!("{:<10} {:>12} {:>12} {:>12}", "", "min", "median", "max");
+    print_spread("elapsed", summary.elapsed, units::format_clock);
+    print_spread("MaxRSS", summary.max_rss, units::format_size);
+    print_spread("MaxVMSize", summary.max_vmsize, units::format_size);
+    println!(
+        "Total CPU time: {}",
+        units::format_duration(summary.total_cpu)
+    );
+    for suggestion in &summary.suggestions {
+        println!("suggestion: {}", suggestion);
+    }
+}
+
+fn print_spread(name: &str, spread: Option<Spread>, format: fn(u64) -> String) {
+    let [min, median, max] = match spread {
+        Some(s) => [s.min, s.median, s.max].map(format),
+        None => ["-", "-", "-"].map(String::from),
+    };
+    println!("{:<10} {:>12} {:>12} {:>12}", name, min, median, max);
+}
+
+/// Parses `sacct --parsable2` rows in [`SACCT_FORMAT`] order. Step rows
+/// (`123.batch`, `123.extern`, `123.0`) are folded into their job: sacct
+/// only reports MaxRSS/MaxVMSize on steps, while the job row carries the
+/// state, limits and summed CPU time.
+pub fn parse_usage(stdout: &str) -> Vec<JobUsage> {
+    let mut jobs: Vec<JobUsage> = Vec::new();
+    let mut step_cpu = HashMap::new();
+    for line in stdout.lines() {
+        let fields = line.split('|').map(str::trim).collect::<Vec<_>>();
+        if fields.len() < 6 || fields[0].is_empty() || fields[0] == "JobID" {
+            continue;
+        }
+        let (job_id, step) = match fields[0].split_once('.') {
+            Some((job, step)) => (job, Some(step)),
+            None => (fields[0], None),
+        };
+        let idx = match jobs.iter().position(|j| j.job_id == job_id) {
+            Some(idx) => idx,
+            None => {
+                jobs.push(JobUsage {
+                    job_id: job_id.to_string(),
+                    ..JobUsage::default()
+                });
+                jobs.len() - 1
+            }
+        };
+        let job = &mut jobs[idx];
+        job.max_rss = max_opt(job.max_rss, parse_mem(fields[2]));
+        job.max_vmsize = max_opt(job.max_vmsize, parse_mem(fields[3]));
+        if step.is_some() {
+            if let Some(cpu) = parse_time(fields[4]) {
+                *step_cpu.entry(job_id.to_string()).or_insert(0) += cpu;
+            }
+            continue;
+        }
+        job.elapsed = parse_time(fields[1]);
+        job.total_cpu = parse_time(fields[4]);
+        // "CANCELLED by 1234" -> "CANCELLED"
+        job.state = fields[5]
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .to_string();
+        let cpus = fields
+            .get(8)
+            .and_then(|c| c.parse::<u64>().ok())
+            .unwrap_or(1);
+        job.req_mem = fields.get(6).and_then(|m| parse_req_mem(m, cpus));
+        job.timelimit = fields.get(7).and_then(|t| parse_time(t));
+    }
+    // Some SLURM versions leave TotalCPU empty on the job row.
+    for job in &mut jobs {
+        if job.total_cpu.is_none() {
+            job.total_cpu = step_cpu.get(&job.job_id).copied();
+        }
+    }
+    jobs
+}
+
+fn max_opt(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn parse_mem(field: &str) -> Option<u64> {
+    if field.is_empty() {
+        return None;
+    }
+    units::parse_size(field).ok()
+}
+
+/// ReqMem is per node (`50G`, older SLURM: `50Gn`) or per CPU (`4Gc`).
+fn parse_req_mem(field: &str, cpus: u64) -> Option<u64> {
+    if let Some(per_cpu) = field.strip_suffix('c') {
+        return parse_mem(per_cpu).map(|m| m * cpus);
+    }
+    parse_mem(field.strip_suffix('n').unwrap_or(field))
+}
+
+/// sacct times: `D-HH:MM:SS`, `HH:MM:SS` or `MM:SS.mmm` (TotalCPU).
+fn parse_time(field: &str) -> Option<u64> {
+    let whole = field.split('.').next()?;
+    if whole.is_empty() || !whole.contains(':') {
+        return None;
+    }
+    units::parse_duration(whole).ok()
+}
+
+fn spread(values: impl Iterator<Item = u64>) -> Option<Spread> {
+    let mut values = values.collect::<Vec<_>>();
+    values.sort_unstable();
+    Some(Spread {
+        min: *values.first()?,
+        median: values[values.len() / 2],
+        max: *values.last()?,
+    })
+}
+
+/// Request changes worth considering given what the jobs actually used.
+fn suggestions(usage: &[JobUsage]) -> Vec<String> {
+    let mut out = Vec::new();
+    let completed = usage
+        .iter()
+        .filter(|u| u.state == "COMPLETED")
+        .collect::<Vec<_>>();
+
+    let oom = usage.iter().filter(|u| u.state == "OUT_OF_MEMORY").count();
+    let peak_rss = usage.iter().filter_map(|u| u.max_rss).max();
+    let req_mem = usage.iter().filter_map(|u| u.req_mem).max();
+    if oom > 0 {
+        out.push(format!(
+            "{} job(s) ran out of memory{} — raise --mem",
+            oom,
+            req_mem.map_or(String::new(), |r| format!(" at {}", units::format_size(r)))
+        ));
+    } else if let (Some(peak), Some(req)) = (peak_rss, req_mem) {
+        if (peak as f64) < req as f64 * UNDERUSE && !completed.is_empty() {
+            let (amount, unit) = units::round_up_mem((peak as f64 * HEADROOM) as u64);
+            out.push(format!(
+                "peak MaxRSS {} vs requested {} — consider --mem={}{}",
+                units::format_size(peak),
+                units::format_size(req),
+                amount,
+                unit
+            ));
+        }
+    }
+
+    let timeouts = usage.iter().filter(|u| u.state == "TIMEOUT").count();
+    let max_elapsed = completed.iter().filter_map(|u| u.elapsed).max();
+    let timelimit = usage.iter().filter_map(|u| u.timelimit).max();
+    if timeouts > 0 {
+        out.push(format!(
+            "{} job(s) hit the time limit{} — raise --time",
+            timeouts,
+            timelimit.map_or(String::new(), |t| format!(" of {}", units::format_clock(t)))
+        ));
+    } else if let (Some(elapsed), Some(limit)) = (max_elapsed, timelimit) {
+        if (elapsed as f64) < limit as f64 * UNDERUSE {
+            let suggested = ((elapsed as f64 * HEADROOM) as u64).div_ceil(60).max(1) * 60;
+            out.push(format!(
+                "longest job took {} vs time limit {} — consider --time={}",
+                units::format_clock(elapsed),
+                units::format_clock(limit),
+                units::format_clock(suggested)
+            ));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const G: u64 = 1024 * 1024 * 1024;
+
+    /// `sacct -n --parsable2 --format` [`SACCT_FORMAT`] of a completed job
+    /// with its steps, a job killed at its time limit with per-CPU memory,
+    /// and a job still pending.
+    const SACCT: &str = "\
+4815162|00:12:31|||11:58.412|COMPLETED|50G|01:00:00|4
+4815162.batch|00:12:31|18874368K|19922944K|11:58.410|COMPLETED|||4
+4815162.extern|00:12:31|1024K|4096K|00:00.002|COMPLETED|||4
+4815163|1-02:00:05|||1-20:00:00|TIMEOUT|4Gc|1-02:00:00|8
+4815163.batch|1-02:00:07|2097152K|3145728K|1-19:59:58|CANCELLED|||8
+4815163.extern|1-02:00:05|0|4096K|00:00:00|COMPLETED|||8
+4815164|00:00:00|||00:00:00|PENDING|50Gn|01:00:00|1
+";
+
+    #[test]
+    fn usage_folds_steps_into_jobs() {
+        assert_eq!(
+            parse_usage(SACCT),
+            [
+                JobUsage {
+                    job_id: "4815162".to_string(),
+                    state: "COMPLETED".to_string(),
+                    elapsed: Some(751),
+                    max_rss: Some(18 * G),
+                    max_vmsize: Some(19 * G),
+                    total_cpu: Some(718),
+                    req_mem: Some(50 * G),
+                    timelimit: Some(3600),
+                },
+                JobUsage {
+                    job_id: "4815163".to_string(),
+                    state: "TIMEOUT".to_string(),
+                    elapsed: Some(93_605),
+                    max_rss: Some(2 * G),
+                    max_vmsize: Some(3 * G),
+                    total_cpu: Some(158_400),
+                    req_mem: Some(32 * G),
+                    timelimit: Some(93_600),
+                },
+                JobUsage {
+                    job_id: "4815164".to_string(),
+                    state: "PENDING".to_string(),
+                    elapsed: Some(0),
+                    max_rss: None,
+                    max_vmsize: None,
+                    total_cpu: Some(0),
+                    req_mem: Some(50 * G),
+                    timelimit: Some(3600),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn usage_with_header_and_cpu_time_on_steps_only() {
+        let stdout = "\
+JobID|Elapsed|MaxRSS|MaxVMSize|TotalCPU|State|ReqMem|Timelimit|AllocCPUS
+4815165|00:10:00||||COMPLETED|1G|00:20:00|1
+4815165.batch|00:10:00|512M|1G|00:03.500|COMPLETED|||1
+4815165.0|00:09:58|600M|1G|05:00|COMPLETED|||1
+
+";
+        let usage = parse_usage(stdout);
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].total_cpu, Some(303));
+        assert_eq!(usage[0].max_rss, Some(600 * 1024 * 1024));
+        assert_eq!(usage[0].elapsed, Some(600));
+        assert_eq!(parse_usage(""), []);
+    }
+
+    #[test]
+    fn spreads_and_suggestions() {
+        let usage = parse_usage(SACCT);
+        assert_eq!(
+            spread(usage.iter().filter_map(|u| u.max_rss)),
+            Some(Spread {
+                min: 2 * G,
+                median: 18 * G,
+                max: 18 * G
+            })
+        );
+        assert_eq!(spread(std::iter::empty()), None);
+        assert_eq!(
+            suggestions(&usage),
+            [
+                "peak MaxRSS 18.0G vs requested 50.0G — consider --mem=23G",
+                "1 job(s) hit the time limit of 1-02:00:00 — raise --time",
+            ]
+        );
+        let completed = &usage[..1];
+        assert_eq!(
+            suggestions(completed),
+            [
+                "peak MaxRSS 18.0G vs requested 50.0G — consider --mem=23G",
+                "longest job took 00:12:31 vs time limit 01:00:00 — consider --time=00:16:00",
+            ]
+        );
+    }
+
+    /// Against a real cluster: `BATCHELOR_TEST_SACCT_JOB=<finished job ID>
+    /// cargo test`.
+    #[test]
+    fn usage_from_real_sacct() {
+        let Ok(job_id) = std::env::var("BATCHELOR_TEST_SACCT_JOB") else {
+            return;
+        };
+        let output = Command::new("sacct")
+            .args(["-n", "--parsable2", "--format", SACCT_FORMAT, "-j", &job_id])
+            .output()
+            .expect("run sacct");
+        assert!(output.status.success());
+        let usage = parse_usage(&String::from_utf8_lossy(&output.stdout));
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].job_id, job_id);
+        assert!(usage[0].elapsed.is_some());
+    }
+}
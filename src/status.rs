@@ -0,0 +1,228 @@
+use crate::scheduler::{is_final_state, JobStatus, Scheduler};
+use crate::state::{JobState, RunState};
+use crate::{metrics, units, wait};
+use clap::{Parser, ValueEnum, ValueHint};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+/// Shown for jobs the scheduler no longer (or never) knew about.
+const UNKNOWN: &str = "UNKNOWN";
+/// Shown for jobs whose ID was never captured at submission.
+const UNTRACKED: &str = "UNTRACKED";
+/// Shown for batches whose submission failed.
+const SUBMIT_FAILED: &str = "SUBMIT_FAILED";
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "batchelor status",
+    about = "Show the scheduler state of the jobs of a previous run"
+)]
+pub struct StatusCli {
+    /// Output directory the run was submitted from.
+    #[arg(long, default_value = ".batchelor", value_hint = ValueHint::DirPath)]
+    out_dir: PathBuf,
+
+    /// Run ID to show (default: the most recent run).
+    #[arg(long)]
+    run: Option<String>,
+
+    /// Refresh every SECS seconds until every job has finished.
+    #[arg(long, value_name = "SECS")]
+    watch: Option<u64>,
+
+    /// Point out jobs pending longer than this, with the scheduler's reason.
+    #[arg(long, default_value = "30m", value_parser = units::parse_duration)]
+    pending_warn: u64,
+
+    /// Output format; `json` prints the run state document (see the
+    /// `state` module), one line per refresh.
+    #[arg(long, value_enum, default_value = "table")]
+    format: StatusFormat,
+
+    /// Also write Prometheus gauges for the run to FILE (e.g. for
+    /// node-exporter's textfile collector), replaced on every refresh.
+    #[arg(long, value_name = "FILE")]
+    metrics_out: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum StatusFormat {
+    Table,
+    Json,
+}
+
+struct StatusRow<'a> {
+    job_name: &'a str,
+    job_id: Option<&'a str>,
+    state: &'a str,
+    elapsed: Option<&'a str>,
+    exit_code: Option<&'a str>,
+    node: Option<&'a str>,
+}
+
+pub fn status(cli: StatusCli) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = RunState::load_run(&cli.out_dir, cli.run.as_deref())?;
+    if state.scheduler != Scheduler::Slurm {
+        return Err(format!(
+            "status needs sacct or squeue (SLURM); run {} was submitted with {:?}",
+            state.run_id, state.submit
+        )
+        .into());
+    }
+    let job_ids = state
+        .jobs
+        .iter()
+        .filter_map(|j| j.job_id.clone())
+        .collect::<Vec<_>>();
+    let ids = job_ids.iter().map(String::as_str).collect::<Vec<_>>();
+
+    loop {
+        let statuses = state
+            .scheduler
+            .job_statuses(&ids)
+            .ok_or("could not run sacct or squeue")?;
+        if let Some(path) = &cli.metrics_out {
+            metrics::write(
+                path,
+                &metrics::render(&state, &statuses, &cli.out_dir, chrono::Utc::now()),
+            )?;
+        }
+        match cli.format {
+            StatusFormat::Json => {
+                state.refresh(&statuses);
+                println!("{}", serde_json::to_string(&state)?);
+            }
+            StatusFormat::Table => {
+                let rows = state
+                    .jobs
+                    .iter()
+                    .map(|job| status_row(job, &statuses))
+                    .collect::<Vec<_>>();
+                if cli.watch.is_some() {
+                    // Clear the screen so the table refreshes in place.
+                    print!("\x1b[2J\x1b[H");
+                }
+                print_table(&state.run_id, &rows);
+                print_pending(state.scheduler, &rows, cli.pending_warn);
+            }
+        }
+        let Some(secs) = cli.watch else {
+            return Ok(());
+        };
+        io::stdout().flush()?;
+        let running = ids
+            .iter()
+            .any(|id| statuses.get(*id).is_some_and(|s| !is_final_state(&s.state)));
+        if !running {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_secs(secs.max(1)));
+    }
+}
+
+fn status_row<'a>(job: &'a JobState, statuses: &'a HashMap<String, JobStatus>) -> StatusRow<'a> {
+    let status = job.job_id.as_ref().and_then(|id| statuses.get(id));
+    let state = match (&job.job_id, status) {
+        (None, _) if job.submit_error.is_some() => SUBMIT_FAILED,
+        (None, _) => UNTRACKED,
+        (Some(_), None) => UNKNOWN,
+        (Some(_), Some(status)) => status.state.as_str(),
+    };
+    StatusRow {
+        job_name: &job.job_name,
+        job_id: job.job_id.as_deref(),
+        state,
+        elapsed: status.and_then(|s| s.elapsed.as_deref()),
+        exit_code: status.and_then(|s| s.exit_code.as_deref()),
+        node: status.and_then(|s| s.node.as_deref()),
+    }
+}
+
+/// Lists jobs pending for at least `warn_secs`, grouped by reason.
+fn print_pending(scheduler: Scheduler, rows: &[StatusRow], warn_secs: u64) {
+    let queued = rows
+        .iter()
+        .filter(|r| r.state == "PENDING")
+        .filter_map(|r| r.job_id)
+        .collect::<Vec<_>>();
+    if queued.is_empty() {
+        return;
+    }
+    let names = rows
+        .iter()
+        .filter_map(|r| Some((r.job_id?, r.job_name)))
+        .collect::<HashMap<_, _>>();
+    let pending = scheduler.pending_jobs(&queued).unwrap_or_default();
+    let long = pending
+        .iter()
+        .filter_map(|job| {
+            let secs = job.pending_secs.filter(|secs| *secs >= warn_secs)?;
+            let name = names.get(job.job_id.as_str()).copied().unwrap_or("?");
+            Some((name, secs, job.reason.as_str()))
+        })
+        .collect::<Vec<_>>();
+    for line in wait::pending_lines(&long) {
+        println!("pending: {}", line);
+    }
+}
+
+fn print_table(run_id: &str, rows: &[StatusRow]) {
+    println!("Run {} ({} job(s))", run_id, rows.len());
+    println!("job_name\tjob_id\tstate\telapsed\texit_code\tnode");
+    for row in rows {
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            row.job_name,
+            row.job_id.unwrap_or("-"),
+            row.state,
+            row.elapsed.unwrap_or("-"),
+            row.exit_code.unwrap_or("-"),
+            row.node.unwrap_or("-")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::parse_sacct;
+
+    #[test]
+    fn rows_for_known_unknown_and_untracked_jobs() {
+        let statuses = parse_sacct("4815162|RUNNING|00:03:10|0:0|node017\n")
+            .into_iter()
+            .map(|s| (s.job_id.clone(), s))
+            .collect::<HashMap<_, _>>();
+        let job = |name: &str, id: Option<&str>, error: Option<&str>| JobState {
+            job_name: name.to_string(),
+            job_id: id.map(str::to_string),
+            submit_error: error.map(str::to_string),
+            ..JobState::default()
+        };
+        let jobs = [
+            job("batch-0001", Some("4815162"), None),
+            job("batch-0002", Some("4815163"), None),
+            job("batch-0003", None, None),
+            job("batch-0004", None, Some("sbatch failed")),
+        ];
+        let rows = jobs
+            .iter()
+            .map(|job| {
+                let row = status_row(job, &statuses);
+                (row.job_name, row.state, row.node)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            [
+                ("batch-0001", "RUNNING", Some("node017")),
+                ("batch-0002", UNKNOWN, None),
+                ("batch-0003", UNTRACKED, None),
+                ("batch-0004", SUBMIT_FAILED, None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! Submitting the batches of a run, with `--submit-parallel` from several
+//! threads. Results are handed back in batch order whatever order the
+//! submissions finish in, so a run prints and records the same either way.
+//! What submit commands write to stderr is passed on as it arrives, on the
+//! thread asking for results.
+
+use crate::plan::JobSpec;
+use crate::submitter::{Submitted, Submitter};
+use crate::{BatchelorError, Interrupt};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::Scope;
+use std::time::Instant;
+
+pub(crate) type SubmitResult = Result<Submitted, BatchelorError>;
+
+/// Called with the job name and each line a submit command writes to
+/// stderr.
+pub(crate) type OnStderr<'f> = &'f mut dyn FnMut(&str, &str);
+
+/// What a submitting thread reports, by batch index.
+enum Event {
+    Stderr(usize, String),
+    Done(usize, SubmitResult),
+}
+
+/// What the submitting threads share: the next batch to take, and whether
+/// to take any more.
+#[derive(Default)]
+pub(crate) struct Shared {
+    next: AtomicUsize,
+    stop: AtomicBool,
+}
+
+pub(crate) struct Submissions<'a> {
+    jobs: &'a [&'a JobSpec],
+    submitter: &'a dyn Submitter,
+    next: usize,
+    pool: Option<Pool<'a>>,
+}
+
+struct Pool<'a> {
+    shared: &'a Shared,
+    events: Receiver<Event>,
+    /// Results that arrived ahead of their turn.
+    buffered: BTreeMap<usize, SubmitResult>,
+}
+
+impl<'a> Submissions<'a> {
+    /// Submits `jobs` one at a time as they are asked for, or with
+    /// `threads` > 1 starts that many threads submitting ahead. With
+    /// `stop_on_failure` they start no new submissions after one failed.
+    pub(crate) fn new<'scope>(
+        scope: &'scope Scope<'scope, 'a>,
+        shared: &'a Shared,
+        jobs: &'a [&'a JobSpec],
+        submitter: &'a dyn Submitter,
+        threads: usize,
+        stop_on_failure: bool,
+        interrupt: &'a Interrupt,
+    ) -> Submissions<'a> {
+        let threads = threads.min(jobs.len());
+        let pool = (threads > 1).then(|| {
+            let (sender, events) = mpsc::channel();
+            for _ in 0..threads {
+                let sender = sender.clone();
+                scope.spawn(move || loop {
+                    if shared.stop.load(Ordering::SeqCst) || interrupt.is_interrupted() {
+                        break;
+                    }
+                    let idx = shared.next.fetch_add(1, Ordering::SeqCst);
+                    let Some(job) = jobs.get(idx) else {
+                        break;
+                    };
+                    let result = submit(submitter, job, &mut |line| {
+                        let _ = sender.send(Event::Stderr(idx, line.to_string()));
+                    });
+                    if stop_on_failure && result.is_err() {
+                        shared.stop.store(true, Ordering::SeqCst);
+                    }
+                    if sender.send(Event::Done(idx, result)).is_err() {
+                        break;
+                    }
+                });
+            }
+            Pool {
+                shared,
+                events,
+                buffered: BTreeMap::new(),
+            }
+        });
+        Submissions {
+            jobs,
+            submitter,
+            next: 0,
+            pool,
+        }
+    }
+
+    /// The result of the next batch. `None` once the threads stopped
+    /// early, when the run was interrupted.
+    pub(crate) fn next(&mut self, on_stderr: OnStderr) -> Option<SubmitResult> {
+        let idx = self.next;
+        let job = self.jobs.get(idx)?;
+        self.next += 1;
+        let Some(pool) = &mut self.pool else {
+            return Some(submit(self.submitter, job, &mut |line| {
+                on_stderr(&job.job_name, line)
+            }));
+        };
+        loop {
+            if let Some(result) = pool.buffered.remove(&idx) {
+                return Some(result);
+            }
+            match pool.events.recv().ok()? {
+                Event::Stderr(from, line) => on_stderr(&self.jobs[from].job_name, &line),
+                Event::Done(done, result) => {
+                    pool.buffered.insert(done, result);
+                }
+            }
+        }
+    }
+
+    /// Stops submitting and returns the results of batches that were
+    /// submitted but not yet handed out, by index. Submissions under way
+    /// are waited for.
+    pub(crate) fn stop(&mut self, on_stderr: OnStderr) -> Vec<(usize, SubmitResult)> {
+        let Some(pool) = &mut self.pool else {
+            return Vec::new();
+        };
+        pool.shared.stop.store(true, Ordering::SeqCst);
+        // Ends when every thread has finished and dropped its sender.
+        while let Ok(event) = pool.events.recv() {
+            match event {
+                Event::Stderr(from, line) => on_stderr(&self.jobs[from].job_name, &line),
+                Event::Done(done, result) => {
+                    pool.buffered.insert(done, result);
+                }
+            }
+        }
+        std::mem::take(&mut pool.buffered).into_iter().collect()
+    }
+}
+
+impl Drop for Submissions<'_> {
+    fn drop(&mut self) {
+        // A run that ends on an error stops the threads after the
+        // submissions under way.
+        if let Some(pool) = &self.pool {
+            pool.shared.stop.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Submits one batch. Errors other than the typed ones say where the
+/// batch's script was kept; [`BatchelorError::SubmitFailed`] says so itself.
+fn submit(
+    submitter: &dyn Submitter,
+    job: &JobSpec,
+    on_stderr: &mut dyn FnMut(&str),
+) -> SubmitResult {
+    let started = Instant::now();
+    let result = submitter.submit_streaming(job, on_stderr).map_err(|e| {
+        match (&job.script, e.is::<BatchelorError>()) {
+            (Some(path), false) => format!("{} (script kept: {})", e, path.display()).into(),
+            _ => BatchelorError::from(e),
+        }
+    });
+    log::debug!(
+        "submitting {} took {:.2}s",
+        job.job_name,
+        started.elapsed().as_secs_f64()
+    );
+    result
+}
@@ -0,0 +1,194 @@
+//! How batches reach the scheduler. [`CommandSubmitter`] runs the submit
+//! command, or only records the submission with `--submit-record`;
+//! library callers can pass their own [`Submitter`] to [`crate::execute`].
+
+use crate::plan::JobSpec;
+use crate::scheduler::{parse_job_id, JobId, Scheduler};
+use crate::{dispatch_submission, wrap_commands, Cli, JobPayload, Submission};
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+
+/// Submitters are shared by the threads of `--submit-parallel`.
+pub trait Submitter: Sync {
+    /// Submits one batch: its script, or with `--wrap` its commands.
+    fn submit(&self, job: &JobSpec) -> Result<Submitted, Box<dyn std::error::Error>>;
+
+    /// Like [`Submitter::submit`], passing what the submission writes to
+    /// stderr to `on_stderr` line by line as it arrives. Submitters that
+    /// cannot stream only submit.
+    fn submit_streaming(
+        &self,
+        job: &JobSpec,
+        on_stderr: &mut dyn FnMut(&str),
+    ) -> Result<Submitted, Box<dyn std::error::Error>> {
+        let _ = on_stderr;
+        self.submit(job)
+    }
+
+    /// Checks that the scheduler would accept `job` without running it,
+    /// before anything real is submitted (`--preflight`). Submitters that
+    /// run no submit command have nothing to check.
+    fn preflight(&self, job: &JobSpec) -> Result<Preflight, Box<dyn std::error::Error>> {
+        let _ = job;
+        Ok(Preflight::NotChecked)
+    }
+
+    /// Cancels a job this submitter submitted.
+    fn cancel(&self, job_id: &JobId) -> Result<(), Box<dyn std::error::Error>> {
+        Err(format!(
+            "cannot cancel job {}: not supported by this submitter",
+            job_id
+        )
+        .into())
+    }
+}
+
+/// How [`Submitter::preflight`] checked a job.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Preflight {
+    /// Not at all: the submitter has no way to.
+    NotChecked,
+    /// The scheduler's test-only mode accepted it, e.g. `sbatch --test-only`.
+    Accepted,
+    /// It was submitted held, accepted and cancelled as this job.
+    ProbeCancelled(JobId),
+}
+
+/// A submission the submitter accepted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Submitted {
+    /// `None` when no job ID could be read from the output; the job is
+    /// then untracked.
+    pub job_id: Option<JobId>,
+    /// What the submit command printed.
+    pub stdout: String,
+}
+
+impl Submitted {
+    /// Reads the job ID from the output of `scheduler`'s submit command.
+    pub fn from_stdout(scheduler: Scheduler, stdout: String) -> Submitted {
+        Submitted {
+            job_id: parse_job_id(scheduler, &stdout),
+            stdout,
+        }
+    }
+}
+
+/// Runs each batch's submit command and cancels with the scheduler's
+/// cancel command.
+#[derive(Clone, Debug)]
+pub struct CommandSubmitter {
+    scheduler: Scheduler,
+    /// Feed scripts on stdin (`--submit-stdin`).
+    stdin: bool,
+    /// Record submissions here instead (`--submit-record`).
+    record_dir: Option<PathBuf>,
+    /// Kill submit commands running longer (`--submit-timeout`).
+    timeout: Option<Duration>,
+}
+
+impl CommandSubmitter {
+    pub fn new(scheduler: Scheduler) -> CommandSubmitter {
+        CommandSubmitter {
+            scheduler,
+            stdin: false,
+            record_dir: None,
+            timeout: None,
+        }
+    }
+
+    /// Submits as `cli` says: with `--submit-stdin`, `--submit-record` and
+    /// `--submit-timeout`.
+    pub fn from_cli(cli: &Cli, scheduler: Scheduler) -> CommandSubmitter {
+        CommandSubmitter {
+            scheduler,
+            stdin: cli.submit_stdin,
+            record_dir: cli.submit_record.clone(),
+            timeout: cli.submit_timeout.map(Duration::from_secs),
+        }
+    }
+}
+
+impl Submitter for CommandSubmitter {
+    fn submit(&self, job: &JobSpec) -> Result<Submitted, Box<dyn std::error::Error>> {
+        self.submit_streaming(job, &mut |_| {})
+    }
+
+    fn submit_streaming(
+        &self,
+        job: &JobSpec,
+        on_stderr: &mut dyn FnMut(&str),
+    ) -> Result<Submitted, Box<dyn std::error::Error>> {
+        let wrapped;
+        let payload = match &job.script {
+            Some(path) if self.stdin => JobPayload::Stdin(path),
+            Some(path) => JobPayload::Script(path),
+            None => {
+                wrapped = wrap_commands(&job.commands);
+                JobPayload::Wrap(&wrapped)
+            }
+        };
+        let submission = Submission {
+            job_name: &job.job_name,
+            extra_args: job.submit_args.clone(),
+            payload,
+        };
+        let stdout = dispatch_submission(
+            &job.submit_command,
+            &submission,
+            self.scheduler,
+            self.record_dir.as_deref(),
+            self.timeout,
+            on_stderr,
+        )?;
+        Ok(Submitted::from_stdout(self.scheduler, stdout))
+    }
+
+    /// Submits `job` with the scheduler's test-only arguments or, where it
+    /// has none, held and cancels it right away. Recorded submissions
+    /// (`--submit-record`) are not checked.
+    fn preflight(&self, job: &JobSpec) -> Result<Preflight, Box<dyn std::error::Error>> {
+        if self.record_dir.is_some() {
+            return Ok(Preflight::NotChecked);
+        }
+        let mut probe = job.clone();
+        if let Some(args) = self.scheduler.test_only_args() {
+            probe.submit_args.extend(args);
+            self.submit(&probe)?;
+            return Ok(Preflight::Accepted);
+        }
+        probe.submit_args.extend(self.scheduler.hold_args());
+        let accepted = self.submit(&probe)?;
+        let job_id = accepted.job_id.ok_or_else(|| {
+            format!(
+                "the probe was submitted but no job ID could be read from {:?}; cancel it by hand",
+                accepted.stdout.trim()
+            )
+        })?;
+        self.cancel(&job_id).map_err(|e| {
+            format!(
+                "could not cancel probe job {}: {}; cancel it by hand",
+                job_id, e
+            )
+        })?;
+        Ok(Preflight::ProbeCancelled(job_id))
+    }
+
+    fn cancel(&self, job_id: &JobId) -> Result<(), Box<dyn std::error::Error>> {
+        let program = self
+            .scheduler
+            .cancel_program()
+            .ok_or("no cancel command known for this submitter")?;
+        let output = Command::new(program).arg(job_id).output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "{} failed: {}",
+                program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
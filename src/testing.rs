@@ -0,0 +1,280 @@
+//! Test doubles for library users (`testing` feature).
+
+use crate::error::BatchelorError;
+use crate::plan::JobSpec;
+use crate::scheduler::JobId;
+use crate::submitter::{Preflight, Submitted, Submitter};
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A [`Submitter`] that submits nothing: it remembers every job it was
+/// given and hands out job IDs 1001, 1002, ... Batches marked with
+/// [`MockSubmitter::failing`] fail like a rejected submit command. The
+/// preflight check accepts every job without submitting it.
+#[derive(Debug, Default)]
+pub struct MockSubmitter {
+    failing: BTreeSet<usize>,
+    latency: Duration,
+    submitted: Mutex<Vec<JobSpec>>,
+    preflighted: Mutex<Vec<JobSpec>>,
+    cancelled: Mutex<Vec<JobId>>,
+}
+
+impl MockSubmitter {
+    pub fn new() -> MockSubmitter {
+        MockSubmitter::default()
+    }
+
+    /// Makes the submission of batch `batch_index` (1-based) fail.
+    pub fn failing(mut self, batch_index: usize) -> MockSubmitter {
+        self.failing.insert(batch_index);
+        self
+    }
+
+    /// Makes every submission take `latency`, like a round trip to the
+    /// scheduler.
+    pub fn latency(mut self, latency: Duration) -> MockSubmitter {
+        self.latency = latency;
+        self
+    }
+
+    /// The jobs submitted so far, in order; failed ones included.
+    pub fn submitted(&self) -> Vec<JobSpec> {
+        self.submitted.lock().unwrap().clone()
+    }
+
+    /// The jobs checked by the preflight check, which are not submitted.
+    pub fn preflighted(&self) -> Vec<JobSpec> {
+        self.preflighted.lock().unwrap().clone()
+    }
+
+    /// The job IDs cancelled so far, in order.
+    pub fn cancelled(&self) -> Vec<JobId> {
+        self.cancelled.lock().unwrap().clone()
+    }
+}
+
+impl Submitter for MockSubmitter {
+    fn submit(&self, job: &JobSpec) -> Result<Submitted, Box<dyn std::error::Error>> {
+        std::thread::sleep(self.latency);
+        let mut submitted = self.submitted.lock().unwrap();
+        submitted.push(job.clone());
+        if self.failing.contains(&job.batch_index) {
+            return Err(BatchelorError::SubmitFailed {
+                program: "mock".to_string(),
+                script: job.script.clone(),
+                job_name: job.job_name.clone(),
+                stderr: "rejected by MockSubmitter".to_string(),
+                status: Some(1),
+            }
+            .into());
+        }
+        let job_id = (1000 + submitted.len()).to_string();
+        Ok(Submitted {
+            stdout: format!("{}\n", job_id),
+            job_id: Some(job_id),
+        })
+    }
+
+    fn preflight(&self, job: &JobSpec) -> Result<Preflight, Box<dyn std::error::Error>> {
+        self.preflighted.lock().unwrap().push(job.clone());
+        Ok(Preflight::Accepted)
+    }
+
+    fn cancel(&self, job_id: &JobId) -> Result<(), Box<dyn std::error::Error>> {
+        self.cancelled.lock().unwrap().push(job_id.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::SubmitOutcome;
+    use crate::reporter::Quiet;
+    use crate::{execute, plan, run_with, Cli, ExecOptions};
+    use std::fs;
+    use std::path::Path;
+    use std::time::Instant;
+
+    /// A run of `script.sh` over `inputs` inputs in batches of one, with
+    /// its scripts and state under `dir`.
+    fn cli(dir: &Path, inputs: usize) -> Cli {
+        fs::write(dir.join("script.sh"), "#!/bin/bash\necho \"$@\"\n").unwrap();
+        fs::create_dir_all(dir.join("in")).unwrap();
+        for i in 1..=inputs {
+            fs::write(dir.join(format!("in/{}.fq", i)), "x").unwrap();
+        }
+        Cli::builder()
+            .script(dir.join("script.sh"))
+            .glob(dir.join("in/*.fq").to_string_lossy())
+            .batch(inputs)
+            .out_dir(dir.join("out"))
+            .skip_submit_check(true)
+            .build()
+            .unwrap()
+    }
+
+    fn names(jobs: &[JobSpec]) -> Vec<&str> {
+        jobs.iter().map(|job| job.job_name.as_str()).collect()
+    }
+
+    #[test]
+    fn submits_the_batches_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let mock = MockSubmitter::new();
+        let report = run_with(cli(dir.path(), 3), &Quiet, Some(&mock)).unwrap();
+        assert_eq!(
+            names(&mock.submitted()),
+            ["batch-0001", "batch-0002", "batch-0003"]
+        );
+        for (i, job) in mock.submitted().iter().enumerate() {
+            assert_eq!(job.batch_index, i + 1);
+            assert!(job.inputs[0].ends_with(&format!("in/{}.fq", i + 1)));
+        }
+        let outcomes = report
+            .jobs()
+            .into_iter()
+            .map(|job| job.outcome)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outcomes,
+            ["1001", "1002", "1003"].map(|id| SubmitOutcome::Submitted(Some(id.to_string())))
+        );
+        assert!(mock.cancelled().is_empty());
+    }
+
+    #[test]
+    fn preflight_checks_are_not_submissions() {
+        let dir = tempfile::tempdir().unwrap();
+        // A default sbatch run, which has preflight on.
+        let checked = cli(dir.path(), 2);
+        assert_eq!(checked.submit, "sbatch");
+        let mock = MockSubmitter::new();
+        run_with(checked, &Quiet, Some(&mock)).unwrap();
+        assert_eq!(names(&mock.preflighted()), ["batch-0001"]);
+        assert_eq!(names(&mock.submitted()), ["batch-0001", "batch-0002"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        cli(dir.path(), 1);
+        let unchecked = Cli::builder()
+            .script(dir.path().join("script.sh"))
+            .glob(dir.path().join("in/*.fq").to_string_lossy())
+            .out_dir(dir.path().join("out"))
+            .skip_submit_check(true)
+            .preflight(false)
+            .build()
+            .unwrap();
+        let mock = MockSubmitter::new();
+        run_with(unchecked, &Quiet, Some(&mock)).unwrap();
+        assert!(mock.preflighted().is_empty());
+        assert_eq!(names(&mock.submitted()), ["batch-0001"]);
+    }
+
+    #[test]
+    fn execute_submits_a_plan() {
+        let dir = tempfile::tempdir().unwrap();
+        let cli = cli(dir.path(), 2);
+        let plan = plan(&cli, &Quiet).unwrap();
+        let mock = MockSubmitter::new();
+        let report = execute(&plan, &ExecOptions::new(&cli), &Quiet, &mock).unwrap();
+        assert_eq!(mock.submitted(), plan.batches);
+        assert_eq!(report.state.jobs.len(), 2);
+    }
+
+    #[test]
+    fn a_failed_batch_stops_the_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let mock = MockSubmitter::new().failing(2);
+        let e = run_with(cli(dir.path(), 3), &Quiet, Some(&mock)).unwrap_err();
+        assert!(
+            matches!(&e, BatchelorError::SubmitFailed { job_name, .. } if job_name == "batch-0002"),
+            "{:?}",
+            e
+        );
+        assert_eq!(e.exit_code(), 5);
+        assert_eq!(names(&mock.submitted()), ["batch-0001", "batch-0002"]);
+        assert!(mock.cancelled().is_empty());
+    }
+
+    #[test]
+    fn keep_going_submits_the_rest() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cli = cli(dir.path(), 4);
+        cli.keep_going = true;
+        let mock = MockSubmitter::new().failing(2).failing(3);
+        let e = run_with(cli, &Quiet, Some(&mock)).unwrap_err();
+        assert!(
+            matches!(
+                e,
+                BatchelorError::PartialFailure {
+                    failed: 2,
+                    attempted: 4
+                }
+            ),
+            "{:?}",
+            e
+        );
+        assert_eq!(mock.submitted().len(), 4);
+        assert!(mock.cancelled().is_empty());
+    }
+
+    #[test]
+    fn cancel_on_failure_cancels_the_submitted_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cli = cli(dir.path(), 4);
+        cli.cancel_on_failure = true;
+        let mock = MockSubmitter::new().failing(3);
+        let e = run_with(cli, &Quiet, Some(&mock)).unwrap_err();
+        assert!(matches!(e, BatchelorError::SubmitFailed { .. }), "{:?}", e);
+        assert_eq!(mock.submitted().len(), 3);
+        assert_eq!(mock.cancelled(), ["1001", "1002"]);
+    }
+
+    #[test]
+    fn parallel_submissions_reach_every_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cli = cli(dir.path(), 6);
+        cli.submit_parallel = 3;
+        let latency = Duration::from_millis(200);
+        let mock = MockSubmitter::new().latency(latency);
+        let started = Instant::now();
+        let report = run_with(cli, &Quiet, Some(&mock)).unwrap();
+        // Three at a time take about two round trips; one at a time, six.
+        assert!(started.elapsed() < latency * 6, "{:?}", started.elapsed());
+
+        let submitted = mock.submitted();
+        let mut names = names(&submitted)
+            .into_iter()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            (1..=6)
+                .map(|i| format!("batch-{:04}", i))
+                .collect::<Vec<_>>()
+        );
+        // Each batch records the ID the mock handed out for it, whichever
+        // order they completed in.
+        for (i, job) in report.jobs().iter().enumerate() {
+            assert_eq!(job.batch_index, i + 1);
+            let SubmitOutcome::Submitted(Some(id)) = &job.outcome else {
+                panic!("{:?}", job.outcome);
+            };
+            let n = id.parse::<usize>().unwrap() - 1001;
+            assert_eq!(submitted[n].job_name, job.job_name);
+        }
+    }
+
+    #[test]
+    fn dry_runs_submit_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cli = cli(dir.path(), 2);
+        cli.dry_run = true;
+        let mock = MockSubmitter::new();
+        run_with(cli, &Quiet, Some(&mock)).unwrap();
+        assert!(mock.submitted().is_empty());
+    }
+}
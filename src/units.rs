@@ -0,0 +1,141 @@
+//! Parsing and formatting of the byte sizes and durations accepted on the
+//! command line (`4G`, `1.5T`, `90m`, `1h30m`, `2-00:00:00`, ...).
+
+const KIB: u64 = 1024;
+const MIB: u64 = 1024 * KIB;
+const GIB: u64 = 1024 * MIB;
+
+/// Parses a byte size such as `512`, `64K`, `4G`, `1.5TB` or `2GiB`.
+/// Suffixes are binary (K = 1024).
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let t = s.trim();
+    let split = t
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(t.len());
+    let (number, unit) = t.split_at(split);
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size {:?} (expected e.g. 500M or 4G)", s))?;
+    let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => KIB,
+        "M" | "MB" | "MIB" => MIB,
+        "G" | "GB" | "GIB" => GIB,
+        "T" | "TB" | "TIB" => 1024 * GIB,
+        _ => return Err(format!("invalid size unit in {:?} (use K, M, G or T)", s)),
+    };
+    Ok((value * multiplier as f64).ceil() as u64)
+}
+
+/// Parses a duration in seconds from `90s`, `15m`, `2h`, `1d`, combinations
+/// like `1h30m`, or scheduler notation `HH:MM:SS` / `D-HH:MM:SS`. A bare
+/// number is seconds.
+pub fn parse_duration(s: &str) -> Result<u64, String> {
+    let t = s.trim();
+    let invalid = || {
+        format!(
+            "invalid duration {:?} (expected e.g. 30m, 2h or 01:30:00)",
+            s
+        )
+    };
+    if t.is_empty() {
+        return Err(invalid());
+    }
+
+    if t.contains(':') {
+        let (days, clock) = match t.split_once('-') {
+            Some((d, rest)) => (d.parse::<u64>().map_err(|_| invalid())?, rest),
+            None => (0, t),
+        };
+        let mut secs = 0u64;
+        for part in clock.split(':') {
+            secs = secs * 60 + part.parse::<u64>().map_err(|_| invalid())?;
+        }
+        return Ok(days * 86_400 + secs);
+    }
+
+    if let Ok(secs) = t.parse::<u64>() {
+        return Ok(secs);
+    }
+
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in t.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 3_600,
+            'd' => 86_400,
+            _ => return Err(invalid()),
+        };
+        let value: u64 = number.parse().map_err(|_| invalid())?;
+        total += value * unit;
+        number.clear();
+    }
+    if !number.is_empty() {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+/// Formats bytes for humans, e.g. `1.5G`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "K", "M", "G", "T"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}B", bytes)
+    } else {
+        format!("{:.1}{}", value, UNITS[unit])
+    }
+}
+
+/// Rounds a memory request up to a unit schedulers accept: whole MiB below
+/// 1 GiB, whole GiB above. Returns the amount and its unit letter.
+pub fn round_up_mem(bytes: u64) -> (u64, char) {
+    if bytes <= GIB {
+        (bytes.div_ceil(MIB).max(1), 'M')
+    } else {
+        (bytes.div_ceil(GIB), 'G')
+    }
+}
+
+/// Formats seconds as `HH:MM:SS`, or `D-HH:MM:SS` from one day on.
+pub fn format_clock(secs: u64) -> String {
+    let (days, rest) = (secs / 86_400, secs % 86_400);
+    let clock = format!(
+        "{:02}:{:02}:{:02}",
+        rest / 3_600,
+        rest % 3_600 / 60,
+        rest % 60
+    );
+    if days > 0 {
+        format!("{}-{}", days, clock)
+    } else {
+        clock
+    }
+}
+
+/// Formats seconds compactly for humans, e.g. `2h13m`.
+pub fn format_duration(secs: u64) -> String {
+    let (d, h, m, s) = (
+        secs / 86_400,
+        secs % 86_400 / 3_600,
+        secs % 3_600 / 60,
+        secs % 60,
+    );
+    match (d, h, m) {
+        (0, 0, 0) => format!("{}s", s),
+        (0, 0, _) => format!("{}m{:02}s", m, s),
+        (0, _, _) => format!("{}h{:02}m", h, m),
+        _ => format!("{}d{:02}h", d, h),
+    }
+}
@@ -0,0 +1,430 @@
+//! `--wait`: poll the scheduler until every submitted job has finished and
+//! summarize how the jobs, and the inputs they covered, came out.
+
+use crate::failures;
+use crate::reporter::Reporter;
+use crate::scheduler::{is_final_state, parse_sacct, JobStatus, Scheduler};
+use crate::state::JobState;
+use crate::units;
+use crate::{BatchelorError, Interrupt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Polls in a row a job may be missing from both sacct and squeue (e.g.
+/// right after submission) before it is counted as UNKNOWN.
+const MISSING_POLLS: usize = 3;
+
+/// Job names listed in a grouped pending line before eliding the rest.
+const PENDING_NAMES_SHOWN: usize = 3;
+
+/// A submitted job, with the inputs and markers its state records.
+pub(crate) struct WaitedJob<'a> {
+    pub(crate) job: &'a JobState,
+    pub(crate) job_id: &'a str,
+}
+
+/// How the jobs of a run ended.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct WaitSummary {
+    /// Final state of each job ID (UNKNOWN when the scheduler lost it).
+    pub jobs: BTreeMap<String, JobStatus>,
+    pub inputs_total: usize,
+    /// Inputs whose command failed, and inputs of failed jobs that never
+    /// finished, as their `.done`/`.failed` markers tell.
+    pub failed_inputs: Vec<String>,
+    /// IDs of jobs that failed as a whole: timed out, killed, lost, still
+    /// pending, or failed without a `.failed` marker saying which input
+    /// made them fail.
+    #[serde(default)]
+    pub failed_jobs: Vec<String>,
+    /// Why waiting stopped early because jobs stayed pending past
+    /// `--pending-fail`, one line per reason; those jobs are still queued.
+    pub stuck: Vec<String>,
+}
+
+/// How long jobs may stay pending before `--wait` warns (`warn`) or gives
+/// up on them (`fail`), in seconds.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct PendingLimits {
+    pub(crate) warn: u64,
+    pub(crate) fail: Option<u64>,
+}
+
+impl WaitSummary {
+    /// The worst outcome: `0` when every input succeeded, `1` when inputs
+    /// failed but every job ran to its end, `2` when any job failed as a
+    /// whole.
+    pub fn exit_code(&self) -> i32 {
+        if !self.failed_jobs.is_empty() {
+            2
+        } else if !self.failed_inputs.is_empty() {
+            1
+        } else {
+            0
+        }
+    }
+
+    /// E.g. `37 jobs: 34 COMPLETED, 2 FAILED (exit 1), 1 TIMEOUT;
+    /// 4980/5000 inputs succeeded`.
+    pub fn line(&self) -> String {
+        let mut states: BTreeMap<&str, (usize, Vec<&str>)> = BTreeMap::new();
+        for status in self.jobs.values() {
+            let entry = states.entry(status.state.as_str()).or_default();
+            entry.0 += 1;
+            if status.state != "COMPLETED" {
+                if let Some(code) = status.exit_code.as_deref().and_then(exit_status) {
+                    if code != "0" && !entry.1.contains(&code) {
+                        entry.1.push(code);
+                    }
+                }
+            }
+        }
+        let parts = states
+            .iter()
+            .map(|(state, (count, codes))| {
+                if codes.is_empty() {
+                    format!("{} {}", count, state)
+                } else {
+                    format!("{} {} (exit {})", count, state, codes.join(", "))
+                }
+            })
+            .collect::<Vec<_>>();
+        format!(
+            "{} jobs: {}; {}/{} inputs succeeded",
+            self.jobs.len(),
+            parts.join(", "),
+            self.inputs_total - self.failed_inputs.len(),
+            self.inputs_total
+        )
+    }
+}
+
+pub(crate) fn succeeded(status: &JobStatus) -> bool {
+    completed_ok(&status.state, status.exit_code.as_deref())
+}
+
+/// Whether a job in `state` with sacct `exit_code` ran successfully.
+pub(crate) fn completed_ok(state: &str, exit_code: Option<&str>) -> bool {
+    state == "COMPLETED"
+        && exit_code
+            .and_then(exit_status)
+            .is_none_or(|code| code == "0")
+}
+
+/// The exit status part of sacct's `exit:signal` ExitCode.
+fn exit_status(code: &str) -> Option<&str> {
+    code.split(':').next().filter(|c| !c.is_empty())
+}
+
+/// How `--wait` polls the scheduler.
+pub(crate) struct Polling<'a> {
+    pub(crate) interval: Duration,
+    /// Replaces sacct/squeue: it gets the comma-separated job IDs as its
+    /// last argument and prints lines in
+    /// `sacct --parsable2 -o JobID,State,Elapsed,ExitCode,NodeList` format.
+    pub(crate) status_command: Option<&'a str>,
+    /// Waiting stops with [`BatchelorError::Interrupted`] once this is
+    /// interrupted.
+    pub(crate) interrupt: &'a Interrupt,
+}
+
+/// Blocks until every job has reached a final state, polling as `polling`
+/// says, then reads the job markers under `out_dir` to tell failed inputs
+/// from failed jobs. `on_poll` sees the latest status of every job after
+/// each poll; progress and pending warnings go to `reporter`.
+pub(crate) fn wait_for_jobs(
+    scheduler: Scheduler,
+    out_dir: &Path,
+    jobs: &[WaitedJob],
+    polling: Polling,
+    limits: PendingLimits,
+    on_poll: &mut dyn FnMut(&HashMap<String, JobStatus>),
+    reporter: &dyn Reporter,
+) -> Result<WaitSummary, Box<dyn std::error::Error>> {
+    let Polling {
+        interval,
+        status_command,
+        interrupt,
+    } = polling;
+    let names = jobs
+        .iter()
+        .map(|j| (j.job_id, j.job.job_name.as_str()))
+        .collect::<HashMap<_, _>>();
+    let mut finished: BTreeMap<String, JobStatus> = BTreeMap::new();
+    let mut missing: HashMap<&str, usize> = HashMap::new();
+    let mut last_progress = String::new();
+    // When each job was first seen pending, for when squeue's submit time
+    // is not available; and the reason last reported for it.
+    let mut pending_since: HashMap<String, Instant> = HashMap::new();
+    let mut reported: HashMap<String, String> = HashMap::new();
+    let mut stuck = Vec::new();
+    loop {
+        let pending = jobs
+            .iter()
+            .map(|j| j.job_id)
+            .filter(|id| !finished.contains_key(*id))
+            .collect::<Vec<_>>();
+        if pending.is_empty() {
+            break;
+        }
+        let statuses = match status_command {
+            Some(command) => run_status_command(command, &pending)?,
+            None => scheduler.job_statuses(&pending).ok_or(
+                "--wait needs sacct or squeue (SLURM) to follow the jobs; see --status-command",
+            )?,
+        };
+
+        let mut active = 0;
+        for id in pending {
+            match statuses.get(id) {
+                Some(status) if is_final_state(&status.state) => {
+                    finished.insert(id.to_string(), status.clone());
+                }
+                Some(_) => {
+                    missing.remove(id);
+                    active += 1;
+                }
+                None => {
+                    let polls = missing.entry(id).or_default();
+                    *polls += 1;
+                    if *polls >= MISSING_POLLS {
+                        finished.insert(id.to_string(), unknown(id));
+                    } else {
+                        active += 1;
+                    }
+                }
+            }
+        }
+        let mut latest = statuses.clone();
+        latest.extend(finished.iter().map(|(id, s)| (id.clone(), s.clone())));
+        on_poll(&latest);
+        if active == 0 {
+            break;
+        }
+
+        let queued = statuses
+            .values()
+            .filter(|s| s.state == "PENDING")
+            .map(|s| s.job_id.as_str())
+            .collect::<Vec<_>>();
+        pending_since.retain(|id, _| queued.contains(&id.as_str()));
+        let waiting = scheduler
+            .pending_jobs(&queued)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|job| {
+                let seen = pending_since
+                    .entry(job.job_id.clone())
+                    .or_insert_with(Instant::now)
+                    .elapsed()
+                    .as_secs();
+                let secs = job.pending_secs.unwrap_or(0).max(seen);
+                let name = names.get(job.job_id.as_str()).copied().unwrap_or("?");
+                (name, job.job_id, secs, job.reason)
+            })
+            .collect::<Vec<_>>();
+
+        let new_reasons = waiting
+            .iter()
+            .filter(|(_, id, secs, reason)| {
+                *secs >= limits.warn && reported.get(id) != Some(reason)
+            })
+            .map(|(name, _, secs, reason)| (*name, *secs, reason.as_str()))
+            .collect::<Vec<_>>();
+        for line in pending_lines(&new_reasons) {
+            reporter.diagnostic(&format!("warning: {}", line));
+        }
+        for (_, id, secs, reason) in &waiting {
+            if *secs >= limits.warn {
+                reported.insert(id.clone(), reason.clone());
+            }
+        }
+
+        if let Some(fail) = limits.fail {
+            let over = waiting
+                .iter()
+                .filter(|(_, _, secs, _)| *secs >= fail)
+                .map(|(name, _, secs, reason)| (*name, *secs, reason.as_str()))
+                .collect::<Vec<_>>();
+            if !over.is_empty() {
+                stuck = pending_lines(&over);
+                // Stop here; unfinished jobs keep their current state.
+                for job in jobs {
+                    if !finished.contains_key(job.job_id) {
+                        let status = statuses
+                            .get(job.job_id)
+                            .cloned()
+                            .unwrap_or_else(|| unknown(job.job_id));
+                        finished.insert(job.job_id.to_string(), status);
+                    }
+                }
+                break;
+            }
+        }
+
+        let progress = format!(
+            "Waiting for {} of {} job(s) to finish...",
+            active,
+            jobs.len()
+        );
+        if progress != last_progress {
+            reporter.message(&progress);
+            last_progress = progress;
+        }
+        let deadline = Instant::now() + interval;
+        while Instant::now() < deadline {
+            if interrupt.is_interrupted() {
+                reporter.diagnostic("Stopped waiting; the jobs keep running.");
+                return Err(BatchelorError::Interrupted.into());
+            }
+            thread::sleep(Duration::from_millis(100).min(interval));
+        }
+    }
+
+    let mut summary = WaitSummary {
+        inputs_total: jobs.iter().map(|j| j.job.inputs.len()).sum(),
+        stuck,
+        ..WaitSummary::default()
+    };
+    for job in jobs {
+        let status = finished
+            .get(job.job_id)
+            .cloned()
+            .unwrap_or_else(|| unknown(job.job_id));
+        // Unreadable markers count as none.
+        let (done, failed) = failures::marker_inputs(out_dir, job.job).unwrap_or_default();
+        let ok = succeeded(&status);
+        summary.failed_inputs.extend(
+            job.job
+                .inputs
+                .iter()
+                .map(|input| &input.path)
+                .filter(|input| {
+                    if ok {
+                        failed.contains(*input)
+                    } else {
+                        !done.contains(*input)
+                    }
+                })
+                .cloned(),
+        );
+        // A failing command ends its job with its exit status; anything
+        // else took the whole job down.
+        let input_failed = status.state == "FAILED" && !failed.is_empty();
+        if !ok && !input_failed {
+            summary.failed_jobs.push(job.job_id.to_string());
+        }
+        summary.jobs.insert(job.job_id.to_string(), status);
+    }
+    Ok(summary)
+}
+
+/// One line per pending reason for `(job name, seconds pending, reason)`,
+/// e.g. `batch-0007 pending 2h13m: ReqNodeNotAvail` or `12 jobs pending up
+/// to 2h13m (batch-0001, batch-0002, batch-0003, ...): Priority`.
+pub(crate) fn pending_lines(jobs: &[(&str, u64, &str)]) -> Vec<String> {
+    let mut by_reason: BTreeMap<&str, Vec<(&str, u64)>> = BTreeMap::new();
+    for (name, secs, reason) in jobs {
+        by_reason.entry(reason).or_default().push((name, *secs));
+    }
+    by_reason
+        .into_iter()
+        .map(|(reason, jobs)| {
+            let longest = jobs.iter().map(|(_, secs)| *secs).max().unwrap_or(0);
+            if let [(name, secs)] = jobs[..] {
+                return format!(
+                    "{} pending {}: {}",
+                    name,
+                    units::format_duration(secs),
+                    reason
+                );
+            }
+            let mut names = jobs
+                .iter()
+                .take(PENDING_NAMES_SHOWN)
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>();
+            if jobs.len() > PENDING_NAMES_SHOWN {
+                names.push("...");
+            }
+            format!(
+                "{} jobs pending up to {} ({}): {}",
+                jobs.len(),
+                units::format_duration(longest),
+                names.join(", "),
+                reason
+            )
+        })
+        .collect()
+}
+
+fn unknown(id: &str) -> JobStatus {
+    JobStatus {
+        job_id: id.to_string(),
+        state: "UNKNOWN".to_string(),
+        elapsed: None,
+        exit_code: None,
+        node: None,
+        start: None,
+        end: None,
+    }
+}
+
+fn run_status_command(
+    command: &str,
+    ids: &[&str],
+) -> Result<HashMap<String, JobStatus>, Box<dyn std::error::Error>> {
+    let parts = shlex::split(command)
+        .ok_or_else(|| format!("could not parse --status-command {:?}", command))?;
+    let (program, args) = parts
+        .split_first()
+        .ok_or("--status-command cannot be empty")?;
+    let output = Command::new(program)
+        .args(args)
+        .arg(ids.join(","))
+        .output()
+        .map_err(|e| format!("could not run --status-command {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "--status-command {} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(parse_sacct(&String::from_utf8_lossy(&output.stdout))
+        .into_iter()
+        .map(|s| (s.job_id.clone(), s))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pending_jobs_grouped_by_reason() {
+        let jobs = [
+            ("batch-0007", 2 * 3600 + 13 * 60, "ReqNodeNotAvail"),
+            ("batch-0001", 1900, "Priority"),
+            ("batch-0002", 2000, "Priority"),
+            ("batch-0003", 1800, "Priority"),
+            ("batch-0004", 4000, "Priority"),
+            ("batch-0005", 1850, "Resources"),
+            ("batch-0006", 1850, "Resources"),
+        ];
+        assert_eq!(
+            pending_lines(&jobs),
+            [
+                "4 jobs pending up to 1h06m (batch-0001, batch-0002, batch-0003, ...): Priority",
+                "batch-0007 pending 2h13m: ReqNodeNotAvail",
+                "2 jobs pending up to 30m50s (batch-0005, batch-0006): Resources",
+            ]
+        );
+        assert!(pending_lines(&[]).is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+use crate::failures::marker_counts;
+use crate::scheduler::{is_final_state, JobStatus, Scheduler};
+use crate::state::{JobState, RunState};
+use crate::{units, wait, Interrupt};
+use clap::{Parser, ValueHint};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How much of the end of each log is read for the tail lines.
+const TAIL_BYTES: u64 = 8 * 1024;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "batchelor watch",
+    about = "Live view of the jobs of a previous run: states, input progress and log tails"
+)]
+pub struct WatchCli {
+    /// Output directory the run was submitted from.
+    #[arg(long, default_value = ".batchelor", value_hint = ValueHint::DirPath)]
+    out_dir: PathBuf,
+
+    /// Run ID to watch (default: the most recent run).
+    #[arg(long)]
+    run: Option<String>,
+
+    /// How often to refresh, e.g. 10s or 1m.
+    #[arg(long, default_value = "10s", value_parser = units::parse_duration)]
+    interval: u64,
+
+    /// Last lines of each running job's log to show (0 for none).
+    #[arg(long, default_value_t = 2)]
+    tail: usize,
+}
+
+/// Watches the run until every job has finished. Returns the exit code:
+/// 0 when every job completed, 2 when any failed, 130 after Ctrl-C (the
+/// jobs keep running).
+pub fn watch(cli: WatchCli) -> Result<i32, Box<dyn std::error::Error>> {
+    let state = RunState::load_run(&cli.out_dir, cli.run.as_deref())?;
+    if state.scheduler != Scheduler::Slurm {
+        return Err(format!(
+            "watch needs sacct or squeue (SLURM); run {} was submitted with {:?}",
+            state.run_id, state.submit
+        )
+        .into());
+    }
+    let jobs = state.jobs.iter().collect::<Vec<_>>();
+    let ids = state.job_ids();
+    let redraw = io::stdout().is_terminal();
+    let interrupt = Interrupt::new();
+    interrupt.install_handler()?;
+
+    loop {
+        let statuses = state
+            .scheduler
+            .job_statuses(&ids)
+            .ok_or("could not run sacct or squeue")?;
+        let mut frame = header(&state, &jobs, &statuses);
+        for job in &jobs {
+            let status = job.job_id.as_ref().and_then(|id| statuses.get(id));
+            frame.push_str(&job_line(&cli, job, status));
+            if cli.tail > 0 && status.is_some_and(|s| s.state == "RUNNING") {
+                for line in tail_lines(job, cli.tail) {
+                    frame.push_str(&format!("    | {}\n", line));
+                }
+            }
+        }
+
+        let mut stdout = io::stdout().lock();
+        if redraw {
+            write!(stdout, "\x1b[2J\x1b[H{}", frame)?;
+        } else {
+            writeln!(stdout, "{}", frame)?;
+        }
+        stdout.flush()?;
+        drop(stdout);
+
+        let running = ids
+            .iter()
+            .any(|id| statuses.get(*id).is_some_and(|s| !is_final_state(&s.state)));
+        if !running {
+            let failed = ids
+                .iter()
+                .any(|id| !statuses.get(*id).is_some_and(wait::succeeded));
+            return Ok(if failed { 2 } else { 0 });
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(cli.interval.max(1));
+        while Instant::now() < deadline {
+            if interrupt.is_interrupted() {
+                eprintln!("Stopped watching; the jobs keep running.");
+                return Ok(130);
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+/// `Run ID (HH:MM:SS): 5 job(s): 2 RUNNING, 3 COMPLETED`
+fn header(state: &RunState, jobs: &[&JobState], statuses: &HashMap<String, JobStatus>) -> String {
+    let mut counts = BTreeMap::new();
+    for job in jobs {
+        let state = match &job.job_id {
+            None => "UNTRACKED",
+            Some(id) => statuses.get(id).map_or("UNKNOWN", |s| s.state.as_str()),
+        };
+        *counts.entry(state).or_insert(0usize) += 1;
+    }
+    let counts = counts
+        .iter()
+        .map(|(state, n)| format!("{} {}", n, state))
+        .collect::<Vec<_>>();
+    format!(
+        "Run {} ({}): {} job(s): {}\n",
+        state.run_id,
+        chrono::Local::now().format("%H:%M:%S"),
+        jobs.len(),
+        counts.join(", ")
+    )
+}
+
+fn job_line(cli: &WatchCli, job: &JobState, status: Option<&JobStatus>) -> String {
+    let state = match (&job.job_id, status) {
+        (None, _) => "UNTRACKED",
+        (Some(_), None) => "UNKNOWN",
+        (Some(_), Some(s)) => s.state.as_str(),
+    };
+    let (done, failed) = marker_counts(&cli.out_dir, job).unwrap_or((0, 0));
+    // Runs from before state files do not record their inputs.
+    let mut progress = match job.inputs.len() {
+        0 => format!("{} inputs done", done),
+        total => format!("{}/{} inputs", done, total),
+    };
+    if failed > 0 {
+        progress.push_str(&format!(", {} failed", failed));
+    }
+    format!(
+        "  {:<16} {:>10} {:<14} {:>10}  {}\n",
+        job.job_name,
+        job.job_id.as_deref().unwrap_or("-"),
+        state,
+        status.and_then(|s| s.elapsed.as_deref()).unwrap_or("-"),
+        progress
+    )
+}
+
+/// The last `count` non-empty lines of the job's log, if it can be read.
+fn tail_lines(job: &JobState, count: usize) -> Vec<String> {
+    let Some(mut file) = job.log_path().and_then(|p| File::open(p).ok()) else {
+        return Vec::new();
+    };
+    let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+    if file
+        .seek(SeekFrom::Start(len.saturating_sub(TAIL_BYTES)))
+        .is_err()
+    {
+        return Vec::new();
+    }
+    let mut bytes = Vec::new();
+    if file.read_to_end(&mut bytes).is_err() {
+        return Vec::new();
+    }
+    let text = String::from_utf8_lossy(&bytes);
+    let lines = text
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .collect::<Vec<_>>();
+    lines[lines.len().saturating_sub(count)..]
+        .iter()
+        .map(|l| l.to_string())
+        .collect()
+}
@@ -0,0 +1,68 @@
+//! PATH lookup for the programs batchelor runs, with did-you-mean hints.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Submit programs worth suggesting even when they are not installed here.
+const KNOWN_SUBMITTERS: &[&str] = &["sbatch", "qsub", "bsub", "msub", "srun", "bash", "sh"];
+
+/// Resolves `program` like a shell would: paths containing `/` are checked
+/// directly, bare names are searched on `PATH`.
+pub fn which(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return is_executable(&path).then_some(path);
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|candidate| is_executable(candidate))
+}
+
+/// Suggests the closest known submitter or PATH executable to `program`.
+pub fn closest_program(program: &str) -> Option<String> {
+    let mut candidates: Vec<String> = KNOWN_SUBMITTERS.iter().map(|s| s.to_string()).collect();
+    if let Some(path) = env::var_os("PATH") {
+        for dir in env::split_paths(&path) {
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            candidates.extend(
+                entries
+                    .filter_map(Result::ok)
+                    .filter_map(|e| e.file_name().into_string().ok()),
+            );
+        }
+    }
+
+    let name = Path::new(program)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(program);
+    let max_distance = (name.len() / 3).max(2);
+    candidates
+        .into_iter()
+        .filter(|c| c != name)
+        .map(|c| (strsim::damerau_levenshtein(name, &c), c))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, c)| c)
+}
+
+fn is_executable(path: &Path) -> bool {
+    let Ok(meta) = fs::metadata(path) else {
+        return false;
+    };
+    if !meta.is_file() {
+        return false;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        meta.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        true
+    }
+}
@@ -0,0 +1,138 @@
+//! `batchelor archive`: the tarball of a run, read back.
+
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::{assert_exit, stdout, Fixture};
+use flate2::read::GzDecoder;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+
+/// The files in the tarball at `path`, by name, with their contents.
+fn contents(path: &std::path::Path) -> BTreeMap<String, Vec<u8>> {
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(path).unwrap()));
+    let mut files = BTreeMap::new();
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        assert!(entry.header().entry_type().is_file());
+        let name = entry.path().unwrap().to_string_lossy().into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).unwrap();
+        assert!(files.insert(name, data).is_none());
+    }
+    files
+}
+
+#[test]
+fn archives_hold_the_run_directory() {
+    let fixture = Fixture::new(4);
+    let output = fixture.submit_recorded(&["--batch", "2", "--keep", "--run-id", "run"]);
+    assert_exit(&output, 0);
+    let run_dir = fixture.join(".batchelor/runs/run");
+    fs::write(run_dir.join("batch-0001.done"), "in/1.fq\n").unwrap();
+
+    let output = fixture.run(["archive", "--run", "run", "--out", "run.tar.gz"]);
+    assert_exit(&output, 0);
+    assert!(stdout(&output).contains("Archived run run (5 file(s)) to run.tar.gz"));
+
+    let files = contents(&fixture.join("run.tar.gz"));
+    assert_eq!(
+        files.keys().collect::<Vec<_>>(),
+        [
+            "batchelor-run/README.txt",
+            "batchelor-run/run/batch-0001.batch.sh",
+            "batchelor-run/run/batch-0001.done",
+            "batchelor-run/run/batch-0002.batch.sh",
+            "batchelor-run/run/state.json",
+        ]
+    );
+    for name in [
+        "batch-0001.batch.sh",
+        "batch-0001.done",
+        "batch-0002.batch.sh",
+        "state.json",
+    ] {
+        assert_eq!(
+            files[&format!("batchelor-run/run/{}", name)],
+            fs::read(run_dir.join(name)).unwrap(),
+            "{}",
+            name
+        );
+    }
+    let readme = String::from_utf8(files["batchelor-run/README.txt"].clone()).unwrap();
+    assert!(readme.starts_with("batchelor run run\n"), "{}", readme);
+    assert!(
+        readme.contains("Batches:   2 covering 4 input(s)"),
+        "{}",
+        readme
+    );
+}
+
+#[test]
+fn archives_collect_files_outside_the_run_directory() {
+    let fixture = Fixture::new(4);
+    let output = fixture.submit_recorded(&[
+        "--batch",
+        "2",
+        "--keep",
+        "--run-id",
+        "flat",
+        "--flat-out-dir",
+        "--job-log-dir",
+        "logs",
+    ]);
+    assert_exit(&output, 0);
+    fixture.write("logs/batch-0001.1.out", "log of job 1\n");
+    fixture.write(".batchelor/batch-0001.done", "in/1.fq\n");
+    let gone = fixture.join(".batchelor/batch-0002.batch.sh");
+    fs::remove_file(&gone).unwrap();
+
+    let output = fixture.run(["archive", "--logs", "--out", "flat.tar.gz"]);
+    assert_exit(&output, 0);
+    assert!(stdout(&output).contains("5 file(s) no longer exist; see README.txt"));
+
+    let files = contents(&fixture.join("flat.tar.gz"));
+    assert_eq!(
+        files.keys().collect::<Vec<_>>(),
+        [
+            "batchelor-flat/README.txt",
+            "batchelor-flat/logs/batch-0001.1.out",
+            "batchelor-flat/markers/batch-0001.done",
+            "batchelor-flat/run/state.json",
+            "batchelor-flat/scripts/batch-0001.batch.sh",
+        ]
+    );
+    assert_eq!(
+        files["batchelor-flat/logs/batch-0001.1.out"],
+        b"log of job 1\n"
+    );
+    assert_eq!(
+        files["batchelor-flat/markers/batch-0001.done"],
+        b"in/1.fq\n"
+    );
+    assert_eq!(
+        files["batchelor-flat/scripts/batch-0001.batch.sh"],
+        fs::read(fixture.join(".batchelor/batch-0001.batch.sh")).unwrap()
+    );
+    let readme = String::from_utf8(files["batchelor-flat/README.txt"].clone()).unwrap();
+    assert!(
+        readme.contains(&format!("scripts {}", gone.display())),
+        "{}",
+        readme
+    );
+    // Markers are missing until the job writes them.
+    let marker = fixture.join(".batchelor/batch-0002.done");
+    assert!(
+        readme.contains(&format!("markers {}", marker.display())),
+        "{}",
+        readme
+    );
+    let log = fixture.join("logs/batch-0002.2.out");
+    assert!(
+        readme.contains(&format!("logs {}", log.display())),
+        "{}",
+        readme
+    );
+}
@@ -0,0 +1,302 @@
+//! `batchelor clean` and the cleanup a run does after submitting.
+
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::{assert_exit, stderr, stdout, walk, Fixture};
+use std::fs;
+
+/// Submits the fixture's inputs as run `run_id`, keeping its scripts.
+fn kept_run(fixture: &Fixture, run_id: &str, args: &[&str]) -> Vec<String> {
+    let mut argv = vec!["--batch", "2", "--keep", "--run-id", run_id];
+    argv.extend(args);
+    assert_exit(&fixture.submit_recorded(&argv), 0);
+    let state = fixture.state_of(run_id);
+    state["jobs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|job| job["script"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn clean_leaves_other_runs_alone() {
+    let fixture = Fixture::new(4);
+    let old = kept_run(&fixture, "old", &[]);
+    let new = kept_run(&fixture, "new", &[]);
+    let contents = new
+        .iter()
+        .map(|script| fs::read(script).unwrap())
+        .collect::<Vec<_>>();
+
+    // The recorded jobs cannot be looked up without sacct: --force.
+    let output = fixture.run(["clean", "--run", "old", "--force"]);
+    assert_exit(&output, 0);
+    assert!(stdout(&output).contains("Cleaned 1 run(s)"));
+    assert!(old.iter().all(|script| !fs::exists(script).unwrap()));
+    assert!(!fixture.join(".batchelor/runs/old").exists());
+    for (script, contents) in new.iter().zip(&contents) {
+        assert_eq!(&fs::read(script).unwrap(), contents);
+    }
+    assert!(fixture.join(".batchelor/runs/new/state.json").is_file());
+}
+
+#[test]
+fn clean_keeps_scripts_another_run_reuses() {
+    let fixture = Fixture::new(4);
+    let old = kept_run(&fixture, "old", &["--flat-out-dir"]);
+    let new = kept_run(&fixture, "new", &["--flat-out-dir"]);
+    // Same job names, same scripts in --out-dir.
+    assert_eq!(old, new);
+
+    let output = fixture.run(["clean", "--run", "old", "--force"]);
+    assert_exit(&output, 0);
+    assert!(new.iter().all(|script| fs::exists(script).unwrap()));
+    assert!(!stdout(&output).contains(".batch.sh"));
+}
+
+#[test]
+fn clean_dry_run_removes_nothing() {
+    let fixture = Fixture::new(2);
+    let old = kept_run(&fixture, "old", &[]);
+    let output = fixture.run(["clean", "--run", "old", "--force", "--dry-run"]);
+    assert_exit(&output, 0);
+    assert!(stdout(&output).contains("Would clean 1 run(s)"));
+    assert!(old.iter().all(|script| fs::exists(script).unwrap()));
+}
+
+#[test]
+fn a_run_does_not_remove_the_scripts_of_earlier_runs() {
+    let fixture = Fixture::new(4);
+    let old = kept_run(&fixture, "old", &[]);
+    assert_exit(
+        &fixture.submit_recorded(&["--batch", "2", "--run-id", "new"]),
+        0,
+    );
+    assert!(old.iter().all(|script| fs::exists(script).unwrap()));
+}
+
+#[test]
+fn unmarked_scripts_are_never_overwritten() {
+    let fixture = Fixture::new(2);
+    let mine = fixture.write(".batchelor/batch-0001.batch.sh", "#!/bin/bash\necho mine\n");
+    let output = fixture.submit_recorded(&["--flat-out-dir"]);
+    assert_exit(&output, 4);
+    let message = format!(
+        "cannot write {}: refusing to overwrite it: it was not generated by batchelor",
+        mine.display()
+    );
+    assert!(stderr(&output).contains(&message), "{}", stderr(&output));
+    assert_eq!(
+        fs::read_to_string(&mine).unwrap(),
+        "#!/bin/bash\necho mine\n"
+    );
+    assert!(fixture.recorded().is_empty());
+}
+
+#[test]
+fn unmarked_scripts_are_never_removed() {
+    let fixture = Fixture::new(2);
+    let old = kept_run(&fixture, "old", &[]);
+    // A hand-written script that took the generated one's place.
+    fs::write(&old[0], "#!/bin/bash\necho mine\n").unwrap();
+    let output = fixture.run(["clean", "--run", "old", "--force"]);
+    assert_exit(&output, 0);
+    assert!(stderr(&output).contains("it was not generated by batchelor"));
+    assert_eq!(
+        fs::read_to_string(&old[0]).unwrap(),
+        "#!/bin/bash\necho mine\n"
+    );
+}
+
+#[test]
+fn no_clean_keeps_the_submitted_scripts() {
+    let fixture = Fixture::new(2);
+    assert_exit(&fixture.submit_recorded(&["--no-clean"]), 0);
+    let script = fixture.recorded()[0]["script"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert!(fs::exists(script).unwrap());
+}
+
+#[test]
+fn clean_only_cleans_finished_runs_and_submits_nothing() {
+    let fixture = Fixture::new(2);
+    fixture.fake_sacct();
+    let old = kept_run(&fixture, "old", &[]);
+    let submitted = fixture.recorded().len();
+
+    let output = fixture.submit_recorded(&["--clean-only", "--dry-run"]);
+    assert_exit(&output, 0);
+    assert!(stdout(&output).contains("Would clean 1 run(s)"));
+    assert!(old.iter().all(|script| fs::exists(script).unwrap()));
+
+    let output = fixture.submit_recorded(&["--clean-only"]);
+    assert_exit(&output, 0);
+    assert!(stdout(&output).contains("Cleaned 1 run(s)"));
+    assert!(old.iter().all(|script| !fs::exists(script).unwrap()));
+    assert!(!fixture.join(".batchelor/runs/old").exists());
+    assert_eq!(fixture.recorded().len(), submitted);
+}
+
+/// Submits the fixture's inputs through a fake `sbatch` that runs `script`
+/// with `$script` set to the batch script it was given.
+fn submit_with(fixture: &Fixture, script: &str, args: &[&str]) -> std::process::Output {
+    fixture.fake_program(
+        "sbatch",
+        &format!("for script; do :; done\n{}echo 1001\n", script),
+    );
+    let mut argv = vec![
+        "--script",
+        "script.sh",
+        "--glob",
+        "in/*.fq",
+        "--no-preflight",
+    ];
+    argv.extend(args);
+    fixture.run(argv)
+}
+
+fn scripts(fixture: &Fixture) -> Vec<String> {
+    walk(fixture.path())
+        .into_iter()
+        .filter(|path| path.ends_with(".batch.sh"))
+        .collect()
+}
+
+#[cfg(unix)]
+#[test]
+fn scripts_that_cannot_be_removed_are_warned_about() {
+    use std::os::unix::fs::PermissionsExt;
+    let fixture = Fixture::new(2);
+    if !fixture.permissions_enforced() {
+        eprintln!("skipped: read-only directories do not stop this user");
+        return;
+    }
+    let read_only = "chmod a-w \"$(dirname \"$script\")\"\n";
+    let writable = |script: &str| {
+        let dir = std::path::Path::new(script).parent().unwrap();
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o755)).unwrap();
+    };
+
+    // A shared --out-dir holding nothing but scripts.
+    let output = submit_with(
+        &fixture,
+        read_only,
+        &["--out-dir", "shared", "--flat-out-dir"],
+    );
+    assert_exit(&output, 0);
+    assert!(stderr(&output).contains("warning: could not remove"));
+    assert!(stderr(&output).contains("1 script(s) were left behind"));
+    let left = scripts(&fixture);
+    assert_eq!(left.len(), 1);
+    writable(&left[0]);
+
+    let fixture = Fixture::new(2);
+    let output = submit_with(
+        &fixture,
+        read_only,
+        &["--out-dir", "shared", "--flat-out-dir", "--clean-strict"],
+    );
+    assert_exit(&output, 1);
+    assert!(
+        stderr(&output).contains("(--clean-strict)"),
+        "{}",
+        stderr(&output)
+    );
+    writable(&scripts(&fixture)[0]);
+}
+
+#[test]
+fn read_only_foreign_files_are_skipped_silently() {
+    let fixture = Fixture::new(2);
+    // Someone else's read-only file took the submitted script's place.
+    let decoy = "chmod u+w \"$script\"\n\
+                 printf '#!/bin/bash\\necho mine\\n' > \"$script\"\n\
+                 chmod 444 \"$script\"\n";
+    let output = submit_with(&fixture, decoy, &["--clean-strict"]);
+    assert_exit(&output, 0);
+    assert!(!stderr(&output).contains("warning"), "{}", stderr(&output));
+    let left = scripts(&fixture);
+    assert_eq!(left.len(), 1);
+    assert_eq!(
+        fs::read_to_string(&left[0]).unwrap(),
+        "#!/bin/bash\necho mine\n"
+    );
+}
+
+/// The contents and mtime of each of `paths`.
+fn snapshot(paths: &[String]) -> Vec<(Vec<u8>, std::time::SystemTime)> {
+    paths
+        .iter()
+        .map(|path| {
+            let metadata = fs::metadata(path).unwrap();
+            (fs::read(path).unwrap(), metadata.modified().unwrap())
+        })
+        .collect()
+}
+
+/// Backdates `path`, so that rewriting it would show in its mtime.
+fn backdate(path: &str) {
+    let an_hour_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(an_hour_ago)
+        .unwrap();
+}
+
+#[test]
+fn dry_runs_leave_kept_scripts_byte_identical() {
+    let fixture = Fixture::new(4);
+    let kept = kept_run(&fixture, "real", &["--flat-out-dir"]);
+    kept.iter().for_each(|script| backdate(script));
+    let before = snapshot(&kept);
+
+    // The same run again, as a preview.
+    for args in [&["--dry-run"][..], &["--dry-run", "--no-write"]] {
+        let mut argv = vec![
+            "--batch",
+            "2",
+            "--keep",
+            "--flat-out-dir",
+            "--run-id",
+            "real",
+        ];
+        argv.extend(args);
+        let output = fixture.submit_recorded(&argv);
+        assert_exit(&output, 0);
+        assert_eq!(snapshot(&kept), before, "{:?}", args);
+    }
+    // The preview went elsewhere.
+    assert!(walk(&fixture.join(".batchelor/dry-run/real"))
+        .iter()
+        .any(|path| path.ends_with("batch-0001.batch.sh")));
+    assert_eq!(fixture.recorded().len(), 2);
+}
+
+#[test]
+fn dry_runs_leave_foreign_scripts_alone() {
+    let fixture = Fixture::new(2);
+    let old = fixture.write(".batchelor/batch-0001.batch.sh", "#!/bin/bash\necho old\n");
+    let old = old.to_string_lossy().into_owned();
+    backdate(&old);
+    let before = snapshot(std::slice::from_ref(&old));
+    let output = fixture.submit_recorded(&["--flat-out-dir", "--dry-run"]);
+    assert_exit(&output, 0);
+    assert_eq!(snapshot(std::slice::from_ref(&old)), before);
+}
+
+#[test]
+fn no_write_writes_nothing() {
+    let fixture = Fixture::new(2);
+    let before = walk(fixture.path());
+    let output = fixture.submit_recorded(&["--dry-run", "--no-write"]);
+    assert_exit(&output, 0);
+    assert!(stdout(&output).contains("[dry-run]"));
+    assert_eq!(walk(fixture.path()), before);
+}
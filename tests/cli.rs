@@ -0,0 +1,161 @@
+//! The command line itself: subcommands, completions and the man page.
+
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::{assert_exit, stdout, Fixture};
+
+#[test]
+fn completions_for_each_shell() {
+    let fixture = Fixture::new(0);
+    for shell in ["bash", "zsh", "fish", "powershell", "elvish"] {
+        let output = fixture.run(["completions", shell]);
+        assert_exit(&output, 0);
+        let script = stdout(&output);
+        assert!(script.contains("--out-dir"), "{}:\n{}", shell, script);
+    }
+    assert_exit(&fixture.run(["completions", "tcsh"]), 2);
+}
+
+#[test]
+fn man_pages_for_batchelor_and_each_subcommand() {
+    let fixture = Fixture::new(0);
+    let output = fixture.run(["man"]);
+    assert_exit(&output, 0);
+    let page = stdout(&output);
+    for option in ["\\-\\-script", "\\-\\-glob", "\\-\\-out\\-dir", "EXAMPLES"] {
+        assert!(page.contains(option), "no {}", option);
+    }
+
+    let output = fixture.run(["man", "--dir", "man"]);
+    assert_exit(&output, 0);
+    for page in ["batchelor.1", "batchelor-status.1", "batchelor-clean.1"] {
+        let text = std::fs::read_to_string(fixture.join("man").join(page)).unwrap();
+        assert!(text.contains(".SH OPTIONS"), "{}", page);
+    }
+}
+
+/// The argv and script of each recorded submission, with the run's
+/// directory left out.
+fn submissions(fixture: &Fixture) -> Vec<(serde_json::Value, String)> {
+    fixture
+        .recorded()
+        .iter()
+        .map(|submission| {
+            let script = submission["script"].as_str().unwrap();
+            let name = std::path::Path::new(script).file_name().unwrap();
+            let mut argv = submission["argv"].clone();
+            *argv.as_array_mut().unwrap().last_mut().unwrap() = name.to_str().unwrap().into();
+            (
+                argv,
+                submission["script_contents"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn bare_flags_are_a_submit() {
+    let args = [
+        "--script",
+        "script.sh",
+        "--glob",
+        "in/*.fq",
+        "--batch",
+        "2",
+        "--submit-record",
+        "record",
+        "--run-id",
+        "run",
+    ];
+    let bare = Fixture::new(3);
+    assert_exit(&bare.run(args), 0);
+    let submit = Fixture::new(3);
+    assert_exit(&submit.run(std::iter::once("submit").chain(args)), 0);
+
+    assert_eq!(bare.recorded().len(), 2);
+    let normalize = |fixture: &Fixture| {
+        submissions(fixture)
+            .into_iter()
+            .map(|(argv, script)| {
+                let dir = fixture.path().to_str().unwrap();
+                (argv, script.replace(dir, "<dir>"))
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(normalize(&bare), normalize(&submit));
+}
+
+#[test]
+fn bare_flags_in_any_order() {
+    let fixture = Fixture::new(2);
+    let output = fixture.run([
+        "--submit-record",
+        "record",
+        "--glob",
+        "in/*.fq",
+        "--script",
+        "script.sh",
+    ]);
+    assert_exit(&output, 0);
+    assert_eq!(fixture.recorded().len(), 1);
+}
+
+#[test]
+fn plan_submits_nothing() {
+    let fixture = Fixture::new(2);
+    let output = fixture.run([
+        "plan",
+        "--script",
+        "script.sh",
+        "--glob",
+        "in/*.fq",
+        "--submit-record",
+        "record",
+    ]);
+    assert_exit(&output, 0);
+    let plan: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(plan["batches"].as_array().unwrap().len(), 1);
+    assert!(fixture.recorded().is_empty());
+}
+
+#[test]
+fn plans_do_not_depend_on_the_expand_threads() {
+    let fixture = Fixture::new(0);
+    let mut args = vec![
+        "plan".to_string(),
+        "--script".to_string(),
+        "script.sh".to_string(),
+        "--run-id".to_string(),
+        "run".to_string(),
+        "--batch".to_string(),
+        "7".to_string(),
+    ];
+    for d in 0..10 {
+        for f in 0..4 {
+            fixture.write(&format!("d{}/{}.fq", d, f), "x");
+        }
+        args.push("--glob".to_string());
+        args.push(format!("d{}/*.fq", d));
+    }
+    let plan = |threads: &str| {
+        let mut args = args.clone();
+        args.extend(["--expand-threads".to_string(), threads.to_string()]);
+        let output = fixture.run(&args);
+        assert_exit(&output, 0);
+        output.stdout
+    };
+    let serial = plan("1");
+    assert!(String::from_utf8_lossy(&serial).contains("d9/3.fq"));
+    assert_eq!(plan("8"), serial);
+    assert_eq!(plan("3"), serial);
+}
+
+#[test]
+fn unknown_subcommands_are_not_runs() {
+    let fixture = Fixture::new(1);
+    let output = fixture.run(["stauts"]);
+    assert_exit(&output, 2);
+    assert!(fixture.recorded().is_empty());
+}
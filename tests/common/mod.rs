@@ -0,0 +1,243 @@
+//! A scratch directory with a script and inputs, and the `batchelor`
+//! binary to run in it. Runs see no config file or batchelor environment
+//! variable of the user running the tests.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+pub struct Fixture {
+    dir: tempfile::TempDir,
+}
+
+impl Fixture {
+    /// A directory holding `script.sh` and the inputs `in/1.fq` ..
+    /// `in/<inputs>.fq`.
+    pub fn new(inputs: usize) -> Fixture {
+        let fixture = Fixture {
+            dir: tempfile::tempdir().expect("create a temporary directory"),
+        };
+        fixture.write("script.sh", "#!/bin/bash\necho \"$@\"\n");
+        for i in 1..=inputs {
+            fixture.write(&format!("in/{}.fq", i), &"x".repeat(i));
+        }
+        fixture
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn join(&self, path: &str) -> PathBuf {
+        self.path().join(path)
+    }
+
+    /// Writes `contents` to `path` under the directory, creating parents.
+    pub fn write(&self, path: &str, contents: &str) -> PathBuf {
+        let path = self.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// `batchelor` with `args`, run in the directory.
+    pub fn command<I, S>(&self, args: I) -> Command
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        let mut command = self.program(env!("CARGO_BIN_EXE_batchelor"));
+        command.args(args);
+        command
+    }
+
+    /// `program`, run in the directory as [`Fixture::command`] runs
+    /// `batchelor`: with the fake programs first on the PATH and without the
+    /// user's config.
+    pub fn program(&self, program: impl AsRef<std::ffi::OsStr>) -> Command {
+        let mut command = Command::new(program);
+        let path = std::env::join_paths(std::iter::once(self.join("bin")).chain(
+            std::env::split_paths(&std::env::var_os("PATH").unwrap_or_default()),
+        ))
+        .unwrap();
+        command
+            .current_dir(self.path())
+            .env("PATH", path)
+            .env("HOME", self.path())
+            .env("XDG_CONFIG_HOME", self.join(".config"))
+            .env("NO_COLOR", "1");
+        for (name, _) in std::env::vars_os() {
+            if name.to_string_lossy().starts_with("BATCHELOR_") {
+                command.env_remove(name);
+            }
+        }
+        command
+    }
+
+    /// Runs `batchelor` with `args`.
+    pub fn run<I, S>(&self, args: I) -> Output
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        self.command(args).output().expect("run batchelor")
+    }
+
+    /// Runs a submission of the inputs with `args`, recording submissions
+    /// in `record/` instead of running sbatch.
+    pub fn submit_recorded(&self, args: &[&str]) -> Output {
+        let mut argv = vec![
+            "--script",
+            "script.sh",
+            "--glob",
+            "in/*.fq",
+            "--submit-record",
+            "record",
+        ];
+        argv.extend(args);
+        self.run(argv)
+    }
+
+    /// Puts a program `name` running the shell script `script` first on the
+    /// PATH of [`Fixture::command`].
+    pub fn fake_program(&self, name: &str, script: &str) {
+        let path = self.write(&format!("bin/{}", name), &format!("#!/bin/sh\n{}", script));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+    }
+
+    /// A fake `sbatch` that writes its arguments, one per line, to
+    /// `sbatch-calls/<n>` and prints job ID `1000 + n`.
+    pub fn fake_sbatch(&self) {
+        self.fake_program(
+            "sbatch",
+            "calls=$(dirname \"$0\")/../sbatch-calls\n\
+             mkdir -p \"$calls\"\n\
+             n=$(( $(ls \"$calls\" | wc -l) + 1 ))\n\
+             printf '%s\\n' \"$@\" > \"$calls/$n\"\n\
+             echo $((1000 + n))\n",
+        );
+    }
+
+    /// A fake `sacct` reporting every job asked about as completed.
+    pub fn fake_sacct(&self) {
+        self.fake_sacct_reporting("COMPLETED|00:00:01|0:0");
+    }
+
+    /// A fake `sacct` reporting every job asked about with `fields`: its
+    /// state, elapsed time and exit code, `|`-separated.
+    pub fn fake_sacct_reporting(&self, fields: &str) {
+        self.fake_program(
+            "sacct",
+            &format!(
+                "while [ $# -gt 0 ]; do [ \"$1\" = -j ] && ids=$2; shift; done\n\
+                 for id in $(echo \"$ids\" | tr , ' '); do echo \"$id|{}|node1\"; done\n",
+                fields
+            ),
+        );
+    }
+
+    /// Whether a read-only directory keeps this process from writing in it;
+    /// it does not for root.
+    pub fn permissions_enforced(&self) -> bool {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let dir = self.join("read-only");
+            fs::create_dir_all(&dir).unwrap();
+            fs::set_permissions(&dir, fs::Permissions::from_mode(0o555)).unwrap();
+            let enforced = fs::write(dir.join("probe"), "").is_err();
+            fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+            fs::remove_dir_all(&dir).unwrap();
+            enforced
+        }
+        #[cfg(not(unix))]
+        false
+    }
+
+    /// The arguments of each call of [`Fixture::fake_sbatch`], in order.
+    pub fn sbatch_calls(&self) -> Vec<Vec<String>> {
+        let mut calls = Vec::new();
+        while let Ok(text) =
+            fs::read_to_string(self.join(&format!("sbatch-calls/{}", calls.len() + 1)))
+        {
+            calls.push(text.lines().map(str::to_string).collect());
+        }
+        calls
+    }
+
+    /// The submissions recorded in `record/`, in order.
+    pub fn recorded(&self) -> Vec<serde_json::Value> {
+        let mut paths = fs::read_dir(self.join("record"))
+            .map(|entries| {
+                entries
+                    .map(|entry| entry.unwrap().path())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        paths.sort();
+        paths
+            .iter()
+            .map(|path| serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap())
+            .collect()
+    }
+
+    /// The state of the latest run under `.batchelor`.
+    pub fn state(&self) -> serde_json::Value {
+        let mut runs = fs::read_dir(self.join(".batchelor/runs"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        runs.sort();
+        let path = runs.last().expect("a run").join("state.json");
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    /// The state of run `run_id` under `.batchelor`.
+    pub fn state_of(&self, run_id: &str) -> serde_json::Value {
+        let path = self.join(&format!(".batchelor/runs/{}/state.json", run_id));
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+}
+
+/// The exit code of `output`, printing its stderr when it is not
+/// `expected` to make failures readable.
+pub fn assert_exit(output: &Output, expected: i32) {
+    assert_eq!(
+        output.status.code(),
+        Some(expected),
+        "stdout:\n{}\nstderr:\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// The files under `dir`, recursively, as strings.
+pub fn walk(dir: &Path) -> Vec<String> {
+    let mut files = Vec::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return files;
+    };
+    for entry in entries {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(walk(&path));
+        } else {
+            files.push(path.to_string_lossy().into_owned());
+        }
+    }
+    files
+}
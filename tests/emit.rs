@@ -0,0 +1,305 @@
+//! `--emit`: the files written for make, Snakemake and Nextflow, checked
+//! against the tools that read them where they are installed and against
+//! golden copies under `tests/golden/emit`.
+
+#![cfg(all(feature = "cli", unix))]
+
+mod common;
+
+use common::{assert_exit, Fixture};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// The fixture's `in/1.fq` next to inputs whose names need quoting in a
+/// shell, a Makefile and a Python string; the script records the input it
+/// was given in `ran.txt`.
+fn fixture() -> Fixture {
+    let fixture = Fixture::new(1);
+    fixture.write(
+        "script.sh",
+        "#!/bin/bash\nprintf '%s\\n' \"$2\" >> \"$(dirname \"$0\")/ran.txt\"\n",
+    );
+    for name in ["a b.fq", "it$s.fq", "it's #1.fq"] {
+        fixture.write(&format!("in/{}", name), "x");
+    }
+    fixture
+}
+
+fn emit(fixture: &Fixture, args: &[&str]) {
+    let mut all = vec!["--script", "script.sh", "--glob", "in/*.fq"];
+    all.extend(args);
+    assert_exit(&fixture.run(all), 0);
+}
+
+/// Whether `program` can be run here; tests of what it makes of an emitted
+/// file are skipped where it cannot.
+fn installed(program: &str) -> bool {
+    Command::new(program).arg("--version").output().is_ok()
+}
+
+/// Compares `path`, with the fixture directory written as `@DIR@`, to the
+/// golden copy `name`. `UPDATE_GOLDEN=1` rewrites the golden copy instead.
+fn assert_golden(fixture: &Fixture, path: &Path, name: &str) {
+    let dir = fixture.path().canonicalize().unwrap();
+    let actual = fs::read_to_string(path)
+        .unwrap()
+        .replace(dir.to_str().unwrap(), "@DIR@");
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden/emit")
+        .join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&golden, &actual).unwrap();
+    }
+    let expected =
+        fs::read_to_string(&golden).unwrap_or_else(|e| panic!("read {}: {}", golden.display(), e));
+    assert_eq!(actual, expected, "{} differs from {}", path.display(), name);
+}
+
+fn make(fixture: &Fixture, args: &[&str]) -> std::process::Output {
+    Command::new("make")
+        .args(["-f", "Makefile"])
+        .args(args)
+        .current_dir(fixture.path())
+        .output()
+        .expect("run make")
+}
+
+#[test]
+fn makefiles_match_the_golden_copy() {
+    let fixture = fixture();
+    emit(&fixture, &["--emit", "make", "Makefile"]);
+    assert_golden(&fixture, &fixture.join("Makefile"), "Makefile");
+}
+
+#[test]
+fn make_runs_every_input_once() {
+    if !installed("make") {
+        return;
+    }
+    let fixture = fixture();
+    emit(&fixture, &["--emit", "make", "Makefile"]);
+
+    let dry = make(&fixture, &["-n"]);
+    assert!(
+        dry.status.success(),
+        "{}",
+        String::from_utf8_lossy(&dry.stderr)
+    );
+    let dry = String::from_utf8_lossy(&dry.stdout);
+    assert_eq!(dry.matches("script.sh --input").count(), 4, "{}", dry);
+    assert!(dry.contains("/in/it$s.fq'"), "{}", dry);
+    assert!(!fixture.join("ran.txt").exists());
+
+    let run = make(&fixture, &[]);
+    assert!(
+        run.status.success(),
+        "{}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+    let mut ran: Vec<String> = fs::read_to_string(fixture.join("ran.txt"))
+        .unwrap()
+        .lines()
+        .map(str::to_owned)
+        .collect();
+    ran.sort();
+    let dir = fixture.path().canonicalize().unwrap();
+    let expected: Vec<String> = ["1.fq", "a b.fq", "it$s.fq", "it's #1.fq"]
+        .iter()
+        .map(|name| dir.join("in").join(name).to_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(ran, expected);
+
+    // Every stamp is in place: nothing is left to run.
+    let again = make(&fixture, &["-q"]);
+    assert_eq!(again.status.code(), Some(0));
+}
+
+/// Checks each line of a Snakefile: rule and directive headers, and entries
+/// that must compile as Python call arguments. Prints the shell commands,
+/// decoded and with the braces `str.format` would undouble undoubled.
+const SNAKEFILE_CHECK: &str = r##"
+import ast, re, sys
+path = sys.argv[1]
+shells, directive = [], None
+for n, line in enumerate(open(path).read().splitlines(), 1):
+    if not line.strip() or line.startswith("#") or re.fullmatch(r"rule \w+:", line):
+        continue
+    header = re.fullmatch(r"    (\w+):", line)
+    if header:
+        directive = header.group(1)
+    elif line.startswith("        "):
+        call = "f(" + line.strip() + ")"
+        compile(call, "%s:%d" % (path, n), "eval")
+        if directive == "shell":
+            shell = ast.literal_eval(ast.parse(call, mode="eval").body.args[0])
+            shells.append(shell.replace("{{", "{").replace("}}", "}"))
+    else:
+        sys.exit("%s:%d: not a Snakefile line: %s" % (path, n, line))
+sys.stdout.write("\n".join(shells) + "\n")
+"##;
+
+#[test]
+fn snakefiles_match_the_golden_copy() {
+    let fixture = fixture();
+    emit(
+        &fixture,
+        &["--emit", "snakemake", "Snakefile", "--mem-base", "2G"],
+    );
+    assert_golden(&fixture, &fixture.join("Snakefile"), "Snakefile");
+}
+
+#[test]
+fn snakefiles_are_python_and_quote_every_input() {
+    if !installed("python3") {
+        return;
+    }
+    let fixture = fixture();
+    // Braces, quotes and backslashes matter to Python strings and to
+    // Snakemake's formatting of the shell command.
+    fixture.write("in/b{1}.fq", "x");
+    fixture.write("in/q\"\\.fq", "x");
+    emit(&fixture, &["--emit", "snakemake", "Snakefile"]);
+
+    let check = Command::new("python3")
+        .args(["-c", SNAKEFILE_CHECK])
+        .arg(fixture.join("Snakefile"))
+        .output()
+        .expect("run python3");
+    assert!(
+        check.status.success(),
+        "{}",
+        String::from_utf8_lossy(&check.stderr)
+    );
+
+    // The commands, run as Snakemake would, get each input unchanged.
+    let run = Command::new("bash")
+        .args(["-euo", "pipefail", "-c"])
+        .arg(String::from_utf8(check.stdout).unwrap())
+        .current_dir(fixture.path())
+        .output()
+        .unwrap();
+    assert!(
+        run.status.success(),
+        "{}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+    let mut ran: Vec<String> = fs::read_to_string(fixture.join("ran.txt"))
+        .unwrap()
+        .lines()
+        .map(str::to_owned)
+        .collect();
+    ran.sort();
+    let dir = fixture.path().canonicalize().unwrap();
+    let mut expected: Vec<String> = [
+        "1.fq",
+        "a b.fq",
+        "b{1}.fq",
+        "it$s.fq",
+        "it's #1.fq",
+        "q\"\\.fq",
+    ]
+    .iter()
+    .map(|name| dir.join("in").join(name).to_str().unwrap().to_owned())
+    .collect();
+    expected.sort();
+    assert_eq!(ran, expected);
+}
+
+/// Runs the process of a `main.nf` once per row of its samplesheet, as
+/// Nextflow would: the file staged under its own name in a work directory of
+/// its own, the `input=` line interpolated and the command unescaped the way
+/// Groovy treats the script block. Prints the samples.
+const NEXTFLOW_RUN: &str = r##"
+import csv, os, re, subprocess, sys
+main_nf, sheet, work = sys.argv[1:4]
+lines = [l.strip() for l in open(main_nf).read().split('    """\n')[1].splitlines()]
+input_line, command = lines[0], "\n".join(lines[1:])
+command = re.sub(r"\\(.)", r"\1", command)
+for n, row in enumerate(csv.DictReader(open(sheet, newline=""))):
+    task = os.path.join(work, str(n))
+    os.makedirs(task)
+    staged = os.path.basename(row["file"])
+    os.symlink(row["file"], os.path.join(task, staged))
+    line = input_line.replace(r"""${file.toString().replace("'", "'\\''")}""", staged.replace("'", "'\\''"))
+    subprocess.run(["bash", "-euo", "pipefail", "-c", line + "\n" + command], cwd=task, check=True)
+    print(row["sample"])
+"##;
+
+fn emit_nextflow(fixture: &Fixture) {
+    for name in ["c,d.fq", "q\"x.fq", "1.fastq"] {
+        fixture.write(&format!("in/{}", name), "x");
+    }
+    let all = [
+        "--script",
+        "script.sh",
+        "--glob",
+        "in/*",
+        "--script-args",
+        "it's $HOME\\",
+        "--emit",
+        "nextflow",
+        "nf",
+    ];
+    assert_exit(&fixture.run(all), 0);
+}
+
+#[test]
+fn nextflow_files_match_the_golden_copies() {
+    let fixture = fixture();
+    emit_nextflow(&fixture);
+    for name in ["samplesheet.csv", "main.nf"] {
+        assert_golden(&fixture, &fixture.join("nf").join(name), name);
+    }
+}
+
+#[test]
+fn nextflow_tasks_get_each_input_and_arg_unchanged() {
+    if !installed("python3") {
+        return;
+    }
+    let fixture = fixture();
+    fixture.write(
+        "script.sh",
+        "#!/bin/bash\nprintf '%s|%s\\n' \"$2\" \"$3\" >> \"$(dirname \"$0\")/ran.txt\"\n",
+    );
+    emit_nextflow(&fixture);
+
+    let run = Command::new("python3")
+        .args(["-c", NEXTFLOW_RUN])
+        .args([
+            fixture.join("nf/main.nf"),
+            fixture.join("nf/samplesheet.csv"),
+        ])
+        .arg(fixture.join("work"))
+        .output()
+        .expect("run python3");
+    assert!(
+        run.status.success(),
+        "{}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(run.stdout).unwrap(),
+        "1\n1_2\na_b\nc,d\nit$s\nit's_#1\nq\"x\n"
+    );
+    let mut ran: Vec<String> = fs::read_to_string(fixture.join("ran.txt"))
+        .unwrap()
+        .lines()
+        .map(str::to_owned)
+        .collect();
+    ran.sort();
+    let expected: Vec<String> = [
+        "1.fastq",
+        "1.fq",
+        "a b.fq",
+        "c,d.fq",
+        "it$s.fq",
+        "it's #1.fq",
+        "q\"x.fq",
+    ]
+    .iter()
+    .map(|name| format!("{}|it's $HOME\\", name))
+    .collect();
+    assert_eq!(ran, expected);
+}
@@ -0,0 +1,191 @@
+//! The exit codes the binary documents, one run per error.
+
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::{assert_exit, stderr, Fixture};
+
+/// Runs a submission of the fixture's inputs with `args` through its fake
+/// `bin/sbatch`.
+fn submit(fixture: &Fixture, args: &[&str]) -> std::process::Output {
+    let mut argv = vec![
+        "--script",
+        "script.sh",
+        "--glob",
+        "in/*.fq",
+        "--no-preflight",
+    ];
+    argv.extend(args);
+    fixture.run(argv)
+}
+
+#[test]
+fn success_is_0() {
+    let fixture = Fixture::new(2);
+    fixture.fake_sbatch();
+    assert_exit(&submit(&fixture, &[]), 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn other_errors_are_1() {
+    let fixture = Fixture::new(1);
+    fixture.fake_sbatch();
+    let output = submit(&fixture, &["--wait", "--status-command", "false"]);
+    assert_exit(&output, 1);
+    assert!(stderr(&output).contains("--status-command false failed"));
+}
+
+#[test]
+fn invalid_batch_count_is_2() {
+    let fixture = Fixture::new(1);
+    let output = fixture.submit_recorded(&["--batch", "0"]);
+    assert_exit(&output, 2);
+    assert!(stderr(&output).contains("--batch must be >= 1"));
+}
+
+#[test]
+fn missing_script_is_2() {
+    let fixture = Fixture::new(1);
+    let output = fixture.run(["--script", "nope.sh", "--glob", "in/*.fq"]);
+    assert_exit(&output, 2);
+    assert!(stderr(&output).contains("script does not exist: nope.sh"));
+}
+
+#[test]
+fn invalid_script_is_2() {
+    let fixture = Fixture::new(1);
+    std::fs::create_dir(fixture.join("dir.sh")).unwrap();
+    let output = fixture.run(["--script", "dir.sh", "--glob", "in/*.fq"]);
+    assert_exit(&output, 2);
+    assert!(stderr(&output).contains("--script dir.sh: "));
+}
+
+#[test]
+fn bad_glob_is_2() {
+    let fixture = Fixture::new(1);
+    let output = fixture.run(["--script", "script.sh", "--glob", "in/[.fq"]);
+    assert_exit(&output, 2);
+    assert!(stderr(&output).contains("--glob in/[.fq: "));
+}
+
+#[test]
+fn invalid_options_are_2() {
+    let fixture = Fixture::new(4);
+    let output = fixture.submit_recorded(&["--wrap", "--max-command-bytes", "64"]);
+    assert_exit(&output, 2);
+    assert!(stderr(&output).contains("byte --wrap command (over --max-command-bytes 64)"));
+
+    let output = fixture.submit_recorded(&["--batch", "8", "--on-clamp", "error"]);
+    assert_exit(&output, 2);
+    let output = fixture.submit_recorded(&["--batch", "2", "--only-batch", "3"]);
+    assert_exit(&output, 2);
+    assert!(stderr(&output).contains("--only-batch 3: batch 3 is out of range"));
+    let output = fixture.submit_recorded(&["--input-flag", "$2"]);
+    assert_exit(&output, 2);
+    let output = fixture.submit_recorded(&["--input-flag", "$0"]);
+    assert_exit(&output, 2);
+    let output = fixture.submit_recorded(&["--strict-names", "--job-name-prefix", "a b"]);
+    assert_exit(&output, 2);
+    let output = fixture.submit_recorded(&["--submit", "bash", "--hold"]);
+    assert_exit(&output, 2);
+    assert!(stderr(&output).contains("--hold needs a scheduler submit command"));
+    assert!(fixture.recorded().is_empty());
+}
+
+#[test]
+fn no_inputs_is_3() {
+    let fixture = Fixture::new(1);
+    let output = fixture.run(["--script", "script.sh", "--glob", "in/*.bam"]);
+    assert_exit(&output, 3);
+    assert!(stderr(&output).contains("no inputs matched"));
+}
+
+#[test]
+fn unwritable_out_dir_is_4() {
+    let fixture = Fixture::new(1);
+    fixture.write("out", "a file");
+    let output = fixture.submit_recorded(&["--out-dir", "out"]);
+    assert_exit(&output, 4);
+    assert!(stderr(&output).contains("--out-dir out is not writable"));
+}
+
+#[test]
+fn failed_submission_is_5() {
+    let fixture = Fixture::new(1);
+    fixture.fake_program(
+        "sbatch",
+        "echo 'sbatch: error: invalid partition' >&2\nexit 1\n",
+    );
+    let output = submit(&fixture, &[]);
+    assert_exit(&output, 5);
+    assert!(stderr(&output).contains("invalid partition"));
+}
+
+#[test]
+fn partial_failure_is_6() {
+    let fixture = Fixture::new(3);
+    // Rejects the second submission.
+    fixture.fake_program(
+        "sbatch",
+        "calls=$(dirname \"$0\")/../calls\n\
+         echo >> \"$calls\"\n\
+         n=$(wc -l < \"$calls\")\n\
+         [ \"$n\" -eq 2 ] && { echo 'sbatch: error: rejected' >&2; exit 1; }\n\
+         echo $((1000 + n))\n",
+    );
+    let output = submit(&fixture, &["--batch", "3", "--keep-going"]);
+    assert_exit(&output, 6);
+    assert!(stderr(&output).contains("1 of 3 submission(s) failed"));
+}
+
+/// See tests/wait.rs for failed inputs (1) against failed jobs.
+#[test]
+fn failed_jobs_with_wait_are_2() {
+    let fixture = Fixture::new(2);
+    fixture.fake_sbatch();
+    // The scripts never ran, so no marker tells which input failed.
+    fixture.fake_sacct_reporting("FAILED|00:00:01|1:0");
+    let output = submit(&fixture, &["--wait", "--wait-interval", "1s"]);
+    assert_exit(&output, 2);
+
+    let fixture = Fixture::new(2);
+    fixture.fake_sbatch();
+    fixture.fake_sacct();
+    let output = submit(&fixture, &["--wait", "--wait-interval", "1s"]);
+    assert_exit(&output, 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn interrupt_is_130() {
+    let fixture = Fixture::new(2);
+    // Ctrl-C while the first job is submitted.
+    fixture.fake_program("sbatch", "kill -INT $PPID\nsleep 1\necho 1001\n");
+    let output = submit(&fixture, &["--batch", "2"]);
+    assert_exit(&output, 130);
+}
+
+#[cfg(unix)]
+#[test]
+fn read_only_out_dir_is_4() {
+    use std::os::unix::fs::PermissionsExt;
+    let fixture = Fixture::new(1);
+    if !fixture.permissions_enforced() {
+        eprintln!("skipped: read-only directories do not stop this user");
+        return;
+    }
+    let out = fixture.join("out");
+    std::fs::create_dir(&out).unwrap();
+    std::fs::set_permissions(&out, std::fs::Permissions::from_mode(0o555)).unwrap();
+    let output = fixture.submit_recorded(&["--out-dir", "out"]);
+    std::fs::set_permissions(&out, std::fs::Permissions::from_mode(0o755)).unwrap();
+    assert_exit(&output, 4);
+    assert!(stderr(&output).contains(
+        "--out-dir out is not writable: Permission denied (os error 13) \
+         (pick another directory with --out-dir)"
+    ));
+    assert_eq!(std::fs::read_dir(&out).unwrap().count(), 0);
+    assert!(fixture.recorded().is_empty());
+}
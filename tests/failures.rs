@@ -0,0 +1,80 @@
+//! `batchelor failures`: the failed inputs of a run, from its markers and
+//! the states of its jobs.
+
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::{assert_exit, stderr, stdout, Fixture};
+use std::fs;
+
+/// A run `run` of the four inputs in two batches, where batch 1 failed
+/// in/1.fq and finished it on a retry, and batch 2 finished in/3.fq before
+/// its job failed as a whole.
+fn failed_run(fixture: &Fixture, args: &[&str]) {
+    let mut argv = vec!["--batch", "2", "--run-id", "run"];
+    argv.extend(args);
+    assert_exit(&fixture.submit_recorded(&argv), 0);
+    let input = |i: usize| format!("{}\n", fixture.join(&format!("in/{}.fq", i)).display());
+    let run_dir = fixture.join(".batchelor/runs/run");
+    fs::write(run_dir.join("batch-0001.failed"), input(1)).unwrap();
+    fs::write(run_dir.join("batch-0001.done"), input(1) + &input(2)).unwrap();
+    fs::write(run_dir.join("batch-0002.done"), input(3)).unwrap();
+
+    let path = run_dir.join("state.json");
+    let mut state: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    state["jobs"][1]["state"] = "FAILED".into();
+    fs::write(&path, state.to_string()).unwrap();
+}
+
+#[test]
+fn failures_list_what_never_finished() {
+    let fixture = Fixture::new(4);
+    failed_run(&fixture, &[]);
+    let output = fixture.run(["failures"]);
+    assert_exit(&output, 0);
+    let list = fixture.join(".batchelor/runs/run/failed_inputs.txt");
+    assert_eq!(
+        fs::read_to_string(&list).unwrap(),
+        format!("{}\n", fixture.join("in/4.fq").display())
+    );
+    let text = stdout(&output);
+    assert!(
+        text.contains("Run run: 1 failed input(s)\n       1  FAILED\n"),
+        "{}",
+        text
+    );
+    // in/3.fq finished before its job failed: it never failed itself.
+    assert!(
+        text.contains("  (1 input(s) failed but later succeeded and are not listed)"),
+        "{}",
+        text
+    );
+    // Where the list went is a status line, apart from the summary.
+    assert!(!text.contains("Written to"), "{}", text);
+    assert!(stderr(&output).contains(
+        "Written to .batchelor/runs/run/failed_inputs.txt\n\
+         Rerun them with --input-list .batchelor/runs/run/failed_inputs.txt\n"
+    ));
+
+    let output = fixture.run(["failures", "--quiet"]);
+    assert_exit(&output, 0);
+    assert_eq!(stdout(&output), text);
+    assert_eq!(stderr(&output), "");
+}
+
+#[cfg(unix)]
+#[test]
+fn failed_input_lists_get_the_artifact_mode() {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = |path: &std::path::Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    let fixture = Fixture::new(4);
+    failed_run(&fixture, &["--artifact-mode", "640"]);
+    let list = fixture.join(".batchelor/runs/run/failed_inputs.txt");
+
+    assert_exit(&fixture.run(["failures"]), 0);
+    assert_eq!(mode(&list), 0o640);
+    assert_exit(&fixture.run(["failures", "--artifact-mode", "600"]), 0);
+    assert_eq!(mode(&list), 0o600);
+}
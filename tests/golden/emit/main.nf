@@ -0,0 +1,27 @@
+#!/usr/bin/env nextflow
+// Generated by batchelor --emit nextflow: a starting point, not a
+// finished pipeline. Add outputs, resources and publishDir as needed.
+
+nextflow.enable.dsl = 2
+
+params.samplesheet = "${projectDir}/samplesheet.csv"
+
+process BATCHELOR {
+    tag "${sample}"
+
+    input:
+    tuple val(sample), path(file)
+
+    script:
+    """
+    input='${file.toString().replace("'", "'\\''")}'
+    bash @DIR@/script.sh --input "\$input" 'it'\\''s \$HOME\\'
+    """
+}
+
+workflow {
+    Channel.fromPath(params.samplesheet)
+        | splitCsv(header: true)
+        | map { row -> tuple(row.sample, file(row.file)) }
+        | BATCHELOR
+}
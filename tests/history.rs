@@ -0,0 +1,33 @@
+//! `batchelor history`: the runs recorded in an output directory.
+
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::{assert_exit, stderr, stdout, Fixture};
+
+#[test]
+fn runs_are_listed_on_stdout() {
+    let fixture = Fixture::new(2);
+    let output = fixture.run(["history"]);
+    assert_exit(&output, 0);
+    assert_eq!(stdout(&output), "");
+    assert!(stderr(&output).contains("No runs recorded under .batchelor/runs"));
+    assert_eq!(stderr(&fixture.run(["history", "--quiet"])), "");
+
+    assert_exit(&fixture.submit_recorded(&["--run-id", "run"]), 0);
+    let output = fixture.run(["history"]);
+    assert_exit(&output, 0);
+    let text = stdout(&output);
+    assert!(
+        text.starts_with("run_id\ttimestamp\tjobs\tinputs\tsubmit\tstatus\nrun\t"),
+        "{}",
+        text
+    );
+    assert_eq!(stderr(&output), "");
+
+    let output = fixture.run(["history", "--json"]);
+    assert_exit(&output, 0);
+    let runs: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(runs[0]["run_id"], "run");
+}
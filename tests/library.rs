@@ -0,0 +1,60 @@
+//! The library as a service embedding it uses it: no `cli` feature, its
+//! own submitter. Runs with `--no-default-features` too.
+
+use batchelor::plan::JobSpec;
+use batchelor::reporter::Quiet;
+use batchelor::submitter::{Submitted, Submitter};
+use batchelor::{execute, plan, Cli, ExecOptions};
+use std::sync::Mutex;
+
+/// Hands out job IDs 1, 2, ... and remembers the job names.
+#[derive(Default)]
+struct Counting {
+    names: Mutex<Vec<String>>,
+}
+
+impl Submitter for Counting {
+    fn submit(&self, job: &JobSpec) -> Result<Submitted, Box<dyn std::error::Error>> {
+        let mut names = self.names.lock().unwrap();
+        names.push(job.job_name.clone());
+        Ok(Submitted {
+            job_id: Some(names.len().to_string()),
+            stdout: String::new(),
+        })
+    }
+}
+
+#[test]
+fn plan_and_execute_without_the_cli() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("script.sh"), "#!/bin/bash\necho \"$@\"\n").unwrap();
+    for name in ["a.fq", "b.fq", "c.fq"] {
+        std::fs::write(dir.path().join(name), name).unwrap();
+    }
+    let cli = Cli::builder()
+        .script(dir.path().join("script.sh"))
+        .glob(dir.path().join("*.fq").to_string_lossy())
+        .batch(2)
+        .out_dir(dir.path().join("out"))
+        .submit("bash")
+        .skip_submit_check(true)
+        .build()
+        .unwrap();
+    let plan = plan(&cli, &Quiet).unwrap();
+    assert_eq!(plan.batches.len(), 2);
+    assert_eq!(plan.inputs.len(), 3);
+
+    let submitter = Counting::default();
+    let report = execute(&plan, &ExecOptions::new(&cli), &Quiet, &submitter).unwrap();
+    assert_eq!(
+        *submitter.names.lock().unwrap(),
+        ["batch-0001", "batch-0002"]
+    );
+    let ids = report
+        .state
+        .jobs
+        .iter()
+        .map(|job| job.job_id.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, [Some("1".to_string()), Some("2".to_string())]);
+}
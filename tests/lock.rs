@@ -0,0 +1,104 @@
+//! Two runs sharing one `--out-dir`: the second finds it locked, or waits
+//! for it with `--wait-for-lock`.
+
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::{assert_exit, stderr, Fixture};
+use std::process::{Child, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Puts a fake `sbatch` in place whose first call blocks until the file
+/// `release` exists (at most 30s), after creating `started`.
+fn blocking_sbatch(fixture: &Fixture) {
+    fixture.fake_program(
+        "sbatch",
+        "dir=$(dirname \"$0\")/..\n\
+         if [ ! -e \"$dir/started\" ]; then\n\
+         touch \"$dir/started\"\n\
+         i=0\n\
+         while [ ! -e \"$dir/release\" ] && [ $i -lt 600 ]; do sleep 0.05; i=$((i + 1)); done\n\
+         fi\n\
+         echo 1001\n",
+    );
+}
+
+fn spawn(fixture: &Fixture, args: &[&str]) -> Child {
+    let mut argv = vec![
+        "--script",
+        "script.sh",
+        "--glob",
+        "in/*.fq",
+        "--no-preflight",
+    ];
+    argv.extend(args);
+    fixture
+        .command(argv)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap()
+}
+
+/// Waits for the first run to be submitting, and so holding the lock.
+fn wait_for_start(fixture: &Fixture) {
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while !fixture.join("started").exists() {
+        assert!(Instant::now() < deadline, "the first run never submitted");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn a_second_run_finds_the_out_dir_locked() {
+    let fixture = Fixture::new(2);
+    blocking_sbatch(&fixture);
+    let first = spawn(&fixture, &[]);
+    wait_for_start(&fixture);
+
+    let second = spawn(&fixture, &[]).wait_with_output().unwrap();
+    assert_exit(&second, 1);
+    let message = format!(".batchelor is locked by PID {} on ", first.id());
+    assert!(stderr(&second).contains(&message), "{}", stderr(&second));
+    assert!(stderr(&second).contains("--wait-for-lock waits for it"));
+
+    fixture.write("release", "");
+    assert_exit(&first.wait_with_output().unwrap(), 0);
+    assert_eq!(fixture.state()["jobs"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn wait_for_lock_waits_for_the_first_run() {
+    let fixture = Fixture::new(2);
+    blocking_sbatch(&fixture);
+    let first = spawn(&fixture, &["--run-id", "first"]);
+    wait_for_start(&fixture);
+
+    let mut second = spawn(&fixture, &["--run-id", "second", "--wait-for-lock", "30s"]);
+    thread::sleep(Duration::from_millis(500));
+    assert!(second.try_wait().unwrap().is_none(), "did not wait");
+    fixture.write("release", "");
+    assert_exit(&first.wait_with_output().unwrap(), 0);
+    assert_exit(&second.wait_with_output().unwrap(), 0);
+    assert!(fixture.join(".batchelor/runs/first/state.json").is_file());
+    assert!(fixture.join(".batchelor/runs/second/state.json").is_file());
+}
+
+#[test]
+fn wait_for_lock_gives_up() {
+    let fixture = Fixture::new(2);
+    blocking_sbatch(&fixture);
+    let first = spawn(&fixture, &[]);
+    wait_for_start(&fixture);
+
+    let second = spawn(&fixture, &["--wait-for-lock", "1s"])
+        .wait_with_output()
+        .unwrap();
+    assert_exit(&second, 1);
+    assert!(stderr(&second).contains("it was not released in time"));
+
+    fixture.write("release", "");
+    assert_exit(&first.wait_with_output().unwrap(), 0);
+}
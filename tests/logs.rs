@@ -0,0 +1,70 @@
+//! `batchelor logs`: the logs of a run's jobs.
+
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::{assert_exit, stderr, stdout, Fixture};
+
+/// A run `run` of two jobs with recorded logs, of which only the first
+/// was written.
+fn logged_run(fixture: &Fixture) {
+    let output =
+        fixture.submit_recorded(&["--batch", "2", "--run-id", "run", "--job-log-dir", "logs"]);
+    assert_exit(&output, 0);
+    fixture.write("logs/batch-0001.1.out", "log of job 1\n");
+}
+
+#[test]
+fn logs_go_to_stdout_and_errors_to_stderr() {
+    let fixture = Fixture::new(4);
+    logged_run(&fixture);
+    let log = |n: usize| fixture.join(&format!("logs/batch-000{0}.{0}.out", n));
+
+    let output = fixture.run(["logs", "--all"]);
+    assert_exit(&output, 0);
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "==> batch-0001 ({}) <==\nlog of job 1\n==> batch-0002 ({}) <==\n",
+            log(1).display(),
+            log(2).display()
+        )
+    );
+    assert!(stderr(&output).starts_with(&format!("{}: No such file", log(2).display())));
+
+    let output = fixture.run(["logs", "--all", "--grep", "job"]);
+    assert_exit(&output, 0);
+    assert_eq!(
+        stdout(&output),
+        format!("batch-0001: {}:1: log of job 1\n", log(1).display())
+    );
+    assert_eq!(stderr(&output), "1 log(s) missing or not recorded\n");
+
+    // Errors are still reported.
+    let output = fixture.run(["logs", "--all", "--grep", "none", "--quiet"]);
+    assert_exit(&output, 0);
+    assert_eq!(stdout(&output), "");
+    assert_eq!(stderr(&output), "");
+    let output = fixture.run(["logs", "--all", "--quiet"]);
+    assert!(stderr(&output).starts_with(&format!("{}: No such file", log(2).display())));
+}
+
+#[test]
+fn errors_are_red_with_color_always() {
+    let fixture = Fixture::new(4);
+    logged_run(&fixture);
+    let output = fixture.run(["logs", "--all", "--color", "always"]);
+    assert_exit(&output, 0);
+    assert!(
+        stderr(&output).starts_with("\x1b[31m"),
+        "{:?}",
+        stderr(&output)
+    );
+    // Neither the headers nor this log start with a marker to color.
+    assert!(stdout(&output).contains("==> batch-0001 ("));
+    assert!(!stdout(&output).contains('\x1b'));
+
+    let output = fixture.run(["logs", "--all", "--color", "never"]);
+    assert!(!stderr(&output).contains('\x1b'));
+}
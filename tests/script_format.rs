@@ -0,0 +1,120 @@
+//! Runs and scripts from a newer batchelor: `resubmit` and `clean` refuse
+//! the script formats they do not know.
+
+#![cfg(all(feature = "cli", unix))]
+
+mod common;
+
+use common::{assert_exit, stderr, stdout, Fixture};
+use std::fs;
+
+const REFUSED: &str = "scripts have format 3, this batchelor knows up to 2; upgrade batchelor \
+                       to use them";
+
+/// A run `run` of two failed jobs with their scripts kept, and its state.
+fn failed_run(fixture: &Fixture) -> serde_json::Value {
+    fixture.fake_sbatch();
+    fixture.fake_sacct_reporting("FAILED|00:00:01|1:0");
+    let output = fixture.run([
+        "--script",
+        "script.sh",
+        "--glob",
+        "in/*.fq",
+        "--batch",
+        "2",
+        "--keep",
+        "--no-preflight",
+        "--run-id",
+        "run",
+    ]);
+    assert_exit(&output, 0);
+    fixture.state_of("run")
+}
+
+/// Rewrites the run state `run` with `script_format` set to `format`.
+fn set_state_format(fixture: &Fixture, format: u32) {
+    let path = fixture.join(".batchelor/runs/run/state.json");
+    let mut state: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    state["script_format"] = format.into();
+    fs::write(&path, state.to_string()).unwrap();
+}
+
+#[test]
+fn runs_of_a_future_format_are_not_resubmitted() {
+    let fixture = Fixture::new(2);
+    failed_run(&fixture);
+    set_state_format(&fixture, 3);
+    let output = fixture.run(["resubmit", "--run", "run"]);
+    assert_exit(&output, 1);
+    assert!(
+        stderr(&output).contains(&format!("cannot resubmit run run: {}", REFUSED)),
+        "{}",
+        stderr(&output)
+    );
+    assert_eq!(fixture.sbatch_calls().len(), 2);
+}
+
+#[test]
+fn scripts_rewritten_by_a_newer_batchelor_are_not_resubmitted() {
+    let fixture = Fixture::new(2);
+    let state = failed_run(&fixture);
+    let scripts = state["jobs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|job| job["script"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    let text = fs::read_to_string(&scripts[0]).unwrap();
+    fs::write(
+        &scripts[0],
+        text.replace(
+            "# batchelor-script-format: 2",
+            "# batchelor-script-format: 3",
+        ),
+    )
+    .unwrap();
+    fs::write(
+        &scripts[1],
+        text.replace(
+            "# batchelor-script-format: 2",
+            "# batchelor-script-format: next",
+        ),
+    )
+    .unwrap();
+
+    let output = fixture.run(["resubmit", "--run", "run"]);
+    assert_exit(&output, 1);
+    let table = stdout(&output);
+    assert!(
+        table.contains(&format!("error: {}: {}", scripts[0], REFUSED)),
+        "{}",
+        table
+    );
+    assert!(
+        table.contains(&format!(
+            "error: {}: unknown script format \"next\"",
+            scripts[1]
+        )),
+        "{}",
+        table
+    );
+    assert!(stderr(&output).contains("2 batch(es) could not be resubmitted"));
+    assert_eq!(fixture.sbatch_calls().len(), 2);
+}
+
+#[test]
+fn runs_of_a_future_format_are_not_cleaned() {
+    let fixture = Fixture::new(2);
+    failed_run(&fixture);
+    set_state_format(&fixture, 3);
+    let output = fixture.run(["clean", "--run", "run", "--force"]);
+    assert_exit(&output, 0);
+    assert!(stdout(&output).contains("; skipped 1 (see above)"));
+    assert!(
+        stderr(&output).contains(&format!("skipping run run: {}", REFUSED)),
+        "{}",
+        stderr(&output)
+    );
+    assert!(fixture.join(".batchelor/runs/run/state.json").is_file());
+}
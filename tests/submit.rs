@@ -0,0 +1,646 @@
+//! Runs of the binary that record their submissions (`--submit-record`)
+//! instead of running sbatch.
+
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::{assert_exit, stderr, walk, Fixture};
+
+#[test]
+fn wrap_refuses_a_batch_over_max_command_bytes() {
+    let fixture = Fixture::new(4);
+    let output = fixture.submit_recorded(&["--wrap", "--max-command-bytes", "64"]);
+    assert_exit(&output, 2);
+    assert!(stderr(&output).contains("--wrap command (over --max-command-bytes 64)"));
+    assert!(fixture.recorded().is_empty());
+    let written = walk(&fixture.join(".batchelor"));
+    assert!(
+        !written.iter().any(|path| path.ends_with(".inputs")),
+        "{:?}",
+        written
+    );
+}
+
+#[test]
+fn wrap_writes_no_script() {
+    let fixture = Fixture::new(2);
+    let output = fixture.submit_recorded(&["--wrap"]);
+    assert_exit(&output, 0);
+    let recorded = fixture.recorded();
+    assert_eq!(recorded.len(), 1);
+    assert!(recorded[0]["script"].is_null());
+    let argv = recorded[0]["argv"].as_array().unwrap();
+    assert!(argv.iter().any(|arg| arg == "--wrap"));
+    assert!(!walk(&fixture.join(".batchelor"))
+        .iter()
+        .any(|path| path.ends_with(".sh")));
+}
+
+#[test]
+fn job_names_reach_sbatch() {
+    let fixture = Fixture::new(4);
+    fixture.fake_sbatch();
+    let output = fixture.run([
+        "--script",
+        "script.sh",
+        "--glob",
+        "in/*.fq",
+        "--batch",
+        "2",
+        "--keep",
+        "--no-preflight",
+    ]);
+    assert_exit(&output, 0);
+    let calls = fixture.sbatch_calls();
+    assert_eq!(calls.len(), 2);
+    for (i, argv) in calls.iter().enumerate() {
+        let name = format!("--job-name=batch-{:04}", i + 1);
+        assert_eq!(argv[..2], ["--parsable".to_string(), name.clone()]);
+        assert!(argv[2].ends_with(&format!("batch-{:04}.batch.sh", i + 1)));
+        let script = std::fs::read_to_string(&argv[2]).unwrap();
+        assert!(
+            script.contains(&format!("#SBATCH {}\n", name)),
+            "{}",
+            script
+        );
+    }
+}
+
+#[test]
+fn job_name_in_submit_is_not_duplicated() {
+    let fixture = Fixture::new(1);
+    fixture.fake_sbatch();
+    let output = fixture.run([
+        "--script",
+        "script.sh",
+        "--glob",
+        "in/*.fq",
+        "--submit",
+        "sbatch -J mine",
+        "--no-preflight",
+    ]);
+    assert_exit(&output, 0);
+    let argv = &fixture.sbatch_calls()[0];
+    assert_eq!(argv[..3], ["-J", "mine", "--parsable"]);
+    assert!(!argv.iter().any(|arg| arg.starts_with("--job-name")));
+}
+
+#[test]
+fn no_auto_job_name_passes_none() {
+    let fixture = Fixture::new(1);
+    let output = fixture.submit_recorded(&["--no-auto-job-name"]);
+    assert_exit(&output, 0);
+    let argv = fixture.recorded()[0]["argv"].clone();
+    assert_eq!(argv[0], "sbatch");
+    assert!(!argv
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|arg| arg.as_str().unwrap().starts_with("--job-name")));
+}
+
+#[test]
+fn record_writes_argv_and_script_per_submission() {
+    let fixture = Fixture::new(4);
+    let output =
+        fixture.submit_recorded(&["--batch", "2", "--keep", "--submit", "sbatch -p short"]);
+    assert_exit(&output, 0);
+    let recorded = fixture.recorded();
+    assert_eq!(recorded.len(), 2);
+    for (i, submission) in recorded.iter().enumerate() {
+        let job_name = format!("batch-{:04}", i + 1);
+        let script = submission["script"].as_str().unwrap();
+        assert_eq!(submission["job_id"], (i + 1).to_string());
+        assert_eq!(submission["job_name"], job_name);
+        assert_eq!(
+            submission["argv"],
+            serde_json::json!([
+                "sbatch",
+                "-p",
+                "short",
+                "--parsable",
+                format!("--job-name={}", job_name),
+                script
+            ])
+        );
+        assert_eq!(submission["stdin"], false);
+        let contents = submission["script_contents"].as_str().unwrap();
+        assert_eq!(contents, std::fs::read_to_string(script).unwrap());
+        for input in [2 * i + 1, 2 * i + 2] {
+            let path = fixture.join(&format!("in/{}.fq", input));
+            assert!(contents.contains(&format!("--input {}", path.display())));
+        }
+    }
+}
+
+#[test]
+fn record_job_ids_reach_the_run_state() {
+    let fixture = Fixture::new(3);
+    let output = fixture.submit_recorded(&["--batch", "3"]);
+    assert_exit(&output, 0);
+    assert!(common::stdout(&output).contains("Submitted batch job 3\n"));
+    let state = fixture.state();
+    let ids = state["jobs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|job| job["job_id"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, ["1", "2", "3"]);
+}
+
+#[test]
+fn record_dir_from_the_environment() {
+    let fixture = Fixture::new(1);
+    let output = fixture
+        .command([
+            "--script",
+            "script.sh",
+            "--glob",
+            "in/*.fq",
+            "--submit-stdin",
+        ])
+        .env("BATCHELOR_SUBMIT_RECORD", fixture.join("record"))
+        .output()
+        .unwrap();
+    assert_exit(&output, 0);
+    let recorded = fixture.recorded();
+    assert_eq!(recorded.len(), 1);
+    assert_eq!(recorded[0]["stdin"], true);
+    let argv = recorded[0]["argv"].as_array().unwrap();
+    assert!(!argv.contains(&recorded[0]["script"]));
+}
+
+#[test]
+fn unknown_log_template_placeholders_are_refused() {
+    let fixture = Fixture::new(1);
+    let output = fixture.submit_recorded(&[
+        "--job-log-dir",
+        "logs",
+        "--job-log-template",
+        "{jobname}.%j.out",
+    ]);
+    assert_exit(&output, 1);
+    assert!(stderr(&output).contains(
+        "--job-log-template: unknown placeholder {jobname} (expected job_name or batch_index)"
+    ));
+    assert!(fixture.recorded().is_empty());
+}
+
+#[test]
+fn bad_prefixes_are_sanitized_or_refused() {
+    let fixture = Fixture::new(1);
+    let output = fixture.submit_recorded(&["--job-name-prefix", "my run/1"]);
+    assert_exit(&output, 0);
+    assert!(stderr(&output).contains(
+        "warning: --job-name-prefix \"my run/1\" contains ' ', '/'; \
+         only letters, digits, '.', '_' and '-' are allowed; using my_run_1"
+    ));
+    assert_eq!(fixture.recorded()[0]["job_name"], "my_run_1-0001");
+
+    let fixture = Fixture::new(1);
+    let output = fixture.submit_recorded(&["--job-name-prefix", "../x", "--strict-names"]);
+    assert_exit(&output, 2);
+    assert!(stderr(&output).contains("--job-name-prefix \"../x\" contains '/'"));
+    assert!(stderr(&output).contains("(--strict-names)"));
+    assert!(fixture.recorded().is_empty());
+    assert!(!walk(fixture.path())
+        .iter()
+        .any(|path| path.ends_with(".sh") && !path.ends_with("script.sh")));
+
+    let fixture = Fixture::new(1);
+    let output = fixture.submit_recorded(&["--job-name-prefix", "align_v1.2", "--strict-names"]);
+    assert_exit(&output, 0);
+    assert_eq!(fixture.recorded()[0]["job_name"], "align_v1.2-0001");
+}
+
+#[test]
+fn preflight_checks_the_first_batch_with_test_only() {
+    let fixture = Fixture::new(4);
+    fixture.fake_sbatch();
+    let output = fixture.run(["--script", "script.sh", "--glob", "in/*.fq", "--batch", "2"]);
+    assert_exit(&output, 0);
+    assert!(stderr(&output).contains("Preflight check passed (batch-0001)"));
+    let calls = fixture.sbatch_calls();
+    assert_eq!(calls.len(), 3);
+    assert!(calls[0].contains(&"--test-only".to_string()));
+    assert!(calls[0].contains(&"--job-name=batch-0001".to_string()));
+    assert!(calls[1..]
+        .iter()
+        .all(|call| !call.contains(&"--test-only".to_string())));
+
+    // Recorded submissions are not checked.
+    let fixture = Fixture::new(4);
+    let output = fixture.submit_recorded(&["--batch", "2"]);
+    assert_exit(&output, 0);
+    assert!(!stderr(&output).contains("Preflight"));
+    assert_eq!(fixture.recorded().len(), 2);
+}
+
+#[test]
+fn a_failed_preflight_submits_nothing() {
+    let fixture = Fixture::new(2);
+    fixture.fake_program(
+        "sbatch",
+        "case \" $* \" in *' --test-only '*) echo 'sbatch: error: invalid account' >&2; exit 1;; esac\n\
+         echo 1001\n",
+    );
+    let output = fixture.run(["--script", "script.sh", "--glob", "in/*.fq"]);
+    assert_exit(&output, 1);
+    assert!(stderr(&output).contains("preflight check failed, nothing was submitted"));
+    assert!(stderr(&output).contains("invalid account"));
+    let output = fixture.run([
+        "--script",
+        "script.sh",
+        "--glob",
+        "in/*.fq",
+        "--no-preflight",
+    ]);
+    assert_exit(&output, 0);
+}
+
+#[test]
+fn incremental_runs_schedule_only_new_and_changed_inputs() {
+    let fixture = Fixture::new(3);
+    // The file names of the inputs run `run_id` scheduled, and of those it
+    // recorded as handled before.
+    let run = |run_id: &str| {
+        let output = fixture.submit_recorded(&["--incremental", "--run-id", run_id]);
+        assert_exit(&output, 0);
+        let state = fixture.state_of(run_id);
+        // Runs with nothing unchanged leave the list out.
+        let names = |inputs: &serde_json::Value| {
+            inputs
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|input| {
+                    let path = std::path::Path::new(input["path"].as_str().unwrap());
+                    path.file_name().unwrap().to_string_lossy().into_owned()
+                })
+                .collect::<Vec<_>>()
+        };
+        let scheduled = state["jobs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|job| names(&job["inputs"]))
+            .collect::<Vec<_>>();
+        (stderr(&output), scheduled, names(&state["unchanged"]))
+    };
+
+    let (log, scheduled, unchanged) = run("r1");
+    assert!(log.contains("Found 3 input files (no earlier run to compare with)"));
+    assert_eq!(scheduled, ["1.fq", "2.fq", "3.fq"]);
+    assert!(unchanged.is_empty());
+
+    fixture.write("in/2.fq", "changed");
+    fixture.write("in/4.fq", "new");
+    let (log, scheduled, unchanged) = run("r2");
+    assert!(log.contains("Found 2 input files (2 unchanged since run r1 left out)"));
+    assert_eq!(scheduled, ["2.fq", "4.fq"]);
+    assert_eq!(unchanged, ["1.fq", "3.fq"]);
+
+    // The union so far stays recorded, gone inputs included.
+    std::fs::remove_file(fixture.join("in/1.fq")).unwrap();
+    fixture.write("in/5.fq", "new");
+    let (log, scheduled, unchanged) = run("r3");
+    assert!(log.contains("Found 1 input files (3 unchanged since run r2 left out)"));
+    assert_eq!(scheduled, ["5.fq"]);
+    assert_eq!(unchanged, ["1.fq", "2.fq", "3.fq", "4.fq"]);
+    assert_eq!(fixture.recorded().len(), 3);
+}
+
+#[test]
+fn identical_scripts_are_not_rewritten() {
+    use std::time::{Duration, SystemTime};
+    let fixture = Fixture::new(4);
+    let run = |args: &[&str]| {
+        let mut argv = vec!["--batch", "2", "--out-dir", "shared", "--flat-out-dir"];
+        argv.extend(args);
+        let output = fixture.submit_recorded(&argv);
+        assert_exit(&output, 0);
+        stderr(&output)
+    };
+    let scripts = ["shared/batch-0001.batch.sh", "shared/batch-0002.batch.sh"];
+    let long_ago = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    let age = |path: &str| {
+        let file = std::fs::File::options()
+            .append(true)
+            .open(fixture.join(path))
+            .unwrap();
+        file.set_modified(long_ago).unwrap();
+    };
+    let modified = |path: &str| {
+        std::fs::metadata(fixture.join(path))
+            .unwrap()
+            .modified()
+            .unwrap()
+    };
+
+    let log = run(&["--keep"]);
+    assert!(!log.contains("unchanged"));
+    let read = |path: &str| std::fs::read_to_string(fixture.join(path)).unwrap();
+    let originals = scripts.map(read);
+    let edit = |path: &str| {
+        fixture.write(path, &(read(path) + "# edited\n"));
+        age(path);
+    };
+    scripts.into_iter().for_each(age);
+
+    // Unchanged: left alone, and not removed without --keep.
+    let log = run(&[]);
+    assert!(
+        log.contains("2 script(s) unchanged, 0 rewritten"),
+        "{}",
+        log
+    );
+    assert_eq!(scripts.map(modified), [long_ago; 2]);
+
+    // Changed and absent: written again.
+    std::fs::remove_file(fixture.join(scripts[0])).unwrap();
+    edit(scripts[1]);
+    let log = run(&["--keep"]);
+    assert!(!log.contains("unchanged"), "{}", log);
+    assert_eq!(scripts.map(read), originals);
+    assert_ne!(modified(scripts[1]), long_ago);
+
+    // One changed, one not.
+    scripts.into_iter().for_each(age);
+    edit(scripts[1]);
+    let log = run(&["--keep"]);
+    assert!(
+        log.contains("1 script(s) unchanged, 1 rewritten"),
+        "{}",
+        log
+    );
+    assert_eq!(modified(scripts[0]), long_ago);
+    assert_ne!(modified(scripts[1]), long_ago);
+
+    let log = run(&["--keep", "--force-rewrite"]);
+    assert!(!log.contains("unchanged"));
+    assert_ne!(modified(scripts[0]), long_ago);
+}
+
+#[test]
+fn matching_rules_add_their_args_in_order() {
+    let fixture = Fixture::new(4);
+    let output = fixture.submit_recorded(&[
+        "--batch",
+        "2",
+        "--resource-rule",
+        "count>=2 => --mem=1G",
+        "--resource-rule",
+        "size>5 => --mem=2G -p big",
+        "--resource-rule",
+        "count>2 => --never",
+    ]);
+    assert_exit(&output, 0);
+    // Inputs of 1 to 4 bytes: batches of 3 and 7 bytes. The later rule's
+    // --mem comes last, so sbatch takes it.
+    let args = fixture
+        .recorded()
+        .iter()
+        .map(|submission| {
+            let argv = submission["argv"].as_array().unwrap();
+            argv[3..argv.len() - 1].to_vec()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        args,
+        [
+            serde_json::json!(["--mem=1G"]),
+            serde_json::json!(["--mem=1G", "--mem=2G", "-p", "big"]),
+        ]
+        .map(|args| args.as_array().unwrap().clone())
+    );
+}
+
+#[test]
+fn rules_come_after_overridden_submits() {
+    let fixture = Fixture::new(3);
+    fixture.write(
+        "overrides.toml",
+        "[overrides]\n\"2\" = \"--mem=8G\"\n\"3\" = { submit = \"sbatch -p gpu\" }\n",
+    );
+    let output = fixture.submit_recorded(&[
+        "--batch",
+        "3",
+        "--submit",
+        "sbatch -p short",
+        "--submit-overrides",
+        "overrides.toml",
+        "--resource-rule",
+        "count>=1 => --mem=1G",
+    ]);
+    assert_exit(&output, 0);
+    let argv = fixture
+        .recorded()
+        .iter()
+        .map(|submission| {
+            let argv = submission["argv"].as_array().unwrap();
+            argv[..argv.len() - 1]
+                .iter()
+                .map(|arg| arg.as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        argv,
+        [
+            vec![
+                "sbatch",
+                "-p",
+                "short",
+                "--parsable",
+                "--job-name=batch-0001",
+                "--mem=1G"
+            ],
+            vec![
+                "sbatch",
+                "-p",
+                "short",
+                "--mem=8G",
+                "--parsable",
+                "--job-name=batch-0002",
+                "--mem=1G"
+            ],
+            vec![
+                "sbatch",
+                "-p",
+                "gpu",
+                "--parsable",
+                "--job-name=batch-0003",
+                "--mem=1G"
+            ],
+        ]
+    );
+}
+
+/// The rows of a `--manifest` file: batch index, job name, script and input.
+fn manifest_rows(path: &std::path::Path, json: bool) -> Vec<(u64, String, String, String)> {
+    let text = std::fs::read_to_string(path).unwrap();
+    if json {
+        let rows: Vec<serde_json::Value> = serde_json::from_str(&text).unwrap();
+        rows.iter()
+            .map(|row| {
+                (
+                    row["batch_index"].as_u64().unwrap(),
+                    row["job_name"].as_str().unwrap().to_string(),
+                    row["script_path"].as_str().unwrap().to_string(),
+                    row["input_path"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect()
+    } else {
+        let mut lines = text.lines();
+        assert_eq!(
+            lines.next(),
+            Some("batch_index\tjob_name\tscript_path\tinput_path")
+        );
+        lines
+            .map(|line| {
+                let fields = line.split('\t').collect::<Vec<_>>();
+                assert_eq!(fields.len(), 4, "{:?}", line);
+                (
+                    fields[0].parse().unwrap(),
+                    fields[1].to_string(),
+                    fields[2].to_string(),
+                    fields[3].to_string(),
+                )
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn manifests_match_the_scripts_and_state() {
+    for format in ["tsv", "json"] {
+        let fixture = Fixture::new(5);
+        fixture.write("in/a b.fq", "x");
+        let output = fixture.submit_recorded(&[
+            "--batch",
+            "3",
+            "--keep",
+            "--manifest",
+            "manifest",
+            "--manifest-format",
+            format,
+        ]);
+        assert_exit(&output, 0);
+        let rows = manifest_rows(&fixture.join("manifest"), format == "json");
+        assert_eq!(rows.len(), 6, "{}", format);
+
+        let state = fixture.state();
+        let jobs = state["jobs"].as_array().unwrap();
+        assert_eq!(jobs.len(), 3);
+        let mut expected = Vec::new();
+        for job in jobs {
+            let script = job["script"].as_str().unwrap();
+            let contents = std::fs::read_to_string(script).unwrap();
+            for input in job["inputs"].as_array().unwrap() {
+                let input = input["path"].as_str().unwrap();
+                let input_name = std::path::Path::new(input).file_name().unwrap();
+                assert!(
+                    contents.contains(&input_name.to_string_lossy().to_string()),
+                    "{} runs {}",
+                    script,
+                    input
+                );
+                expected.push((
+                    job["batch_index"].as_u64().unwrap(),
+                    job["job_name"].as_str().unwrap().to_string(),
+                    script.to_string(),
+                    input.to_string(),
+                ));
+            }
+        }
+        assert_eq!(rows, expected, "{}", format);
+        // Each script is the one submitted for its batch.
+        for (job, submission) in jobs.iter().zip(fixture.recorded()) {
+            assert_eq!(job["script"], submission["script"]);
+        }
+    }
+}
+
+#[test]
+fn on_clamp_warns_fails_or_pads() {
+    let fixture = Fixture::new(3);
+    let output = fixture.submit_recorded(&["--batch", "5"]);
+    assert_exit(&output, 0);
+    assert!(stderr(&output).contains(
+        "warning: --batch 5 is more than the 3 input(s); creating 3 batch(es) (--on-clamp pad keeps all 5)"
+    ));
+    assert_eq!(fixture.recorded().len(), 3);
+
+    let fixture = Fixture::new(3);
+    let output = fixture.submit_recorded(&["--batch", "5", "--on-clamp", "error"]);
+    assert_exit(&output, 2);
+    assert!(stderr(&output).contains("--batch 5 is more than the 3 input(s) (--on-clamp error)"));
+    assert!(fixture.recorded().is_empty());
+    assert!(!fixture.join(".batchelor/runs").exists());
+
+    let fixture = Fixture::new(3);
+    let output = fixture.submit_recorded(&["--batch", "5", "--on-clamp", "pad"]);
+    assert_exit(&output, 0);
+    assert!(!stderr(&output).contains("warning"));
+    let recorded = fixture.recorded();
+    assert_eq!(recorded.len(), 5);
+    for (i, submission) in recorded.iter().enumerate() {
+        let contents = submission["script_contents"].as_str().unwrap();
+        let padded = contents.contains("# No inputs (--on-clamp pad).");
+        assert_eq!(padded, i >= 3, "{}", contents);
+        assert_eq!(contents.contains("--input "), i < 3, "{}", contents);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn unreadable_glob_entries_are_skipped_or_fail() {
+    use std::os::unix::fs::PermissionsExt;
+    let fixture = Fixture::new(2);
+    if !fixture.permissions_enforced() {
+        eprintln!("skipped: unreadable directories do not stop this user");
+        return;
+    }
+    fixture.write("in/locked/3.fq", "xxx");
+    let locked = fixture.join("in/locked");
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+    let run = |glob_errors: &str| {
+        fixture.submit_recorded(&[
+            "--glob",
+            "in/*/*.fq",
+            "--glob-errors",
+            glob_errors,
+            "--dry-run",
+        ])
+    };
+    let (warn, skip, fail) = (run("warn"), run("skip"), run("fail"));
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let message = "--glob in/*/*.fq: could not read in/locked: Permission denied (os error 13)";
+    assert_exit(&warn, 0);
+    assert!(
+        stderr(&warn).contains(&format!("warning: skipping {}", message)),
+        "{}",
+        stderr(&warn)
+    );
+    assert!(stderr(&warn).contains("1 unreadable entries skipped"));
+    assert_exit(&skip, 0);
+    assert!(!stderr(&skip).contains("warning: skipping"));
+    assert!(stderr(&skip).contains("1 unreadable entries skipped"));
+    for output in [&warn, &skip] {
+        assert!(
+            stderr(output).contains("Found 2 input files"),
+            "{}",
+            stderr(output)
+        );
+    }
+    assert_exit(&fail, 1);
+    assert!(
+        stderr(&fail).contains(&format!("{} (--glob-errors warn skips it)", message)),
+        "{}",
+        stderr(&fail)
+    );
+}
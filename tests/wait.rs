@@ -0,0 +1,161 @@
+//! `--wait` against a fake scheduler that runs each job as it is submitted
+//! and reports how it ended through `--status-command`.
+
+#![cfg(all(feature = "cli", unix))]
+
+mod common;
+
+use common::{assert_exit, stderr, Fixture};
+use std::fs;
+
+/// A fake `sbatch` that runs the job script right away and keeps its exit
+/// status in `jobs/<job ID>`, and `status.sh` reporting each job from it as
+/// sacct would: COMPLETED, FAILED with the exit status, or OUT_OF_MEMORY
+/// when the script was killed.
+fn fake_slurm(fixture: &Fixture) {
+    fixture.fake_program(
+        "sbatch",
+        "jobs=$(dirname \"$0\")/../jobs\n\
+         mkdir -p \"$jobs\"\n\
+         id=$(( $(ls \"$jobs\" | wc -l) + 1001 ))\n\
+         for script; do :; done\n\
+         bash \"$script\" > /dev/null 2>&1\n\
+         echo $? > \"$jobs/$id\"\n\
+         echo $id\n",
+    );
+    fixture.fake_program(
+        "status.sh",
+        "jobs=$(dirname \"$0\")/../jobs\n\
+         for id in $(echo \"$1\" | tr , ' '); do\n\
+           rc=$(cat \"$jobs/$id\")\n\
+           case $rc in\n\
+             0) echo \"$id|COMPLETED|00:00:01|0:0|node1\" ;;\n\
+             137) echo \"$id|OUT_OF_MEMORY|00:00:01|0:9|node1\" ;;\n\
+             *) echo \"$id|FAILED|00:00:01|$rc:0|node1\" ;;\n\
+           esac\n\
+         done\n",
+    );
+}
+
+/// Waits for a run of the fixture's three inputs, one per job, whose
+/// script behaves as `script` says; it gets `--input <input>`.
+fn wait(fixture: &Fixture, script: &str) -> std::process::Output {
+    fixture.write("script.sh", &format!("#!/bin/bash\n{}\n", script));
+    fake_slurm(fixture);
+    let status = fixture.join("bin/status.sh");
+    fixture.run([
+        "--script",
+        "script.sh",
+        "--glob",
+        "in/*.fq",
+        "--batch",
+        "3",
+        "--run-id",
+        "run",
+        "--no-preflight",
+        "--wait",
+        "--wait-interval",
+        "1s",
+        "--status-command",
+        status.to_str().unwrap(),
+    ])
+}
+
+fn failed_inputs(fixture: &Fixture) -> Option<String> {
+    fs::read_to_string(fixture.join(".batchelor/runs/run/failed_inputs.txt")).ok()
+}
+
+#[test]
+fn every_input_succeeding_is_0() {
+    let fixture = Fixture::new(3);
+    let output = wait(&fixture, "true");
+    assert_exit(&output, 0);
+    assert!(stderr(&output).contains("3 jobs: 3 COMPLETED; 3/3 inputs succeeded"));
+    assert_eq!(failed_inputs(&fixture), None);
+}
+
+#[test]
+fn a_failed_input_is_1() {
+    let fixture = Fixture::new(3);
+    let output = wait(&fixture, "case $2 in *2.fq) exit 3 ;; esac");
+    assert_exit(&output, 1);
+    assert!(
+        stderr(&output).contains("3 jobs: 2 COMPLETED, 1 FAILED (exit 3); 2/3 inputs succeeded")
+    );
+    let input = fixture.join("in/2.fq");
+    assert_eq!(
+        failed_inputs(&fixture).unwrap(),
+        format!("{}\n", input.display())
+    );
+}
+
+#[test]
+fn a_killed_job_is_2() {
+    let fixture = Fixture::new(3);
+    // Takes the job script down with it, before it records anything.
+    let output = wait(&fixture, "case $2 in *3.fq) kill -9 $PPID ;; esac");
+    assert_exit(&output, 2);
+    assert!(stderr(&output).contains("3 jobs: 2 COMPLETED, 1 OUT_OF_MEMORY; 2/3 inputs succeeded"));
+    let input = fixture.join("in/3.fq");
+    assert_eq!(
+        failed_inputs(&fixture).unwrap(),
+        format!("{}\n", input.display())
+    );
+}
+
+#[test]
+fn a_killed_job_outranks_failed_inputs() {
+    let fixture = Fixture::new(3);
+    let output = wait(
+        &fixture,
+        "case $2 in *2.fq) exit 1 ;; *3.fq) kill -9 $PPID ;; esac",
+    );
+    assert_exit(&output, 2);
+    assert!(stderr(&output)
+        .contains("3 jobs: 1 COMPLETED, 1 FAILED (exit 1), 1 OUT_OF_MEMORY; 1/3 inputs succeeded"));
+    assert_eq!(
+        failed_inputs(&fixture).unwrap(),
+        format!(
+            "{}\n{}\n",
+            fixture.join("in/2.fq").display(),
+            fixture.join("in/3.fq").display()
+        )
+    );
+}
+
+#[test]
+fn rerun_scripts_resubmit_the_failed_inputs() {
+    let fixture = Fixture::new(3);
+    // 2.fq fails the first time only.
+    let output = wait(
+        &fixture,
+        "case $2 in *2.fq) [ -e failed-once ] || { touch failed-once; exit 3; } ;; esac",
+    );
+    assert_exit(&output, 1);
+    let rerun = fixture.join(".batchelor/runs/run/rerun.sh");
+    assert!(stderr(&output).contains("Rerun them with .batchelor/runs/run/rerun.sh"));
+
+    let output = fixture
+        .program("bash")
+        .arg(&rerun)
+        .output()
+        .expect("run rerun.sh");
+    assert_exit(&output, 0);
+    assert!(stderr(&output).contains("1 jobs: 1 COMPLETED; 1/1 inputs succeeded"));
+    // A new run, of just that input, with the rest of the command line.
+    let runs = fs::read_dir(fixture.join(".batchelor/runs"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|run| run != "run")
+        .collect::<Vec<_>>();
+    assert_eq!(runs.len(), 1, "{:?}", runs);
+    let state = fixture.state_of(&runs[0]);
+    let jobs = state["jobs"].as_array().unwrap();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(
+        jobs[0]["inputs"],
+        serde_json::json!([{ "path": fixture.join("in/2.fq"), "status": "done" }])
+    );
+    let args = state["args"].as_array().unwrap();
+    assert!(args.windows(2).any(|pair| pair == ["--batch", "3"]));
+}